@@ -1,5 +1,9 @@
 use crate::{
-    bot::Bot, character::Character, level::UpdateContext, message::Message, player::Player,
+    bot::Bot,
+    character::{Character, Team},
+    level::UpdateContext,
+    message::Message,
+    player::Player,
 };
 use fyrox::{
     core::{
@@ -41,6 +45,11 @@ impl Actor {
     pub fn clean_up(&mut self, scene: &mut Scene) {
         static_dispatch!(self, clean_up, scene)
     }
+
+    /// Turns actor around the vertical axis, `yaw` is in radians.
+    pub fn set_yaw(&mut self, yaw: f32) {
+        static_dispatch!(self, set_yaw, yaw)
+    }
 }
 
 impl Deref for Actor {
@@ -71,6 +80,8 @@ pub struct TargetDescriptor {
     pub handle: Handle<Actor>,
     pub health: f32,
     pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub team: Team,
 }
 
 #[derive(Default, Visit)]
@@ -96,6 +107,14 @@ impl ActorContainer {
         self.pool.borrow(actor)
     }
 
+    pub fn try_get(&self, actor: Handle<Actor>) -> Option<&Actor> {
+        self.pool.try_borrow(actor)
+    }
+
+    pub fn try_get_mut(&mut self, actor: Handle<Actor>) -> Option<&mut Actor> {
+        self.pool.try_borrow_mut(actor)
+    }
+
     pub fn contains(&self, actor: Handle<Actor>) -> bool {
         self.pool.is_valid_handle(actor)
     }
@@ -125,11 +144,16 @@ impl ActorContainer {
                 handle,
                 health: actor.health,
                 position: actor.position(&context.scene.graph),
+                velocity: context.scene.graph[actor.get_body()]
+                    .as_rigid_body()
+                    .lin_vel(),
+                team: actor.team(),
             });
         }
 
         for (handle, actor) in self.pool.pair_iter_mut() {
             let is_dead = actor.is_dead();
+            actor.spawn_protection = (actor.spawn_protection - context.time.delta).max(0.0);
 
             match actor {
                 Actor::Bot(bot) => bot.update(handle, context, &self.target_descriptors),
@@ -140,7 +164,11 @@ impl ActorContainer {
                     let distance = (context.scene.graph[item.get_pivot()].global_position()
                         - actor.position(&context.scene.graph))
                     .norm();
-                    if distance < 1.25 && !item.is_picked_up() {
+                    if distance < 1.25
+                        && context
+                            .weapon_rules
+                            .can_pick_up(item, actor, context.weapons)
+                    {
                         actor
                             .sender
                             .as_ref()
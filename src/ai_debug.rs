@@ -0,0 +1,120 @@
+//! AI debugging tools. Every bot keeps a trace of its last decisions: what it wants to do,
+//! which options it has considered and why it made its last decision. The trace is cheap to
+//! maintain, it is formatted to text only when AI inspector is visible or when the trace is
+//! dumped to a log file, so AI regressions could be diagnosed without a debugger.
+
+use crate::{actor::Actor, weapon::WeaponKind};
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    gui::{
+        message::MessageDirection,
+        text::{TextBuilder, TextMessage},
+        widget::{WidgetBuilder, WidgetMessage},
+        HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
+    },
+};
+use std::fmt::{Display, Formatter};
+
+/// Path to the file where per-tick AI trace is written to.
+pub const AI_TRACE_PATH: &str = "ai_trace.log";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BotGoal {
+    Dead,
+    Attack,
+    ReachPointOfInterest,
+}
+
+impl Default for BotGoal {
+    fn default() -> Self {
+        Self::ReachPointOfInterest
+    }
+}
+
+/// An option that bot has considered while making a decision. The higher the score the
+/// better, `None` means that option was rejected.
+#[derive(Copy, Clone, Debug)]
+pub enum ConsideredOption {
+    Target {
+        actor: Handle<Actor>,
+        score: Option<f32>,
+    },
+    Weapon {
+        kind: WeaponKind,
+        score: f32,
+    },
+}
+
+#[derive(Default, Debug)]
+pub struct DecisionTrace {
+    pub goal: BotGoal,
+    pub target: Handle<Actor>,
+    pub point_of_interest: Vector3<f32>,
+    pub options: Vec<ConsideredOption>,
+    /// Reason of the last decision that changed bot's behaviour.
+    pub reason: &'static str,
+}
+
+impl DecisionTrace {
+    pub fn decide(&mut self, reason: &'static str) {
+        self.reason = reason;
+    }
+}
+
+/// Helper that formats a score, rejected options are shown as such.
+pub struct Score(pub Option<f32>);
+
+impl Display for Score {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(score) => write!(f, "{:.2}", score),
+            None => write!(f, "rejected"),
+        }
+    }
+}
+
+/// In-game panel that shows decision traces of every bot and respawn timers of items.
+pub struct AiInspector {
+    text: Handle<UiNode>,
+    visible: bool,
+}
+
+impl AiInspector {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let text = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_width(600.0)
+                .with_margin(Thickness::uniform(5.0))
+                .with_horizontal_alignment(HorizontalAlignment::Right)
+                .with_vertical_alignment(VerticalAlignment::Top)
+                .with_visibility(false),
+        )
+        .build(&mut ui.build_ctx());
+
+        Self {
+            text,
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool, ui: &mut UserInterface) {
+        self.visible = visible;
+        ui.send_message(WidgetMessage::visibility(
+            self.text,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    pub fn set_report(&self, report: String, ui: &mut UserInterface) {
+        ui.send_message(TextMessage::text(
+            self.text,
+            MessageDirection::ToWidget,
+            report,
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+//! Team-level AI. Bots of the same team share their knowledge through a blackboard: every bot
+//! reports enemies it sees and calls for help when it is badly damaged, other bots of the team
+//! read that information and use it to select their points of interest. Each bot also has a
+//! role in its team which defines how it reacts to the shared knowledge.
+//!
+//! Blackboards are filled by messages (`ReportEnemy`, `CallForHelp`), so bots know nothing
+//! about each other directly.
+
+use crate::{actor::Actor, character::Team, MatchOptions};
+use fyrox::core::{algebra::Vector3, pool::Handle};
+use std::collections::HashMap;
+
+/// How long (in seconds) team remembers spotted enemies and help requests.
+const MEMORY_TIME: f64 = 8.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum BotRole {
+    /// Hunts for enemies spotted by teammates and helps teammates in trouble.
+    Attacker,
+    /// Responds only to help requests, otherwise controls items.
+    Defender,
+    /// Follows the flag carrier. There are no flags yet, so escort follows closest attacker.
+    Escort,
+}
+
+struct SpottedEnemy {
+    enemy: Handle<Actor>,
+    position: Vector3<f32>,
+    time: f64,
+}
+
+struct HelpRequest {
+    requester: Handle<Actor>,
+    position: Vector3<f32>,
+    time: f64,
+}
+
+struct Member {
+    role: BotRole,
+    position: Vector3<f32>,
+}
+
+#[derive(Default)]
+pub struct Blackboard {
+    spotted_enemies: Vec<SpottedEnemy>,
+    help_requests: Vec<HelpRequest>,
+    members: HashMap<Handle<Actor>, Member>,
+}
+
+impl Blackboard {
+    /// Remembers position of an enemy. Returns `true` if enemy was not known by the team
+    /// before, this is used to not spam notifications.
+    pub fn report_enemy(
+        &mut self,
+        enemy: Handle<Actor>,
+        position: Vector3<f32>,
+        time: f64,
+    ) -> bool {
+        if let Some(spotted) = self.spotted_enemies.iter_mut().find(|s| s.enemy == enemy) {
+            spotted.position = position;
+            spotted.time = time;
+            false
+        } else {
+            self.spotted_enemies.push(SpottedEnemy {
+                enemy,
+                position,
+                time,
+            });
+            true
+        }
+    }
+
+    /// Registers help request. Returns `true` if requester has not asked for help recently.
+    pub fn call_for_help(
+        &mut self,
+        requester: Handle<Actor>,
+        position: Vector3<f32>,
+        time: f64,
+    ) -> bool {
+        if let Some(request) = self
+            .help_requests
+            .iter_mut()
+            .find(|r| r.requester == requester)
+        {
+            request.position = position;
+            request.time = time;
+            false
+        } else {
+            self.help_requests.push(HelpRequest {
+                requester,
+                position,
+                time,
+            });
+            true
+        }
+    }
+
+    pub fn role(&self, bot: Handle<Actor>) -> Option<BotRole> {
+        self.members.get(&bot).map(|m| m.role)
+    }
+
+    fn forget_actor(&mut self, actor: Handle<Actor>) {
+        self.spotted_enemies.retain(|s| s.enemy != actor);
+        self.help_requests.retain(|r| r.requester != actor);
+        self.members.remove(&actor);
+    }
+
+    fn closest_help_request(
+        &self,
+        bot: Handle<Actor>,
+        position: Vector3<f32>,
+    ) -> Option<&HelpRequest> {
+        self.help_requests
+            .iter()
+            .filter(|r| r.requester != bot)
+            .min_by(|a, b| {
+                a.position
+                    .metric_distance(&position)
+                    .total_cmp(&b.position.metric_distance(&position))
+            })
+    }
+
+    /// Selects point of interest for a bot according to its role. `None` means that team
+    /// has nothing to suggest and bot should decide by itself.
+    pub fn point_of_interest(
+        &self,
+        bot: Handle<Actor>,
+        position: Vector3<f32>,
+    ) -> Option<Vector3<f32>> {
+        match self.role(bot)? {
+            BotRole::Attacker => self
+                .closest_help_request(bot, position)
+                .map(|r| r.position)
+                .or_else(|| {
+                    self.spotted_enemies
+                        .iter()
+                        .max_by(|a, b| a.time.total_cmp(&b.time))
+                        .map(|s| s.position)
+                }),
+            BotRole::Defender => self.closest_help_request(bot, position).map(|r| r.position),
+            BotRole::Escort => self
+                .members
+                .iter()
+                .filter(|(handle, member)| **handle != bot && member.role == BotRole::Attacker)
+                .map(|(_, member)| member.position)
+                .min_by(|a, b| {
+                    a.metric_distance(&position)
+                        .total_cmp(&b.metric_distance(&position))
+                }),
+        }
+    }
+
+    /// Distributes roles between bots of the team. Bots must be passed in stable order,
+    /// otherwise roles would be shuffled every frame.
+    fn assign_roles(&mut self, bots: &[(Handle<Actor>, Vector3<f32>)], options: &MatchOptions) {
+        let pattern: &[BotRole] = match options {
+            MatchOptions::CaptureTheFlag(_) => {
+                &[BotRole::Attacker, BotRole::Defender, BotRole::Escort]
+            }
+            _ => &[BotRole::Attacker, BotRole::Attacker, BotRole::Defender],
+        };
+
+        self.members.clear();
+        for (i, (handle, position)) in bots.iter().enumerate() {
+            self.members.insert(
+                *handle,
+                Member {
+                    role: pattern[i % pattern.len()],
+                    position: *position,
+                },
+            );
+        }
+    }
+
+    fn update(&mut self, time: f64) {
+        self.spotted_enemies
+            .retain(|s| time - s.time <= MEMORY_TIME);
+        self.help_requests.retain(|r| time - r.time <= MEMORY_TIME);
+    }
+}
+
+#[derive(Default)]
+pub struct BlackboardContainer {
+    blackboards: HashMap<Team, Blackboard>,
+}
+
+impl BlackboardContainer {
+    /// Returns blackboard of given team, actors without team have no blackboard.
+    pub fn get(&self, team: Team) -> Option<&Blackboard> {
+        self.blackboards.get(&team)
+    }
+
+    pub fn get_mut(&mut self, team: Team) -> Option<&mut Blackboard> {
+        if team == Team::None {
+            None
+        } else {
+            Some(self.blackboards.entry(team).or_default())
+        }
+    }
+
+    pub fn on_actor_removed(&mut self, actor: Handle<Actor>) {
+        for blackboard in self.blackboards.values_mut() {
+            blackboard.forget_actor(actor);
+        }
+    }
+
+    /// `bots` is a list of bots with their teams and positions.
+    pub fn update(
+        &mut self,
+        bots: &[(Handle<Actor>, Team, Vector3<f32>)],
+        options: &MatchOptions,
+        time: f64,
+    ) {
+        for team in [Team::Red, Team::Blue] {
+            let members = bots
+                .iter()
+                .filter(|(_, bot_team, _)| *bot_team == team)
+                .map(|(handle, _, position)| (*handle, *position))
+                .collect::<Vec<_>>();
+            if let Some(blackboard) = self.get_mut(team) {
+                blackboard.assign_roles(&members, options);
+                blackboard.update(time);
+            }
+        }
+    }
+}
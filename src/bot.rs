@@ -1,10 +1,13 @@
 use crate::{
     actor::{Actor, TargetDescriptor},
-    character::Character,
+    ai_debug::{BotGoal, ConsideredOption, DecisionTrace},
+    blackboard::Blackboard,
+    character::{Character, Team},
     item::ItemContainer,
     level::UpdateContext,
     message::Message,
-    weapon::WeaponContainer,
+    projectile::Projectile,
+    weapon::{FireMode, Weapon, WeaponContainer, WeaponKind, WeaponRules, PLAYER_LOADOUT},
     GameTime,
 };
 use fyrox::scene::graph::physics::CoefficientCombineRule;
@@ -19,11 +22,10 @@ use fyrox::{
         color::Color,
         math::{frustum::Frustum, ray::Ray, SmoothAngle, Vector3Ext},
         pool::Handle,
-        rand::Rng,
+        rand::{rngs::StdRng, Rng},
         visitor::{Visit, VisitResult, Visitor},
     },
     engine::resource_manager::ResourceManager,
-    rand,
     resource::model::Model,
     scene::{
         self,
@@ -45,13 +47,15 @@ use std::{
     sync::mpsc::Sender,
 };
 
+/// Only beasts are available. Humanoid kinds that aim rifles instead of whipping need humanoid
+/// models with rifle idle, aim, fire and reload animations, there are none in `data/models`.
+/// Until then beasts spawn with the loadout of the player and follow the same weapon rules.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Visit)]
 pub enum BotKind {
     // Beasts
     Mutant,
     Parasite,
     Maw,
-    // Humans
 }
 
 impl Default for BotKind {
@@ -68,11 +72,62 @@ impl BotKind {
             BotKind::Maw => "Maw",
         }
     }
+
+    pub const ALL: [BotKind; 3] = [BotKind::Mutant, BotKind::Parasite, BotKind::Maw];
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Visit)]
+pub enum BotSkill {
+    Novice,
+    Average,
+    Veteran,
+}
+
+impl Default for BotSkill {
+    fn default() -> Self {
+        Self::Average
+    }
+}
+
+impl BotSkill {
+    pub const ALL: [BotSkill; 3] = [BotSkill::Novice, BotSkill::Average, BotSkill::Veteran];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            BotSkill::Novice => "Novice",
+            BotSkill::Average => "Average",
+            BotSkill::Veteran => "Veteran",
+        }
+    }
+
+    /// Returns how fast (in rad/s) bot turns to its target.
+    fn turn_speed(self) -> f32 {
+        match self {
+            BotSkill::Novice => 140.0f32.to_radians(),
+            BotSkill::Average => 260.0f32.to_radians(),
+            BotSkill::Veteran => 400.0f32.to_radians(),
+        }
+    }
+
+    /// Returns max deviation (in radians) of a shot from ideal direction.
+    fn aim_spread(self) -> f32 {
+        match self {
+            BotSkill::Novice => 5.0f32.to_radians(),
+            BotSkill::Average => 2.0f32.to_radians(),
+            BotSkill::Veteran => 0.5f32.to_radians(),
+        }
+    }
+
+    /// Novices just shoot at target, others lead moving targets.
+    fn leads_targets(self) -> bool {
+        self != BotSkill::Novice
+    }
 }
 
 #[derive(Debug, Default, Visit)]
 pub struct Target {
     position: Vector3<f32>,
+    velocity: Vector3<f32>,
     handle: Handle<Actor>,
 }
 
@@ -80,6 +135,7 @@ pub struct Target {
 pub struct Bot {
     target: Option<Target>,
     kind: BotKind,
+    skill: BotSkill,
     model: Handle<Node>,
     character: Character,
     locomotion_machine: LocomotionMachine,
@@ -96,6 +152,14 @@ pub struct Bot {
     spine: Handle<Node>,
     yaw: SmoothAngle,
     pitch: SmoothAngle,
+    #[visit(skip)]
+    last_enemy_report_time: f64,
+    #[visit(skip)]
+    last_help_call_time: f64,
+    #[visit(skip)]
+    last_weapon_switch_time: f64,
+    #[visit(skip)]
+    trace: DecisionTrace,
 }
 
 impl Deref for Bot {
@@ -117,6 +181,7 @@ impl Default for Bot {
         Self {
             character: Default::default(),
             kind: BotKind::Mutant,
+            skill: Default::default(),
             model: Default::default(),
             target: Default::default(),
             locomotion_machine: Default::default(),
@@ -140,6 +205,10 @@ impl Default for Bot {
                 target: 0.0,
                 speed: 260.0f32.to_radians(), // rad/s
             },
+            last_enemy_report_time: -10.0,
+            last_help_call_time: -10.0,
+            last_weapon_switch_time: -10.0,
+            trace: Default::default(),
         }
     }
 }
@@ -165,6 +234,8 @@ pub struct BotDefinition {
     pub right_leg_name: &'static str,
     pub spine: &'static str,
     pub v_aim_angle_hack: f32,
+    /// Weapons that bot has when spawned.
+    pub loadout: &'static [WeaponKind],
 }
 
 fn prepare_animation(
@@ -190,6 +261,16 @@ fn disable_leg_tracks(
     animation.set_tracks_enabled_from(graph.find_by_name(root, leg_name), false, graph)
 }
 
+fn disable_legs(
+    animation: &mut Animation,
+    root: Handle<Node>,
+    definition: &BotDefinition,
+    graph: &Graph,
+) {
+    disable_leg_tracks(animation, root, definition.left_leg_name, graph);
+    disable_leg_tracks(animation, root, definition.right_leg_name, graph);
+}
+
 #[derive(Visit)]
 struct LocomotionMachine {
     machine: Machine,
@@ -497,13 +578,12 @@ impl CombatMachine {
 
         let aim_animation = prepare_animation(scene, aim_animation.unwrap(), model, spine);
         let whip_animation = prepare_animation(scene, whip_animation.unwrap(), model, spine);
-        let hit_reaction_animation =
-            prepare_animation(scene, hit_reaction_animation.unwrap(), model, spine);
-
         scene
             .animations
             .get_mut(whip_animation)
             .add_signal(AnimationSignal::new(Self::HIT_SIGNAL, 0.9));
+        let hit_reaction_animation =
+            prepare_animation(scene, hit_reaction_animation.unwrap(), model, spine);
 
         scene
             .animations
@@ -512,42 +592,22 @@ impl CombatMachine {
             .set_speed(2.0);
 
         // These animations must *not* affect legs, because legs animated using locomotion machine
-        disable_leg_tracks(
+        disable_legs(
             scene.animations.get_mut(aim_animation),
             model,
-            definition.left_leg_name,
+            definition,
             &scene.graph,
         );
-        disable_leg_tracks(
-            scene.animations.get_mut(aim_animation),
-            model,
-            definition.right_leg_name,
-            &scene.graph,
-        );
-
-        disable_leg_tracks(
+        disable_legs(
             scene.animations.get_mut(whip_animation),
             model,
-            definition.left_leg_name,
+            definition,
             &scene.graph,
         );
-        disable_leg_tracks(
-            scene.animations.get_mut(whip_animation),
-            model,
-            definition.right_leg_name,
-            &scene.graph,
-        );
-
-        disable_leg_tracks(
+        disable_legs(
             scene.animations.get_mut(hit_reaction_animation),
             model,
-            definition.left_leg_name,
-            &scene.graph,
-        );
-        disable_leg_tracks(
-            scene.animations.get_mut(hit_reaction_animation),
-            model,
-            definition.right_leg_name,
+            definition,
             &scene.graph,
         );
 
@@ -561,6 +621,21 @@ impl CombatMachine {
         let aim_node = machine.add_node(machine::PoseNode::make_play_animation(aim_animation));
         let aim_state = machine.add_state(State::new("Aim", aim_node));
 
+        machine.add_transition(machine::Transition::new(
+            "Aim->HitReaction",
+            aim_state,
+            hit_reaction_state,
+            0.2,
+            Self::AIM_TO_HIT_REACTION_PARAM,
+        ));
+        machine.add_transition(machine::Transition::new(
+            "HitReaction->Aim",
+            hit_reaction_state,
+            aim_state,
+            0.5,
+            Self::HIT_REACTION_TO_AIM_PARAM,
+        ));
+
         let whip_node = machine.add_node(machine::PoseNode::make_play_animation(whip_animation));
         let whip_state = machine.add_state(State::new("Whip", whip_node));
 
@@ -585,20 +660,6 @@ impl CombatMachine {
             0.2,
             Self::WHIP_TO_HIT_REACTION_PARAM,
         ));
-        machine.add_transition(machine::Transition::new(
-            "Aim->HitReaction",
-            aim_state,
-            hit_reaction_state,
-            0.2,
-            Self::AIM_TO_HIT_REACTION_PARAM,
-        ));
-        machine.add_transition(machine::Transition::new(
-            "HitReaction->Aim",
-            hit_reaction_state,
-            aim_state,
-            0.5,
-            Self::HIT_REACTION_TO_AIM_PARAM,
-        ));
 
         Self {
             machine,
@@ -612,6 +673,10 @@ impl CombatMachine {
         clean_machine(&self.machine, scene)
     }
 
+    fn can_shoot(&self) -> bool {
+        self.machine.active_state() == self.aim_state
+    }
+
     fn apply(
         &mut self,
         scene: &mut Scene,
@@ -671,6 +736,7 @@ impl Bot {
                     weapon_scale: 2.6,
                     health: 100.0,
                     v_aim_angle_hack: -2.0,
+                    loadout: &PLAYER_LOADOUT,
                 };
                 &DEFINITION
             }
@@ -696,6 +762,7 @@ impl Bot {
                     weapon_scale: 2.5,
                     health: 100.0,
                     v_aim_angle_hack: 12.0,
+                    loadout: &PLAYER_LOADOUT,
                 };
                 &DEFINITION
             }
@@ -721,6 +788,7 @@ impl Bot {
                     weapon_scale: 2.5,
                     health: 100.0,
                     v_aim_angle_hack: 16.0,
+                    loadout: &PLAYER_LOADOUT,
                 };
                 &DEFINITION
             }
@@ -729,6 +797,7 @@ impl Bot {
 
     pub async fn new(
         kind: BotKind,
+        skill: BotSkill,
         resource_manager: ResourceManager,
         scene: &mut Scene,
         position: Vector3<f32>,
@@ -828,6 +897,17 @@ impl Bot {
             last_health: definition.health,
             model,
             kind,
+            skill,
+            yaw: SmoothAngle {
+                angle: 0.0,
+                target: 0.0,
+                speed: skill.turn_speed(),
+            },
+            pitch: SmoothAngle {
+                angle: 0.0,
+                target: 0.0,
+                speed: skill.turn_speed(),
+            },
             locomotion_machine,
             combat_machine,
             dying_machine,
@@ -835,12 +915,34 @@ impl Bot {
         }
     }
 
+    pub fn skill(&self) -> BotSkill {
+        self.skill
+    }
+
+    pub fn trace(&self) -> &DecisionTrace {
+        &self.trace
+    }
+
+    /// Returns names of active states of locomotion and combat machines.
+    pub fn animation_states(&self) -> (&str, &str) {
+        let state_name = |machine: &Machine| {
+            machine
+                .states()
+                .try_borrow(machine.active_state())
+                .map_or("None", |state| state.name())
+        };
+        (
+            state_name(&self.locomotion_machine.machine),
+            state_name(&self.combat_machine.machine),
+        )
+    }
+
     pub fn can_be_removed(&self) -> bool {
         self.dying_machine.machine.active_state() == self.dying_machine.dead_state
     }
 
     pub fn can_shoot(&self) -> bool {
-        self.combat_machine.machine.active_state() == self.combat_machine.aim_state
+        self.combat_machine.can_shoot()
     }
 
     fn select_target(
@@ -854,8 +956,20 @@ impl Bot {
         let mut closest_distance = std::f32::MAX;
 
         let mut query_buffer = Vec::default();
+        let prev_target = self.trace.target;
+
         'target_loop: for desc in targets {
-            if desc.handle != self_handle && self.frustum.is_contains_point(desc.position) {
+            let is_teammate = self.character.team != Team::None && desc.team == self.character.team;
+            if desc.handle == self_handle || is_teammate {
+                continue 'target_loop;
+            }
+
+            self.trace.options.push(ConsideredOption::Target {
+                actor: desc.handle,
+                score: None,
+            });
+
+            if self.frustum.is_contains_point(desc.position) {
                 let ray = Ray::from_two_points(desc.position, position);
                 scene.graph.physics.cast_ray(
                     RayCastOptions {
@@ -884,45 +998,161 @@ impl Bot {
                 }
 
                 let sqr_d = position.sqr_distance(&desc.position);
+                if let Some(ConsideredOption::Target { score, .. }) = self.trace.options.last_mut()
+                {
+                    *score = Some(-sqr_d.sqrt());
+                }
                 if sqr_d < closest_distance {
                     self.target = Some(Target {
                         position: desc.position,
+                        velocity: desc.velocity,
                         handle: desc.handle,
                     });
                     closest_distance = sqr_d;
                 }
             }
         }
+
+        self.trace.target = self.target.as_ref().map_or(Handle::NONE, |t| t.handle);
+        if self.trace.target != prev_target {
+            if self.trace.target.is_some() {
+                self.trace.decide("selected closest visible enemy");
+            } else {
+                self.trace.decide("lost sight of enemy");
+            }
+        }
     }
 
-    fn select_point_of_interest(&mut self, items: &ItemContainer, scene: &Scene, time: &GameTime) {
+    fn select_point_of_interest(
+        &mut self,
+        self_handle: Handle<Actor>,
+        items: &ItemContainer,
+        weapons: &WeaponContainer,
+        weapon_rules: WeaponRules,
+        blackboard: Option<&Blackboard>,
+        scene: &Scene,
+        time: &GameTime,
+    ) {
         if time.elapsed - self.last_poi_update_time >= 1.25 {
-            // Select closest non-despawned item as point of interest.
             let self_position = self.position(&scene.graph);
-            let mut closest_distance = std::f32::MAX;
-            for item in items.iter() {
-                if !item.is_picked_up() {
-                    let item_position = item.position(&scene.graph);
-                    let sqr_d = item_position.sqr_distance(&self_position);
-                    if sqr_d < closest_distance {
-                        closest_distance = sqr_d;
-                        self.point_of_interest = item_position;
+            if let Some(poi) =
+                blackboard.and_then(|b| b.point_of_interest(self_handle, self_position))
+            {
+                // Team knows better where to go.
+                self.point_of_interest = poi;
+                if self.target.is_none() {
+                    self.trace.decide("following team blackboard");
+                }
+            } else {
+                if self.target.is_none() {
+                    self.trace.decide("going to closest item");
+                }
+                // Select closest item that can be picked up as point of interest.
+                let mut closest_distance = std::f32::MAX;
+                for item in items.iter() {
+                    if weapon_rules.can_pick_up(item, &self.character, weapons) {
+                        let item_position = item.position(&scene.graph);
+                        let sqr_d = item_position.sqr_distance(&self_position);
+                        if sqr_d < closest_distance {
+                            closest_distance = sqr_d;
+                            self.point_of_interest = item_position;
+                        }
                     }
                 }
             }
             self.last_poi_update_time = time.elapsed;
         }
+        self.trace.point_of_interest = self.point_of_interest;
     }
 
-    fn select_weapon(&mut self, weapons: &WeaponContainer) {
-        if self.character.current_weapon().is_some()
-            && weapons[self.character.current_weapon()].ammo() == 0
+    /// Shares knowledge with the team: reports visible enemy and asks for help when health
+    /// is low. Reports are throttled to not flood message queue.
+    fn communicate(
+        &mut self,
+        self_handle: Handle<Actor>,
+        was_damaged: bool,
+        scene: &Scene,
+        time: GameTime,
+    ) {
+        if self.character.team == Team::None {
+            return;
+        }
+
+        let sender = self.character.sender.as_ref().unwrap();
+
+        if let Some(target) = self.target.as_ref() {
+            if time.elapsed - self.last_enemy_report_time >= 1.0 {
+                sender
+                    .send(Message::ReportEnemy {
+                        reporter: self_handle,
+                        enemy: target.handle,
+                        position: target.position,
+                    })
+                    .unwrap();
+                self.last_enemy_report_time = time.elapsed;
+            }
+        }
+
+        if was_damaged
+            && self.character.health < 50.0
+            && time.elapsed - self.last_help_call_time >= 5.0
         {
-            for (i, handle) in self.character.weapons().iter().enumerate() {
-                if weapons[*handle].ammo() > 0 {
-                    self.character.set_current_weapon(i);
-                    break;
-                }
+            sender
+                .send(Message::CallForHelp {
+                    actor: self_handle,
+                    position: self.character.position(&scene.graph),
+                })
+                .unwrap();
+            self.last_help_call_time = time.elapsed;
+            self.trace.decide("called for help, health is low");
+        }
+    }
+
+    /// Selects the most suitable weapon for current distance to target. Bot does not switch
+    /// weapons too often unless current weapon is out of ammo.
+    fn select_weapon(&mut self, weapons: &WeaponContainer, scene: &mut Scene, time: GameTime) {
+        let current_weapon = self.character.current_weapon();
+        let out_of_ammo = current_weapon.is_some() && weapons[current_weapon].ammo() == 0;
+        let distance = self.target.as_ref().map(|target| {
+            target
+                .position
+                .metric_distance(&self.character.position(&scene.graph))
+        });
+
+        if !out_of_ammo && (distance.is_none() || time.elapsed - self.last_weapon_switch_time < 2.0)
+        {
+            return;
+        }
+
+        let distance = distance.unwrap_or(10.0);
+        for handle in self.character.weapons() {
+            let kind = weapons[*handle].get_kind();
+            self.trace.options.push(ConsideredOption::Weapon {
+                kind,
+                score: weapon_preference(kind, distance),
+            });
+        }
+        let best_weapon = self
+            .character
+            .weapons()
+            .iter()
+            .enumerate()
+            .filter(|(_, handle)| weapons[**handle].ammo() > 0)
+            .max_by(|(_, a), (_, b)| {
+                weapon_preference(weapons[**a].get_kind(), distance)
+                    .total_cmp(&weapon_preference(weapons[**b].get_kind(), distance))
+            })
+            .map(|(i, handle)| (i, *handle));
+
+        if let Some((index, handle)) = best_weapon {
+            if handle != current_weapon {
+                self.character.set_current_weapon(index);
+                self.last_weapon_switch_time = time.elapsed;
+                self.trace.decide(if out_of_ammo {
+                    "switched weapon, current is out of ammo"
+                } else {
+                    "switched to better weapon for current distance"
+                });
             }
         }
     }
@@ -941,6 +1171,22 @@ impl Bot {
         context.draw_sphere(self.navmesh_agent.position(), 10, 10, 0.25, Color::RED);
 
         context.draw_frustum(&self.frustum, Color::from_rgba(0, 200, 0, 255));
+
+        context.draw_sphere(
+            self.point_of_interest,
+            10,
+            10,
+            0.3,
+            Color::opaque(0, 0, 255),
+        );
+
+        if let Some(target) = self.target.as_ref() {
+            context.add_line(scene::debug::Line {
+                begin: self.navmesh_agent.position(),
+                end: target.position,
+                color: Color::opaque(255, 255, 0),
+            });
+        }
     }
 
     fn update_frustum(&mut self, position: Vector3<f32>, graph: &Graph) {
@@ -992,6 +1238,11 @@ impl Bot {
         targets: &[TargetDescriptor],
     ) {
         if self.character.is_dead() {
+            if self.trace.goal != BotGoal::Dead {
+                self.trace.goal = BotGoal::Dead;
+                self.trace.decide("killed");
+            }
+
             self.dying_machine
                 .apply(context.scene, context.time, self.character.is_dead());
 
@@ -1002,9 +1253,24 @@ impl Bot {
             lin_vel.z = 0.0;
             body.set_lin_vel(lin_vel);
         } else {
+            self.trace.options.clear();
             self.select_target(self_handle, context.scene, targets);
-            self.select_weapon(context.weapons);
-            self.select_point_of_interest(context.items, context.scene, &context.time);
+            self.select_weapon(context.weapons, context.scene, context.time);
+            self.select_point_of_interest(
+                self_handle,
+                context.items,
+                context.weapons,
+                context.weapon_rules,
+                context.blackboards.get(self.character.team),
+                context.scene,
+                &context.time,
+            );
+
+            self.trace.goal = if self.target.is_some() {
+                BotGoal::Attack
+            } else {
+                BotGoal::ReachPointOfInterest
+            };
 
             let has_ground_contact = self.character.has_ground_contact(&context.scene.graph);
             let body = context.scene.graph[self.character.body].as_rigid_body_mut();
@@ -1069,6 +1335,8 @@ impl Bot {
                 }
             }
 
+            self.communicate(self_handle, was_damaged, context.scene, context.time);
+
             self.update_frustum(position, &context.scene.graph);
 
             if let Some(look_dir) = look_dir.try_normalize(f32::EPSILON) {
@@ -1093,38 +1361,58 @@ impl Bot {
 
             let sender = self.character.sender.as_ref().unwrap();
 
-            if !in_close_combat && can_aim && self.can_shoot() && self.target.is_some() {
-                if let Some(weapon) = self
-                    .character
-                    .weapons
-                    .get(self.character.current_weapon as usize)
-                {
-                    sender
-                        .send(Message::ShootWeapon {
-                            weapon: *weapon,
-                            initial_velocity: Vector3::default(),
-                            direction: Some(look_dir),
-                        })
-                        .unwrap();
+            if !in_close_combat && can_aim && self.can_shoot() {
+                if let Some(target) = self.target.as_ref() {
+                    let weapon = self.character.current_weapon();
+                    if weapon.is_some() && !context.weapons[weapon].is_ready() {
+                        self.trace.decide("holding fire, weapon is not ready");
+                    } else if weapon.is_some() {
+                        let distance = target
+                            .position
+                            .metric_distance(&self.character.position(&context.scene.graph));
+                        let mode = choose_fire_mode(&context.weapons[weapon], distance);
+                        if let Some(direction) = self.calculate_aim_direction(
+                            &context.weapons[weapon],
+                            mode,
+                            target,
+                            &context.scene.graph,
+                            context.time,
+                            context.rng,
+                        ) {
+                            sender
+                                .send(Message::ShootWeapon {
+                                    weapon,
+                                    initial_velocity: Vector3::default(),
+                                    direction: Some(direction),
+                                    mode,
+                                })
+                                .unwrap();
+                        } else {
+                            self.trace
+                                .decide("holding fire, explosion would be too close");
+                        }
+                    }
                 }
             }
 
             // Apply damage to target from melee attack
-            if let Some(target) = self.target.as_ref() {
-                while let Some(event) = context
-                    .scene
-                    .animations
-                    .get_mut(self.combat_machine.whip_animation)
-                    .pop_event()
-                {
-                    if event.signal_id == CombatMachine::HIT_SIGNAL && in_close_combat {
-                        sender
-                            .send(Message::DamageActor {
-                                actor: target.handle,
-                                who: Default::default(),
-                                amount: 20.0,
-                            })
-                            .unwrap();
+            if self.combat_machine.whip_animation.is_some() {
+                if let Some(target) = self.target.as_ref() {
+                    while let Some(event) = context
+                        .scene
+                        .animations
+                        .get_mut(self.combat_machine.whip_animation)
+                        .pop_event()
+                    {
+                        if event.signal_id == CombatMachine::HIT_SIGNAL && in_close_combat {
+                            sender
+                                .send(Message::DamageActor {
+                                    actor: target.handle,
+                                    who: Default::default(),
+                                    amount: 20.0,
+                                })
+                                .unwrap();
+                        }
                     }
                 }
             }
@@ -1146,8 +1434,7 @@ impl Bot {
                         ];
                         sender
                             .send(Message::PlaySound {
-                                path: footsteps[rand::thread_rng().gen_range(0..footsteps.len())]
-                                    .into(),
+                                path: footsteps[context.rng.gen_range(0..footsteps.len())].into(),
                                 position,
                                 gain: 1.0,
                                 rolloff_factor: 2.0,
@@ -1162,6 +1449,97 @@ impl Bot {
         }
     }
 
+    /// Calculates direction of shot at given target. Bot leads the target using its velocity
+    /// and projectile speed, splash weapons are aimed at target's feet. Accuracy depends on
+    /// bot's skill. Returns `None` if bot would be within explosion of its shot.
+    fn calculate_aim_direction(
+        &self,
+        weapon: &Weapon,
+        mode: FireMode,
+        target: &Target,
+        graph: &Graph,
+        time: GameTime,
+        rng: &mut StdRng,
+    ) -> Option<Vector3<f32>> {
+        let projectile = Projectile::get_definition(weapon.definition().fire_mode(mode).projectile);
+        let shot_position = weapon.get_shot_position(graph);
+
+        let mut aim_point = if self.skill.leads_targets() {
+            predict_intercept_point(
+                shot_position,
+                target.position,
+                target.velocity,
+                projectile.speed / time.delta,
+            )
+        } else {
+            target.position
+        };
+
+        let mut query_buffer = Vec::default();
+
+        if projectile.splash_radius > 0.0 {
+            // Splash damage is much more effective when projectile hits ground near target.
+            graph.physics.cast_ray(
+                RayCastOptions {
+                    ray_origin: Point3::from(aim_point),
+                    ray_direction: -Vector3::y(),
+                    groups: InteractionGroups::default(),
+                    max_len: 2.0,
+                    sort_results: true,
+                },
+                &mut query_buffer,
+            );
+            for hit in query_buffer.iter() {
+                if matches!(
+                    graph[hit.collider].as_collider().shape(),
+                    ColliderShape::Trimesh(_)
+                ) {
+                    aim_point = hit.position.coords;
+                    break;
+                }
+            }
+
+            // Find where projectile will actually explode and make sure that bot is
+            // far enough from the explosion.
+            let ray = Ray::from_two_points(shot_position, aim_point);
+            graph.physics.cast_ray(
+                RayCastOptions {
+                    ray_origin: Point3::from(ray.origin),
+                    ray_direction: ray.dir,
+                    groups: InteractionGroups::default(),
+                    max_len: ray.dir.norm(),
+                    sort_results: true,
+                },
+                &mut query_buffer,
+            );
+            let impact_point = query_buffer
+                .iter()
+                .find(|hit| hit.collider != self.character.collider)
+                .map_or(aim_point, |hit| hit.position.coords);
+            if impact_point.metric_distance(&self.character.position(graph))
+                <= projectile.splash_radius
+            {
+                return None;
+            }
+        }
+
+        if projectile.gravity > 0.0 {
+            // Aim higher, so the projectile falls down to the aim point.
+            let flight_time =
+                aim_point.metric_distance(&shot_position) / (projectile.speed / time.delta);
+            aim_point.y += 0.5 * projectile.gravity * flight_time * flight_time;
+        }
+
+        let spread = self.skill.aim_spread();
+        let deviation = UnitQuaternion::from_euler_angles(
+            rng.gen_range(-spread..=spread),
+            rng.gen_range(-spread..=spread),
+            0.0,
+        );
+
+        (deviation * (aim_point - shot_position)).try_normalize(f32::EPSILON)
+    }
+
     pub fn clean_up(&mut self, scene: &mut Scene) {
         self.combat_machine.clean_up(scene);
         self.dying_machine.clean_up(scene);
@@ -1177,12 +1555,104 @@ impl Bot {
         }
     }
 
+    pub fn set_yaw(&mut self, yaw: f32) {
+        self.yaw.angle = yaw;
+        self.yaw.target = yaw;
+    }
+
     pub fn set_point_of_interest(&mut self, poi: Vector3<f32>, time: GameTime) {
         self.point_of_interest = poi;
         self.last_poi_update_time = time.elapsed;
     }
 }
 
+/// Returns point where projectile with given speed (in units per second) will meet target
+/// that moves with constant velocity. If there is no such point, target position is returned.
+pub fn predict_intercept_point(
+    shooter: Vector3<f32>,
+    target: Vector3<f32>,
+    target_velocity: Vector3<f32>,
+    projectile_speed: f32,
+) -> Vector3<f32> {
+    let d = target - shooter;
+    let a = target_velocity.dot(&target_velocity) - projectile_speed * projectile_speed;
+    let b = 2.0 * d.dot(&target_velocity);
+    let c = d.dot(&d);
+
+    let time = if a.abs() <= f32::EPSILON {
+        if b.abs() > f32::EPSILON {
+            -c / b
+        } else {
+            -1.0
+        }
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            -1.0
+        } else {
+            let sqrt_discriminant = discriminant.sqrt();
+            let t1 = (-b - sqrt_discriminant) / (2.0 * a);
+            let t2 = (-b + sqrt_discriminant) / (2.0 * a);
+            match (t1 > 0.0, t2 > 0.0) {
+                (true, true) => t1.min(t2),
+                (true, false) => t1,
+                (false, true) => t2,
+                (false, false) => -1.0,
+            }
+        }
+    };
+
+    if time > 0.0 {
+        target + target_velocity.scale(time)
+    } else {
+        target
+    }
+}
+
+/// Returns how good a weapon of given kind is for shooting at a target at given distance,
+/// the higher the better.
+fn weapon_preference(kind: WeaponKind, distance: f32) -> f32 {
+    match kind {
+        // Rockets are deadly at mid range, but bot does not fire them at close range.
+        WeaponKind::RocketLauncher => {
+            if distance > 6.0 && distance < 20.0 {
+                4.0
+            } else {
+                0.0
+            }
+        }
+        WeaponKind::PlasmaRifle => {
+            if distance < 15.0 {
+                3.0
+            } else {
+                1.0
+            }
+        }
+        WeaponKind::M4 => 2.0,
+        WeaponKind::Ak47 => 1.5,
+    }
+}
+
+/// Chooses fire mode of a weapon for given distance to the target. Secondary modes cost more
+/// ammo, so they are used only where they are better.
+fn choose_fire_mode(weapon: &Weapon, distance: f32) -> FireMode {
+    let prefers_secondary = match weapon.get_kind() {
+        // Bursts are better than single shots when the target is too far for steady fire.
+        WeaponKind::M4 => distance > 15.0,
+        // Grenades are good at mid range, bot does not fire them at close range.
+        WeaponKind::Ak47 => distance > 6.0 && distance < 15.0,
+        // There is time to charge a shot only when the target is far.
+        WeaponKind::PlasmaRifle => distance > 12.0,
+        // Guided rocket follows the target that would dodge a rocket at long range.
+        WeaponKind::RocketLauncher => distance > 15.0,
+    };
+    if prefers_secondary && weapon.ammo() >= weapon.definition().secondary.ammo_cost {
+        FireMode::Secondary
+    } else {
+        FireMode::Primary
+    }
+}
+
 fn clean_machine(machine: &Machine, scene: &mut Scene) {
     for node in machine.nodes() {
         if let PoseNode::PlayAnimation(node) = node {
@@ -1,4 +1,7 @@
-use crate::{message::Message, weapon::Weapon};
+use crate::{
+    message::Message,
+    weapon::{Weapon, WeaponContainer, WeaponKind, WeaponState},
+};
 use fyrox::{
     core::{
         algebra::Vector3,
@@ -22,6 +25,12 @@ pub struct Character {
     #[visit(skip)]
     pub sender: Option<Sender<Message>>,
     pub team: Team,
+    /// Previously selected weapon for the quick switch, it is not saved.
+    #[visit(skip)]
+    pub last_weapon: Handle<Weapon>,
+    /// Time left of spawn protection. Protection lasts only a few seconds, it is not saved.
+    #[visit(skip)]
+    pub spawn_protection: f32,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Visit)]
@@ -48,8 +57,10 @@ impl Default for Character {
             weapons: Vec::new(),
             current_weapon: 0,
             weapon_pivot: Handle::NONE,
+            last_weapon: Handle::NONE,
             sender: None,
             team: Team::None,
+            spawn_protection: 0.0,
         }
     }
 }
@@ -79,6 +90,15 @@ impl Character {
         self.team
     }
 
+    pub fn set_spawn_protection(&mut self, time: f32) {
+        self.spawn_protection = time;
+    }
+
+    /// Protected actors do not take damage from other actors.
+    pub fn is_spawn_protected(&self) -> bool {
+        self.spawn_protection > 0.0
+    }
+
     pub fn get_health(&self) -> f32 {
         self.health
     }
@@ -129,7 +149,25 @@ impl Character {
         &self.weapons
     }
 
-    pub fn add_weapon(&mut self, weapon: Handle<Weapon>) {
+    pub fn find_weapon(
+        &self,
+        kind: WeaponKind,
+        weapons: &WeaponContainer,
+    ) -> Option<Handle<Weapon>> {
+        self.weapons
+            .iter()
+            .copied()
+            .find(|&weapon| weapons[weapon].get_kind() == kind)
+    }
+
+    /// Adds a weapon and makes it current if `select` is set. The first weapon is always
+    /// selected.
+    pub fn add_weapon(&mut self, weapon: Handle<Weapon>, select: bool) {
+        if !select && !self.weapons.is_empty() {
+            self.weapons.push(weapon);
+            return;
+        }
+
         if let Some(sender) = self.sender.as_ref() {
             for other_weapon in self.weapons.iter() {
                 sender
@@ -155,6 +193,25 @@ impl Character {
         }
     }
 
+    /// Procedural motion of weapon switching, it follows the weapon that is lowered and then
+    /// the one that is raised.
+    pub fn weapon_switch_offset(&self, weapons: &WeaponContainer) -> Vector3<f32> {
+        self.weapons
+            .iter()
+            .map(|&weapon| &weapons[weapon])
+            .find(|weapon| weapon.state() == WeaponState::Lowering)
+            .or_else(|| {
+                let current_weapon = self.current_weapon();
+                if current_weapon.is_some() {
+                    Some(&weapons[current_weapon])
+                } else {
+                    None
+                }
+            })
+            .map(|weapon| weapon.switch_offset())
+            .unwrap_or_default()
+    }
+
     fn request_current_weapon_visible(&self, state: bool) {
         if let Some(sender) = self.sender.as_ref() {
             if let Some(current_weapon) = self.weapons.get(self.current_weapon as usize) {
@@ -168,30 +225,54 @@ impl Character {
         }
     }
 
-    pub fn next_weapon(&mut self) {
-        if !self.weapons.is_empty() && (self.current_weapon as usize) < self.weapons.len() - 1 {
-            self.request_current_weapon_visible(false);
-
-            self.current_weapon += 1;
-
-            self.request_current_weapon_visible(true);
+    /// Selects next (or previous) weapon in slot order. Weapons without ammo are skipped.
+    pub fn cycle_weapon(&mut self, forward: bool, weapons: &WeaponContainer) {
+        let current = self.current_weapon as usize;
+        let mut order = (0..self.weapons.len())
+            .filter(|&i| i == current || weapons[self.weapons[i]].ammo() > 0)
+            .collect::<Vec<_>>();
+        order.sort_by_key(|&i| weapons[self.weapons[i]].get_kind().slot());
+        if let Some(position) = order.iter().position(|&i| i == current) {
+            let next = if forward {
+                order.get(position + 1)
+            } else {
+                position
+                    .checked_sub(1)
+                    .and_then(|position| order.get(position))
+            };
+            if let Some(&next) = next {
+                self.set_current_weapon(next);
+            }
         }
     }
 
-    pub fn prev_weapon(&mut self) {
-        if self.current_weapon > 0 {
-            self.request_current_weapon_visible(false);
-
-            self.current_weapon -= 1;
+    /// Selects the weapon of given [slot](WeaponKind::slot), does nothing if there is no such
+    /// weapon or it is out of ammo.
+    pub fn select_slot(&mut self, slot: usize, weapons: &WeaponContainer) {
+        if let Some(i) = self.weapons.iter().position(|&weapon| {
+            weapons[weapon].get_kind().slot() == slot && weapons[weapon].ammo() > 0
+        }) {
+            self.set_current_weapon(i);
+        }
+    }
 
-            self.request_current_weapon_visible(true);
+    /// Switches back to the previously selected weapon.
+    pub fn select_last_weapon(&mut self) {
+        if let Some(i) = self
+            .weapons
+            .iter()
+            .position(|&weapon| weapon == self.last_weapon)
+        {
+            self.set_current_weapon(i);
         }
     }
 
     pub fn set_current_weapon(&mut self, i: usize) {
-        if i < self.weapons.len() {
+        if i < self.weapons.len() && i != self.current_weapon as usize {
             self.request_current_weapon_visible(false);
 
+            self.last_weapon = self.current_weapon();
+
             self.current_weapon = i as u32;
 
             self.request_current_weapon_visible(true);
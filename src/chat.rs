@@ -0,0 +1,202 @@
+//! Text chat of a match. Player opens chat input with the chat key (T by default) or team chat
+//! key (Y), types a message and sends it with Enter, Escape cancels typing. Messages of team
+//! chat are shown only to teammates, in matches without teams they are shown to everyone.
+//!
+//! In multiplayer matches chat goes through the server: it limits rate of messages of every
+//! client, writes them to its log and sends them only to clients that can see them.
+
+use crate::{
+    character::Team,
+    control_scheme::{ControlButton, ControlScheme},
+};
+use fyrox::{
+    core::visitor::{Visit, VisitResult, Visitor},
+    event::{DeviceEvent, ElementState, Event, VirtualKeyCode, WindowEvent},
+};
+use std::collections::VecDeque;
+
+/// Longer messages are truncated.
+pub const MAX_CHAT_MESSAGE_LEN: usize = 120;
+
+/// Sender can send this many messages within [`RATE_LIMIT_WINDOW`], further messages are
+/// rejected.
+const RATE_LIMIT_MESSAGES: usize = 4;
+
+/// Length of the window of rate limiting in seconds.
+const RATE_LIMIT_WINDOW: f64 = 5.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Visit)]
+pub enum ChatChannel {
+    All,
+    Team,
+}
+
+impl Default for ChatChannel {
+    fn default() -> Self {
+        ChatChannel::All
+    }
+}
+
+/// Returns `true` if a player of `viewer_team` can see a message that was sent to given channel
+/// by a player of `sender_team`.
+pub fn is_visible_to(channel: ChatChannel, sender_team: Team, viewer_team: Team) -> bool {
+    channel == ChatChannel::All || sender_team == viewer_team
+}
+
+/// Removes control characters and surrounding whitespace and truncates the text to
+/// [`MAX_CHAT_MESSAGE_LEN`] characters. Returns `None` if nothing is left.
+pub fn sanitize(text: &str) -> Option<String> {
+    let text = text
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_CHAT_MESSAGE_LEN)
+        .collect::<String>();
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_owned())
+    }
+}
+
+/// Line of chat log as it is shown to players.
+pub fn format_line(sender: &str, channel: ChatChannel, text: &str) -> String {
+    match channel {
+        ChatChannel::All => format!("{}: {}", sender, text),
+        ChatChannel::Team => format!("[Team] {}: {}", sender, text),
+    }
+}
+
+/// Limits amount of messages of one sender within a sliding window of time.
+#[derive(Default)]
+pub struct ChatLimiter {
+    send_times: VecDeque<f64>,
+}
+
+impl ChatLimiter {
+    /// Returns `true` and remembers the message if it can be sent at given time in seconds.
+    pub fn try_send(&mut self, now: f64) -> bool {
+        // Time goes back when a new level starts, messages of previous level do not count.
+        self.send_times
+            .retain(|&time| time <= now && now - time < RATE_LIMIT_WINDOW);
+
+        if self.send_times.len() < RATE_LIMIT_MESSAGES {
+            self.send_times.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub enum ChatInputEvent {
+    /// Event has nothing to do with chat, it must be handled as usual.
+    Ignored,
+    /// Event was used by chat input, nobody else must handle it.
+    Consumed,
+    Send {
+        channel: ChatChannel,
+        text: String,
+    },
+}
+
+/// Message that the player is typing.
+#[derive(Default)]
+pub struct ChatInput {
+    channel: Option<ChatChannel>,
+    text: String,
+    /// Key that opens chat also produces a character, it must not get into the message.
+    skip_character: bool,
+}
+
+impl ChatInput {
+    pub fn is_open(&self) -> bool {
+        self.channel.is_some()
+    }
+
+    /// Returns channel and text of the message if the input is open.
+    pub fn message(&self) -> Option<(ChatChannel, &str)> {
+        self.channel.map(|channel| (channel, self.text.as_str()))
+    }
+
+    pub fn close(&mut self) {
+        self.channel = None;
+        self.text.clear();
+    }
+
+    pub fn process_input_event(
+        &mut self,
+        event: &Event<()>,
+        control_scheme: &ControlScheme,
+    ) -> ChatInputEvent {
+        if self.is_open() {
+            self.process_typing(event)
+        } else {
+            let button = match event {
+                Event::WindowEvent {
+                    event: WindowEvent::KeyboardInput { input, .. },
+                    ..
+                } if input.state == ElementState::Pressed => {
+                    input.virtual_keycode.map(ControlButton::Key)
+                }
+                Event::DeviceEvent {
+                    event:
+                        DeviceEvent::Button {
+                            button,
+                            state: ElementState::Pressed,
+                        },
+                    ..
+                } => Some(ControlButton::Mouse(*button as u16)),
+                _ => None,
+            };
+
+            let channel = match button {
+                Some(button) if button == control_scheme.chat.button => ChatChannel::All,
+                Some(button) if button == control_scheme.team_chat.button => ChatChannel::Team,
+                _ => return ChatInputEvent::Ignored,
+            };
+            self.channel = Some(channel);
+            self.skip_character = matches!(button, Some(ControlButton::Key(_)));
+            ChatInputEvent::Consumed
+        }
+    }
+
+    fn process_typing(&mut self, event: &Event<()>) -> ChatInputEvent {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::ReceivedCharacter(c) => {
+                    if self.skip_character {
+                        self.skip_character = false;
+                    } else if !c.is_control() && self.text.chars().count() < MAX_CHAT_MESSAGE_LEN {
+                        self.text.push(*c);
+                    }
+                }
+                WindowEvent::KeyboardInput { input, .. }
+                    if input.state == ElementState::Pressed =>
+                {
+                    match input.virtual_keycode {
+                        Some(VirtualKeyCode::Back) => {
+                            self.text.pop();
+                        }
+                        Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::NumpadEnter) => {
+                            let channel = self.channel.unwrap_or_default();
+                            let text = sanitize(&self.text);
+                            self.close();
+                            if let Some(text) = text {
+                                return ChatInputEvent::Send { channel, text };
+                            }
+                        }
+                        Some(VirtualKeyCode::Escape) => self.close(),
+                        _ => (),
+                    }
+                }
+                WindowEvent::KeyboardInput { .. } => (),
+                _ => return ChatInputEvent::Ignored,
+            },
+            // Player does not look around while typing.
+            Event::DeviceEvent { .. } => (),
+            _ => return ChatInputEvent::Ignored,
+        }
+        ChatInputEvent::Consumed
+    }
+}
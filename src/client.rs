@@ -0,0 +1,587 @@
+//! Client of multiplayer matches. Client loads the same map as the server and simulates only
+//! its own player, so controls are responsive. Everything else comes from the server: other
+//! actors are shown as proxies - bare models without physics and AI - and replicated messages
+//! create projectiles, effects and sounds.
+//!
+//! Movement of the player is predicted: every input is applied locally right away and is
+//! remembered together with the position it led to. When a snapshot acknowledges an input,
+//! the position that server has for it is compared with the predicted one and the player is
+//! shifted by the difference, so inputs that server has not seen yet are not lost. Proxies
+//! are shown a bit in the past, interpolating between two received snapshots.
+//!
+//! If the server waits in a lobby, the client stays there and tells the server its chosen
+//! team and readiness until the match starts.
+//!
+//! `rusty-shooter --connect 192.168.0.10:7777 --name Bob`
+
+use crate::{
+    actor::Actor,
+    bot::{Bot, BotKind},
+    character::Team,
+    chat::ChatChannel,
+    leader_board::PersonalScore,
+    level::Level,
+    message::Message,
+    net::{
+        ClientId, ClientPacket, NetworkConditions, ReplicatedActorKind, ServerPacket, Snapshot,
+        Socket, CONNECTION_TIMEOUT, DEFAULT_PORT, INPUT_REDUNDANCY, MAX_PACKET_SIZE,
+    },
+    player::PlayerInput,
+};
+use fyrox::{
+    core::{
+        algebra::{UnitQuaternion, Vector3},
+        futures::executor::block_on,
+        pool::Handle,
+    },
+    engine::resource_manager::ResourceManager,
+    scene::{base::BaseBuilder, node::Node, pivot::PivotBuilder, Scene},
+    utils::log::{Log, MessageKind},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::PI,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    path::Path,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+/// Connection request is repeated with this interval until server answers.
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Choice of the player is repeated with this interval while client is in the lobby.
+const LOBBY_CHOICE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Height of proxy bodies, it is the same as height of bodies of bots.
+const PROXY_BODY_HEIGHT: f32 = 1.25;
+
+/// Proxies are shown this much in the past, so there is a snapshot on both sides of shown
+/// time even if one snapshot is lost.
+const INTERPOLATION_DELAY: f32 = 0.1;
+
+/// Shown time is snapped to the time of the server if it drifts away more than this.
+const MAX_RENDER_TIME_DRIFT: f32 = 0.25;
+
+/// Predicted steps are forgotten if server does not acknowledge them for this many steps.
+const MAX_PREDICTED_STEPS: usize = 120;
+
+/// Prediction errors smaller than this are ignored to avoid jitter.
+const PREDICTION_TOLERANCE: f32 = 0.01;
+
+struct PredictedStep {
+    sequence: u32,
+    input: PlayerInput,
+    /// Position of the player after the step, it is known after the level is updated.
+    position: Option<Vector3<f32>>,
+}
+
+struct ProxySample {
+    time: f32,
+    position: Vector3<f32>,
+    yaw: f32,
+}
+
+struct Proxy {
+    node: Handle<Node>,
+    samples: VecDeque<ProxySample>,
+}
+
+pub struct Client {
+    socket: Socket,
+    server: SocketAddr,
+    name: String,
+    id: Option<ClientId>,
+    /// `true` when the match has started for the client, before that the client is in the
+    /// lobby.
+    accepted: bool,
+    last_packet_time: Instant,
+    last_connect_time: Instant,
+    team: Team,
+    ready: bool,
+    last_lobby_choice_time: Instant,
+    last_tick: u32,
+    next_sequence: u32,
+    /// Inputs that were applied locally but are not acknowledged by the server yet.
+    predicted: VecDeque<PredictedStep>,
+    /// Time of the server at which proxies are shown.
+    render_time: f32,
+    /// Models of actors of the server, they are identified by server handles.
+    proxies: HashMap<Handle<Actor>, Proxy>,
+}
+
+impl Client {
+    /// Starts connecting to a server, port can be omitted from the address.
+    pub fn connect(address: &str, name: String, conditions: NetworkConditions) -> io::Result<Self> {
+        let address = if address.contains(':') {
+            address.to_owned()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+        let server = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unable to resolve address {}", address),
+            )
+        })?;
+
+        let mut client = Self {
+            socket: Socket::bind(("0.0.0.0", 0), conditions)?,
+            server,
+            name,
+            id: None,
+            accepted: false,
+            last_packet_time: Instant::now(),
+            last_connect_time: Instant::now(),
+            team: Team::None,
+            ready: false,
+            last_lobby_choice_time: Instant::now(),
+            last_tick: 0,
+            next_sequence: 1,
+            predicted: Default::default(),
+            render_time: 0.0,
+            proxies: Default::default(),
+        };
+        client.send(&ClientPacket::Connect {
+            name: client.name.clone(),
+        });
+
+        Log::writeln(
+            MessageKind::Information,
+            format!("Connecting to {}...", server),
+        );
+
+        Ok(client)
+    }
+
+    /// Returns `true` if nothing was received from the server for too long, this includes
+    /// the case when server does not answer to connection request.
+    pub fn is_timed_out(&self) -> bool {
+        self.last_packet_time.elapsed() > CONNECTION_TIMEOUT
+    }
+
+    fn send(&mut self, packet: &ClientPacket) {
+        let data = packet.encode();
+        self.socket.send(self.server, &data);
+    }
+
+    /// Sends input of current simulation step. Every packet repeats a few previous inputs, so
+    /// a lost packet does not lose input.
+    pub fn send_input(&mut self, input: &PlayerInput) {
+        if !self.accepted {
+            return;
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.predicted.push_back(PredictedStep {
+            sequence,
+            input: *input,
+            position: None,
+        });
+        while self.predicted.len() > MAX_PREDICTED_STEPS {
+            self.predicted.pop_front();
+        }
+
+        let inputs = self
+            .predicted
+            .iter()
+            .skip(self.predicted.len().saturating_sub(INPUT_REDUNDANCY))
+            .map(|step| step.input)
+            .collect();
+        self.send(&ClientPacket::Input {
+            sequence,
+            view_time: self.render_time,
+            inputs,
+        });
+    }
+
+    /// Remembers predicted position of the player and moves proxies, must be called after
+    /// every update of the level.
+    pub fn update(&mut self, level: &Level, scene: &mut Scene, dt: f32) {
+        if let (Some(step), Some(player)) = (
+            self.predicted.back_mut(),
+            level.actors().try_get(level.get_player()),
+        ) {
+            if step.position.is_none() {
+                step.position = Some(player.position(&scene.graph));
+            }
+        }
+
+        self.render_time += dt;
+        for proxy in self.proxies.values_mut() {
+            // Keep one sample before shown time to interpolate from it.
+            while proxy.samples.len() > 2 && proxy.samples[1].time <= self.render_time {
+                proxy.samples.pop_front();
+            }
+
+            if let Some((position, yaw)) = interpolate(&proxy.samples, self.render_time) {
+                scene.graph[proxy.node]
+                    .local_transform_mut()
+                    .set_position(position)
+                    .set_rotation(UnitQuaternion::from_axis_angle(&Vector3::y_axis(), yaw));
+            }
+        }
+    }
+
+    /// Tells the server of the lobby which team the player wants to play for and whether the
+    /// player is ready.
+    pub fn set_ready(&mut self, team: Team, ready: bool) {
+        self.team = team;
+        self.ready = ready;
+        self.send_lobby_choice();
+    }
+
+    /// Sends a chat message to the server, it comes back as [`Message::Chat`] if the server
+    /// accepts it.
+    pub fn send_chat(&mut self, channel: ChatChannel, text: String) {
+        if self.accepted {
+            self.send(&ClientPacket::Chat { channel, text });
+        }
+    }
+
+    fn send_lobby_choice(&mut self) {
+        self.last_lobby_choice_time = Instant::now();
+        self.send(&ClientPacket::LobbyChoice {
+            team: self.team,
+            ready: self.ready,
+        });
+    }
+
+    /// Returns packets that came from the server since last call. Repeated answers to
+    /// connection request and outdated snapshots are filtered out.
+    pub fn receive(&mut self) -> Vec<ServerPacket> {
+        if self.id.is_none() && self.last_connect_time.elapsed() > CONNECT_RETRY_INTERVAL {
+            self.last_connect_time = Instant::now();
+            self.send(&ClientPacket::Connect {
+                name: self.name.clone(),
+            });
+        }
+        if self.id.is_some()
+            && !self.accepted
+            && self.last_lobby_choice_time.elapsed() > LOBBY_CHOICE_INTERVAL
+        {
+            self.send_lobby_choice();
+        }
+
+        let mut packets = Vec::new();
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            let (size, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(result) => result,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    Log::writeln(
+                        MessageKind::Warning,
+                        format!("Failed to receive packet, reason: {}", e),
+                    );
+                    continue;
+                }
+            };
+
+            if address != self.server {
+                continue;
+            }
+
+            if let Some(packet) = ServerPacket::decode(&buffer[..size]) {
+                self.last_packet_time = Instant::now();
+
+                match &packet {
+                    ServerPacket::Lobby { client, .. } => {
+                        // Lobby state can come late, after the match has started.
+                        if self.accepted {
+                            continue;
+                        }
+                        if self.id.is_none() {
+                            self.id = Some(*client);
+                            Log::writeln(
+                                MessageKind::Information,
+                                format!("Joined lobby of {}", self.server),
+                            );
+                        }
+                    }
+                    ServerPacket::Accepted { client, .. } => {
+                        if self.accepted {
+                            continue;
+                        }
+                        self.accepted = true;
+                        self.id = Some(*client);
+                        Log::writeln(
+                            MessageKind::Information,
+                            format!("Connected to {} as client {}", self.server, client),
+                        );
+                    }
+                    ServerPacket::Snapshot(snapshot) => {
+                        // Datagrams can come out of order.
+                        if snapshot.tick <= self.last_tick {
+                            continue;
+                        }
+                        self.last_tick = snapshot.tick;
+                    }
+                    _ => (),
+                }
+
+                packets.push(packet);
+            }
+        }
+        packets
+    }
+
+    /// Brings client's level to the state of the server.
+    pub fn apply_snapshot(
+        &mut self,
+        snapshot: &Snapshot,
+        level: &mut Level,
+        scene: &mut Scene,
+        resource_manager: &ResourceManager,
+        sender: &Sender<Message>,
+    ) {
+        level.set_time(snapshot.time);
+
+        let target_render_time = snapshot.time - INTERPOLATION_DELAY;
+        if (self.render_time - target_render_time).abs() > MAX_RENDER_TIME_DRIFT {
+            self.render_time = target_render_time;
+        }
+
+        self.update_own_player(snapshot, level, scene, sender);
+        self.update_proxies(snapshot, scene, resource_manager);
+
+        for item in snapshot.items.iter() {
+            if level.items().contains(item.item) {
+                level.items_mut().get_mut(item.item).set_active(item.active);
+            }
+        }
+
+        for score in snapshot.scores.iter() {
+            *level.leader_board.get_or_add_actor(&score.name) = PersonalScore {
+                kills: score.kills,
+                deaths: score.deaths,
+            };
+        }
+        for &(team, score) in snapshot.team_scores.iter() {
+            level.leader_board.set_team_score(team, score);
+        }
+    }
+
+    fn update_own_player(
+        &mut self,
+        snapshot: &Snapshot,
+        level: &mut Level,
+        scene: &mut Scene,
+        sender: &Sender<Message>,
+    ) {
+        let player = level.get_player();
+
+        let state = match snapshot
+            .actors
+            .iter()
+            .find(|state| state.actor == snapshot.own_actor)
+        {
+            Some(state) => state,
+            None => {
+                // Player is dead on the server, local player dies too and will be respawned
+                // the same way as in a single player match.
+                if let Some(actor) = level.actors_mut().try_get_mut(player) {
+                    actor.health = 0.0;
+                }
+                return;
+            }
+        };
+
+        let actor = match level.actors_mut().try_get_mut(player) {
+            Some(actor) => actor,
+            None => return,
+        };
+        self.reconcile(actor, snapshot, state.position, scene);
+        actor.health = state.health;
+        actor.armor = state.armor;
+        actor.name = state.name.clone();
+        actor.set_team(state.team);
+
+        let weapons = actor.weapons().to_vec();
+        for &(kind, ammo) in snapshot.own_weapons.iter() {
+            match weapons
+                .iter()
+                .find(|&&weapon| level.weapons()[weapon].get_kind() == kind)
+            {
+                Some(&weapon) => level.weapons_mut()[weapon].set_ammo(ammo),
+                None => sender
+                    .send(Message::GiveNewWeapon {
+                        actor: player,
+                        kind,
+                    })
+                    .unwrap(),
+            }
+        }
+    }
+
+    /// Corrects predicted position of the player using the position that server has for the
+    /// last acknowledged input.
+    fn reconcile(
+        &mut self,
+        actor: &mut Actor,
+        snapshot: &Snapshot,
+        server_position: Vector3<f32>,
+        scene: &mut Scene,
+    ) {
+        while self
+            .predicted
+            .front()
+            .map_or(false, |step| step.sequence < snapshot.ack_input)
+        {
+            self.predicted.pop_front();
+        }
+
+        let acknowledged = match self.predicted.front() {
+            Some(step) if step.sequence == snapshot.ack_input => {
+                self.predicted.pop_front().and_then(|step| step.position)
+            }
+            _ => None,
+        };
+
+        match acknowledged {
+            Some(predicted_position) => {
+                let error = server_position - predicted_position;
+                if error.norm() > PREDICTION_TOLERANCE {
+                    let position = actor.position(&scene.graph) + error;
+                    actor.set_position(&mut scene.graph, position);
+                    for step in self.predicted.iter_mut() {
+                        if let Some(position) = step.position.as_mut() {
+                            *position += error;
+                        }
+                    }
+                }
+            }
+            None => {
+                // Nothing to compare with - player has just spawned or too many packets were
+                // lost, take the state of the server as is.
+                actor.set_position(&mut scene.graph, server_position);
+                scene.graph[actor.get_body()]
+                    .as_rigid_body_mut()
+                    .set_lin_vel(snapshot.own_velocity);
+                self.predicted.clear();
+            }
+        }
+    }
+
+    fn update_proxies(
+        &mut self,
+        snapshot: &Snapshot,
+        scene: &mut Scene,
+        resource_manager: &ResourceManager,
+    ) {
+        for state in snapshot
+            .actors
+            .iter()
+            .filter(|state| state.actor != snapshot.own_actor)
+        {
+            let proxy = self.proxies.entry(state.actor).or_insert_with(|| Proxy {
+                node: create_proxy(state.kind, scene, resource_manager),
+                samples: Default::default(),
+            });
+
+            proxy.samples.push_back(ProxySample {
+                time: snapshot.time,
+                position: state.position,
+                yaw: state.yaw,
+            });
+        }
+
+        // Dead actors are removed from the level of the server, so are their proxies.
+        self.proxies.retain(|&actor, proxy| {
+            let exists = actor != snapshot.own_actor
+                && snapshot.actors.iter().any(|state| state.actor == actor);
+            if !exists {
+                scene.graph.remove_node(proxy.node);
+            }
+            exists
+        });
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // Let the server know that the player is gone instead of waiting for a timeout.
+        if self.id.is_some() {
+            self.send(&ClientPacket::Disconnect);
+        }
+    }
+}
+
+/// Returns position and yaw of a proxy at given time. Proxy stays at the last known position
+/// if snapshots are late.
+fn interpolate(samples: &VecDeque<ProxySample>, time: f32) -> Option<(Vector3<f32>, f32)> {
+    let next = samples.iter().position(|sample| sample.time >= time);
+    match next {
+        Some(i) if i > 0 => {
+            let (prev, next) = (&samples[i - 1], &samples[i]);
+            let t = (time - prev.time) / (next.time - prev.time);
+
+            // Turn the shortest way.
+            let mut delta = next.yaw - prev.yaw;
+            if delta > PI {
+                delta -= 2.0 * PI;
+            } else if delta < -PI {
+                delta += 2.0 * PI;
+            }
+
+            Some((prev.position.lerp(&next.position, t), prev.yaw + delta * t))
+        }
+        Some(_) => samples.front().map(|sample| (sample.position, sample.yaw)),
+        None => samples.back().map(|sample| (sample.position, sample.yaw)),
+    }
+}
+
+fn create_proxy(
+    kind: ReplicatedActorKind,
+    scene: &mut Scene,
+    resource_manager: &ResourceManager,
+) -> Handle<Node> {
+    // Players of other clients are shown using model of the default bot.
+    let bot_kind = match kind {
+        ReplicatedActorKind::Player => BotKind::default(),
+        ReplicatedActorKind::Bot(kind) => kind,
+    };
+    let definition = Bot::get_definition(bot_kind);
+
+    // Proxy without a model is still useful, it keeps position of the actor for sounds and
+    // effects, so failed load is not fatal.
+    let mut children = Vec::new();
+    match block_on(resource_manager.request_model(Path::new(definition.model))) {
+        Ok(resource) => {
+            let model = resource.instantiate_geometry(scene);
+            scene.graph[model]
+                .local_transform_mut()
+                .set_position(Vector3::new(0.0, -PROXY_BODY_HEIGHT * 0.5, 0.0))
+                .set_scale(Vector3::new(
+                    definition.scale,
+                    definition.scale,
+                    definition.scale,
+                ));
+            children.push(model);
+        }
+        Err(e) => Log::writeln(
+            MessageKind::Error,
+            format!(
+                "Unable to load proxy model {}. Reason: {:?}",
+                definition.model, e
+            ),
+        ),
+    }
+
+    PivotBuilder::new(BaseBuilder::new().with_children(&children)).build(&mut scene.graph)
+}
+
+/// Returns `true` if a message that was produced by client's own level must be handled. Only
+/// messages that are needed to simulate client's player are handled, outcome of everything
+/// else is decided by the server.
+pub fn is_handled_locally(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::ShowWeapon { .. }
+            | Message::GiveNewWeapon { .. }
+            | Message::RespawnActor { .. }
+            | Message::SpawnPlayer
+    )
+}
@@ -0,0 +1,280 @@
+//! Settings of the user: key bindings, mouse, graphics, video mode and audio. They are stored in
+//! a RON file in the config directory of the user, loaded when the game starts and saved when
+//! the options window is closed after changes. Unknown fields of the file are ignored and missing
+//! fields get default values, so the file survives updates of the game.
+
+use crate::control_scheme::ControlScheme;
+use fyrox::{
+    monitor::MonitorHandle,
+    renderer::{CsmSettings, QualitySettings, ShadowMapPrecision},
+    utils::log::{Log, MessageKind},
+    window::Fullscreen,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE_NAME: &str = "config.ron";
+
+/// Returns directory of the game inside of a directory of the user, like `~/.config`.
+/// `xdg_var` is the XDG variable of the directory and `home_subdir` is its default location in
+/// the home directory, Windows keeps everything in `APPDATA`.
+pub fn user_dir(xdg_var: &str, home_subdir: &str) -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os(xdg_var)
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(home_subdir)))
+    };
+    dir.map(|dir| dir.join("rusty-shooter"))
+}
+
+/// Config is put to the working directory if the home directory is unknown.
+pub fn config_path() -> PathBuf {
+    user_dir("XDG_CONFIG_HOME", ".config").map_or_else(
+        || PathBuf::from(CONFIG_FILE_NAME),
+        |dir| dir.join(CONFIG_FILE_NAME),
+    )
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct VideoModeConfig {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate_millihertz: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub controls: ControlScheme,
+    #[serde(with = "graphics")]
+    pub graphics: QualitySettings,
+    /// Video mode of exclusive fullscreen, `None` means borderless fullscreen.
+    pub video_mode: Option<VideoModeConfig>,
+    pub fullscreen: bool,
+    pub sound_volume: f32,
+    pub music_volume: f32,
+    /// Show when major items are about to appear.
+    pub item_announcements: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            controls: Default::default(),
+            graphics: Default::default(),
+            video_mode: None,
+            fullscreen: false,
+            sound_volume: 1.0,
+            music_volume: 0.25,
+            item_announcements: true,
+        }
+    }
+}
+
+/// Form of [`QualitySettings`] in the config file. Settings of the renderer have no defaults for
+/// missing fields, so they are mirrored here.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct GraphicsConfig {
+    point_shadow_map_size: usize,
+    point_soft_shadows: bool,
+    point_shadows_enabled: bool,
+    point_shadows_distance: f32,
+    point_shadow_map_precision: ShadowMapPrecision,
+    spot_shadow_map_size: usize,
+    spot_soft_shadows: bool,
+    spot_shadows_enabled: bool,
+    spot_shadows_distance: f32,
+    spot_shadow_map_precision: ShadowMapPrecision,
+    csm_settings: CsmConfig,
+    use_ssao: bool,
+    ssao_radius: f32,
+    light_scatter_enabled: bool,
+    fxaa: bool,
+    use_parallax_mapping: bool,
+    use_bloom: bool,
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        QualitySettings::default().into()
+    }
+}
+
+impl From<QualitySettings> for GraphicsConfig {
+    fn from(settings: QualitySettings) -> Self {
+        Self {
+            point_shadow_map_size: settings.point_shadow_map_size,
+            point_soft_shadows: settings.point_soft_shadows,
+            point_shadows_enabled: settings.point_shadows_enabled,
+            point_shadows_distance: settings.point_shadows_distance,
+            point_shadow_map_precision: settings.point_shadow_map_precision,
+            spot_shadow_map_size: settings.spot_shadow_map_size,
+            spot_soft_shadows: settings.spot_soft_shadows,
+            spot_shadows_enabled: settings.spot_shadows_enabled,
+            spot_shadows_distance: settings.spot_shadows_distance,
+            spot_shadow_map_precision: settings.spot_shadow_map_precision,
+            csm_settings: settings.csm_settings.into(),
+            use_ssao: settings.use_ssao,
+            ssao_radius: settings.ssao_radius,
+            light_scatter_enabled: settings.light_scatter_enabled,
+            fxaa: settings.fxaa,
+            use_parallax_mapping: settings.use_parallax_mapping,
+            use_bloom: settings.use_bloom,
+        }
+    }
+}
+
+impl From<GraphicsConfig> for QualitySettings {
+    fn from(config: GraphicsConfig) -> Self {
+        Self {
+            point_shadow_map_size: config.point_shadow_map_size,
+            point_soft_shadows: config.point_soft_shadows,
+            point_shadows_enabled: config.point_shadows_enabled,
+            point_shadows_distance: config.point_shadows_distance,
+            point_shadow_map_precision: config.point_shadow_map_precision,
+            spot_shadow_map_size: config.spot_shadow_map_size,
+            spot_soft_shadows: config.spot_soft_shadows,
+            spot_shadows_enabled: config.spot_shadows_enabled,
+            spot_shadows_distance: config.spot_shadows_distance,
+            spot_shadow_map_precision: config.spot_shadow_map_precision,
+            csm_settings: config.csm_settings.into(),
+            use_ssao: config.use_ssao,
+            ssao_radius: config.ssao_radius,
+            light_scatter_enabled: config.light_scatter_enabled,
+            fxaa: config.fxaa,
+            use_parallax_mapping: config.use_parallax_mapping,
+            use_bloom: config.use_bloom,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct CsmConfig {
+    enabled: bool,
+    size: usize,
+    precision: ShadowMapPrecision,
+    pcf: bool,
+}
+
+impl Default for CsmConfig {
+    fn default() -> Self {
+        CsmSettings::default().into()
+    }
+}
+
+impl From<CsmSettings> for CsmConfig {
+    fn from(settings: CsmSettings) -> Self {
+        Self {
+            enabled: settings.enabled,
+            size: settings.size,
+            precision: settings.precision,
+            pcf: settings.pcf,
+        }
+    }
+}
+
+impl From<CsmConfig> for CsmSettings {
+    fn from(config: CsmConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            size: config.size,
+            precision: config.precision,
+            pcf: config.pcf,
+        }
+    }
+}
+
+/// Stores [`QualitySettings`] as [`GraphicsConfig`].
+mod graphics {
+    use super::GraphicsConfig;
+    use fyrox::renderer::QualitySettings;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        settings: &QualitySettings,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        GraphicsConfig::from(settings.clone()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<QualitySettings, D::Error> {
+        GraphicsConfig::deserialize(deserializer).map(Into::into)
+    }
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        ron::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// Loads config of the user, defaults are used if there is no config or it is broken.
+    pub fn load() -> Self {
+        let path = config_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).unwrap_or_else(|e| {
+                Log::writeln(
+                    MessageKind::Warning,
+                    format!(
+                        "Unable to parse config {}, defaults are used. Reason: {}",
+                        path.display(),
+                        e
+                    ),
+                );
+                Default::default()
+            }),
+            Err(_) => Default::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = config_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())?;
+        fs::write(&path, text).map_err(|e| e.to_string())
+    }
+
+    /// Returns fullscreen mode of the window, exclusive fullscreen falls back to borderless if
+    /// the monitor does not support the video mode.
+    pub fn window_fullscreen(&self, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+        if !self.fullscreen {
+            return None;
+        }
+        let video_mode = self.video_mode.as_ref().and_then(|config| {
+            monitor.as_ref().and_then(|monitor| {
+                monitor.video_modes().find(|mode| {
+                    mode.size().width == config.width
+                        && mode.size().height == config.height
+                        && mode.refresh_rate_millihertz() == config.refresh_rate_millihertz
+                })
+            })
+        });
+        Some(match video_mode {
+            Some(video_mode) => Fullscreen::Exclusive(video_mode),
+            None => Fullscreen::Borderless(monitor),
+        })
+    }
+
+    pub fn set_window_fullscreen(&mut self, fullscreen: Option<Fullscreen>) {
+        self.fullscreen = fullscreen.is_some();
+        self.video_mode = match fullscreen {
+            Some(Fullscreen::Exclusive(video_mode)) => Some(VideoModeConfig {
+                width: video_mode.size().width,
+                height: video_mode.size().height,
+                refresh_rate_millihertz: video_mode.refresh_rate_millihertz(),
+            }),
+            _ => None,
+        };
+    }
+}
@@ -1,4 +1,7 @@
+use crate::weapon::AutoSwitch;
 use fyrox::event::VirtualKeyCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ControlButton {
@@ -26,11 +29,220 @@ impl ControlButton {
     }
 }
 
+/// Form of [`ControlButton`] in the config file, keys are stored by names of their codes.
+#[derive(Serialize, Deserialize)]
+enum ControlButtonConfig {
+    Mouse(u16),
+    Key(String),
+    WheelUp,
+    WheelDown,
+}
+
+impl From<ControlButton> for ControlButtonConfig {
+    fn from(button: ControlButton) -> Self {
+        match button {
+            ControlButton::Mouse(index) => ControlButtonConfig::Mouse(index),
+            ControlButton::Key(code) => ControlButtonConfig::Key(format!("{:?}", code)),
+            ControlButton::WheelUp => ControlButtonConfig::WheelUp,
+            ControlButton::WheelDown => ControlButtonConfig::WheelDown,
+        }
+    }
+}
+
+impl ControlButtonConfig {
+    /// Returns `None` if the config names an unknown key.
+    fn to_button(&self) -> Option<ControlButton> {
+        match self {
+            ControlButtonConfig::Mouse(index) => Some(ControlButton::Mouse(*index)),
+            ControlButtonConfig::Key(name) => KEY_CODES
+                .iter()
+                .find(|code| format!("{:?}", code) == *name)
+                .map(|code| ControlButton::Key(*code)),
+            ControlButtonConfig::WheelUp => Some(ControlButton::WheelUp),
+            ControlButtonConfig::WheelDown => Some(ControlButton::WheelDown),
+        }
+    }
+}
+
+/// Every key that can be bound in order of declaration, it is used to find a key by its name.
+/// The list must follow `VirtualKeyCode`, a test checks that no key is missing.
+pub const KEY_CODES: [VirtualKeyCode; 163] = {
+    use VirtualKeyCode::*;
+    [
+        Key1,
+        Key2,
+        Key3,
+        Key4,
+        Key5,
+        Key6,
+        Key7,
+        Key8,
+        Key9,
+        Key0,
+        A,
+        B,
+        C,
+        D,
+        E,
+        F,
+        G,
+        H,
+        I,
+        J,
+        K,
+        L,
+        M,
+        N,
+        O,
+        P,
+        Q,
+        R,
+        S,
+        T,
+        U,
+        V,
+        W,
+        X,
+        Y,
+        Z,
+        Escape,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+        F13,
+        F14,
+        F15,
+        F16,
+        F17,
+        F18,
+        F19,
+        F20,
+        F21,
+        F22,
+        F23,
+        F24,
+        Snapshot,
+        Scroll,
+        Pause,
+        Insert,
+        Home,
+        Delete,
+        End,
+        PageDown,
+        PageUp,
+        Left,
+        Up,
+        Right,
+        Down,
+        Back,
+        Return,
+        Space,
+        Compose,
+        Caret,
+        Numlock,
+        Numpad0,
+        Numpad1,
+        Numpad2,
+        Numpad3,
+        Numpad4,
+        Numpad5,
+        Numpad6,
+        Numpad7,
+        Numpad8,
+        Numpad9,
+        NumpadAdd,
+        NumpadDivide,
+        NumpadDecimal,
+        NumpadComma,
+        NumpadEnter,
+        NumpadEquals,
+        NumpadMultiply,
+        NumpadSubtract,
+        AbntC1,
+        AbntC2,
+        Apostrophe,
+        Apps,
+        Asterisk,
+        At,
+        Ax,
+        Backslash,
+        Calculator,
+        Capital,
+        Colon,
+        Comma,
+        Convert,
+        Equals,
+        Grave,
+        Kana,
+        Kanji,
+        LAlt,
+        LBracket,
+        LControl,
+        LShift,
+        LWin,
+        Mail,
+        MediaSelect,
+        MediaStop,
+        Minus,
+        Mute,
+        MyComputer,
+        NavigateForward,
+        NavigateBackward,
+        NextTrack,
+        NoConvert,
+        OEM102,
+        Period,
+        PlayPause,
+        Plus,
+        Power,
+        PrevTrack,
+        RAlt,
+        RBracket,
+        RControl,
+        RShift,
+        RWin,
+        Semicolon,
+        Slash,
+        Sleep,
+        Stop,
+        Sysrq,
+        Tab,
+        Underline,
+        Unlabeled,
+        VolumeDown,
+        VolumeUp,
+        Wake,
+        WebBack,
+        WebFavorites,
+        WebForward,
+        WebHome,
+        WebRefresh,
+        WebSearch,
+        WebStop,
+        Yen,
+        Copy,
+        Paste,
+        Cut,
+    ]
+};
+
+#[derive(Clone)]
 pub struct ControlButtonDefinition {
     pub description: String,
     pub button: ControlButton,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "ControlSchemeConfig", into = "ControlSchemeConfig")]
 pub struct ControlScheme {
     pub move_forward: ControlButtonDefinition,
     pub move_backward: ControlButtonDefinition,
@@ -39,13 +251,27 @@ pub struct ControlScheme {
     pub jump: ControlButtonDefinition,
     pub crouch: ControlButtonDefinition,
     pub shoot: ControlButtonDefinition,
+    pub alt_fire: ControlButtonDefinition,
     pub next_weapon: ControlButtonDefinition,
     pub prev_weapon: ControlButtonDefinition,
     pub run: ControlButtonDefinition,
+    pub chat: ControlButtonDefinition,
+    pub team_chat: ControlButtonDefinition,
+    /// Buttons selecting weapons by [slots](crate::weapon::WeaponKind::slot), from 1 to 9.
+    pub weapon_slots: [ControlButtonDefinition; 9],
+    pub last_weapon: ControlButtonDefinition,
     pub mouse_sens: f32,
     pub mouse_y_inverse: bool,
     pub smooth_mouse: bool,
     pub shake_camera: bool,
+    pub auto_switch: AutoSwitch,
+}
+
+fn weapon_slot(slot: usize, code: VirtualKeyCode) -> ControlButtonDefinition {
+    ControlButtonDefinition {
+        description: format!("Weapon {}", slot),
+        button: ControlButton::Key(code),
+    }
 }
 
 impl Default for ControlScheme {
@@ -79,6 +305,10 @@ impl Default for ControlScheme {
                 description: "Shoot".to_string(),
                 button: ControlButton::Mouse(1),
             },
+            alt_fire: ControlButtonDefinition {
+                description: "Alternative Fire".to_string(),
+                button: ControlButton::Mouse(2),
+            },
             next_weapon: ControlButtonDefinition {
                 description: "Next Weapon".to_string(),
                 button: ControlButton::WheelUp,
@@ -91,17 +321,41 @@ impl Default for ControlScheme {
                 description: "Run".to_string(),
                 button: ControlButton::Key(VirtualKeyCode::LShift),
             },
+            chat: ControlButtonDefinition {
+                description: "Chat".to_string(),
+                button: ControlButton::Key(VirtualKeyCode::T),
+            },
+            team_chat: ControlButtonDefinition {
+                description: "Team Chat".to_string(),
+                button: ControlButton::Key(VirtualKeyCode::Y),
+            },
+            weapon_slots: [
+                weapon_slot(1, VirtualKeyCode::Key1),
+                weapon_slot(2, VirtualKeyCode::Key2),
+                weapon_slot(3, VirtualKeyCode::Key3),
+                weapon_slot(4, VirtualKeyCode::Key4),
+                weapon_slot(5, VirtualKeyCode::Key5),
+                weapon_slot(6, VirtualKeyCode::Key6),
+                weapon_slot(7, VirtualKeyCode::Key7),
+                weapon_slot(8, VirtualKeyCode::Key8),
+                weapon_slot(9, VirtualKeyCode::Key9),
+            ],
+            last_weapon: ControlButtonDefinition {
+                description: "Last Weapon".to_string(),
+                button: ControlButton::Key(VirtualKeyCode::Q),
+            },
             mouse_sens: 0.3,
             mouse_y_inverse: false,
             smooth_mouse: false,
             shake_camera: true,
+            auto_switch: AutoSwitch::Always,
         }
     }
 }
 
 impl ControlScheme {
-    pub fn buttons_mut(&mut self) -> [&mut ControlButtonDefinition; 10] {
-        [
+    pub fn buttons_mut(&mut self) -> Vec<&mut ControlButtonDefinition> {
+        let mut buttons = vec![
             &mut self.move_forward,
             &mut self.move_backward,
             &mut self.move_left,
@@ -109,14 +363,20 @@ impl ControlScheme {
             &mut self.jump,
             &mut self.crouch,
             &mut self.shoot,
+            &mut self.alt_fire,
             &mut self.next_weapon,
             &mut self.prev_weapon,
             &mut self.run,
-        ]
+            &mut self.chat,
+            &mut self.team_chat,
+        ];
+        buttons.extend(self.weapon_slots.iter_mut());
+        buttons.push(&mut self.last_weapon);
+        buttons
     }
 
-    pub fn buttons(&self) -> [&ControlButtonDefinition; 10] {
-        [
+    pub fn buttons(&self) -> Vec<&ControlButtonDefinition> {
+        let mut buttons = vec![
             &self.move_forward,
             &self.move_backward,
             &self.move_left,
@@ -124,13 +384,78 @@ impl ControlScheme {
             &self.jump,
             &self.crouch,
             &self.shoot,
+            &self.alt_fire,
             &self.next_weapon,
             &self.prev_weapon,
             &self.run,
-        ]
+            &self.chat,
+            &self.team_chat,
+        ];
+        buttons.extend(self.weapon_slots.iter());
+        buttons.push(&self.last_weapon);
+        buttons
     }
 
     pub fn reset(&mut self) {
         *self = Default::default();
     }
 }
+
+/// Form of [`ControlScheme`] in the config file. Buttons are stored by descriptions of their
+/// actions, so new actions get default buttons and unknown actions or keys are ignored.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct ControlSchemeConfig {
+    buttons: BTreeMap<String, ControlButtonConfig>,
+    mouse_sens: f32,
+    mouse_y_inverse: bool,
+    smooth_mouse: bool,
+    shake_camera: bool,
+    auto_switch: AutoSwitch,
+}
+
+impl Default for ControlSchemeConfig {
+    fn default() -> Self {
+        ControlScheme::default().into()
+    }
+}
+
+impl From<ControlScheme> for ControlSchemeConfig {
+    fn from(scheme: ControlScheme) -> Self {
+        Self {
+            buttons: scheme
+                .buttons()
+                .iter()
+                .map(|definition| (definition.description.clone(), definition.button.into()))
+                .collect(),
+            mouse_sens: scheme.mouse_sens,
+            mouse_y_inverse: scheme.mouse_y_inverse,
+            smooth_mouse: scheme.smooth_mouse,
+            shake_camera: scheme.shake_camera,
+            auto_switch: scheme.auto_switch,
+        }
+    }
+}
+
+impl From<ControlSchemeConfig> for ControlScheme {
+    fn from(config: ControlSchemeConfig) -> Self {
+        let mut scheme = ControlScheme {
+            mouse_sens: config.mouse_sens,
+            mouse_y_inverse: config.mouse_y_inverse,
+            smooth_mouse: config.smooth_mouse,
+            shake_camera: config.shake_camera,
+            auto_switch: config.auto_switch,
+            ..Default::default()
+        };
+        for definition in scheme.buttons_mut() {
+            if let Some(button) = config
+                .buttons
+                .get(&definition.description)
+                .and_then(|button| button.to_button())
+            {
+                definition.button = button;
+            }
+        }
+        scheme
+    }
+}
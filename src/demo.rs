@@ -0,0 +1,414 @@
+//! Demos are recordings of matches. Simulation is deterministic, so a demo stores only match
+//! options, seed of the level and input of the player for every simulation step - everything
+//! else is re-simulated on playback. Chat does not affect simulation, so chat messages are
+//! stored separately together with the step at which they were shown. The last played match is
+//! always recorded to [`DEMO_PATH`], it can be watched with `rusty-shooter --demo demo.bin`.
+//!
+//! Playback controls: Space - pause, Left/Right - seek 10 seconds back/forward, Up/Down -
+//! change playback speed, C - toggle free camera (WASD + mouse).
+
+use crate::{
+    character::Team, chat::ChatChannel, control_scheme::ControlScheme, message::Message,
+    player::PlayerInput, weapon::AutoSwitch, MatchOptions, FIXED_FPS,
+};
+use fyrox::{
+    core::{
+        algebra::{UnitQuaternion, Vector3},
+        pool::Handle,
+        visitor::{PodVecView, Visit, VisitError, VisitResult, Visitor},
+    },
+    event::{DeviceEvent, ElementState, Event, VirtualKeyCode, WindowEvent},
+    scene::{
+        base::BaseBuilder,
+        camera::{Camera, CameraBuilder},
+        node::Node,
+        Scene,
+    },
+};
+use std::path::Path;
+
+pub const DEMO_PATH: &str = "demo.bin";
+
+/// Version of demo format, demos of other versions cannot be played.
+const DEMO_VERSION: u32 = 4;
+
+/// Size of one packed [`PlayerInput`] in bytes.
+const INPUT_SIZE: usize = 11;
+
+const SEEK_STEP_SECS: f32 = 10.0;
+
+/// Input of every simulation step packed into bytes. It is stored as a single binary blob
+/// instead of a node per step to keep demos compact.
+#[derive(Default)]
+struct InputTrack(Vec<u8>);
+
+impl Visit for InputTrack {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        PodVecView::from_pod_vec(&mut self.0).visit(name, visitor)
+    }
+}
+
+impl InputTrack {
+    fn push(&mut self, input: PlayerInput) {
+        self.0.extend_from_slice(&input.controls.to_le_bytes());
+        self.0.push(input.current_weapon);
+        self.0.extend_from_slice(&input.yaw.to_le_bytes());
+        self.0.extend_from_slice(&input.pitch.to_le_bytes());
+    }
+
+    fn get(&self, step: usize) -> Option<PlayerInput> {
+        let bytes = self.0.get(step * INPUT_SIZE..(step + 1) * INPUT_SIZE)?;
+        let f32_at =
+            |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(PlayerInput {
+            controls: u16::from_le_bytes([bytes[0], bytes[1]]),
+            current_weapon: bytes[2],
+            yaw: f32_at(3),
+            pitch: f32_at(7),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.0.len() / INPUT_SIZE
+    }
+}
+
+#[derive(Default, Visit)]
+struct ChatRecord {
+    /// Amount of simulation steps that were done before the message was shown.
+    step: u32,
+    sender: String,
+    team: Team,
+    channel: ChatChannel,
+    text: String,
+}
+
+#[derive(Default, Visit)]
+pub struct Demo {
+    version: u32,
+    options: MatchOptions,
+    seed: u64,
+    // Mouse smoothing and camera shaking affect where player shoots and automatic switching
+    // affects which weapon player shoots, so they are part of the recording.
+    smooth_mouse: bool,
+    shake_camera: bool,
+    auto_switch: AutoSwitch,
+    inputs: InputTrack,
+    chat: Vec<ChatRecord>,
+}
+
+impl Demo {
+    pub fn new(options: MatchOptions, seed: u64, control_scheme: &ControlScheme) -> Self {
+        Self {
+            version: DEMO_VERSION,
+            options,
+            seed,
+            smooth_mouse: control_scheme.smooth_mouse,
+            shake_camera: control_scheme.shake_camera,
+            auto_switch: control_scheme.auto_switch,
+            inputs: Default::default(),
+            chat: Default::default(),
+        }
+    }
+
+    pub fn options(&self) -> &MatchOptions {
+        &self.options
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Control scheme that was used while recording, only settings that affect simulation
+    /// are restored.
+    pub fn control_scheme(&self) -> ControlScheme {
+        ControlScheme {
+            smooth_mouse: self.smooth_mouse,
+            shake_camera: self.shake_camera,
+            auto_switch: self.auto_switch,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, input: PlayerInput) {
+        self.inputs.push(input);
+    }
+
+    pub fn push_chat(&mut self, sender: &str, team: Team, channel: ChatChannel, text: &str) {
+        self.chat.push(ChatRecord {
+            step: self.inputs.len() as u32,
+            sender: sender.to_owned(),
+            team,
+            channel,
+            text: text.to_owned(),
+        });
+    }
+
+    /// Amount of recorded simulation steps.
+    pub fn step_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn save(&mut self, path: &Path) -> VisitResult {
+        let mut visitor = Visitor::new();
+        self.visit("Demo", &mut visitor)?;
+        visitor.save_binary(path)
+    }
+
+    pub async fn load(path: &Path) -> Result<Self, VisitError> {
+        let mut visitor = Visitor::load_binary(path).await?;
+        let mut demo = Demo::default();
+        demo.visit("Demo", &mut visitor)?;
+        if demo.version != DEMO_VERSION {
+            return Err(VisitError::User(format!(
+                "Unsupported demo version {}, expected {}",
+                demo.version, DEMO_VERSION
+            )));
+        }
+        Ok(demo)
+    }
+}
+
+/// Camera that is controlled by mouse and WASD keys, it is used to look around a level while
+/// watching a demo.
+pub struct FreeCamera {
+    camera: Handle<Node>,
+    yaw: f32,
+    pitch: f32,
+    move_forward: bool,
+    move_backward: bool,
+    move_left: bool,
+    move_right: bool,
+}
+
+impl FreeCamera {
+    const SPEED: f32 = 8.0;
+    const MOUSE_SENSITIVITY: f32 = 0.3;
+
+    pub fn new(scene: &mut Scene) -> Self {
+        let camera = CameraBuilder::new(BaseBuilder::new())
+            .enabled(false)
+            .build(&mut scene.graph);
+
+        Self {
+            camera,
+            yaw: 0.0,
+            pitch: 0.0,
+            move_forward: false,
+            move_backward: false,
+            move_left: false,
+            move_right: false,
+        }
+    }
+
+    pub fn is_enabled(&self, scene: &Scene) -> bool {
+        scene.graph[self.camera]
+            .cast::<Camera>()
+            .map_or(false, |camera| camera.is_enabled())
+    }
+
+    /// Enables or disables the camera, enabled camera starts at given position.
+    pub fn set_enabled(&mut self, scene: &mut Scene, enabled: bool, position: Vector3<f32>) {
+        if let Some(camera) = scene.graph[self.camera].cast_mut::<Camera>() {
+            camera.set_enabled(enabled);
+            if enabled {
+                camera.local_transform_mut().set_position(position);
+            }
+        }
+    }
+
+    pub fn process_input_event(&mut self, event: &Event<()>) {
+        match event {
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                self.yaw -= delta.0 as f32 * Self::MOUSE_SENSITIVITY;
+                self.pitch = (self.pitch + delta.1 as f32 * Self::MOUSE_SENSITIVITY)
+                    .max(-90.0)
+                    .min(90.0);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { input, .. },
+                ..
+            } => {
+                let pressed = input.state == ElementState::Pressed;
+                match input.virtual_keycode {
+                    Some(VirtualKeyCode::W) => self.move_forward = pressed,
+                    Some(VirtualKeyCode::S) => self.move_backward = pressed,
+                    Some(VirtualKeyCode::A) => self.move_left = pressed,
+                    Some(VirtualKeyCode::D) => self.move_right = pressed,
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Moves the camera, `dt` is wall clock time, so the camera could be moved while
+    /// playback is paused.
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) {
+        let node = &mut scene.graph[self.camera];
+
+        let rotation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.yaw.to_radians())
+            * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.pitch.to_radians());
+
+        let look = rotation * Vector3::z();
+        let side = rotation * Vector3::x();
+        let mut velocity = Vector3::default();
+        if self.move_forward {
+            velocity += look;
+        }
+        if self.move_backward {
+            velocity -= look;
+        }
+        if self.move_left {
+            velocity += side;
+        }
+        if self.move_right {
+            velocity -= side;
+        }
+
+        let transform = node.local_transform_mut();
+        transform.set_rotation(rotation);
+        if let Some(direction) = velocity.try_normalize(f32::EPSILON) {
+            transform.offset(direction.scale(Self::SPEED * dt));
+        }
+
+        // Global transforms must be up to date even if the scene is not updated because of
+        // pause.
+        scene.graph.update_hierarchical_data();
+    }
+}
+
+pub struct DemoPlayback {
+    demo: Demo,
+    /// Index of the next simulation step.
+    step: usize,
+    paused: bool,
+    speed: f32,
+    /// Step to fast-forward to, it is set by seeking.
+    seek_target: Option<usize>,
+    free_camera: Option<FreeCamera>,
+}
+
+impl DemoPlayback {
+    pub fn new(demo: Demo) -> Self {
+        Self {
+            demo,
+            step: 0,
+            paused: false,
+            speed: 1.0,
+            seek_target: None,
+            free_camera: None,
+        }
+    }
+
+    pub fn demo(&self) -> &Demo {
+        &self.demo
+    }
+
+    /// Must be called when level of the demo is (re)created.
+    pub fn on_level_started(&mut self, scene: &mut Scene) {
+        self.step = 0;
+        self.free_camera = Some(FreeCamera::new(scene));
+    }
+
+    pub fn free_camera_mut(&mut self) -> Option<&mut FreeCamera> {
+        self.free_camera.as_mut()
+    }
+
+    /// How fast simulation should go relative to real time.
+    pub fn time_scale(&self) -> f32 {
+        if self.paused {
+            0.0
+        } else {
+            self.speed
+        }
+    }
+
+    /// Returns input for the next simulation step, `None` means that recording has ended and
+    /// level must not be updated anymore.
+    pub fn next_input(&mut self) -> Option<PlayerInput> {
+        let input = self.demo.inputs.get(self.step);
+        if input.is_some() {
+            self.step += 1;
+        } else {
+            self.paused = true;
+        }
+        input
+    }
+
+    /// Returns chat messages that were shown at current step of playback.
+    pub fn chat_messages(&self) -> impl Iterator<Item = Message> + '_ {
+        self.demo
+            .chat
+            .iter()
+            .filter(move |record| record.step as usize == self.step)
+            .map(|record| Message::Chat {
+                sender: record.sender.clone(),
+                team: record.team,
+                channel: record.channel,
+                text: record.text.clone(),
+            })
+    }
+
+    /// Returns `true` if playback must fast-forward to the step that was requested by
+    /// seeking. Seeking is finished when this method returns `false`.
+    pub fn is_fast_forwarding(&mut self) -> bool {
+        match self.seek_target {
+            Some(target) if self.step < target && self.step < self.demo.step_count() => true,
+            _ => {
+                self.seek_target = None;
+                false
+            }
+        }
+    }
+
+    /// Handles playback controls, returns `true` if level must be restarted, this happens
+    /// when seeking back.
+    pub fn process_input_event(&mut self, event: &Event<()>) -> bool {
+        if let Some(free_camera) = self.free_camera.as_mut() {
+            free_camera.process_input_event(event);
+        }
+
+        if let Event::WindowEvent {
+            event: WindowEvent::KeyboardInput { input, .. },
+            ..
+        } = event
+        {
+            if input.state == ElementState::Pressed {
+                let seek_steps = (SEEK_STEP_SECS * FIXED_FPS) as usize;
+                match input.virtual_keycode {
+                    Some(VirtualKeyCode::Space) => self.paused = !self.paused,
+                    Some(VirtualKeyCode::Up) => self.speed = (self.speed * 2.0).min(8.0),
+                    Some(VirtualKeyCode::Down) => self.speed = (self.speed * 0.5).max(0.125),
+                    Some(VirtualKeyCode::Right) => {
+                        self.seek_target = Some(self.step + seek_steps);
+                    }
+                    Some(VirtualKeyCode::Left) => {
+                        // Simulation cannot go backwards, level is re-simulated from the
+                        // beginning.
+                        self.seek_target = Some(self.step.saturating_sub(seek_steps));
+                        return true;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Short description of playback state for HUD.
+    pub fn status(&self) -> String {
+        let time = |step: usize| step as f32 / FIXED_FPS;
+        format!(
+            "Demo {:.1}/{:.1} s, x{}{}",
+            time(self.step),
+            time(self.demo.step_count()),
+            self.speed,
+            if self.paused { ", paused" } else { "" }
+        )
+    }
+}
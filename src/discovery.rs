@@ -0,0 +1,106 @@
+//! Discovery of servers in local network. Server browser broadcasts discovery requests to the
+//! default port and every server that listens on it answers with its description. Round trip
+//! time of the answer is the ping of the server.
+
+use crate::net::{
+    ClientPacket, NetworkConditions, ServerInfo, ServerPacket, Socket, DEFAULT_PORT,
+    MAX_PACKET_SIZE,
+};
+use fyrox::utils::log::{Log, MessageKind};
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+/// Servers that did not answer for this time are removed from the list.
+const SERVER_LIFETIME: Duration = Duration::from_secs(5);
+
+pub struct DiscoveredServer {
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+    pub ping: Duration,
+    last_answer_time: Instant,
+}
+
+pub struct Discovery {
+    socket: Socket,
+    token: u32,
+    request_time: Instant,
+    servers: Vec<DiscoveredServer>,
+}
+
+impl Discovery {
+    pub fn new() -> io::Result<Self> {
+        let socket = Socket::bind(("0.0.0.0", 0), NetworkConditions::default())?;
+        socket.set_broadcast(true)?;
+
+        Ok(Self {
+            socket,
+            token: 0,
+            request_time: Instant::now(),
+            servers: Default::default(),
+        })
+    }
+
+    /// Asks servers to describe themselves, answers are collected by [`Self::poll`].
+    pub fn refresh(&mut self) {
+        self.token = self.token.wrapping_add(1);
+        self.request_time = Instant::now();
+
+        let packet = ClientPacket::Discover { token: self.token };
+        self.socket.send(
+            SocketAddr::from((Ipv4Addr::BROADCAST, DEFAULT_PORT)),
+            &packet.encode(),
+        );
+    }
+
+    /// Handles answers of servers, returns `true` if the list of servers has changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            let (size, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(result) => result,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    Log::writeln(
+                        MessageKind::Warning,
+                        format!("Failed to receive packet, reason: {}", e),
+                    );
+                    continue;
+                }
+            };
+
+            if let Some(ServerPacket::Info { token, info }) = ServerPacket::decode(&buffer[..size])
+            {
+                // Answers to previous requests would have wrong ping.
+                if token != self.token {
+                    continue;
+                }
+
+                let server = DiscoveredServer {
+                    address,
+                    info,
+                    ping: self.request_time.elapsed(),
+                    last_answer_time: Instant::now(),
+                };
+                match self.servers.iter_mut().find(|s| s.address == address) {
+                    Some(existing) => *existing = server,
+                    None => self.servers.push(server),
+                }
+                changed = true;
+            }
+        }
+
+        let count = self.servers.len();
+        self.servers
+            .retain(|server| server.last_answer_time.elapsed() < SERVER_LIFETIME);
+        changed || count != self.servers.len()
+    }
+
+    pub fn servers(&self) -> &[DiscoveredServer] {
+        &self.servers
+    }
+}
@@ -4,7 +4,8 @@
 
 use fyrox::core::pool::Handle;
 use fyrox::gui::{
-    check_box::CheckBoxBuilder, scroll_bar::ScrollBarBuilder, scroll_viewer::ScrollViewerBuilder,
+    border::BorderBuilder, check_box::CheckBoxBuilder, decorator::DecoratorBuilder,
+    scroll_bar::ScrollBarBuilder, scroll_viewer::ScrollViewerBuilder, text::TextBuilder,
     widget::WidgetBuilder, BuildContext, HorizontalAlignment, Orientation, Thickness, UiNode,
     VerticalAlignment,
 };
@@ -94,3 +95,23 @@ pub fn create_scroll_viewer(ctx: &mut BuildContext) -> Handle<UiNode> {
         ))
         .build(ctx)
 }
+
+pub fn create_dropdown_items(ctx: &mut BuildContext, items: &[&str]) -> Vec<Handle<UiNode>> {
+    items
+        .iter()
+        .map(|text| {
+            DecoratorBuilder::new(BorderBuilder::new(
+                WidgetBuilder::new().with_height(30.0).with_child(
+                    TextBuilder::new(
+                        WidgetBuilder::new()
+                            .with_horizontal_alignment(HorizontalAlignment::Center)
+                            .with_vertical_alignment(VerticalAlignment::Center),
+                    )
+                    .with_text(text)
+                    .build(ctx),
+                ),
+            ))
+            .build(ctx)
+        })
+        .collect()
+}
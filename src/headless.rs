@@ -0,0 +1,168 @@
+//! Headless mode runs a match between bots without a window, a renderer and a sound device.
+//! Map geometry and physics are loaded as usual, level is updated with fixed time step as
+//! fast as possible and final leader board is printed to standard output. It is used to run
+//! bot-vs-bot matches in CI and on servers:
+//!
+//! `rusty-shooter --headless --mode tdm --map dm6 --frag-limit 20 --fill-to 6 --seed 42`
+//!
+//! Simulation is deterministic, so runs with the same arguments have the same outcome.
+
+use crate::{
+    control_scheme::ControlScheme,
+    level::{Level, LevelContext},
+    map::MapPath,
+    message::Message,
+    roster::BotRoster,
+    GameMode, GameTime, MatchOptions, FIXED_FPS,
+};
+use fyrox::{
+    core::{algebra::Vector2, futures::executor::block_on},
+    engine::{resource_manager::ResourceManager, SerializationContext},
+};
+use std::{
+    sync::{mpsc, Arc, RwLock},
+    time::Instant,
+};
+
+#[derive(Clone, Debug)]
+pub struct HeadlessOptions {
+    pub mode: GameMode,
+    pub map: MapPath,
+    pub frag_limit: u32,
+    pub time_limit_secs: f32,
+    /// See [`BotRoster::fill_to`], zero keeps default roster.
+    pub fill_to: u32,
+    pub seed: u64,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::DeathMatch,
+            map: Default::default(),
+            frag_limit: 30,
+            time_limit_secs: 10.0 * 60.0,
+            fill_to: 0,
+            seed: 0,
+        }
+    }
+}
+
+impl HeadlessOptions {
+    /// Parses command line arguments, unknown arguments are reported as errors.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {} argument", arg))
+            };
+
+            match arg.as_str() {
+                "--headless" => (),
+                "--mode" => {
+                    options.mode = match value()?.as_str() {
+                        "dm" => GameMode::DeathMatch,
+                        "tdm" => GameMode::TeamDeathMatch,
+                        "ctf" => GameMode::CaptureTheFlag,
+                        other => return Err(format!("Unknown match mode {}", other)),
+                    }
+                }
+                "--map" => {
+                    let map = MapPath::resolve(value()?);
+                    if !map.exists() {
+                        return Err(format!("Map {} does not exist", map.0));
+                    }
+                    options.map = map;
+                }
+                "--frag-limit" => options.frag_limit = parse(arg, value()?)?,
+                "--time-limit" => {
+                    options.time_limit_secs = parse::<f32>(arg, value()?)? * 60.0;
+                }
+                "--fill-to" => options.fill_to = parse(arg, value()?)?,
+                "--seed" => options.seed = parse(arg, value()?)?,
+                other => return Err(format!("Unknown argument {}", other)),
+            }
+        }
+
+        Ok(options)
+    }
+
+    pub fn match_options(&self) -> MatchOptions {
+        let roster = BotRoster {
+            fill_to: self.fill_to,
+            ..Default::default()
+        };
+
+        MatchOptions::new(
+            self.mode,
+            self.time_limit_secs,
+            self.frag_limit,
+            roster,
+            self.map.clone(),
+        )
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {} for {} argument", value, arg))
+}
+
+/// Runs a match until frag limit or time limit is reached and prints final leader board.
+pub fn run(options: HeadlessOptions) {
+    let resource_manager = ResourceManager::new(Arc::new(SerializationContext::new()));
+    let (sender, receiver) = mpsc::channel();
+
+    let (mut level, mut scene) = block_on(Level::new(
+        resource_manager.clone(),
+        Arc::new(RwLock::new(ControlScheme::default())),
+        sender,
+        options.match_options(),
+        false,
+        options.seed,
+    ));
+
+    let clock = Instant::now();
+    let mut time = GameTime::new(1.0 / FIXED_FPS);
+
+    let mut ticks = 0u64;
+    let mut match_over = false;
+    while !match_over && level.time() < options.time_limit_secs {
+        time.advance();
+        ticks += 1;
+
+        // Frame size does not matter, there is nothing to render.
+        scene.update(Vector2::new(1.0, 1.0), time.delta);
+
+        let mut context = LevelContext {
+            scene: &mut scene,
+            resource_manager: resource_manager.clone(),
+        };
+
+        level.update(&mut context, time);
+
+        while let Ok(message) = receiver.try_recv() {
+            if let Message::EndMatch = message {
+                match_over = true;
+            }
+            block_on(level.handle_message(&mut context, &message, time));
+        }
+    }
+
+    println!(
+        "Match finished after {:.1} s of game time ({} ticks, {:.1} s of real time){}",
+        level.time(),
+        ticks,
+        clock.elapsed().as_secs_f32(),
+        if match_over {
+            ""
+        } else {
+            ", time limit reached"
+        }
+    );
+    println!("{}", level.leader_board);
+}
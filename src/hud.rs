@@ -1,6 +1,10 @@
 use crate::{
+    chat::{self, ChatChannel, ChatInput, ChatInputEvent},
+    control_scheme::ControlScheme,
+    item::ItemKind,
     leader_board::{LeaderBoard, LeaderBoardUI},
     message::Message,
+    weapon::{WeaponKind, WEAPON_SLOTS},
     GameTime, MatchOptions,
 };
 use fyrox::core::parking_lot::Mutex;
@@ -11,6 +15,7 @@ use fyrox::{
     gui::{
         border::BorderBuilder,
         brush::Brush,
+        formatted_text::WrapMode,
         grid::{Column, GridBuilder, Row},
         image::ImageBuilder,
         message::MessageDirection,
@@ -25,11 +30,35 @@ use fyrox::{
 };
 use std::{collections::VecDeque, path::Path, sync::Arc};
 
+/// Amount of lines in chat log, older lines scroll away.
+const CHAT_LOG_SIZE: usize = 6;
+
+/// Lines of chat log disappear after this time in seconds.
+const CHAT_LINE_LIFETIME: f32 = 10.0;
+
+/// Color of weapons the player does not have in the weapon bar.
+const WEAPON_MISSING_COLOR: Color = Color::opaque(90, 90, 90);
+
+fn weapon_bar_text(kind: WeaponKind, ammo: Option<u32>) -> String {
+    let name = ItemKind::from_weapon(kind).description();
+    match ammo {
+        Some(ammo) => format!("{} {}\n{}", kind.slot(), name, ammo),
+        None => format!("{} {}\n-", kind.slot(), name),
+    }
+}
+
+struct ChatLine {
+    text: String,
+    time_left: f32,
+}
+
 pub struct Hud {
     root: Handle<UiNode>,
     health: Handle<UiNode>,
     armor: Handle<UiNode>,
     ammo: Handle<UiNode>,
+    /// Texts of the weapon bar, one per [slot](WeaponKind::slot).
+    weapon_bar: Vec<Handle<UiNode>>,
     time: Handle<UiNode>,
     message: Handle<UiNode>,
     message_queue: VecDeque<String>,
@@ -39,10 +68,16 @@ pub struct Hud {
     first_score: Handle<UiNode>,
     second_score: Handle<UiNode>,
     died: Handle<UiNode>,
+    chat_log: Handle<UiNode>,
+    chat_lines: VecDeque<ChatLine>,
+    chat_input_text: Handle<UiNode>,
+    chat_input: ChatInput,
+    /// Show when major items are about to appear.
+    item_announcements: bool,
 }
 
 impl Hud {
-    pub fn new(engine: &mut Engine) -> Self {
+    pub fn new(engine: &mut Engine, item_announcements: bool) -> Self {
         let leader_board = LeaderBoardUI::new(engine);
 
         let frame_size = engine.renderer.get_frame_size();
@@ -60,12 +95,28 @@ impl Hud {
         let health;
         let armor;
         let ammo;
+        let weapon_bar = WEAPON_SLOTS
+            .iter()
+            .map(|&kind| {
+                TextBuilder::new(
+                    WidgetBuilder::new()
+                        .with_margin(Thickness::uniform(2.0))
+                        .with_width(120.0)
+                        .with_foreground(Brush::Solid(WEAPON_MISSING_COLOR)),
+                )
+                .with_horizontal_text_alignment(HorizontalAlignment::Center)
+                .with_text(weapon_bar_text(kind, None))
+                .build(ctx)
+            })
+            .collect::<Vec<_>>();
         let message;
         let time;
         let first_score;
         let second_score;
         let match_limit;
         let died;
+        let chat_log;
+        let chat_input_text;
         let root = GridBuilder::new(
             WidgetBuilder::new()
                 .with_width(frame_size.0 as f32)
@@ -273,6 +324,18 @@ impl Hud {
                     .with_orientation(Orientation::Horizontal)
                     .build(ctx),
                 )
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::bottom(55.0))
+                            .on_column(1)
+                            .with_vertical_alignment(VerticalAlignment::Bottom)
+                            .with_horizontal_alignment(HorizontalAlignment::Center)
+                            .with_children(weapon_bar.iter().copied()),
+                    )
+                    .with_orientation(Orientation::Horizontal)
+                    .build(ctx),
+                )
                 .with_child(
                     StackPanelBuilder::new(
                         WidgetBuilder::new()
@@ -332,6 +395,38 @@ impl Hud {
                     .build(ctx);
                     message
                 })
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .on_row(0)
+                            .on_column(0)
+                            .with_vertical_alignment(VerticalAlignment::Bottom)
+                            .with_margin(Thickness {
+                                left: 50.0,
+                                top: 0.0,
+                                right: 0.0,
+                                bottom: 190.0,
+                            })
+                            .with_width(500.0)
+                            .with_child({
+                                chat_log = TextBuilder::new(WidgetBuilder::new())
+                                    .with_wrap(WrapMode::Word)
+                                    .build(ctx);
+                                chat_log
+                            })
+                            .with_child({
+                                chat_input_text = TextBuilder::new(
+                                    WidgetBuilder::new().with_visibility(false).with_foreground(
+                                        Brush::Solid(Color::opaque(255, 220, 120)),
+                                    ),
+                                )
+                                .with_wrap(WrapMode::Word)
+                                .build(ctx);
+                                chat_input_text
+                            }),
+                    )
+                    .build(ctx),
+                )
                 .with_child({
                     died = TextBuilder::new(
                         WidgetBuilder::new()
@@ -360,6 +455,7 @@ impl Hud {
             health,
             armor,
             ammo,
+            weapon_bar,
             message,
             time,
             first_score,
@@ -368,6 +464,11 @@ impl Hud {
             died,
             message_timeout: 0.0,
             message_queue: Default::default(),
+            chat_log,
+            chat_lines: Default::default(),
+            chat_input_text,
+            chat_input: Default::default(),
+            item_announcements,
         }
     }
 
@@ -395,6 +496,37 @@ impl Hud {
         ));
     }
 
+    /// Updates the weapon bar, `weapons` are owned weapons with their ammo.
+    pub fn set_weapons(
+        &mut self,
+        ui: &mut UserInterface,
+        weapons: &[(WeaponKind, u32)],
+        current: Option<WeaponKind>,
+    ) {
+        for (&kind, &text) in WEAPON_SLOTS.iter().zip(self.weapon_bar.iter()) {
+            let ammo = weapons
+                .iter()
+                .find(|(other, _)| *other == kind)
+                .map(|(_, ammo)| *ammo);
+            let color = match ammo {
+                _ if current == Some(kind) => Color::opaque(255, 220, 120),
+                Some(0) => Color::opaque(180, 14, 22),
+                Some(_) => Color::WHITE,
+                None => WEAPON_MISSING_COLOR,
+            };
+            ui.send_message(TextMessage::text(
+                text,
+                MessageDirection::ToWidget,
+                weapon_bar_text(kind, ammo),
+            ));
+            ui.send_message(WidgetMessage::foreground(
+                text,
+                MessageDirection::ToWidget,
+                Brush::Solid(color),
+            ));
+        }
+    }
+
     pub fn set_visible(&mut self, ui: &mut UserInterface, visible: bool) {
         ui.send_message(WidgetMessage::visibility(
             self.root,
@@ -427,6 +559,87 @@ impl Hud {
         self.message_queue.push_back(message.as_ref().to_owned())
     }
 
+    pub fn item_announcements(&self) -> bool {
+        self.item_announcements
+    }
+
+    pub fn set_item_announcements(&mut self, enabled: bool) {
+        self.item_announcements = enabled;
+    }
+
+    pub fn is_chat_open(&self) -> bool {
+        self.chat_input.is_open()
+    }
+
+    /// Lets the player type a chat message, see [`ChatInput::process_input_event`].
+    pub fn process_chat_input(
+        &mut self,
+        ui: &mut UserInterface,
+        event: &Event<()>,
+        control_scheme: &ControlScheme,
+    ) -> ChatInputEvent {
+        let result = self.chat_input.process_input_event(event, control_scheme);
+        if !matches!(result, ChatInputEvent::Ignored) {
+            self.sync_chat_input(ui);
+        }
+        result
+    }
+
+    pub fn close_chat(&mut self, ui: &mut UserInterface) {
+        self.chat_input.close();
+        self.sync_chat_input(ui);
+    }
+
+    fn sync_chat_input(&self, ui: &mut UserInterface) {
+        ui.send_message(WidgetMessage::visibility(
+            self.chat_input_text,
+            MessageDirection::ToWidget,
+            self.chat_input.is_open(),
+        ));
+        if let Some((channel, text)) = self.chat_input.message() {
+            let prompt = match channel {
+                ChatChannel::All => "Say",
+                ChatChannel::Team => "Say to team",
+            };
+            ui.send_message(TextMessage::text(
+                self.chat_input_text,
+                MessageDirection::ToWidget,
+                format!("{}: {}_", prompt, text),
+            ));
+        }
+    }
+
+    fn add_chat_line(&mut self, ui: &mut UserInterface, text: String) {
+        self.chat_lines.push_back(ChatLine {
+            text,
+            time_left: CHAT_LINE_LIFETIME,
+        });
+        while self.chat_lines.len() > CHAT_LOG_SIZE {
+            self.chat_lines.pop_front();
+        }
+        self.sync_chat_log(ui);
+    }
+
+    /// Removes every line of chat log, it is done when a new level starts.
+    pub fn clear_chat(&mut self, ui: &mut UserInterface) {
+        self.chat_lines.clear();
+        self.sync_chat_log(ui);
+    }
+
+    fn sync_chat_log(&self, ui: &mut UserInterface) {
+        let text = self
+            .chat_lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        ui.send_message(TextMessage::text(
+            self.chat_log,
+            MessageDirection::ToWidget,
+            text,
+        ));
+    }
+
     pub fn process_event(&mut self, engine: &mut Engine, event: &Event<()>) {
         if let Event::WindowEvent { event, .. } = event {
             if let WindowEvent::Resized(new_size) = event {
@@ -453,6 +666,15 @@ impl Hud {
     pub fn update(&mut self, ui: &mut UserInterface, time: &GameTime) {
         self.message_timeout -= time.delta;
 
+        let line_count = self.chat_lines.len();
+        for line in self.chat_lines.iter_mut() {
+            line.time_left -= time.delta;
+        }
+        self.chat_lines.retain(|line| line.time_left > 0.0);
+        if line_count != self.chat_lines.len() {
+            self.sync_chat_log(ui);
+        }
+
         if self.message_timeout <= 0.0 {
             if let Some(message) = self.message_queue.pop_front() {
                 ui.send_message(TextMessage::text(
@@ -517,6 +739,17 @@ impl Hud {
     ) {
         match message {
             Message::AddNotification { text } => self.add_message(text),
+            Message::AnnounceItem { kind, time } => {
+                if self.item_announcements {
+                    self.add_message(format!("{} in {:.0}s", kind.description(), time))
+                }
+            }
+            Message::Chat {
+                sender,
+                channel,
+                text,
+                ..
+            } => self.add_chat_line(ui, chat::format_line(sender, *channel, text)),
             Message::AddBot { .. }
             | Message::RemoveActor { .. }
             | Message::RespawnActor { .. }
@@ -1,17 +1,49 @@
-use crate::{effects::EffectKind, fyrox::core::math::Vector3Ext, message::Message, GameTime};
+use crate::{
+    effects::EffectKind,
+    fyrox::core::math::Vector3Ext,
+    message::Message,
+    weapon::{Weapon, WeaponKind},
+    GameTime,
+};
 use fyrox::{
     core::{
         algebra::Vector3,
+        color::Color,
+        parking_lot::Mutex,
         pool::{Handle, Pool},
+        sstorage::ImmutableString,
         visitor::{Visit, VisitResult, Visitor},
     },
     engine::resource_manager::ResourceManager,
+    material::PropertyValue,
     scene::{
-        base::BaseBuilder, graph::Graph, node::Node, pivot::PivotBuilder,
-        transform::TransformBuilder, Scene,
+        base::BaseBuilder,
+        graph::Graph,
+        mesh::{Mesh, RenderPath},
+        node::Node,
+        pivot::PivotBuilder,
+        transform::TransformBuilder,
+        Scene,
     },
+    utils::log::{Log, MessageKind},
+};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc},
 };
-use std::{path::Path, sync::mpsc::Sender};
+
+/// Major items are announced this amount of seconds before they appear.
+pub const ANNOUNCEMENT_TIME: f32 = 10.0;
+
+/// Name of the hologram that marks the place of an inactive item.
+const GHOST_NAME: &str = "Ghost";
+
+const GHOST_COLOR: Color = Color::from_rgba(0, 200, 255, 100);
+
+/// Played when an item appears again, it must differ from the sound of pickup so players can tell
+/// one from another without seeing the item.
+const RESPAWN_SOUND: &str = "data/sounds/plasma_shot.ogg";
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Visit)]
 pub enum ItemKind {
@@ -27,6 +59,69 @@ pub enum ItemKind {
     Ak47,
     M4,
     RocketLauncher,
+
+    // Ammo, new kinds are added to the end to keep saves compatible.
+    RocketAmmo,
+}
+
+/// Settings of a single item placed on a map, they override the definition of its kind. They
+/// are written in the name of the marker as `key=value` tags separated by underscores, like
+/// `Ammo_Rocket_respawn=45_delay=10_amount=5`:
+///
+/// - `respawn` - time in seconds until the item appears again after it was picked up.
+/// - `delay` - time in seconds until the item appears first time after the match started.
+/// - `amount` - amount of health or ammo that the item gives.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ItemOverrides {
+    pub respawn_time: Option<f32>,
+    pub initial_delay: Option<f32>,
+    pub amount: Option<u32>,
+}
+
+impl Visit for ItemOverrides {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        // Saves of format version 2 and older do not have overrides.
+        let reading = visitor.is_reading();
+        let mut region = match visitor.enter_region(name) {
+            Ok(region) => region,
+            Err(_) if reading => {
+                *self = Default::default();
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.respawn_time.visit("respawn_time", &mut region)?;
+        self.initial_delay.visit("initial_delay", &mut region)?;
+        self.amount.visit("amount", &mut region)?;
+
+        Ok(())
+    }
+}
+
+impl ItemOverrides {
+    /// Reads overrides from the name of a marker, tags without `=` are ignored.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        fn value<T: std::str::FromStr>(key: &str, value: &str) -> Result<Option<T>, String> {
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid value {} of {}", value, key))
+        }
+
+        let mut overrides = Self::default();
+        for tag in name.split('_') {
+            if let Some((key, v)) = tag.split_once('=') {
+                match key {
+                    "respawn" => overrides.respawn_time = value(key, v)?,
+                    "delay" => overrides.initial_delay = value(key, v)?,
+                    "amount" => overrides.amount = value(key, v)?,
+                    _ => return Err(format!("unknown setting {}", key)),
+                }
+            }
+        }
+        Ok(overrides)
+    }
 }
 
 #[derive(Visit)]
@@ -42,6 +137,59 @@ pub struct Item {
     #[visit(skip)]
     pub sender: Option<Sender<Message>>,
     lifetime: Option<f32>,
+    overrides: ItemOverrides,
+    /// Hologram of the item, it is shown while the item is inactive and grows until the item
+    /// appears. Saved as a part of the scene, the handle is restored by [`Item::resolve`].
+    #[visit(skip)]
+    ghost: Handle<Node>,
+}
+
+impl ItemKind {
+    pub fn description(self) -> &'static str {
+        match self {
+            ItemKind::Medkit => "Medkit",
+            ItemKind::Plasma => "Plasma",
+            ItemKind::Ak47Ammo => "AK47 Ammo",
+            ItemKind::M4Ammo => "M4 Ammo",
+            ItemKind::PlasmaGun => "Plasma Gun",
+            ItemKind::Ak47 => "AK47",
+            ItemKind::M4 => "M4",
+            ItemKind::RocketLauncher => "Rocket Launcher",
+            ItemKind::RocketAmmo => "Rockets",
+        }
+    }
+
+    /// Weapon that an item of this kind gives.
+    pub fn weapon(self) -> Option<WeaponKind> {
+        match self {
+            ItemKind::PlasmaGun => Some(WeaponKind::PlasmaRifle),
+            ItemKind::Ak47 => Some(WeaponKind::Ak47),
+            ItemKind::M4 => Some(WeaponKind::M4),
+            ItemKind::RocketLauncher => Some(WeaponKind::RocketLauncher),
+            _ => None,
+        }
+    }
+
+    /// Weapon whose ammo an item of this kind gives.
+    pub fn ammo(self) -> Option<WeaponKind> {
+        match self {
+            ItemKind::Plasma => Some(WeaponKind::PlasmaRifle),
+            ItemKind::Ak47Ammo => Some(WeaponKind::Ak47),
+            ItemKind::M4Ammo => Some(WeaponKind::M4),
+            ItemKind::RocketAmmo => Some(WeaponKind::RocketLauncher),
+            _ => None,
+        }
+    }
+
+    /// Item that is dropped by a dead actor that had a weapon of given kind.
+    pub fn from_weapon(kind: WeaponKind) -> Self {
+        match kind {
+            WeaponKind::M4 => ItemKind::M4,
+            WeaponKind::Ak47 => ItemKind::Ak47,
+            WeaponKind::PlasmaRifle => ItemKind::PlasmaGun,
+            WeaponKind::RocketLauncher => ItemKind::RocketLauncher,
+        }
+    }
 }
 
 impl Default for Item {
@@ -57,6 +205,8 @@ impl Default for Item {
             active: true,
             sender: None,
             lifetime: None,
+            overrides: Default::default(),
+            ghost: Default::default(),
         }
     }
 }
@@ -65,6 +215,21 @@ pub struct ItemDefinition {
     model: &'static str,
     scale: f32,
     reactivation_interval: f32,
+    /// Health of a medkit or ammo of an ammo item. Weapons have no amount, they give ammo of
+    /// their weapon definition, see [`Item::default_amount`].
+    amount: u32,
+    /// Players are told when major items are about to appear, see [`ANNOUNCEMENT_TIME`].
+    major: bool,
+}
+
+impl ItemDefinition {
+    pub fn reactivation_interval(&self) -> f32 {
+        self.reactivation_interval
+    }
+
+    pub fn is_major(&self) -> bool {
+        self.major
+    }
 }
 
 impl Item {
@@ -75,6 +240,8 @@ impl Item {
                     model: "data/models/medkit.fbx",
                     scale: 1.0,
                     reactivation_interval: 20.0,
+                    amount: 20,
+                    major: false,
                 };
                 &DEFINITION
             }
@@ -83,6 +250,8 @@ impl Item {
                     model: "data/models/yellow_box.FBX",
                     scale: 0.25,
                     reactivation_interval: 15.0,
+                    amount: 200,
+                    major: false,
                 };
                 &DEFINITION
             }
@@ -91,6 +260,8 @@ impl Item {
                     model: "data/models/box_medium.FBX",
                     scale: 0.30,
                     reactivation_interval: 14.0,
+                    amount: 200,
+                    major: false,
                 };
                 &DEFINITION
             }
@@ -99,6 +270,8 @@ impl Item {
                     model: "data/models/box_small.FBX",
                     scale: 0.30,
                     reactivation_interval: 13.0,
+                    amount: 200,
+                    major: false,
                 };
                 &DEFINITION
             }
@@ -107,6 +280,8 @@ impl Item {
                     model: "data/models/plasma_rifle.FBX",
                     scale: 3.0,
                     reactivation_interval: 30.0,
+                    amount: 0,
+                    major: true,
                 };
                 &DEFINITION
             }
@@ -115,6 +290,8 @@ impl Item {
                     model: "data/models/ak47.FBX",
                     scale: 3.0,
                     reactivation_interval: 30.0,
+                    amount: 0,
+                    major: true,
                 };
                 &DEFINITION
             }
@@ -123,6 +300,8 @@ impl Item {
                     model: "data/models/m4.FBX",
                     scale: 3.0,
                     reactivation_interval: 30.0,
+                    amount: 0,
+                    major: true,
                 };
                 &DEFINITION
             }
@@ -131,6 +310,18 @@ impl Item {
                     model: "data/models/Rpg7.FBX",
                     scale: 3.0,
                     reactivation_interval: 30.0,
+                    amount: 0,
+                    major: true,
+                };
+                &DEFINITION
+            }
+            ItemKind::RocketAmmo => {
+                static DEFINITION: ItemDefinition = ItemDefinition {
+                    model: "data/models/box_large.FBX",
+                    scale: 0.30,
+                    reactivation_interval: 20.0,
+                    amount: 10,
+                    major: false,
                 };
                 &DEFINITION
             }
@@ -168,15 +359,36 @@ impl Item {
 
         scene.graph.link_nodes(model, pivot);
 
+        let ghost = resource_manager
+            .request_model(Path::new(definition.model))
+            .await
+            .unwrap()
+            .instantiate_geometry(scene);
+        let ghost_node = &mut scene.graph[ghost];
+        ghost_node.set_name(GHOST_NAME);
+        ghost_node.set_visibility(false);
+        make_transparent(&mut scene.graph, ghost);
+        scene.graph.link_nodes(ghost, pivot);
+
         Self {
             pivot,
             kind,
             model,
+            ghost,
             sender: Some(sender),
             ..Default::default()
         }
     }
 
+    /// Finds the hologram of an item loaded from a save. Materials of the hologram are restored
+    /// from the model on load, so they are made transparent again.
+    pub fn resolve(&mut self, graph: &mut Graph) {
+        self.ghost = graph.find_by_name(self.pivot, GHOST_NAME);
+        if self.ghost.is_some() {
+            make_transparent(graph, self.ghost);
+        }
+    }
+
     pub fn get_pivot(&self) -> Handle<Node> {
         self.pivot
     }
@@ -190,7 +402,7 @@ impl Item {
 
         let amp = 0.085;
         self.dest_offset = Vector3::new(0.0, amp + amp * self.offset_factor.sin(), 0.0);
-        self.offset.follow(&self.dest_offset, 0.2);
+        self.offset.follow(&self.dest_offset, time.smoothing(0.2));
 
         let position = graph[self.pivot].global_position();
 
@@ -199,20 +411,53 @@ impl Item {
         model.local_transform_mut().set_position(self.offset);
 
         if !self.active {
+            let previous_time = self.reactivation_timer;
             self.reactivation_timer -= time.delta;
+
+            let sender = self.sender.as_ref().unwrap();
+            if self.definition().is_major()
+                && !self.is_temporary()
+                && previous_time > ANNOUNCEMENT_TIME
+                && self.reactivation_timer <= ANNOUNCEMENT_TIME
+            {
+                sender
+                    .send(Message::AnnounceItem {
+                        kind: self.kind,
+                        time: ANNOUNCEMENT_TIME,
+                    })
+                    .unwrap();
+            }
+
             if self.reactivation_timer <= 0.0 {
                 self.active = true;
 
-                self.sender
-                    .as_ref()
-                    .unwrap()
+                sender
                     .send(Message::CreateEffect {
                         kind: EffectKind::ItemAppear,
                         position,
                     })
                     .unwrap();
+                sender
+                    .send(Message::PlaySound {
+                        path: PathBuf::from(RESPAWN_SOUND),
+                        position,
+                        gain: 0.6,
+                        rolloff_factor: 3.0,
+                        radius: 2.0,
+                    })
+                    .unwrap();
             }
         }
+
+        if self.ghost.is_some() {
+            let ghost = &mut graph[self.ghost];
+            ghost.set_visibility(!self.active);
+            let progress = 1.0 - (self.reactivation_timer / self.respawn_time()).clamp(0.0, 1.0);
+            ghost
+                .local_transform_mut()
+                .set_position(self.offset)
+                .set_scale(Vector3::repeat(0.5 + 0.5 * progress));
+        }
     }
 
     pub fn get_kind(&self) -> ItemKind {
@@ -223,15 +468,70 @@ impl Item {
         Self::get_definition(self.kind)
     }
 
+    /// Time in seconds until the item appears again after it was picked up.
+    pub fn respawn_time(&self) -> f32 {
+        self.overrides
+            .respawn_time
+            .unwrap_or(self.definition().reactivation_interval())
+    }
+
+    /// Time in seconds until an inactive item appears.
+    pub fn time_until_respawn(&self) -> Option<f32> {
+        if self.active {
+            None
+        } else {
+            Some(self.reactivation_timer.max(0.0))
+        }
+    }
+
     pub fn pick_up(&mut self) {
-        self.reactivation_timer = self.definition().reactivation_interval;
+        self.reactivation_timer = self.respawn_time();
         self.active = false;
     }
 
+    /// Applies settings of an item placed on a map, an item with initial delay stays hidden
+    /// until the delay is over.
+    pub fn set_overrides(&mut self, overrides: ItemOverrides) {
+        if let Some(delay) = overrides.initial_delay.filter(|&delay| delay > 0.0) {
+            self.active = false;
+            self.reactivation_timer = delay;
+        }
+        self.overrides = overrides;
+    }
+
+    /// Amount of health or ammo that items of given kind give.
+    pub fn default_amount(kind: ItemKind) -> u32 {
+        match kind.weapon() {
+            Some(weapon) => Weapon::get_definition(weapon).ammo,
+            None => Self::get_definition(kind).amount,
+        }
+    }
+
+    /// Amount of health or ammo that the item gives.
+    pub fn amount(&self) -> u32 {
+        self.overrides
+            .amount
+            .unwrap_or_else(|| Self::default_amount(self.kind))
+    }
+
+    /// Temporary items are dropped by dead actors, they disappear after a while.
+    pub fn is_temporary(&self) -> bool {
+        self.lifetime.is_some()
+    }
+
     pub fn is_picked_up(&self) -> bool {
         !self.active
     }
 
+    /// Sets state of the item received from a server, inactive item stays hidden until the
+    /// server reactivates it.
+    pub fn set_active(&mut self, active: bool) {
+        if !active && self.active {
+            self.pick_up();
+        }
+        self.active = active;
+    }
+
     fn cleanup(&self, graph: &mut Graph) {
         graph.remove_node(self.pivot)
     }
@@ -268,6 +568,10 @@ impl ItemContainer {
         self.pool.spawn(item)
     }
 
+    pub fn get(&self, item: Handle<Item>) -> &Item {
+        self.pool.borrow(item)
+    }
+
     pub fn get_mut(&mut self, item: Handle<Item>) -> &mut Item {
         self.pool.borrow_mut(item)
     }
@@ -288,6 +592,39 @@ impl ItemContainer {
         self.pool.iter_mut()
     }
 
+    pub fn resolve(&mut self, graph: &mut Graph) {
+        for item in self.pool.iter_mut() {
+            item.resolve(graph);
+        }
+    }
+
+    /// Lists inactive items of the map with time until they appear, the closest first.
+    pub fn respawn_report(&self, graph: &Graph) -> String {
+        let mut timers = self
+            .pool
+            .iter()
+            .filter(|item| !item.is_temporary())
+            .filter_map(|item| item.time_until_respawn().map(|time| (time, item)))
+            .collect::<Vec<_>>();
+        timers.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut report = String::new();
+        for (time, item) in timers {
+            let position = item.position(graph);
+            writeln!(
+                report,
+                "{} ({:.1}, {:.1}, {:.1}): {:.1}s",
+                item.kind.description(),
+                position.x,
+                position.y,
+                position.z,
+                time
+            )
+            .unwrap();
+        }
+        report
+    }
+
     pub fn update(&mut self, scene: &mut Scene, time: GameTime) {
         for item in self.pool.iter_mut() {
             item.update(&mut scene.graph, time);
@@ -302,3 +639,30 @@ impl ItemContainer {
         self.pool.retain(|i| !i.can_be_removed())
     }
 }
+
+/// Makes every mesh of a hierarchy translucent, the meshes get their own copies of materials so
+/// the model resource is not affected.
+fn make_transparent(graph: &mut Graph, root: Handle<Node>) {
+    let meshes = graph
+        .traverse_handle_iter(root)
+        .filter(|&handle| graph[handle].cast::<Mesh>().is_some())
+        .collect::<Vec<_>>();
+    for handle in meshes {
+        let mesh = graph[handle].cast_mut::<Mesh>().unwrap();
+        // Deferred renderer does not support blending.
+        mesh.set_render_path(RenderPath::Forward);
+        for surface in mesh.surfaces_mut() {
+            let mut material = surface.material().lock().clone();
+            if let Err(e) = material.set_property(
+                &ImmutableString::new("diffuseColor"),
+                PropertyValue::Color(GHOST_COLOR),
+            ) {
+                Log::writeln(
+                    MessageKind::Error,
+                    format!("Unable to make hologram of an item transparent: {:?}", e),
+                );
+            }
+            surface.set_material(Arc::new(Mutex::new(material)));
+        }
+    }
+}
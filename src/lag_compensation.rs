@@ -0,0 +1,118 @@
+//! Lag compensation of shots of network players. A client shows other actors slightly in the
+//! past - between two received snapshots - and its shots reach the server even later. To let
+//! players hit what they aim at, server remembers recent positions of actors and tests shots
+//! of remote players against positions that actors had at the time the shooter saw them.
+
+use crate::actor::{Actor, ActorContainer};
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    scene::graph::Graph,
+};
+use std::collections::VecDeque;
+
+/// How far in the past shots can be rewound, clients with higher latency are compensated
+/// only partially.
+pub const MAX_REWIND_SECS: f32 = 1.0;
+
+/// Actors are approximated by vertical capsules of this size in rewound hit tests.
+const HIT_CAPSULE_RADIUS: f32 = 0.4;
+const HIT_CAPSULE_HALF_HEIGHT: f32 = 0.6;
+
+struct Frame {
+    time: f32,
+    positions: Vec<(Handle<Actor>, Vector3<f32>)>,
+}
+
+/// Positions of actors at recent simulation steps.
+#[derive(Default)]
+pub struct ActorHistory {
+    frames: VecDeque<Frame>,
+}
+
+impl ActorHistory {
+    pub fn record(&mut self, time: f32, actors: &ActorContainer, graph: &Graph) {
+        self.frames.push_back(Frame {
+            time,
+            positions: actors
+                .pair_iter()
+                .map(|(handle, actor)| (handle, actor.position(graph)))
+                .collect(),
+        });
+
+        while self
+            .frames
+            .front()
+            .map_or(false, |frame| frame.time < time - MAX_REWIND_SECS)
+        {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Returns position of an actor at given time, positions between recorded steps are
+    /// interpolated. Time is clamped to recorded period.
+    pub fn position_at(&self, actor: Handle<Actor>, time: f32) -> Option<Vector3<f32>> {
+        let position_in = |frame: &Frame| {
+            frame.positions.iter().find_map(|&(handle, position)| {
+                if handle == actor {
+                    Some(position)
+                } else {
+                    None
+                }
+            })
+        };
+
+        let next = self.frames.iter().position(|frame| frame.time >= time);
+        match next {
+            Some(0) => position_in(self.frames.front()?),
+            Some(i) => {
+                let (prev, next) = (&self.frames[i - 1], &self.frames[i]);
+                match (position_in(prev), position_in(next)) {
+                    (Some(a), Some(b)) => {
+                        let t = (time - prev.time) / (next.time - prev.time);
+                        Some(a.lerp(&b, t))
+                    }
+                    // Actor has just appeared or disappeared.
+                    (a, b) => a.or(b),
+                }
+            }
+            None => position_in(self.frames.back()?),
+        }
+    }
+}
+
+/// Returns distance along a ray to the capsule of an actor standing at given position,
+/// `direction` must be normalized.
+pub fn ray_hit_distance(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+    actor_position: Vector3<f32>,
+) -> Option<f32> {
+    // Find point of the axis of the capsule that is closest to the line of the ray, then
+    // find point of the ray that is closest to it.
+    let axis = Vector3::y();
+    let bottom = actor_position - axis.scale(HIT_CAPSULE_HALF_HEIGHT);
+    let w = origin - bottom;
+    let b = direction.dot(&axis);
+    let denominator = 1.0 - b * b;
+
+    let axis_t = if denominator.abs() < f32::EPSILON {
+        // Vertical shot, any point of the axis will do.
+        0.0
+    } else {
+        (axis.dot(&w) - b * direction.dot(&w)) / denominator
+    };
+    let axis_point = bottom + axis.scale(axis_t.max(0.0).min(2.0 * HIT_CAPSULE_HALF_HEIGHT));
+    let ray_t = (axis_point - origin).dot(&direction).max(0.0);
+
+    let closest = origin + direction.scale(ray_t);
+    if closest.metric_distance(&axis_point) <= HIT_CAPSULE_RADIUS && ray_t <= max_distance {
+        Some(ray_t)
+    } else {
+        None
+    }
+}
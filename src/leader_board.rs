@@ -16,7 +16,10 @@ use fyrox::{
         HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
     },
 };
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+};
 
 #[derive(Copy, Clone, Visit)]
 pub struct PersonalScore {
@@ -54,6 +57,16 @@ impl LeaderBoard {
         self.get_or_add_actor(actor_name).deaths += 1;
     }
 
+    pub fn add_team_frag(&mut self, team: Team) {
+        if team != Team::None {
+            *self.team_score.entry(team).or_insert(0) += 1;
+        }
+    }
+
+    pub fn set_team_score(&mut self, team: Team, score: u32) {
+        self.team_score.insert(team, score);
+    }
+
     pub fn team_score(&self, team: Team) -> u32 {
         match self.team_score.get(&team) {
             None => 0,
@@ -128,6 +141,32 @@ impl Default for LeaderBoard {
     }
 }
 
+impl Display for LeaderBoard {
+    /// Prints personal scores sorted by kills, team scores are printed only if there are any.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut scores = self.personal_score.iter().collect::<Vec<_>>();
+        scores.sort_by(|(a_name, a), (b_name, b)| {
+            b.kills
+                .cmp(&a.kills)
+                .then(a.deaths.cmp(&b.deaths))
+                .then(a_name.cmp(b_name))
+        });
+
+        writeln!(f, "{:<24} {:>6} {:>6}", "Name", "Kills", "Deaths")?;
+        for (name, score) in scores {
+            writeln!(f, "{:<24} {:>6} {:>6}", name, score.kills, score.deaths)?;
+        }
+
+        for team in [Team::Red, Team::Blue] {
+            if let Some(score) = self.team_score.get(&team) {
+                writeln!(f, "{:?} team: {}", team, score)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub struct LeaderBoardUI {
     root: Handle<UiNode>,
 }
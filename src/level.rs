@@ -1,16 +1,24 @@
 use crate::{
     actor::{Actor, ActorContainer},
-    bot::{Bot, BotKind},
+    ai_debug::{ConsideredOption, Score, AI_TRACE_PATH},
+    blackboard::BlackboardContainer,
+    bot::{Bot, BotKind, BotSkill},
+    character::Team,
     control_scheme::ControlScheme,
-    effects,
-    item::{Item, ItemContainer, ItemKind},
+    effects::{self, EffectKind},
+    item::{Item, ItemContainer, ItemKind, ItemOverrides},
     jump_pad::{JumpPad, JumpPadContainer},
+    lag_compensation::{self, ActorHistory, MAX_REWIND_SECS},
     leader_board::LeaderBoard,
     message::Message,
+    net::ClientId,
     player::Player,
     projectile::{Projectile, ProjectileContainer, ProjectileKind},
-    weapon::{Weapon, WeaponContainer, WeaponKind},
-    GameTime, MatchOptions,
+    save, validator,
+    weapon::{
+        AutoSwitch, FireMode, Weapon, WeaponContainer, WeaponKind, WeaponRules, PLAYER_LOADOUT,
+    },
+    GameMode, GameTime, MatchOptions,
 };
 use fyrox::core::algebra::Vector2;
 use fyrox::{
@@ -21,12 +29,11 @@ use fyrox::{
         math::Vector3Ext,
         math::{aabb::AxisAlignedBoundingBox, ray::Ray, PositionProvider},
         pool::Handle,
-        rand::Rng,
+        rand::{rngs::StdRng, Rng, SeedableRng},
         visitor::{Visit, VisitResult, Visitor},
     },
     engine::{resource_manager::ResourceManager, Engine},
     event::Event,
-    rand,
     scene::{
         self,
         base::BaseBuilder,
@@ -45,12 +52,19 @@ use fyrox::{
     utils::log::{Log, MessageKind},
 };
 use std::{
-    path::{Path, PathBuf},
+    collections::HashMap,
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
     sync::{mpsc::Sender, Arc, RwLock},
 };
 
 pub const RESPAWN_TIME: f32 = 4.0;
 
+/// Spawned actors can not be damaged by other actors for this time or until they shoot.
+pub const SPAWN_PROTECTION_TIME: f32 = 3.0;
+
 #[derive(Default, Visit)]
 pub struct SoundManager {
     reverb: Handle<Effect>,
@@ -144,6 +158,24 @@ pub struct Level {
     spectator_camera: Handle<Node>,
     target_spectator_position: Vector3<f32>,
     sound_manager: SoundManager,
+    #[visit(skip)]
+    blackboards: BlackboardContainer,
+    #[visit(skip)]
+    ai_trace: Option<BufWriter<File>>,
+    seed: u64,
+    /// All random decisions of gameplay must be made using this generator, so a match with
+    /// the same seed and the same inputs would always have the same outcome.
+    #[visit(skip)]
+    rng: StdRng,
+    /// Players of network clients, they exist only on a server.
+    #[visit(skip)]
+    remote_players: HashMap<ClientId, Handle<Actor>>,
+    /// Time of the server at which clients see other actors, shots of remote players are
+    /// tested against actors at that time.
+    #[visit(skip)]
+    view_times: HashMap<ClientId, f32>,
+    #[visit(skip)]
+    actor_history: ActorHistory,
 }
 
 impl Default for Level {
@@ -168,6 +200,13 @@ impl Default for Level {
             spectator_camera: Default::default(),
             target_spectator_position: Default::default(),
             sound_manager: Default::default(),
+            blackboards: Default::default(),
+            ai_trace: None,
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
+            remote_players: Default::default(),
+            view_times: Default::default(),
+            actor_history: Default::default(),
         }
     }
 }
@@ -185,12 +224,31 @@ impl Default for DeathZone {
     }
 }
 
+/// Parts of the engine that are used by a level. Level does not need the whole engine, so a
+/// match could be simulated without a window, a renderer and a sound device.
+pub struct LevelContext<'a> {
+    pub scene: &'a mut Scene,
+    pub resource_manager: ResourceManager,
+}
+
+impl<'a> LevelContext<'a> {
+    pub fn from_engine(engine: &'a mut Engine, scene: Handle<Scene>) -> Self {
+        Self {
+            scene: &mut engine.scenes[scene],
+            resource_manager: engine.resource_manager.clone(),
+        }
+    }
+}
+
 pub struct UpdateContext<'a> {
     pub time: GameTime,
     pub scene: &'a mut Scene,
     pub items: &'a ItemContainer,
     pub jump_pads: &'a JumpPadContainer,
     pub weapons: &'a WeaponContainer,
+    pub blackboards: &'a BlackboardContainer,
+    pub rng: &'a mut StdRng,
+    pub weapon_rules: WeaponRules,
 }
 
 #[derive(Visit)]
@@ -209,6 +267,8 @@ struct BotRespawnEntry {
     name: String,
     kind: BotKind,
     time_left: f32,
+    team: Team,
+    skill: BotSkill,
 }
 
 impl Default for BotRespawnEntry {
@@ -217,14 +277,25 @@ impl Default for BotRespawnEntry {
             name: "".to_string(),
             kind: BotKind::Mutant,
             time_left: 0.0,
+            team: Team::None,
+            skill: BotSkill::Average,
         }
     }
 }
 
+#[derive(Default, Visit)]
+struct RemotePlayerRespawnEntry {
+    client: ClientId,
+    name: String,
+    time_left: f32,
+    team: Team,
+}
+
 #[derive(Visit)]
 enum RespawnEntry {
     Bot(BotRespawnEntry),
     Player(PlayerRespawnEntry),
+    RemotePlayer(RemotePlayerRespawnEntry),
 }
 
 impl Default for RespawnEntry {
@@ -233,6 +304,51 @@ impl Default for RespawnEntry {
     }
 }
 
+/// Kinds of map markers, markers are scene nodes whose names start with a known prefix.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Marker {
+    JumpPad,
+    Item(ItemKind),
+    SpawnPoint,
+    DeathZone,
+}
+
+pub const MARKERS: [(&str, Marker); 12] = [
+    ("JumpPad", Marker::JumpPad),
+    ("Medkit", Marker::Item(ItemKind::Medkit)),
+    ("Ammo_Ak47", Marker::Item(ItemKind::Ak47Ammo)),
+    ("Ammo_M4", Marker::Item(ItemKind::M4Ammo)),
+    ("Ammo_Plasma", Marker::Item(ItemKind::Plasma)),
+    ("Ammo_Rocket", Marker::Item(ItemKind::RocketAmmo)),
+    ("Weapon_Ak47", Marker::Item(ItemKind::Ak47)),
+    ("Weapon_M4", Marker::Item(ItemKind::M4)),
+    ("Weapon_PlasmaGun", Marker::Item(ItemKind::PlasmaGun)),
+    (
+        "Weapon_RocketLauncher",
+        Marker::Item(ItemKind::RocketLauncher),
+    ),
+    ("SpawnPoint", Marker::SpawnPoint),
+    ("DeathZone", Marker::DeathZone),
+];
+
+impl Marker {
+    pub fn from_name(name: &str) -> Option<Self> {
+        MARKERS
+            .iter()
+            .find(|(prefix, _)| name.starts_with(prefix))
+            .map(|&(_, marker)| marker)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Marker::JumpPad => "Jump pad",
+            Marker::Item(kind) => kind.description(),
+            Marker::SpawnPoint => "Spawn point",
+            Marker::DeathZone => "Death zone",
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct AnalysisResult {
     jump_pads: JumpPadContainer,
@@ -253,46 +369,43 @@ pub async fn analyze(
     let mut death_zones = Vec::new();
     for (handle, node) in scene.graph.pair_iter() {
         let position = node.global_position();
-        let name = node.name();
-        if name.starts_with("JumpPad") {
-            let begin = scene.graph.find_by_name(handle, "Begin");
-            let end = scene.graph.find_by_name(handle, "End");
-            if begin.is_some() && end.is_some() {
-                let begin = scene.graph[begin].global_position();
-                let end = scene.graph[end].global_position();
-                let d = end - begin;
-                let len = d.norm();
-                let force = d.try_normalize(std::f32::EPSILON);
-                let force = force.unwrap_or(Vector3::y()).scale(len * 2.0);
-                let collider = scene.graph.find(handle, &mut |n| n.is_collider());
-                result.jump_pads.add(JumpPad::new(collider, force));
-            };
-        } else if name.starts_with("Medkit") {
-            items.push((ItemKind::Medkit, position));
-        } else if name.starts_with("Ammo_Ak47") {
-            items.push((ItemKind::Ak47Ammo, position));
-        } else if name.starts_with("Ammo_M4") {
-            items.push((ItemKind::M4Ammo, position));
-        } else if name.starts_with("Ammo_Plasma") {
-            items.push((ItemKind::Plasma, position));
-        } else if name.starts_with("SpawnPoint") {
-            spawn_points.push(node.global_position())
-        } else if name.starts_with("DeathZone") {
-            death_zones.push(handle);
+        // Broken markers are skipped here, the validator reports them.
+        match Marker::from_name(node.name()) {
+            Some(Marker::JumpPad) => {
+                let begin = scene.graph.find_by_name(handle, "Begin");
+                let end = scene.graph.find_by_name(handle, "End");
+                if begin.is_some() && end.is_some() {
+                    let begin = scene.graph[begin].global_position();
+                    let end = scene.graph[end].global_position();
+                    let d = end - begin;
+                    let len = d.norm();
+                    let force = d.try_normalize(std::f32::EPSILON);
+                    let force = force.unwrap_or(Vector3::y()).scale(len * 2.0);
+                    let collider = scene.graph.find(handle, &mut |n| n.is_collider());
+                    result.jump_pads.add(JumpPad::new(collider, force));
+                };
+            }
+            Some(Marker::Item(kind)) => {
+                let overrides = ItemOverrides::parse(node.name()).unwrap_or_default();
+                items.push((kind, position, overrides));
+            }
+            Some(Marker::SpawnPoint) => spawn_points.push(SpawnPoint::from_marker(node)),
+            Some(Marker::DeathZone) => death_zones.push(handle),
+            None => (),
         }
     }
 
-    for (kind, position) in items {
-        result.items.add(
-            Item::new(
-                kind,
-                position,
-                scene,
-                resource_manager.clone(),
-                sender.clone(),
-            )
-            .await,
-        );
+    for (kind, position, overrides) in items {
+        let mut item = Item::new(
+            kind,
+            position,
+            scene,
+            resource_manager.clone(),
+            sender.clone(),
+        )
+        .await;
+        item.set_overrides(overrides);
+        result.items.add(item);
     }
     for handle in death_zones {
         let node = &mut scene.graph[handle];
@@ -301,47 +414,44 @@ pub async fn analyze(
             bounds: node.world_bounding_box(),
         });
     }
-    result.spawn_points = spawn_points
-        .into_iter()
-        .map(|p| SpawnPoint { position: p })
-        .collect();
+    result.spawn_points = spawn_points;
 
     result
 }
 
 async fn spawn_player(
     spawn_points: &[SpawnPoint],
+    mode: GameMode,
+    rng: &mut StdRng,
     actors: &mut ActorContainer,
     weapons: &mut WeaponContainer,
     sender: Sender<Message>,
     resource_manager: ResourceManager,
     control_scheme: Arc<RwLock<ControlScheme>>,
+    team: Team,
     scene: &mut Scene,
 ) -> Handle<Actor> {
-    let index = find_suitable_spawn_point(spawn_points, actors, scene);
-    let spawn_position = spawn_points.get(index).map_or(Vector3::default(), |pt| {
+    let spawn_point = find_suitable_spawn_point(spawn_points, mode, team, actors, scene, rng);
+    let spawn_position = spawn_point.map_or(Vector3::default(), |pt| {
         pt.position + Vector3::new(0.0, 1.5, 0.0)
     });
     let mut player = Player::new(scene, sender.clone());
     player.set_control_scheme(control_scheme);
+    player.set_team(team);
+    player.set_yaw(spawn_point.map_or(0.0, |pt| pt.yaw));
+    player.set_spawn_protection(SPAWN_PROTECTION_TIME);
     let player = actors.add(Actor::Player(player));
     actors
         .get_mut(player)
         .set_position(&mut scene.graph, spawn_position);
 
-    let weapons_to_give = [
-        WeaponKind::M4,
-        WeaponKind::Ak47,
-        WeaponKind::PlasmaRifle,
-        WeaponKind::RocketLauncher,
-    ];
-    for (i, &weapon) in weapons_to_give.iter().enumerate() {
+    for (i, &weapon) in PLAYER_LOADOUT.iter().enumerate() {
         give_new_weapon(
             weapon,
             player,
             sender.clone(),
             resource_manager.clone(),
-            i == weapons_to_give.len() - 1,
+            i == PLAYER_LOADOUT.len() - 1,
             weapons,
             actors,
             scene,
@@ -352,24 +462,27 @@ async fn spawn_player(
     player
 }
 
+/// Gives a new weapon to an actor, the weapon becomes current if `select` is set.
 async fn give_new_weapon(
     kind: WeaponKind,
     actor: Handle<Actor>,
     sender: Sender<Message>,
     resource_manager: ResourceManager,
-    visible: bool,
+    select: bool,
     weapons: &mut WeaponContainer,
     actors: &mut ActorContainer,
     scene: &mut Scene,
-) {
+) -> Handle<Weapon> {
     if actors.contains(actor) {
         let mut weapon = Weapon::new(kind, resource_manager, scene, sender.clone()).await;
         weapon.set_owner(actor);
         let weapon_model = weapon.get_model();
-        scene.graph[weapon_model].set_visibility(visible);
         let actor = actors.get_mut(actor);
+        // The first weapon is always selected.
+        let select = select || actor.weapons().is_empty();
+        scene.graph[weapon_model].set_visibility(select);
         let weapon_handle = weapons.add(weapon);
-        actor.add_weapon(weapon_handle);
+        actor.add_weapon(weapon_handle, select);
         scene.graph.link_nodes(weapon_model, actor.weapon_pivot());
 
         sender
@@ -377,35 +490,98 @@ async fn give_new_weapon(
                 text: format!("Actor picked up weapon {:?}", kind),
             })
             .unwrap();
+
+        weapon_handle
+    } else {
+        Handle::NONE
     }
 }
 
-fn find_suitable_spawn_point(
-    spawn_points: &[SpawnPoint],
+/// Checks whether an enemy could see an actor at a spawn point. Actors do not block the view.
+fn is_seen_by_enemies(
+    point: &SpawnPoint,
+    team: Team,
     actors: &ActorContainer,
     scene: &Scene,
-) -> usize {
-    // Find spawn point with least amount of enemies nearby.
-    let mut index = rand::thread_rng().gen_range(0..spawn_points.len());
-    let mut max_distance = -std::f32::MAX;
-    for (i, pt) in spawn_points.iter().enumerate() {
+) -> bool {
+    let eye = point.position + Vector3::new(0.0, 1.5, 0.0);
+    actors
+        .iter()
+        .filter(|actor| is_enemy(team, actor.team()))
+        .any(|enemy| {
+            let to_enemy = enemy.position(&scene.graph) - eye;
+            let mut query_buffer = Vec::default();
+            scene.graph.physics.cast_ray(
+                RayCastOptions {
+                    ray_origin: Point3::from(eye),
+                    ray_direction: to_enemy,
+                    max_len: to_enemy.norm(),
+                    groups: InteractionGroups::default(),
+                    sort_results: false,
+                },
+                &mut query_buffer,
+            );
+            query_buffer
+                .iter()
+                .all(|hit| actors.iter().any(|actor| actor.collider == hit.collider))
+        })
+}
+
+/// Everyone is an enemy of actors without a team.
+fn is_enemy(team: Team, other: Team) -> bool {
+    team == Team::None || team != other
+}
+
+/// Finds a spawn point for an actor of a team, `None` if a map has no spawn points at all.
+/// Spawn points not seen by enemies are preferred, then the ones farthest from enemies.
+fn find_suitable_spawn_point<'a>(
+    spawn_points: &'a [SpawnPoint],
+    mode: GameMode,
+    team: Team,
+    actors: &ActorContainer,
+    scene: &Scene,
+    rng: &mut StdRng,
+) -> Option<&'a SpawnPoint> {
+    let mut candidates = spawn_points
+        .iter()
+        .filter(|pt| pt.supports(mode, team))
+        .collect::<Vec<_>>();
+    // A map for other modes is still playable.
+    if candidates.is_empty() {
+        candidates = spawn_points.iter().collect();
+    }
+    if candidates.is_empty() {
+        return None;
+    }
+
+    // Equally good spawn points are chosen randomly.
+    let start = rng.gen_range(0..candidates.len());
+    let mut best = None;
+    let mut best_score = (false, -std::f32::MAX);
+    for i in 0..candidates.len() {
+        let pt = candidates[(start + i) % candidates.len()];
         let mut sum_distance = 0.0;
-        for actor in actors.iter() {
+        for actor in actors.iter().filter(|actor| is_enemy(team, actor.team())) {
             let position = actor.position(&scene.graph);
             sum_distance += pt.position.metric_distance(&position);
         }
-        if sum_distance > max_distance {
-            max_distance = sum_distance;
-            index = i;
+        let score = (!is_seen_by_enemies(pt, team, actors, scene), sum_distance);
+        if best.is_none() || score > best_score {
+            best_score = score;
+            best = Some(pt);
         }
     }
-    index
+    best
 }
 
 async fn spawn_bot(
     kind: BotKind,
     name: Option<String>,
+    team: Team,
+    skill: BotSkill,
     spawn_points: &[SpawnPoint],
+    mode: GameMode,
+    rng: &mut StdRng,
     actors: &mut ActorContainer,
     weapons: &mut WeaponContainer,
     resource_manager: ResourceManager,
@@ -413,15 +589,15 @@ async fn spawn_bot(
     leader_board: &mut LeaderBoard,
     scene: &mut Scene,
 ) -> Handle<Actor> {
-    let index = find_suitable_spawn_point(spawn_points, actors, scene);
-    let spawn_position = spawn_points
-        .get(index)
-        .map_or(Vector3::default(), |pt| pt.position);
+    let spawn_point = find_suitable_spawn_point(spawn_points, mode, team, actors, scene, rng);
+    let spawn_position = spawn_point.map_or(Vector3::default(), |pt| pt.position);
 
     let bot = add_bot(
         kind,
         spawn_position,
         name,
+        team,
+        skill,
         actors,
         weapons,
         resource_manager,
@@ -431,6 +607,10 @@ async fn spawn_bot(
     )
     .await;
 
+    let actor = actors.get_mut(bot);
+    actor.set_yaw(spawn_point.map_or(0.0, |pt| pt.yaw));
+    actor.set_spawn_protection(SPAWN_PROTECTION_TIME);
+
     bot
 }
 
@@ -438,6 +618,8 @@ async fn add_bot(
     kind: BotKind,
     position: Vector3<f32>,
     name: Option<String>,
+    team: Team,
+    skill: BotSkill,
     actors: &mut ActorContainer,
     weapons: &mut WeaponContainer,
     resource_manager: ResourceManager,
@@ -447,6 +629,7 @@ async fn add_bot(
 ) -> Handle<Actor> {
     let bot = Bot::new(
         kind,
+        skill,
         resource_manager.clone(),
         scene,
         position,
@@ -456,31 +639,74 @@ async fn add_bot(
     let name = name.unwrap_or_else(|| format!("Bot {:?} {}", kind, actors.count()));
     leader_board.get_or_add_actor(&name);
     let bot = actors.add(Actor::Bot(bot));
-    give_new_weapon(
-        WeaponKind::Ak47,
-        bot,
-        sender.clone(),
-        resource_manager,
-        true,
-        weapons,
-        actors,
-        scene,
-    )
-    .await;
+    let character = actors.get_mut(bot);
+    character.name = name;
+    character.set_team(team);
+    let loadout = Bot::get_definition(kind).loadout;
+    for (i, &weapon) in loadout.iter().enumerate() {
+        give_new_weapon(
+            weapon,
+            bot,
+            sender.clone(),
+            resource_manager.clone(),
+            i == loadout.len() - 1,
+            weapons,
+            actors,
+            scene,
+        )
+        .await;
+    }
     bot
 }
 
 impl Level {
+    /// Creates new level with bots from match roster. `with_player` is `false` for matches
+    /// between bots only, such as headless simulations. Matches with the same `seed` and
+    /// the same inputs have the same outcome.
     pub async fn new(
         resource_manager: ResourceManager,
         control_scheme: Arc<RwLock<ControlScheme>>,
         sender: Sender<Message>,
         options: MatchOptions,
+        with_player: bool,
+        seed: u64,
     ) -> (Level, Scene) {
         let mut scene = Scene::new();
 
         scene.ambient_lighting_color = Color::opaque(60, 60, 60);
 
+        // Instantiate map
+        let map_root = resource_manager
+            .request_model(options.map().path())
+            .await
+            .unwrap()
+            .instantiate_geometry(&mut scene);
+
+        Self::from_scene(
+            scene,
+            map_root,
+            resource_manager,
+            control_scheme,
+            sender,
+            options,
+            with_player,
+            seed,
+        )
+        .await
+    }
+
+    /// Creates new level on top of a scene that already contains map geometry. Spawn points,
+    /// items, jump pads and death zones are found by names of scene nodes.
+    pub async fn from_scene(
+        mut scene: Scene,
+        map_root: Handle<Node>,
+        resource_manager: ResourceManager,
+        control_scheme: Arc<RwLock<ControlScheme>>,
+        sender: Sender<Message>,
+        options: MatchOptions,
+        with_player: bool,
+        seed: u64,
+    ) -> (Level, Scene) {
         let sound_manager = SoundManager::new(&mut scene.graph.sound_context);
 
         // Spectator camera is used when there is no player on level.
@@ -490,16 +716,11 @@ impl Level {
             .enabled(false)
             .build(&mut scene.graph);
 
-        // Instantiate map
-        let map_root = resource_manager
-            .request_model(Path::new("data/levels/dm6.rgs"))
-            .await
-            .unwrap()
-            .instantiate_geometry(&mut scene);
-
         // Make sure global coordinates are calculated.
         scene.update(Vector2::new(1.0, 1.0), 0.0);
 
+        validator::validate(&scene).log(&options.map().file_name());
+
         let AnalysisResult {
             jump_pads,
             items,
@@ -509,12 +730,26 @@ impl Level {
         let mut actors = ActorContainer::new();
         let mut weapons = WeaponContainer::new();
         let mut leader_board = LeaderBoard::default();
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let player_team = if with_player {
+            player_team(&options)
+        } else {
+            Team::None
+        };
 
-        for &kind in &[BotKind::Maw, BotKind::Mutant, BotKind::Parasite] {
+        for bot in options
+            .roster()
+            .resolve(options.is_team_match(), player_team)
+        {
             spawn_bot(
-                kind,
-                Some(kind.description().to_owned()),
+                bot.kind,
+                Some(bot.name),
+                bot.team,
+                bot.skill,
                 &spawn_points,
+                options.mode(),
+                &mut rng,
                 &mut actors,
                 &mut weapons,
                 resource_manager.clone(),
@@ -525,17 +760,26 @@ impl Level {
             .await;
         }
 
-        let level = Level {
-            player: spawn_player(
+        let player = if with_player {
+            spawn_player(
                 &spawn_points,
+                options.mode(),
+                &mut rng,
                 &mut actors,
                 &mut weapons,
                 sender.clone(),
                 resource_manager.clone(),
                 control_scheme.clone(),
+                player_team,
                 &mut scene,
             )
-            .await,
+            .await
+        } else {
+            Handle::NONE
+        };
+
+        let level = Level {
+            player,
             map_root,
             options,
             spectator_camera,
@@ -554,6 +798,13 @@ impl Level {
             projectiles: ProjectileContainer::new(),
             target_spectator_position: Default::default(),
             sound_manager,
+            blackboards: Default::default(),
+            ai_trace: None,
+            seed,
+            rng,
+            remote_players: Default::default(),
+            view_times: Default::default(),
+            actor_history: Default::default(),
         };
 
         (level, scene)
@@ -565,33 +816,49 @@ impl Level {
 
     async fn give_new_weapon(
         &mut self,
-        engine: &mut Engine,
+        context: &mut LevelContext<'_>,
         actor: Handle<Actor>,
         kind: WeaponKind,
-    ) {
+        select: bool,
+    ) -> Handle<Weapon> {
         give_new_weapon(
             kind,
             actor,
             self.sender.clone().unwrap(),
-            engine.resource_manager.clone(),
-            true,
+            context.resource_manager.clone(),
+            select,
             &mut self.weapons,
             &mut self.actors,
-            &mut engine.scenes[self.scene],
+            &mut *context.scene,
         )
-        .await;
+        .await
     }
 
-    async fn spawn_player(&mut self, engine: &mut Engine) -> Handle<Actor> {
-        let scene = &mut engine.scenes[self.scene];
+    /// Preference of an actor about switching to picked up weapons. Only the local player has
+    /// a preference, bots choose weapons by themselves and players of network clients always
+    /// switch.
+    fn auto_switch(&self, actor: Handle<Actor>) -> AutoSwitch {
+        match self.control_scheme.as_ref() {
+            Some(control_scheme) if actor == self.player => {
+                control_scheme.read().unwrap().auto_switch
+            }
+            _ => AutoSwitch::Always,
+        }
+    }
+
+    async fn spawn_player(&mut self, context: &mut LevelContext<'_>) -> Handle<Actor> {
+        let scene = &mut *context.scene;
 
         let player = spawn_player(
             &self.spawn_points,
+            self.options.mode(),
+            &mut self.rng,
             &mut self.actors,
             &mut self.weapons,
             self.sender.clone().unwrap(),
-            engine.resource_manager.clone(),
+            context.resource_manager.clone(),
             self.control_scheme.clone().unwrap(),
+            player_team(&self.options),
             scene,
         )
         .await;
@@ -607,10 +874,104 @@ impl Level {
         self.player
     }
 
+    /// Returns actor of a network client, [`Handle::NONE`] if the client is unknown or its
+    /// player is dead.
+    pub fn remote_player(&self, client: ClientId) -> Handle<Actor> {
+        self.remote_players
+            .get(&client)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_view_time(&mut self, client: ClientId, time: f32) {
+        self.view_times.insert(client, time);
+    }
+
+    async fn spawn_remote_player(
+        &mut self,
+        context: &mut LevelContext<'_>,
+        client: ClientId,
+        name: String,
+        team: Team,
+    ) {
+        let scene = &mut *context.scene;
+
+        // Players that did not choose a team join the smallest one to keep teams balanced.
+        let team = if !self.options.is_team_match() {
+            Team::None
+        } else if team != Team::None {
+            team
+        } else {
+            let count = |team| self.actors.iter().filter(|a| a.team() == team).count();
+            if count(Team::Blue) < count(Team::Red) {
+                Team::Blue
+            } else {
+                Team::Red
+            }
+        };
+
+        let player = spawn_player(
+            &self.spawn_points,
+            self.options.mode(),
+            &mut self.rng,
+            &mut self.actors,
+            &mut self.weapons,
+            self.sender.clone().unwrap(),
+            context.resource_manager.clone(),
+            self.control_scheme.clone().unwrap(),
+            team,
+            scene,
+        )
+        .await;
+
+        if let Actor::Player(remote_player) = self.actors.get_mut(player) {
+            remote_player.name = name.clone();
+            // The world is viewed through this player on the client, not on the server.
+            if let Some(camera) = scene.graph[remote_player.camera()].cast_mut::<Camera>() {
+                camera.set_enabled(false);
+            }
+        }
+
+        self.leader_board.get_or_add_actor(&name);
+        self.remote_players.insert(client, player);
+    }
+
+    async fn remove_remote_player(&mut self, context: &mut LevelContext<'_>, client: ClientId) {
+        if let Some(player) = self.remote_players.remove(&client) {
+            self.remove_actor(context, player).await;
+        }
+        self.view_times.remove(&client);
+        self.respawn_list.retain(|entry| match entry {
+            RespawnEntry::RemotePlayer(v) => v.client != client,
+            _ => true,
+        });
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Enables or disables cameras of the game (camera of the player or spectator camera), it
+    /// is used when level is viewed through some other camera.
+    pub fn set_game_cameras_enabled(&self, scene: &mut Scene, enabled: bool) {
+        let mut has_player_camera = false;
+        if self.actors.contains(self.player) {
+            if let Actor::Player(player) = self.actors.get(self.player) {
+                if let Some(camera) = scene.graph[player.camera()].cast_mut::<Camera>() {
+                    camera.set_enabled(enabled);
+                    has_player_camera = true;
+                }
+            }
+        }
+        if let Some(spectator_camera) = scene.graph[self.spectator_camera].cast_mut::<Camera>() {
+            spectator_camera.set_enabled(enabled && !has_player_camera);
+        }
+    }
+
     pub fn process_input_event(&mut self, event: &Event<()>) -> bool {
         if self.player.is_some() {
             if let Actor::Player(player) = self.actors.get_mut(self.player) {
-                return player.process_input_event(event);
+                return player.process_input_event(event, &self.weapons);
             }
         }
         false
@@ -628,8 +989,17 @@ impl Level {
         &self.weapons
     }
 
-    fn pick(&self, engine: &mut Engine, from: Vector3<f32>, to: Vector3<f32>) -> Vector3<f32> {
-        let scene = &mut engine.scenes[self.scene];
+    pub fn weapons_mut(&mut self) -> &mut WeaponContainer {
+        &mut self.weapons
+    }
+
+    fn pick(
+        &self,
+        context: &mut LevelContext<'_>,
+        from: Vector3<f32>,
+        to: Vector3<f32>,
+    ) -> Vector3<f32> {
+        let scene = &mut *context.scene;
         let ray = Ray::from_two_points(from, to);
         let options = RayCastOptions {
             ray_origin: Point3::from(ray.origin),
@@ -647,122 +1017,137 @@ impl Level {
         }
     }
 
-    fn remove_weapon(&mut self, engine: &mut Engine, weapon: Handle<Weapon>) {
+    fn remove_weapon(&mut self, context: &mut LevelContext<'_>, weapon: Handle<Weapon>) {
         for projectile in self.projectiles.iter_mut() {
             if projectile.owner == weapon {
                 // Reset owner because handle to weapon will be invalid after weapon freed.
                 projectile.owner = Handle::NONE;
             }
         }
-        self.weapons[weapon].clean_up(&mut engine.scenes[self.scene]);
+        self.weapons[weapon].clean_up(&mut *context.scene);
         self.weapons.free(weapon);
     }
 
     async fn add_bot(
         &mut self,
-        engine: &mut Engine,
+        context: &mut LevelContext<'_>,
         kind: BotKind,
         position: Vector3<f32>,
         name: Option<String>,
+        team: Team,
+        skill: BotSkill,
     ) -> Handle<Actor> {
         add_bot(
             kind,
             position,
             name,
+            team,
+            skill,
             &mut self.actors,
             &mut self.weapons,
-            engine.resource_manager.clone(),
+            context.resource_manager.clone(),
             self.sender.clone().unwrap(),
             &mut self.leader_board,
-            &mut engine.scenes[self.scene],
+            &mut *context.scene,
         )
         .await
     }
 
-    async fn remove_actor(&mut self, engine: &mut Engine, actor: Handle<Actor>) {
+    async fn remove_actor(&mut self, context: &mut LevelContext<'_>, actor: Handle<Actor>) {
         if self.actors.contains(actor) {
-            let scene = &mut engine.scenes[self.scene];
+            let scene = &mut *context.scene;
             let character = self.actors.get(actor);
 
             // Make sure to remove weapons and drop appropriate items (items will be temporary).
             let drop_position = character.position(&scene.graph);
+            let current_weapon = character.current_weapon();
             let weapons = character
                 .weapons()
                 .iter()
                 .copied()
                 .collect::<Vec<Handle<Weapon>>>();
+            let drop_current_only = self.options.weapon_rules().drop_current_only;
             for weapon in weapons {
-                let item_kind = match self.weapons[weapon].get_kind() {
-                    WeaponKind::M4 => ItemKind::M4,
-                    WeaponKind::Ak47 => ItemKind::Ak47,
-                    WeaponKind::PlasmaRifle => ItemKind::PlasmaGun,
-                    WeaponKind::RocketLauncher => ItemKind::RocketLauncher,
-                };
-                self.spawn_item(engine, item_kind, drop_position, true, Some(20.0))
-                    .await;
-                self.remove_weapon(engine, weapon);
+                if !drop_current_only || weapon == current_weapon {
+                    let item_kind = ItemKind::from_weapon(self.weapons[weapon].get_kind());
+                    self.spawn_item(context, item_kind, drop_position, true, Some(20.0))
+                        .await;
+                }
+                self.remove_weapon(context, weapon);
             }
 
-            let scene = &mut engine.scenes[self.scene];
+            let scene = &mut *context.scene;
             self.actors.get_mut(actor).clean_up(scene);
             self.actors.free(actor);
+            self.blackboards.on_actor_removed(actor);
 
             if self.player == actor {
                 self.player = Handle::NONE;
             }
+            self.remote_players.retain(|_, player| *player != actor);
         }
     }
 
-    async fn give_item(&mut self, engine: &mut Engine, actor: Handle<Actor>, kind: ItemKind) {
-        if self.actors.contains(actor) {
-            let character = self.actors.get_mut(actor);
-            match kind {
-                ItemKind::Medkit => character.heal(20.0),
-                ItemKind::Ak47 | ItemKind::PlasmaGun | ItemKind::M4 | ItemKind::RocketLauncher => {
-                    let weapon_kind = match kind {
-                        ItemKind::Ak47 => WeaponKind::Ak47,
-                        ItemKind::PlasmaGun => WeaponKind::PlasmaRifle,
-                        ItemKind::M4 => WeaponKind::M4,
-                        ItemKind::RocketLauncher => WeaponKind::RocketLauncher,
-                        _ => unreachable!(),
-                    };
+    async fn give_item(
+        &mut self,
+        context: &mut LevelContext<'_>,
+        actor: Handle<Actor>,
+        kind: ItemKind,
+        amount: u32,
+    ) {
+        if !self.actors.contains(actor) {
+            return;
+        }
 
-                    let mut found = false;
-                    for weapon_handle in character.weapons() {
-                        let weapon = &mut self.weapons[*weapon_handle];
-                        // If actor already has weapon of given kind, then just add ammo to it.
-                        if weapon.get_kind() == weapon_kind {
-                            found = true;
-                            weapon.add_ammo(200);
-                            break;
-                        }
-                    }
-                    // Finally if actor does not have such weapon, give new one to him.
-                    if !found {
-                        self.give_new_weapon(engine, actor, weapon_kind).await;
-                    }
-                }
-                ItemKind::Plasma | ItemKind::Ak47Ammo | ItemKind::M4Ammo => {
-                    for weapon in character.weapons() {
-                        let weapon = &mut self.weapons[*weapon];
-                        let (weapon_kind, ammo) = match kind {
-                            ItemKind::Plasma => (WeaponKind::PlasmaRifle, 200),
-                            ItemKind::Ak47Ammo => (WeaponKind::Ak47, 200),
-                            ItemKind::M4Ammo => (WeaponKind::M4, 200),
-                            _ => continue,
-                        };
-                        if weapon.get_kind() == weapon_kind {
-                            weapon.add_ammo(ammo);
-                            break;
-                        }
+        if kind == ItemKind::Medkit {
+            self.actors.get_mut(actor).heal(amount as f32);
+        } else if let Some(weapon_kind) = kind.weapon() {
+            let character = self.actors.get(actor);
+            match character.find_weapon(weapon_kind, &self.weapons) {
+                // If actor already has weapon of given kind, then just add ammo to it.
+                Some(weapon) => self.weapons[weapon].add_ammo(amount),
+                // Otherwise give new one with the ammo of the item.
+                None => {
+                    let current_weapon = character.current_weapon();
+                    let current_kind = if current_weapon.is_some() {
+                        Some(self.weapons[current_weapon].get_kind())
+                    } else {
+                        None
+                    };
+                    let select = self
+                        .auto_switch(actor)
+                        .should_switch(current_kind, weapon_kind);
+                    let weapon = self
+                        .give_new_weapon(context, actor, weapon_kind, select)
+                        .await;
+                    if weapon.is_some() {
+                        self.weapons[weapon].set_ammo(amount);
                     }
                 }
             }
+        } else if let Some(weapon_kind) = kind.ammo() {
+            let character = self.actors.get(actor);
+            if let Some(weapon) = character.find_weapon(weapon_kind, &self.weapons) {
+                self.weapons[weapon].add_ammo(amount);
+            }
         }
     }
 
-    async fn pickup_item(&mut self, engine: &mut Engine, actor: Handle<Actor>, item: Handle<Item>) {
+    async fn pickup_item(
+        &mut self,
+        context: &mut LevelContext<'_>,
+        actor: Handle<Actor>,
+        item: Handle<Item>,
+    ) {
         if self.actors.contains(actor) && self.items.contains(item) {
+            let weapon_rules = self.options.weapon_rules();
+            if !weapon_rules.can_pick_up(
+                self.items.get(item),
+                self.actors.get(actor),
+                &self.weapons,
+            ) {
+                return;
+            }
             let item = self.items.get_mut(item);
 
             self.sender
@@ -773,10 +1158,14 @@ impl Level {
                 })
                 .unwrap();
 
-            let scene = &mut engine.scenes[self.scene];
+            let scene = &mut *context.scene;
             let position = item.position(&scene.graph);
-            item.pick_up();
+            // Staying weapons remain for other actors.
+            if !weapon_rules.item_stays(item) {
+                item.pick_up();
+            }
             let kind = item.get_kind();
+            let amount = item.amount();
             self.sender
                 .as_ref()
                 .unwrap()
@@ -788,13 +1177,13 @@ impl Level {
                     radius: 2.0,
                 })
                 .unwrap();
-            self.give_item(engine, actor, kind).await;
+            self.give_item(context, actor, kind, amount).await;
         }
     }
 
     async fn create_projectile(
         &mut self,
-        engine: &mut Engine,
+        context: &mut LevelContext<'_>,
         kind: ProjectileKind,
         position: Vector3<f32>,
         direction: Vector3<f32>,
@@ -802,10 +1191,10 @@ impl Level {
         owner: Handle<Weapon>,
         basis: Matrix3<f32>,
     ) {
-        let scene = &mut engine.scenes[self.scene];
+        let scene = &mut *context.scene;
         let projectile = Projectile::new(
             kind,
-            engine.resource_manager.clone(),
+            context.resource_manager.clone(),
             scene,
             direction,
             position,
@@ -818,59 +1207,186 @@ impl Level {
         self.projectiles.add(projectile);
     }
 
-    async fn shoot_weapon(
+    fn shoot_weapon(
         &mut self,
-        engine: &mut Engine,
+        context: &mut LevelContext<'_>,
         weapon_handle: Handle<Weapon>,
         initial_velocity: Vector3<f32>,
         time: GameTime,
         direction: Option<Vector3<f32>>,
+        mode: FireMode,
     ) {
         if self.weapons.contains(weapon_handle) {
-            let scene = &mut engine.scenes[self.scene];
-            let weapon = &mut self.weapons[weapon_handle];
-            if weapon.try_shoot(scene, time) {
-                let kind = weapon.definition().projectile;
-                let position = weapon.get_shot_position(&scene.graph);
-                let direction = direction
-                    .unwrap_or_else(|| weapon.get_shot_direction(&scene.graph))
-                    .try_normalize(std::f32::EPSILON)
-                    .unwrap_or_else(|| Vector3::z());
-                let basis = weapon.world_basis(&scene.graph);
-                self.create_projectile(
-                    engine,
+            let scene = &mut *context.scene;
+            if let Some(kind) = self.weapons[weapon_handle].try_shoot(scene, time, mode, direction)
+            {
+                self.fire_projectile(scene, weapon_handle, kind, initial_velocity, direction);
+            }
+        }
+    }
+
+    /// Creates projectile of a shot of a weapon, it is used for shots that weapons fire by
+    /// themselves too.
+    fn fire_projectile(
+        &mut self,
+        scene: &mut Scene,
+        weapon_handle: Handle<Weapon>,
+        kind: ProjectileKind,
+        initial_velocity: Vector3<f32>,
+        direction: Option<Vector3<f32>>,
+    ) {
+        let weapon = &self.weapons[weapon_handle];
+        let shooter = weapon.owner();
+        if let Some(shooter) = self.actors.try_get_mut(shooter) {
+            shooter.set_spawn_protection(0.0);
+        }
+        let position = weapon.get_shot_position(&scene.graph);
+        let direction = direction
+            .unwrap_or_else(|| weapon.get_shot_direction(&scene.graph))
+            .try_normalize(std::f32::EPSILON)
+            .unwrap_or_else(|| Vector3::z());
+        let basis = weapon.world_basis(&scene.graph);
+
+        let view_time = self
+            .remote_players
+            .iter()
+            .find(|(_, &player)| player == shooter)
+            .and_then(|(client, _)| self.view_times.get(client))
+            .copied();
+        let owner = match view_time {
+            // Bullets of remote players hit instantly at what the client saw, the
+            // projectile only shows the shot.
+            Some(view_time) if kind == ProjectileKind::Bullet => {
+                self.lag_compensated_shot(
+                    &scene.graph,
+                    shooter,
                     kind,
                     position,
                     direction,
-                    initial_velocity,
-                    weapon_handle,
-                    basis,
-                )
-                .await;
+                    view_time,
+                );
+                Handle::NONE
             }
+            _ => weapon_handle,
+        };
+
+        // Projectile is created by the message, so clients will see the shot too.
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(Message::CreateProjectile {
+                kind,
+                position,
+                direction,
+                initial_velocity,
+                owner,
+                basis,
+            })
+            .unwrap();
+    }
+
+    /// Tests a shot of a remote player against positions that actors had at given time.
+    fn lag_compensated_shot(
+        &self,
+        graph: &Graph,
+        shooter: Handle<Actor>,
+        kind: ProjectileKind,
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+        view_time: f32,
+    ) {
+        let time = view_time.max(self.time - MAX_REWIND_SECS).min(self.time);
+
+        // Walls are not rewound, the shot stops at the first obstacle that is not an actor.
+        let mut query_buffer = Vec::default();
+        graph.physics.cast_ray(
+            RayCastOptions {
+                ray_origin: Point3::from(origin),
+                ray_direction: direction,
+                max_len: 100.0,
+                groups: InteractionGroups::default(),
+                sort_results: true,
+            },
+            &mut query_buffer,
+        );
+        let max_distance = query_buffer
+            .iter()
+            .find(|hit| {
+                let body = graph[hit.collider].parent();
+                self.actors.iter().all(|actor| actor.get_body() != body)
+            })
+            .map_or(100.0, |hit| hit.toi);
+
+        // Dead actors and teammates of the shooter cannot be hit.
+        let shooter_team = self
+            .actors
+            .try_get(shooter)
+            .map_or(Team::None, |actor| actor.team());
+        let target = self
+            .actors
+            .pair_iter()
+            .filter(|&(handle, actor)| {
+                handle != shooter && !actor.is_dead() && is_enemy(shooter_team, actor.team())
+            })
+            .filter_map(|(handle, actor)| {
+                let position = self
+                    .actor_history
+                    .position_at(handle, time)
+                    .unwrap_or_else(|| actor.position(graph));
+                lag_compensation::ray_hit_distance(origin, direction, max_distance, position)
+                    .map(|distance| (handle, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((actor, distance)) = target {
+            let sender = self.sender.as_ref().unwrap();
+            sender
+                .send(Message::DamageActor {
+                    actor,
+                    who: shooter,
+                    amount: Projectile::get_definition(kind).damage,
+                })
+                .unwrap();
+            sender
+                .send(Message::CreateEffect {
+                    kind: EffectKind::BulletImpact,
+                    position: origin + direction.scale(distance),
+                })
+                .unwrap();
         }
     }
 
-    fn show_weapon(&mut self, engine: &mut Engine, weapon_handle: Handle<Weapon>, state: bool) {
-        self.weapons[weapon_handle].set_visibility(state, &mut engine.scenes[self.scene].graph)
+    /// Raises or lowers a weapon, visibility of the weapon is changed by its state machine.
+    fn show_weapon(&mut self, weapon_handle: Handle<Weapon>, state: bool) {
+        if state {
+            self.weapons.raise(weapon_handle);
+        } else {
+            self.weapons[weapon_handle].lower();
+        }
     }
 
     async fn spawn_bot(
         &mut self,
-        engine: &mut Engine,
+        context: &mut LevelContext<'_>,
         kind: BotKind,
         name: Option<String>,
+        team: Team,
+        skill: BotSkill,
     ) -> Handle<Actor> {
         let bot = spawn_bot(
             kind,
             name,
+            team,
+            skill,
             &self.spawn_points,
+            self.options.mode(),
+            &mut self.rng,
             &mut self.actors,
             &mut self.weapons,
-            engine.resource_manager.clone(),
+            context.resource_manager.clone(),
             self.sender.clone().unwrap(),
             &mut self.leader_board,
-            &mut engine.scenes[self.scene],
+            &mut *context.scene,
         )
         .await;
 
@@ -887,7 +1403,7 @@ impl Level {
 
     fn damage_actor(
         &mut self,
-        engine: &Engine,
+        context: &LevelContext<'_>,
         actor: Handle<Actor>,
         who: Handle<Actor>,
         amount: f32,
@@ -896,6 +1412,11 @@ impl Level {
         if self.actors.contains(actor)
             && (who.is_none() || who.is_some() && self.actors.contains(who))
         {
+            // Damage of the environment, like death zones, goes through spawn protection.
+            if who.is_some() && self.actors.get(actor).is_spawn_protected() {
+                return;
+            }
+
             let mut who_name = Default::default();
             let message = if who.is_some() {
                 who_name = self.actors.get(who).name.clone();
@@ -916,7 +1437,7 @@ impl Level {
                 .unwrap();
 
             let who_position = if who.is_some() {
-                let scene = &engine.scenes[self.scene];
+                let scene = &*context.scene;
                 Some(self.actors.get(who).position(&scene.graph))
             } else {
                 None
@@ -929,31 +1450,37 @@ impl Level {
             }
             let was_dead = actor.is_dead();
             actor.damage(amount);
+            let victim_team = actor.team();
             if !was_dead && actor.is_dead() && who.is_some() {
-                self.leader_board.add_frag(who_name)
+                // Killing a teammate is not a frag, neither for the killer nor for the team.
+                let killer_team = self.actors.get(who).team();
+                if is_enemy(killer_team, victim_team) {
+                    self.leader_board.add_frag(who_name);
+                    self.leader_board.add_team_frag(killer_team);
+                }
             }
         }
     }
 
     async fn spawn_item(
         &mut self,
-        engine: &mut Engine,
+        context: &mut LevelContext<'_>,
         kind: ItemKind,
         position: Vector3<f32>,
         adjust_height: bool,
         lifetime: Option<f32>,
     ) {
         let position = if adjust_height {
-            self.pick(engine, position, position - Vector3::new(0.0, 1000.0, 0.0))
+            self.pick(context, position, position - Vector3::new(0.0, 1000.0, 0.0))
         } else {
             position
         };
-        let scene = &mut engine.scenes[self.scene];
+        let scene = &mut *context.scene;
         let mut item = Item::new(
             kind,
             position,
             scene,
-            engine.resource_manager.clone(),
+            context.resource_manager.clone(),
             self.sender.as_ref().unwrap().clone(),
         )
         .await;
@@ -961,10 +1488,36 @@ impl Level {
         self.items.add(item);
     }
 
+    pub fn items(&self) -> &ItemContainer {
+        &self.items
+    }
+
+    pub fn items_mut(&mut self) -> &mut ItemContainer {
+        &mut self.items
+    }
+
+    /// Restores states of weapons which are not saved, see [`WeaponContainer::resolve`].
+    pub fn resolve_weapons(&mut self, graph: &mut Graph) {
+        self.weapons.resolve(&self.actors, graph);
+    }
+
     pub fn time(&self) -> f32 {
         self.time
     }
 
+    /// Network clients follow time of the server, so the match ends at the same moment.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Returns time left until a dead bot with given name will be spawned again.
+    pub fn respawn_time_left(&self, name: &str) -> Option<f32> {
+        self.respawn_list.iter().find_map(|entry| match entry {
+            RespawnEntry::Bot(v) if v.name == name => Some(v.time_left),
+            _ => None,
+        })
+    }
+
     fn update_respawn(&mut self, time: GameTime) {
         // Respawn is done in deferred manner: we just gather all info needed
         // for respawn, wait some time and then re-create actor. Actor is spawned
@@ -981,6 +1534,8 @@ impl Level {
                             .send(Message::SpawnBot {
                                 kind: v.kind,
                                 name: v.name.clone(),
+                                team: v.team,
+                                skill: v.skill,
                             })
                             .unwrap();
                     }
@@ -995,19 +1550,34 @@ impl Level {
                             .unwrap();
                     }
                 }
+                RespawnEntry::RemotePlayer(v) => {
+                    v.time_left -= time.delta;
+                    if v.time_left <= 0.0 {
+                        self.sender
+                            .as_mut()
+                            .unwrap()
+                            .send(Message::SpawnRemotePlayer {
+                                client: v.client,
+                                name: v.name.clone(),
+                                team: v.team,
+                            })
+                            .unwrap();
+                    }
+                }
             }
         }
 
         self.respawn_list.retain(|entry| match entry {
             RespawnEntry::Bot(v) => v.time_left >= 0.0,
             RespawnEntry::Player(v) => v.time_left >= 0.0,
+            RespawnEntry::RemotePlayer(v) => v.time_left >= 0.0,
         });
     }
 
-    fn update_spectator_camera(&mut self, scene: &mut Scene) {
+    fn update_spectator_camera(&mut self, scene: &mut Scene, time: GameTime) {
         if let Some(spectator_camera) = scene.graph[self.spectator_camera].cast_mut::<Camera>() {
             let mut position = spectator_camera.global_position();
-            position.follow(&self.target_spectator_position, 0.1);
+            position.follow(&self.target_spectator_position, time.smoothing(0.1));
             spectator_camera
                 .local_transform_mut()
                 .set_position(position);
@@ -1041,44 +1611,248 @@ impl Level {
         }
     }
 
-    pub fn update(&mut self, engine: &mut Engine, time: GameTime) {
+    fn update_blackboards(&mut self, scene: &Scene, time: GameTime) {
+        let bots = self
+            .actors
+            .pair_iter()
+            .filter(|(_, actor)| matches!(actor, Actor::Bot(_)) && !actor.is_dead())
+            .map(|(handle, actor)| (handle, actor.team(), actor.position(&scene.graph)))
+            .collect::<Vec<_>>();
+        self.blackboards.update(&bots, &self.options, time.elapsed);
+    }
+
+    /// Describes a place on the map using closest item, for example "near Rocket Launcher".
+    fn describe_location(&self, graph: &Graph, position: Vector3<f32>) -> String {
+        self.items
+            .iter()
+            .min_by(|a, b| {
+                a.position(graph)
+                    .metric_distance(&position)
+                    .total_cmp(&b.position(graph).metric_distance(&position))
+            })
+            .map_or_else(
+                || "somewhere".to_owned(),
+                |item| format!("near {}", item.get_kind().description()),
+            )
+    }
+
+    /// Shows team message on HUD, only messages of player's team are visible.
+    fn notify_team(&self, team: Team, text: String) {
+        if team == player_team(&self.options) {
+            self.sender
+                .as_ref()
+                .unwrap()
+                .send(Message::AddNotification { text })
+                .unwrap();
+        }
+    }
+
+    fn report_enemy(
+        &mut self,
+        context: &LevelContext<'_>,
+        reporter: Handle<Actor>,
+        enemy: Handle<Actor>,
+        position: Vector3<f32>,
+        time: GameTime,
+    ) {
+        if self.actors.contains(reporter) && self.actors.contains(enemy) {
+            let team = self.actors.get(reporter).team();
+            if let Some(blackboard) = self.blackboards.get_mut(team) {
+                if blackboard.report_enemy(enemy, position, time.elapsed) {
+                    let graph = &context.scene.graph;
+                    let text = format!(
+                        "{}: Enemy spotted {}",
+                        self.actors.get(reporter).name,
+                        self.describe_location(graph, position)
+                    );
+                    self.notify_team(team, text);
+                }
+            }
+        }
+    }
+
+    fn call_for_help(
+        &mut self,
+        context: &LevelContext<'_>,
+        actor: Handle<Actor>,
+        position: Vector3<f32>,
+        time: GameTime,
+    ) {
+        if self.actors.contains(actor) {
+            let team = self.actors.get(actor).team();
+            if let Some(blackboard) = self.blackboards.get_mut(team) {
+                if blackboard.call_for_help(actor, position, time.elapsed) {
+                    let graph = &context.scene.graph;
+                    let text = format!(
+                        "{}: I need help {}!",
+                        self.actors.get(actor).name,
+                        self.describe_location(graph, position)
+                    );
+                    self.notify_team(team, text);
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, context: &mut LevelContext<'_>, time: GameTime) {
         self.time += time.delta;
         self.update_respawn(time);
-        let scene = &mut engine.scenes[self.scene];
-        self.update_spectator_camera(scene);
+        let scene = &mut *context.scene;
+        self.update_spectator_camera(scene, time);
         self.update_death_zones(scene);
-        self.weapons.update(scene, &self.actors);
+        let shots = self.weapons.update(scene, &self.actors, time);
+        for (weapon, kind) in shots {
+            let direction = self.weapons[weapon].trigger_direction();
+            self.fire_projectile(scene, weapon, kind, Vector3::default(), direction);
+        }
         self.projectiles
             .update(scene, &self.actors, &self.weapons, time);
         self.items.update(scene, time);
+        self.update_blackboards(scene, time);
         let mut ctx = UpdateContext {
             time,
             scene,
             items: &self.items,
             jump_pads: &self.jump_pads,
             weapons: &self.weapons,
+            blackboards: &self.blackboards,
+            rng: &mut self.rng,
+            weapon_rules: self.options.weapon_rules(),
         };
         self.actors.update(&mut ctx);
 
+        // History is needed only to compensate latency of network players.
+        if !self.remote_players.is_empty() {
+            self.actor_history
+                .record(self.time, &self.actors, &context.scene.graph);
+        } else {
+            self.actor_history.clear();
+        }
+
+        self.write_ai_trace(time);
+
         self.update_game_ending();
     }
 
-    pub async fn respawn_actor(&mut self, engine: &mut Engine, actor: Handle<Actor>) {
+    /// Describes state and last decisions of every bot, one line per bot.
+    pub fn ai_report(&self) -> String {
+        let actor_name = |handle: Handle<Actor>| {
+            if self.actors.contains(handle) {
+                self.actors.get(handle).name.as_str()
+            } else {
+                "none"
+            }
+        };
+
+        let mut report = String::new();
+        for (handle, actor) in self.actors.pair_iter() {
+            if let Actor::Bot(bot) = actor {
+                let trace = bot.trace();
+                let (locomotion_state, combat_state) = bot.animation_states();
+                let role = self
+                    .blackboards
+                    .get(bot.team())
+                    .and_then(|blackboard| blackboard.role(handle));
+                let poi = trace.point_of_interest;
+                write!(
+                    report,
+                    "{} [{:?}/{:?}] goal: {:?}, target: {}, poi: ({:.1}, {:.1}, {:.1}), \
+                     states: {}/{}, options: [",
+                    bot.name,
+                    bot.team(),
+                    role,
+                    trace.goal,
+                    actor_name(trace.target),
+                    poi.x,
+                    poi.y,
+                    poi.z,
+                    locomotion_state,
+                    combat_state,
+                )
+                .unwrap();
+                for (i, option) in trace.options.iter().enumerate() {
+                    if i > 0 {
+                        report.push_str(", ");
+                    }
+                    match *option {
+                        ConsideredOption::Target { actor, score } => {
+                            write!(report, "{}: {}", actor_name(actor), Score(score))
+                        }
+                        ConsideredOption::Weapon { kind, score } => {
+                            write!(report, "{:?}: {}", kind, Score(Some(score)))
+                        }
+                    }
+                    .unwrap();
+                }
+                writeln!(report, "], reason: {}", trace.reason).unwrap();
+            }
+        }
+        report
+    }
+
+    pub fn is_ai_trace_enabled(&self) -> bool {
+        self.ai_trace.is_some()
+    }
+
+    /// Enables or disables writing of per-tick AI trace to a file.
+    pub fn set_ai_trace_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.ai_trace = None;
+        } else if self.ai_trace.is_none() {
+            match File::create(AI_TRACE_PATH) {
+                Ok(file) => self.ai_trace = Some(BufWriter::new(file)),
+                Err(e) => Log::writeln(
+                    MessageKind::Error,
+                    format!("Unable to create AI trace file. Reason: {:?}", e),
+                ),
+            }
+        }
+    }
+
+    fn write_ai_trace(&mut self, time: GameTime) {
+        if self.ai_trace.is_some() {
+            let report = self.ai_report();
+            if let Some(trace) = self.ai_trace.as_mut() {
+                let _ = write!(trace, "--- {:.3} s\n{}", time.elapsed, report);
+            }
+        }
+    }
+
+    pub async fn respawn_actor(&mut self, context: &mut LevelContext<'_>, actor: Handle<Actor>) {
         if self.actors.contains(actor) {
             let name = self.actors.get(actor).name.clone();
 
             self.leader_board.add_death(&name);
 
-            let entry = match self.actors.get(actor) {
-                Actor::Bot(bot) => RespawnEntry::Bot(BotRespawnEntry {
+            let remote_client = self.remote_players.iter().find_map(|(&client, &player)| {
+                if player == actor {
+                    Some(client)
+                } else {
+                    None
+                }
+            });
+
+            let entry = match (self.actors.get(actor), remote_client) {
+                // Remote player is viewed on its client, spectator camera is not needed.
+                (Actor::Player(player), Some(client)) => {
+                    RespawnEntry::RemotePlayer(RemotePlayerRespawnEntry {
+                        client,
+                        name,
+                        time_left: RESPAWN_TIME,
+                        team: player.team(),
+                    })
+                }
+                (Actor::Bot(bot), _) => RespawnEntry::Bot(BotRespawnEntry {
                     name,
                     kind: bot.definition().kind,
                     time_left: RESPAWN_TIME,
+                    team: bot.team(),
+                    skill: bot.skill(),
                 }),
-                Actor::Player(player) => {
+                (Actor::Player(player), None) => {
                     // Turn on spectator camera and prepare its target position. Spectator
                     // camera will be used to render world until player is despawned.
-                    let scene = &mut engine.scenes[self.scene];
+                    let scene = &mut *context.scene;
                     let position = scene.graph[player.camera()].global_position();
                     if let Some(spectator_camera) =
                         scene.graph[self.spectator_camera].cast_mut::<Camera>()
@@ -1116,47 +1890,54 @@ impl Level {
                 }
             };
 
-            self.remove_actor(engine, actor).await;
+            self.remove_actor(context, actor).await;
 
             self.respawn_list.push(entry);
         }
     }
 
-    pub async fn handle_message(&mut self, engine: &mut Engine, message: &Message, time: GameTime) {
+    pub async fn handle_message(
+        &mut self,
+        context: &mut LevelContext<'_>,
+        message: &Message,
+        time: GameTime,
+    ) {
         self.sound_manager
             .handle_message(
-                &mut engine.scenes[self.scene].graph,
-                engine.resource_manager.clone(),
+                &mut context.scene.graph,
+                context.resource_manager.clone(),
                 &message,
             )
             .await;
 
         match message {
             &Message::GiveNewWeapon { actor, kind } => {
-                self.give_new_weapon(engine, actor, kind).await;
+                self.give_new_weapon(context, actor, kind, true).await;
             }
             Message::AddBot {
                 kind,
                 position,
                 name,
+                team,
+                skill,
             } => {
-                self.add_bot(engine, *kind, *position, name.clone()).await;
+                self.add_bot(context, *kind, *position, name.clone(), *team, *skill)
+                    .await;
             }
-            &Message::RemoveActor { actor } => self.remove_actor(engine, actor).await,
+            &Message::RemoveActor { actor } => self.remove_actor(context, actor).await,
             &Message::GiveItem { actor, kind } => {
-                self.give_item(engine, actor, kind).await;
+                let amount = Item::default_amount(kind);
+                self.give_item(context, actor, kind, amount).await;
             }
             &Message::PickUpItem { actor, item } => {
-                self.pickup_item(engine, actor, item).await;
+                self.pickup_item(context, actor, item).await;
             }
             &Message::ShootWeapon {
                 weapon,
                 initial_velocity,
                 direction,
-            } => {
-                self.shoot_weapon(engine, weapon, initial_velocity, time, direction)
-                    .await
-            }
+                mode,
+            } => self.shoot_weapon(context, weapon, initial_velocity, time, direction, mode),
             &Message::CreateProjectile {
                 kind,
                 position,
@@ -1166,7 +1947,7 @@ impl Level {
                 basis,
             } => {
                 self.create_projectile(
-                    engine,
+                    context,
                     kind,
                     position,
                     direction,
@@ -1176,23 +1957,36 @@ impl Level {
                 )
                 .await
             }
-            &Message::ShowWeapon { weapon, state } => self.show_weapon(engine, weapon, state),
-            Message::SpawnBot { kind, name } => {
-                self.spawn_bot(engine, *kind, Some(name.clone())).await;
+            &Message::ShowWeapon { weapon, state } => self.show_weapon(weapon, state),
+            Message::SpawnBot {
+                kind,
+                name,
+                team,
+                skill,
+            } => {
+                self.spawn_bot(context, *kind, Some(name.clone()), *team, *skill)
+                    .await;
             }
             &Message::DamageActor { actor, who, amount } => {
-                self.damage_actor(engine, actor, who, amount, time);
+                self.damage_actor(context, actor, who, amount, time);
             }
             &Message::CreateEffect { kind, position } => {
                 effects::create(
                     kind,
-                    &mut engine.scenes[self.scene].graph,
-                    engine.resource_manager.clone(),
+                    &mut context.scene.graph,
+                    context.resource_manager.clone(),
                     position,
                 );
             }
             Message::SpawnPlayer => {
-                self.player = self.spawn_player(engine).await;
+                self.player = self.spawn_player(context).await;
+            }
+            Message::SpawnRemotePlayer { client, name, team } => {
+                self.spawn_remote_player(context, *client, name.clone(), *team)
+                    .await;
+            }
+            &Message::RemoveRemotePlayer { client } => {
+                self.remove_remote_player(context, client).await;
             }
             &Message::SpawnItem {
                 kind,
@@ -1200,14 +1994,26 @@ impl Level {
                 adjust_height,
                 lifetime,
             } => {
-                self.spawn_item(engine, kind, position, adjust_height, lifetime)
+                self.spawn_item(context, kind, position, adjust_height, lifetime)
                     .await
             }
-            &Message::RespawnActor { actor } => self.respawn_actor(engine, actor).await,
+            &Message::RespawnActor { actor } => self.respawn_actor(context, actor).await,
+            &Message::ReportEnemy {
+                reporter,
+                enemy,
+                position,
+            } => self.report_enemy(context, reporter, enemy, position, time),
+            &Message::CallForHelp { actor, position } => {
+                self.call_for_help(context, actor, position, time)
+            }
             _ => (),
         }
     }
 
+    pub fn set_spawn_points(&mut self, spawn_points: Vec<SpawnPoint>) {
+        self.spawn_points = spawn_points;
+    }
+
     pub fn set_message_sender(&mut self, sender: Sender<Message>) {
         self.sender = Some(sender.clone());
 
@@ -1259,7 +2065,126 @@ impl Level {
     }
 }
 
-#[derive(Visit, Default)]
+/// Returns team of the local player, it is always red in team matches.
+pub fn player_team(options: &MatchOptions) -> Team {
+    if options.is_team_match() {
+        Team::Red
+    } else {
+        Team::None
+    }
+}
+
+/// Place where actors appear. Tags of a marker follow its prefix and are separated by
+/// underscores, like `SpawnPoint_Red_CTF_2`: a team (`Red` or `Blue`) and game modes (`DM`,
+/// `TDM` or `CTF`), numbers are ignored. Spawn points without a team are used by everyone and
+/// without modes in every mode. Actors look in the direction of the marker.
+#[derive(Clone, Debug)]
 pub struct SpawnPoint {
     position: Vector3<f32>,
+    team: Team,
+    modes: Vec<GameMode>,
+    /// Rotation around the vertical axis, in radians.
+    yaw: f32,
+}
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        Self {
+            position: Default::default(),
+            team: Team::None,
+            modes: GameMode::ALL.to_vec(),
+            yaw: 0.0,
+        }
+    }
+}
+
+impl Visit for SpawnPoint {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        let mut region = visitor.enter_region(name)?;
+
+        self.position.visit("position", &mut region)?;
+        // Saves of format version 1 have only positions, the rest is restored by migration.
+        if save::has_state_of_version(2) {
+            self.team.visit("team", &mut region)?;
+            self.modes.visit("modes", &mut region)?;
+            self.yaw.visit("yaw", &mut region)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn spawn_point_tags(name: &str) -> impl Iterator<Item = &str> {
+    name.get("SpawnPoint".len()..)
+        .unwrap_or_default()
+        .split('_')
+        .map(|tag| tag.trim_end_matches(|c: char| c.is_ascii_digit()))
+        .filter(|tag| !tag.is_empty())
+}
+
+fn tag_team(tag: &str) -> Option<Team> {
+    [("Red", Team::Red), ("Blue", Team::Blue)]
+        .iter()
+        .find(|(name, _)| tag.eq_ignore_ascii_case(name))
+        .map(|&(_, team)| team)
+}
+
+fn tag_mode(tag: &str) -> Option<GameMode> {
+    [
+        ("DM", GameMode::DeathMatch),
+        ("TDM", GameMode::TeamDeathMatch),
+        ("CTF", GameMode::CaptureTheFlag),
+    ]
+    .iter()
+    .find(|(name, _)| tag.eq_ignore_ascii_case(name))
+    .map(|&(_, mode)| mode)
+}
+
+impl SpawnPoint {
+    pub fn new(name: &str, position: Vector3<f32>, yaw: f32) -> Self {
+        let mut team = Team::None;
+        let mut modes = Vec::new();
+        for tag in spawn_point_tags(name) {
+            if let Some(tag_team) = tag_team(tag) {
+                team = tag_team;
+            } else if let Some(mode) = tag_mode(tag) {
+                modes.push(mode);
+            }
+        }
+        if modes.is_empty() {
+            modes = GameMode::ALL.to_vec();
+        }
+
+        Self {
+            position,
+            team,
+            modes,
+            yaw,
+        }
+    }
+
+    pub fn from_marker(node: &Node) -> Self {
+        let look = node.look_vector();
+        Self::new(node.name(), node.global_position(), look.x.atan2(look.z))
+    }
+
+    /// Returns tags of a marker that are neither a team nor a game mode.
+    pub fn unknown_tags(name: &str) -> Vec<&str> {
+        spawn_point_tags(name)
+            .filter(|tag| tag_team(tag).is_none() && tag_mode(tag).is_none())
+            .collect()
+    }
+
+    pub fn supports(&self, mode: GameMode, team: Team) -> bool {
+        self.modes.contains(&mode) && (self.team == Team::None || self.team == team)
+    }
+}
+
+/// Finds spawn points of a map, global transforms of the scene must be up to date.
+pub fn find_spawn_points(graph: &Graph) -> Vec<SpawnPoint> {
+    graph
+        .linear_iter()
+        .filter(|node| Marker::from_name(node.name()) == Some(Marker::SpawnPoint))
+        .map(SpawnPoint::from_marker)
+        .collect()
 }
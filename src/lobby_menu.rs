@@ -0,0 +1,555 @@
+use crate::{
+    discovery::Discovery,
+    gui::{create_check_box, create_dropdown_items},
+    match_menu::{TEAMS, TEAM_NAMES},
+    message::Message,
+    net::{Lobby, LobbyPlayer, DEFAULT_PORT},
+};
+use fyrox::{
+    core::pool::Handle,
+    gui::{
+        button::{ButtonBuilder, ButtonMessage},
+        check_box::CheckBoxMessage,
+        dropdown_list::{DropdownList, DropdownListBuilder, DropdownListMessage},
+        grid::{Column, GridBuilder, Row},
+        message::{MessageDirection, UiMessage},
+        scroll_viewer::ScrollViewerBuilder,
+        stack_panel::StackPanelBuilder,
+        text::{TextBuilder, TextMessage},
+        text_box::{TextBox, TextBoxBuilder},
+        widget::{WidgetBuilder, WidgetMessage},
+        window::{WindowBuilder, WindowMessage, WindowTitle},
+        Thickness, UiNode, UserInterface, VerticalAlignment,
+    },
+    utils::log::{Log, MessageKind},
+};
+use std::{
+    net::SocketAddr,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+/// Server browser asks servers to describe themselves with this interval.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Server browser with direct connection by address, and the lobby of a joined or hosted
+/// server.
+pub struct LobbyMenu {
+    sender: Sender<Message>,
+    browser_window: Handle<UiNode>,
+    tb_name: Handle<UiNode>,
+    sp_servers: Handle<UiNode>,
+    /// Buttons of discovered servers, a click connects to the server.
+    server_buttons: Vec<(Handle<UiNode>, SocketAddr)>,
+    refresh_button: Handle<UiNode>,
+    tb_address: Handle<UiNode>,
+    connect_button: Handle<UiNode>,
+    browser_status: Handle<UiNode>,
+    discovery: Option<Discovery>,
+    last_refresh_time: Instant,
+    lobby_window: Handle<UiNode>,
+    lobby_title: Handle<UiNode>,
+    sp_players: Handle<UiNode>,
+    player_rows: Vec<Handle<UiNode>>,
+    dl_team: Handle<UiNode>,
+    cb_ready: Handle<UiNode>,
+    start_button: Handle<UiNode>,
+    leave_button: Handle<UiNode>,
+    lobby_status: Handle<UiNode>,
+    /// Last shown state of the lobby, `None` if the lobby window is closed.
+    lobby: Option<Lobby>,
+    ready: bool,
+}
+
+impl LobbyMenu {
+    pub fn new(ui: &mut UserInterface, sender: Sender<Message>) -> Self {
+        let common_row = Row::strict(36.0);
+
+        let ctx = &mut ui.build_ctx();
+        let tb_name;
+        let sp_servers;
+        let refresh_button;
+        let tb_address;
+        let connect_button;
+        let browser_status;
+        let browser_window = WindowBuilder::new(WidgetBuilder::new().with_width(700.0))
+            .with_title(WindowTitle::text("Multiplayer"))
+            .open(false)
+            .with_content(
+                GridBuilder::new(
+                    WidgetBuilder::new()
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(0).on_column(0))
+                                .with_text("Player Name")
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx),
+                        )
+                        .with_child({
+                            tb_name = TextBoxBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(0)
+                                    .on_column(1)
+                                    .with_margin(Thickness::uniform(2.0)),
+                            )
+                            .with_text("Unnamed Player".to_owned())
+                            .with_vertical_text_alignment(VerticalAlignment::Center)
+                            .build(ctx);
+                            tb_name
+                        })
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(1).on_column(0))
+                                .with_text("Local Servers")
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx),
+                        )
+                        .with_child({
+                            refresh_button =
+                                ButtonBuilder::new(WidgetBuilder::new().on_row(1).on_column(1))
+                                    .with_text("Refresh")
+                                    .build(ctx);
+                            refresh_button
+                        })
+                        .with_child(
+                            ScrollViewerBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(2)
+                                    .on_column(1)
+                                    .with_height(200.0),
+                            )
+                            .with_content({
+                                sp_servers =
+                                    StackPanelBuilder::new(WidgetBuilder::new()).build(ctx);
+                                sp_servers
+                            })
+                            .build(ctx),
+                        )
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(3).on_column(0))
+                                .with_text("Address")
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx),
+                        )
+                        .with_child({
+                            tb_address = TextBoxBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(3)
+                                    .on_column(1)
+                                    .with_margin(Thickness::uniform(2.0)),
+                            )
+                            .with_text(format!("127.0.0.1:{}", DEFAULT_PORT))
+                            .with_vertical_text_alignment(VerticalAlignment::Center)
+                            .build(ctx);
+                            tb_address
+                        })
+                        .with_child({
+                            connect_button =
+                                ButtonBuilder::new(WidgetBuilder::new().on_row(4).on_column(1))
+                                    .with_text("Connect")
+                                    .build(ctx);
+                            connect_button
+                        })
+                        .with_child({
+                            browser_status =
+                                TextBuilder::new(WidgetBuilder::new().on_row(5).on_column(1))
+                                    .build(ctx);
+                            browser_status
+                        }),
+                )
+                .add_column(Column::strict(200.0))
+                .add_column(Column::stretch())
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(Row::auto())
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(Row::stretch())
+                .build(ctx),
+            )
+            .build(ctx);
+
+        let lobby_title;
+        let sp_players;
+        let dl_team;
+        let cb_ready;
+        let start_button;
+        let leave_button;
+        let lobby_status;
+        let lobby_window = WindowBuilder::new(WidgetBuilder::new().with_width(500.0))
+            .with_title(WindowTitle::text("Lobby"))
+            .open(false)
+            .can_close(false)
+            .with_content(
+                GridBuilder::new(
+                    WidgetBuilder::new()
+                        .with_child({
+                            lobby_title = TextBuilder::new(WidgetBuilder::new().on_row(0))
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx);
+                            lobby_title
+                        })
+                        .with_child(
+                            ScrollViewerBuilder::new(
+                                WidgetBuilder::new().on_row(1).with_height(200.0),
+                            )
+                            .with_content({
+                                sp_players =
+                                    StackPanelBuilder::new(WidgetBuilder::new()).build(ctx);
+                                sp_players
+                            })
+                            .build(ctx),
+                        )
+                        .with_child(
+                            GridBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(2)
+                                    .with_child(
+                                        TextBuilder::new(WidgetBuilder::new().on_column(0))
+                                            .with_text("Team")
+                                            .with_vertical_text_alignment(VerticalAlignment::Center)
+                                            .build(ctx),
+                                    )
+                                    .with_child({
+                                        dl_team = DropdownListBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(1)
+                                                .with_margin(Thickness::uniform(2.0)),
+                                        )
+                                        .with_items(create_dropdown_items(ctx, &TEAM_NAMES))
+                                        .with_selected(0)
+                                        .build(ctx);
+                                        dl_team
+                                    })
+                                    .with_child(
+                                        TextBuilder::new(WidgetBuilder::new().on_column(2))
+                                            .with_text("Ready")
+                                            .with_vertical_text_alignment(VerticalAlignment::Center)
+                                            .build(ctx),
+                                    )
+                                    .with_child({
+                                        cb_ready = create_check_box(ctx, 0, 3, false);
+                                        cb_ready
+                                    }),
+                            )
+                            .add_column(Column::strict(60.0))
+                            .add_column(Column::stretch())
+                            .add_column(Column::strict(60.0))
+                            .add_column(Column::strict(30.0))
+                            .add_row(Row::stretch())
+                            .build(ctx),
+                        )
+                        .with_child({
+                            start_button = ButtonBuilder::new(WidgetBuilder::new().on_row(3))
+                                .with_text("Start Match")
+                                .build(ctx);
+                            start_button
+                        })
+                        .with_child({
+                            leave_button = ButtonBuilder::new(WidgetBuilder::new().on_row(4))
+                                .with_text("Leave")
+                                .build(ctx);
+                            leave_button
+                        })
+                        .with_child({
+                            lobby_status = TextBuilder::new(WidgetBuilder::new().on_row(5))
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx);
+                            lobby_status
+                        }),
+                )
+                .add_column(Column::stretch())
+                .add_row(common_row)
+                .add_row(Row::auto())
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(common_row)
+                .build(ctx),
+            )
+            .build(ctx);
+
+        Self {
+            sender,
+            browser_window,
+            tb_name,
+            sp_servers,
+            server_buttons: Default::default(),
+            refresh_button,
+            tb_address,
+            connect_button,
+            browser_status,
+            discovery: None,
+            last_refresh_time: Instant::now(),
+            lobby_window,
+            lobby_title,
+            sp_players,
+            player_rows: Default::default(),
+            dl_team,
+            cb_ready,
+            start_button,
+            leave_button,
+            lobby_status,
+            lobby: None,
+            ready: false,
+        }
+    }
+
+    pub fn open_browser(&mut self, ui: &mut UserInterface) {
+        ui.send_message(WindowMessage::open(
+            self.browser_window,
+            MessageDirection::ToWidget,
+            true,
+        ));
+
+        match Discovery::new() {
+            Ok(mut discovery) => {
+                discovery.refresh();
+                self.last_refresh_time = Instant::now();
+                self.discovery = Some(discovery);
+            }
+            Err(e) => Log::writeln(
+                MessageKind::Error,
+                format!("Failed to start server discovery, reason: {}", e),
+            ),
+        }
+    }
+
+    /// Shows a message under the server browser, like connection errors.
+    pub fn set_status(&self, ui: &mut UserInterface, text: &str) {
+        ui.send_message(TextMessage::text(
+            self.browser_status,
+            MessageDirection::ToWidget,
+            text.to_owned(),
+        ));
+    }
+
+    pub fn set_lobby_status(&self, ui: &mut UserInterface, text: &str) {
+        ui.send_message(TextMessage::text(
+            self.lobby_status,
+            MessageDirection::ToWidget,
+            text.to_owned(),
+        ));
+    }
+
+    /// Must be called every frame, it collects answers of servers.
+    pub fn update(&mut self, ui: &mut UserInterface) {
+        let discovery = match self.discovery.as_mut() {
+            Some(discovery) => discovery,
+            None => return,
+        };
+
+        if self.last_refresh_time.elapsed() > REFRESH_INTERVAL {
+            self.last_refresh_time = Instant::now();
+            discovery.refresh();
+        }
+
+        if !discovery.poll() {
+            return;
+        }
+
+        for (button, _) in self.server_buttons.drain(..) {
+            ui.send_message(WidgetMessage::remove(button, MessageDirection::ToWidget));
+        }
+        for server in discovery.servers() {
+            let text = format!(
+                "{} | {} | {} | {} players{} | {} ms",
+                server.info.name,
+                server.info.map,
+                server.info.options.name(),
+                server.info.players,
+                if server.info.in_lobby { " (lobby)" } else { "" },
+                server.ping.as_millis()
+            );
+            let button = ButtonBuilder::new(
+                WidgetBuilder::new()
+                    .with_height(30.0)
+                    .with_margin(Thickness::uniform(1.0)),
+            )
+            .with_text(&text)
+            .build(&mut ui.build_ctx());
+            ui.send_message(WidgetMessage::link(
+                button,
+                MessageDirection::ToWidget,
+                self.sp_servers,
+            ));
+            self.server_buttons.push((button, server.address));
+        }
+    }
+
+    /// Opens the lobby window or updates it. Host starts the match, other players choose
+    /// their teams and get ready.
+    pub fn show_lobby(&mut self, ui: &mut UserInterface, lobby: &Lobby, is_host: bool) {
+        if self.lobby.is_none() {
+            ui.send_message(WindowMessage::close(
+                self.browser_window,
+                MessageDirection::ToWidget,
+            ));
+            ui.send_message(WindowMessage::open(
+                self.lobby_window,
+                MessageDirection::ToWidget,
+                true,
+            ));
+            self.discovery = None;
+            self.ready = false;
+            ui.send_message(CheckBoxMessage::checked(
+                self.cb_ready,
+                MessageDirection::ToWidget,
+                Some(false),
+            ));
+            self.set_lobby_status(ui, "");
+
+            ui.send_message(WidgetMessage::visibility(
+                self.start_button,
+                MessageDirection::ToWidget,
+                is_host,
+            ));
+            for widget in [self.dl_team, self.cb_ready] {
+                ui.send_message(WidgetMessage::enabled(
+                    widget,
+                    MessageDirection::ToWidget,
+                    !is_host,
+                ));
+            }
+        }
+
+        ui.send_message(TextMessage::text(
+            self.lobby_title,
+            MessageDirection::ToWidget,
+            format!("{} - {}", lobby.server_name, lobby.options.name()),
+        ));
+
+        let players_changed = self
+            .lobby
+            .as_ref()
+            .map_or(true, |shown| shown.players != lobby.players);
+        if players_changed {
+            for row in self.player_rows.drain(..) {
+                ui.send_message(WidgetMessage::remove(row, MessageDirection::ToWidget));
+            }
+            for player in lobby.players.iter() {
+                let row = TextBuilder::new(WidgetBuilder::new().with_height(30.0))
+                    .with_text(describe_player(player, lobby.options.is_team_match()))
+                    .with_vertical_text_alignment(VerticalAlignment::Center)
+                    .build(&mut ui.build_ctx());
+                ui.send_message(WidgetMessage::link(
+                    row,
+                    MessageDirection::ToWidget,
+                    self.sp_players,
+                ));
+                self.player_rows.push(row);
+            }
+        }
+
+        self.lobby = Some(lobby.clone());
+    }
+
+    pub fn close_lobby(&mut self, ui: &mut UserInterface) {
+        ui.send_message(WindowMessage::close(
+            self.lobby_window,
+            MessageDirection::ToWidget,
+        ));
+        self.lobby = None;
+    }
+
+    pub fn close(&mut self, ui: &mut UserInterface) {
+        ui.send_message(WindowMessage::close(
+            self.browser_window,
+            MessageDirection::ToWidget,
+        ));
+        self.discovery = None;
+        self.close_lobby(ui);
+    }
+
+    fn player_name(&self, ui: &UserInterface) -> String {
+        ui.node(self.tb_name)
+            .cast::<TextBox>()
+            .map(|text_box| text_box.text().trim().to_owned())
+            .unwrap_or_default()
+    }
+
+    fn send_choice(&self, ui: &UserInterface) {
+        let team = ui
+            .node(self.dl_team)
+            .cast::<DropdownList>()
+            .and_then(|dropdown_list| dropdown_list.selection())
+            .unwrap_or_default();
+        self.sender
+            .send(Message::SetReady {
+                team: TEAMS[team.min(TEAMS.len() - 1)],
+                ready: self.ready,
+            })
+            .unwrap();
+    }
+
+    fn connect(&self, ui: &mut UserInterface, address: String) {
+        self.set_status(ui, &format!("Connecting to {}...", address));
+        self.sender
+            .send(Message::ConnectToServer {
+                address,
+                name: self.player_name(ui),
+            })
+            .unwrap();
+    }
+
+    pub fn handle_ui_event(&mut self, ui: &mut UserInterface, message: &UiMessage) {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.refresh_button {
+                if let Some(discovery) = self.discovery.as_mut() {
+                    discovery.refresh();
+                    self.last_refresh_time = Instant::now();
+                }
+            } else if message.destination() == self.connect_button {
+                let address = ui
+                    .node(self.tb_address)
+                    .cast::<TextBox>()
+                    .map(|text_box| text_box.text().trim().to_owned())
+                    .unwrap_or_default();
+                self.connect(ui, address);
+            } else if message.destination() == self.start_button {
+                self.sender.send(Message::StartHostedMatch).unwrap();
+            } else if message.destination() == self.leave_button {
+                self.sender.send(Message::LeaveLobby).unwrap();
+                self.close_lobby(ui);
+                self.open_browser(ui);
+            } else if let Some(&(_, address)) = self
+                .server_buttons
+                .iter()
+                .find(|(button, _)| *button == message.destination())
+            {
+                self.connect(ui, address.to_string());
+            }
+        } else if let Some(CheckBoxMessage::Check(value)) = message.data() {
+            if message.destination() == self.cb_ready
+                && message.direction() == MessageDirection::FromWidget
+            {
+                self.ready = value.unwrap_or(false);
+                self.send_choice(ui);
+            }
+        } else if let Some(DropdownListMessage::SelectionChanged(_)) = message.data() {
+            if message.destination() == self.dl_team
+                && message.direction() == MessageDirection::FromWidget
+            {
+                self.send_choice(ui);
+            }
+        } else if let Some(WindowMessage::Close) = message.data() {
+            // Servers are not discovered when nobody looks at them.
+            if message.destination() == self.browser_window
+                && message.direction() == MessageDirection::FromWidget
+            {
+                self.discovery = None;
+            }
+        }
+    }
+}
+
+fn describe_player(player: &LobbyPlayer, is_team_match: bool) -> String {
+    let mut text = player.name.clone();
+    if is_team_match {
+        let team = TEAMS.iter().position(|t| *t == player.team).unwrap_or(0);
+        text += &format!(" - {}", TEAM_NAMES[team]);
+    }
+    if player.ready {
+        text += " - Ready";
+    }
+    text
+}
@@ -5,31 +5,66 @@ extern crate crossbeam;
 extern crate fyrox;
 
 mod actor;
+mod ai_debug;
+mod blackboard;
 mod bot;
 mod character;
+mod chat;
+mod client;
+mod config;
 mod control_scheme;
+mod demo;
+mod discovery;
 mod effects;
 mod gui;
+mod headless;
 mod hud;
 mod item;
 mod jump_pad;
+mod lag_compensation;
 mod leader_board;
 mod level;
+mod lobby_menu;
+mod map;
 mod match_menu;
 mod menu;
 mod message;
+mod net;
 mod options_menu;
 mod player;
 mod projectile;
+mod roster;
+mod save;
+mod save_menu;
+mod server;
+#[cfg(test)]
+mod tests;
+mod validator;
 mod weapon;
 
 use crate::{
-    actor::Actor, control_scheme::ControlScheme, hud::Hud, level::Level, menu::Menu,
+    actor::Actor,
+    ai_debug::AiInspector,
+    chat::{ChatChannel, ChatInputEvent, ChatLimiter},
+    client::Client,
+    config::Config,
+    control_scheme::ControlScheme,
+    demo::{Demo, DemoPlayback, DEMO_PATH},
+    hud::Hud,
+    level::{Level, LevelContext},
+    map::{self, MapPath, MapRotation, MAPS_DIR},
+    menu::Menu,
     message::Message,
+    net::{NetworkConditions, ServerPacket},
+    roster::BotRoster,
+    save::{Thumbnail, AUTOSAVE_INTERVAL, AUTOSAVE_SLOT},
+    server::{Server, DEFAULT_SERVER_NAME},
+    weapon::WeaponRules,
 };
 use fyrox::window::CursorGrabMode;
 use fyrox::{
     core::{
+        algebra::Vector3,
         futures::executor::block_on,
         pool::Handle,
         visitor::{Visit, VisitResult, Visitor},
@@ -49,13 +84,14 @@ use fyrox::{
         base::BaseBuilder,
         node::Node,
         sound::{SoundBuilder, Status},
-        Scene, SceneLoader,
+        Scene,
     },
     utils::{
         log::{Log, MessageKind},
         translate_event,
     },
 };
+use serde::{Deserialize, Serialize};
 use std::{
     fs::File,
     io::Write,
@@ -64,7 +100,7 @@ use std::{
         mpsc::{self, Receiver, Sender},
         Arc, Mutex, RwLock,
     },
-    time::{self, Instant},
+    time::Instant,
 };
 
 const FIXED_FPS: f32 = 60.0;
@@ -76,9 +112,29 @@ pub struct Game {
     level: Option<Level>,
     debug_text: Handle<UiNode>,
     debug_string: String,
+    ai_inspector: AiInspector,
     running: bool,
     control_scheme: Arc<RwLock<ControlScheme>>,
     time: GameTime,
+    /// Wall clock time of the last update, it is used only to decide how many fixed steps
+    /// simulation should do to catch up with real time.
+    last_update_time: Instant,
+    lag: f64,
+    /// Recording of current match, it is saved when the level is destroyed.
+    recorder: Option<Demo>,
+    playback: Option<DemoPlayback>,
+    /// Connection to a server, level of the game shows the match of the server.
+    client: Option<Client>,
+    /// Listen server that lets other players join matches of the game.
+    server: Option<Server>,
+    /// Port of listen server, it is started for every new match if set.
+    host_port: Option<u16>,
+    /// Simulated latency and packet loss of the client or the listen server.
+    network_conditions: NetworkConditions,
+    chat_limiter: ChatLimiter,
+    /// Time of the match when it was saved last time.
+    last_autosave_time: f32,
+    map_rotation: MapRotation,
     events_receiver: Receiver<Message>,
     events_sender: Sender<Message>,
     load_context: Option<Arc<Mutex<LoadContext>>>,
@@ -136,13 +192,33 @@ impl LoadingScreen {
     }
 }
 
+/// Simulation time. It advances only in fixed steps and does not depend on wall clock, so
+/// the same inputs always produce the same simulation.
 #[derive(Copy, Clone)]
 pub struct GameTime {
-    clock: time::Instant,
     elapsed: f64,
     delta: f32,
 }
 
+impl GameTime {
+    pub fn new(delta: f32) -> Self {
+        Self {
+            elapsed: 0.0,
+            delta,
+        }
+    }
+
+    pub fn advance(&mut self) {
+        self.elapsed += self.delta as f64;
+    }
+
+    /// Converts smoothing factor that was tuned for one step at [`FIXED_FPS`] to the factor
+    /// for current time step, so smoothing has the same speed with any time step.
+    pub fn smoothing(&self, factor_per_step: f32) -> f32 {
+        1.0 - (1.0 - factor_per_step).powf(self.delta * FIXED_FPS)
+    }
+}
+
 // Disable false-positive lint, isize *is* portable.
 #[allow(clippy::enum_clike_unportable_variant)]
 pub enum CollisionGroups {
@@ -152,25 +228,63 @@ pub enum CollisionGroups {
     All = std::isize::MAX,
 }
 
-#[derive(Copy, Clone, Debug, Visit, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Visit, Serialize, Deserialize)]
+pub enum GameMode {
+    DeathMatch,
+    TeamDeathMatch,
+    CaptureTheFlag,
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::DeathMatch
+    }
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 3] = [
+        GameMode::DeathMatch,
+        GameMode::TeamDeathMatch,
+        GameMode::CaptureTheFlag,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::DeathMatch => "Deathmatch",
+            GameMode::TeamDeathMatch => "Team Deathmatch",
+            GameMode::CaptureTheFlag => "Capture The Flag",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Visit, Default)]
 pub struct DeathMatch {
     pub time_limit_secs: f32,
     pub frag_limit: u32,
+    pub roster: BotRoster,
+    pub map: MapPath,
+    pub weapon_rules: WeaponRules,
 }
 
-#[derive(Copy, Clone, Debug, Visit, Default)]
+#[derive(Clone, Debug, Visit, Default)]
 pub struct TeamDeathMatch {
     pub time_limit_secs: f32,
     pub team_frag_limit: u32,
+    pub roster: BotRoster,
+    pub map: MapPath,
+    pub weapon_rules: WeaponRules,
 }
 
-#[derive(Copy, Clone, Debug, Visit, Default)]
+#[derive(Clone, Debug, Visit, Default)]
 pub struct CaptureTheFlag {
     pub time_limit_secs: f32,
     pub flag_limit: u32,
+    pub roster: BotRoster,
+    pub map: MapPath,
+    pub weapon_rules: WeaponRules,
 }
 
-#[derive(Copy, Clone, Debug, Visit)]
+#[derive(Clone, Debug, Visit)]
 pub enum MatchOptions {
     DeathMatch(DeathMatch),
     TeamDeathMatch(TeamDeathMatch),
@@ -183,12 +297,124 @@ impl Default for MatchOptions {
     }
 }
 
+impl MatchOptions {
+    /// Creates options of a match of given mode, `limit` is the frag limit, the team frag limit
+    /// or the flag limit depending on the mode.
+    pub fn new(
+        mode: GameMode,
+        time_limit_secs: f32,
+        limit: u32,
+        roster: BotRoster,
+        map: MapPath,
+    ) -> Self {
+        match mode {
+            GameMode::DeathMatch => MatchOptions::DeathMatch(DeathMatch {
+                time_limit_secs,
+                frag_limit: limit,
+                roster,
+                map,
+                weapon_rules: Default::default(),
+            }),
+            GameMode::TeamDeathMatch => MatchOptions::TeamDeathMatch(TeamDeathMatch {
+                time_limit_secs,
+                team_frag_limit: limit,
+                roster,
+                map,
+                weapon_rules: Default::default(),
+            }),
+            GameMode::CaptureTheFlag => MatchOptions::CaptureTheFlag(CaptureTheFlag {
+                time_limit_secs,
+                flag_limit: limit,
+                roster,
+                map,
+                weapon_rules: Default::default(),
+            }),
+        }
+    }
+
+    pub fn mode(&self) -> GameMode {
+        match self {
+            MatchOptions::DeathMatch(_) => GameMode::DeathMatch,
+            MatchOptions::TeamDeathMatch(_) => GameMode::TeamDeathMatch,
+            MatchOptions::CaptureTheFlag(_) => GameMode::CaptureTheFlag,
+        }
+    }
+
+    pub fn map(&self) -> &MapPath {
+        match self {
+            MatchOptions::DeathMatch(dm) => &dm.map,
+            MatchOptions::TeamDeathMatch(tdm) => &tdm.map,
+            MatchOptions::CaptureTheFlag(ctf) => &ctf.map,
+        }
+    }
+
+    pub fn set_map(&mut self, map: MapPath) {
+        match self {
+            MatchOptions::DeathMatch(dm) => dm.map = map,
+            MatchOptions::TeamDeathMatch(tdm) => tdm.map = map,
+            MatchOptions::CaptureTheFlag(ctf) => ctf.map = map,
+        }
+    }
+
+    pub fn weapon_rules(&self) -> WeaponRules {
+        match self {
+            MatchOptions::DeathMatch(dm) => dm.weapon_rules,
+            MatchOptions::TeamDeathMatch(tdm) => tdm.weapon_rules,
+            MatchOptions::CaptureTheFlag(ctf) => ctf.weapon_rules,
+        }
+    }
+
+    pub fn set_weapon_rules(&mut self, weapon_rules: WeaponRules) {
+        match self {
+            MatchOptions::DeathMatch(dm) => dm.weapon_rules = weapon_rules,
+            MatchOptions::TeamDeathMatch(tdm) => tdm.weapon_rules = weapon_rules,
+            MatchOptions::CaptureTheFlag(ctf) => ctf.weapon_rules = weapon_rules,
+        }
+    }
+
+    pub fn is_team_match(&self) -> bool {
+        match self {
+            MatchOptions::DeathMatch(_) => false,
+            MatchOptions::TeamDeathMatch(_) | MatchOptions::CaptureTheFlag(_) => true,
+        }
+    }
+
+    pub fn roster(&self) -> &BotRoster {
+        match self {
+            MatchOptions::DeathMatch(dm) => &dm.roster,
+            MatchOptions::TeamDeathMatch(tdm) => &tdm.roster,
+            MatchOptions::CaptureTheFlag(ctf) => &ctf.roster,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.mode().name()
+    }
+}
+
+/// What the game does after start.
+pub enum StartMode {
+    Menu,
+    Demo(Demo),
+    Connect {
+        address: String,
+        name: String,
+        conditions: NetworkConditions,
+    },
+    /// Shows main menu, matches that are started from it are hosted on given port.
+    Host {
+        port: u16,
+        conditions: NetworkConditions,
+    },
+}
+
 pub struct LoadContext {
     level: Option<(Level, Scene)>,
 }
 
 impl Game {
-    pub fn run() {
+    /// Runs the game until its window is closed.
+    pub fn run(start_mode: StartMode) {
         let events_loop = EventLoop::<()>::new();
 
         let primary_monitor = events_loop.primary_monitor().unwrap();
@@ -212,15 +438,22 @@ impl Game {
         })
         .unwrap();
 
-        let control_scheme = Arc::new(RwLock::new(ControlScheme::default()));
+        let config = Config::load();
+        if let Err(err) = engine.renderer.set_quality_settings(&config.graphics) {
+            Log::writeln(
+                MessageKind::Error,
+                format!("Failed to set renderer quality settings! Reason: {:?}", err),
+            );
+        }
+        engine.set_sound_gain(config.sound_volume);
+        let window = engine.get_window();
+        window.set_fullscreen(config.window_fullscreen(window.current_monitor()));
+
+        let control_scheme = Arc::new(RwLock::new(config.controls));
 
         let fixed_timestep = 1.0 / FIXED_FPS;
 
-        let time = GameTime {
-            clock: Instant::now(),
-            elapsed: 0.0,
-            delta: fixed_timestep,
-        };
+        let time = GameTime::new(fixed_timestep);
 
         let (tx, rx) = mpsc::channel();
         let buffer = fyrox::core::futures::executor::block_on(
@@ -235,7 +468,7 @@ impl Game {
             .with_buffer(Some(buffer))
             .with_looping(true)
             .with_status(Status::Playing)
-            .with_gain(0.25)
+            .with_gain(config.music_volume)
             .build(&mut menu_scene.graph);
 
         let mut game = Game {
@@ -246,15 +479,33 @@ impl Game {
             ),
             menu_scene: engine.scenes.add(menu_scene),
             music,
-            hud: Hud::new(&mut engine),
+            hud: Hud::new(&mut engine, config.item_announcements),
+            ai_inspector: AiInspector::new(&mut engine.user_interface),
             running: true,
-            menu: Menu::new(&mut engine, control_scheme.clone(), tx.clone()),
+            menu: Menu::new(
+                &mut engine,
+                control_scheme.clone(),
+                tx.clone(),
+                config.music_volume,
+                config.item_announcements,
+            ),
             control_scheme,
             debug_text: Handle::NONE,
             engine,
             level: None,
             debug_string: String::new(),
             time,
+            last_update_time: Instant::now(),
+            lag: 0.0,
+            recorder: None,
+            playback: None,
+            client: None,
+            server: None,
+            host_port: None,
+            network_conditions: Default::default(),
+            chat_limiter: Default::default(),
+            last_autosave_time: 0.0,
+            map_rotation: MapRotation::new(map::discover(Path::new(MAPS_DIR))),
             events_receiver: rx,
             events_sender: tx,
             load_context: None,
@@ -262,22 +513,40 @@ impl Game {
 
         game.create_debug_ui();
 
+        match start_mode {
+            StartMode::Menu => (),
+            StartMode::Demo(demo) => game.start_playback(demo),
+            StartMode::Connect {
+                address,
+                name,
+                conditions,
+            } => {
+                game.network_conditions = conditions;
+                game.connect(&address, name);
+            }
+            StartMode::Host { port, conditions } => {
+                game.network_conditions = conditions;
+                game.host_port = Some(port);
+            }
+        }
+
         events_loop.run(move |event, _, control_flow| {
             game.process_input_event(&event);
 
             match event {
                 Event::MainEventsCleared => {
-                    let mut dt = game.time.clock.elapsed().as_secs_f64() - game.time.elapsed;
-                    while dt >= fixed_timestep as f64 {
-                        dt -= fixed_timestep as f64;
-                        game.time.elapsed += fixed_timestep as f64;
-
-                        game.update(game.time, control_flow);
-
-                        while let Some(ui_event) = game.engine.user_interface.poll_message() {
-                            game.menu.handle_ui_event(&mut game.engine, &ui_event);
-                        }
+                    let now = Instant::now();
+                    let frame_time = (now - game.last_update_time).as_secs_f32();
+                    game.lag += frame_time as f64 * game.time_scale() as f64;
+                    game.last_update_time = now;
+                    while game.lag >= fixed_timestep as f64 {
+                        game.lag -= fixed_timestep as f64;
+                        game.step(control_flow);
+                    }
+                    while game.is_fast_forwarding() {
+                        game.step(control_flow);
                     }
+                    game.update_free_camera(frame_time);
                     if !game.running {
                         *control_flow = ControlFlow::Exit;
                     }
@@ -286,8 +555,8 @@ impl Game {
                 Event::RedrawRequested(_) => {
                     game.update_statistics(game.time.elapsed);
 
-                    // <<<<< ENABLE THIS TO SHOW DEBUG GEOMETRY >>>>>
-                    if false {
+                    // Debug geometry is shown together with AI inspector (F3).
+                    if game.ai_inspector.is_visible() {
                         game.debug_render();
                     }
 
@@ -316,9 +585,73 @@ impl Game {
         });
     }
 
+    fn step(&mut self, control_flow: &mut ControlFlow) {
+        self.time.advance();
+
+        self.update(self.time, control_flow);
+
+        while let Some(ui_event) = self.engine.user_interface.poll_message() {
+            self.menu.handle_ui_event(&mut self.engine, &ui_event);
+        }
+    }
+
+    /// How fast simulation goes relative to real time, it is changed only by demo playback.
+    fn time_scale(&self) -> f32 {
+        self.playback
+            .as_ref()
+            .map_or(1.0, |playback| playback.time_scale())
+    }
+
+    fn is_fast_forwarding(&mut self) -> bool {
+        self.level.is_some()
+            && self
+                .playback
+                .as_mut()
+                .map_or(false, |playback| playback.is_fast_forwarding())
+    }
+
+    fn update_free_camera(&mut self, dt: f32) {
+        if let (Some(level), Some(playback)) = (self.level.as_ref(), self.playback.as_mut()) {
+            if let Some(free_camera) = playback.free_camera_mut() {
+                let scene = &mut self.engine.scenes[level.scene];
+                if free_camera.is_enabled(scene) {
+                    // Game cameras are re-enabled when player respawns.
+                    level.set_game_cameras_enabled(scene, false);
+                    free_camera.update(scene, dt);
+                }
+            }
+        }
+    }
+
+    fn toggle_free_camera(&mut self) {
+        if let (Some(level), Some(playback)) = (self.level.as_ref(), self.playback.as_mut()) {
+            if let Some(free_camera) = playback.free_camera_mut() {
+                let scene = &mut self.engine.scenes[level.scene];
+                let enabled = !free_camera.is_enabled(scene);
+                let player = level.get_player();
+                let position = if let Some(Actor::Player(player)) = level.actors().try_get(player) {
+                    scene.graph[player.camera()].global_position()
+                } else {
+                    Vector3::new(0.0, 5.0, 0.0)
+                };
+                free_camera.set_enabled(scene, enabled, position);
+                level.set_game_cameras_enabled(scene, !enabled);
+            }
+        }
+    }
+
     fn debug_render(&mut self) {
         if let Some(level) = self.level.as_mut() {
             level.debug_draw(&mut self.engine);
+            let report = format!(
+                "{}\nItem respawn timers:\n{}",
+                level.ai_report(),
+                level
+                    .items()
+                    .respawn_report(&self.engine.scenes[level.scene].graph)
+            );
+            self.ai_inspector
+                .set_report(report, &mut self.engine.user_interface);
         }
     }
 
@@ -327,52 +660,54 @@ impl Game {
             .build(&mut self.engine.user_interface.build_ctx());
     }
 
-    pub fn save_game(&mut self) -> VisitResult {
-        if let Some(level) = self.level.as_mut() {
-            let mut visitor = Visitor::new();
-
-            self.engine.scenes[level.scene].save("Scene", &mut visitor)?;
-            level.visit("Level", &mut visitor)?;
-
-            // Debug output
-            if let Ok(mut file) = File::create(Path::new("save.txt")) {
-                file.write_all(visitor.save_text().as_bytes()).unwrap();
+    /// Saves the match to a slot, only single player matches can be saved.
+    pub fn save_game(&mut self, slot: &str) -> Result<(), String> {
+        let level = match self.level.as_mut() {
+            Some(level)
+                if self.client.is_none() && self.server.is_none() && self.playback.is_none() =>
+            {
+                level
             }
+            Some(_) => return Err("Only single player matches can be saved".to_owned()),
+            None => return Err("There is no match to save".to_owned()),
+        };
 
-            visitor.save_binary(Path::new("save.bin"))
-        } else {
-            Ok(())
-        }
-    }
+        let thumbnail = Thumbnail::capture(&mut self.engine.renderer, level.scene);
+        let scene = &mut self.engine.scenes[level.scene];
+        save::write(slot, level, scene, thumbnail)
+            .map_err(|e| format!("Unable to save the game: {}", e))?;
+        self.last_autosave_time = level.time();
 
-    pub fn load_game(&mut self) -> VisitResult {
         Log::writeln(
             MessageKind::Information,
-            "Attempting load a save...".to_owned(),
+            format!("Game saved to slot {}", slot),
         );
 
-        let mut visitor = block_on(Visitor::load_binary(Path::new("save.bin")))?;
-
-        // Clean up.
-        self.destroy_level();
+        Ok(())
+    }
 
-        // Load engine state first
+    /// Loads the match from a slot. Current match goes on if the save cannot be loaded.
+    pub fn load_game(&mut self, slot: &str) -> Result<(), String> {
         Log::writeln(
             MessageKind::Information,
-            "Trying to load a save file...".to_owned(),
+            format!("Attempting to load save {}...", slot),
         );
 
-        let scene = block_on(
-            SceneLoader::load(
-                "Scene",
-                self.engine.serialization_context.clone(),
-                &mut visitor,
-            )?
-            .finish(self.engine.resource_manager.clone()),
-        );
+        let (mut level, scene) = save::read(
+            slot,
+            self.engine.serialization_context.clone(),
+            self.engine.resource_manager.clone(),
+        )
+        .map_err(|e| format!("Unable to load save \"{}\": {}", slot, e))?;
+
+        // Clean up. Loaded match is not recorded: demos are replayed from a fresh level made
+        // from match options and seed, they can not start from the state of a save.
+        self.destroy_level();
+        self.load_context = None;
+        self.playback = None;
+        self.client = None;
 
-        let mut level = Level::default();
-        level.visit("Level", &mut visitor)?;
+        self.last_autosave_time = level.time();
         level.scene = self.engine.scenes.add(scene);
         self.level = Some(level);
 
@@ -394,12 +729,81 @@ impl Game {
             }
         }
 
-        self.time.elapsed = self.time.clock.elapsed().as_secs_f64();
+        // Do not try to catch up with the time spent on loading.
+        self.last_update_time = Instant::now();
+        self.lag = 0.0;
 
         Ok(())
     }
 
+    /// Starts the next map of the rotation. Clients wait until the server starts it and demos
+    /// return to the menu.
+    fn end_match(&mut self) {
+        let mut options = match self.level.as_ref() {
+            Some(level) if self.client.is_none() && self.playback.is_none() => {
+                level.options.clone()
+            }
+            _ => {
+                self.destroy_level();
+                self.hud
+                    .leader_board()
+                    .set_visible(true, &mut self.engine.user_interface);
+                return;
+            }
+        };
+
+        let next_map = self.map_rotation.next(options.map(), options.mode());
+        Log::writeln(
+            MessageKind::Information,
+            format!("Match is over, next map is {}", next_map.file_name()),
+        );
+        options.set_map(next_map);
+
+        // Clients of the server stay connected and load the next map with the server.
+        let server = self.server.take();
+        self.start_new_game(options);
+        self.server = server;
+    }
+
+    fn save_config(&self) {
+        let mut config = Config {
+            controls: self.control_scheme.read().unwrap().clone(),
+            graphics: self.engine.renderer.get_quality_settings(),
+            sound_volume: self.engine.sound_gain(),
+            music_volume: self.engine.scenes[self.menu_scene].graph[self.music]
+                .as_sound()
+                .gain(),
+            item_announcements: self.hud.item_announcements(),
+            ..Default::default()
+        };
+        config.set_window_fullscreen(self.engine.get_window().fullscreen());
+
+        if let Err(e) = config.save() {
+            Log::writeln(
+                MessageKind::Error,
+                format!("Unable to save config, reason: {}", e),
+            );
+        }
+    }
+
     fn destroy_level(&mut self) {
+        if let Some(mut server) = self.server.take() {
+            server.disconnect_all("Server stopped");
+        }
+
+        if let Some(mut recorder) = self.recorder.take() {
+            match recorder.save(Path::new(DEMO_PATH)) {
+                Ok(_) => Log::writeln(
+                    MessageKind::Information,
+                    format!("Demo saved to {}", DEMO_PATH),
+                ),
+                Err(e) => Log::writeln(
+                    MessageKind::Error,
+                    format!("Failed to save demo, reason: {:?}", e),
+                ),
+            }
+        }
+
         if let Some(ref mut level) = self.level.take() {
             level.destroy(&mut self.engine);
             Log::writeln(
@@ -410,6 +814,180 @@ impl Game {
     }
 
     pub fn start_new_game(&mut self, options: MatchOptions) {
+        self.playback = None;
+        self.client = None;
+        self.start_level(options, fyrox::rand::random(), self.control_scheme.clone());
+    }
+
+    pub fn start_playback(&mut self, demo: Demo) {
+        self.client = None;
+        self.playback = Some(DemoPlayback::new(demo));
+        self.restart_playback();
+    }
+
+    fn restart_playback(&mut self) {
+        if let Some(playback) = self.playback.as_ref() {
+            let demo = playback.demo();
+            self.start_level(
+                demo.options().clone(),
+                demo.seed(),
+                Arc::new(RwLock::new(demo.control_scheme())),
+            );
+        }
+    }
+
+    /// Connects to a server, level is loaded when server accepts the connection. If the
+    /// server waits in a lobby, the lobby is shown until the match starts.
+    pub fn connect(&mut self, address: &str, name: String) {
+        self.destroy_level();
+        self.playback = None;
+        match Client::connect(address, name, self.network_conditions) {
+            Ok(client) => self.client = Some(client),
+            Err(e) => {
+                let text = format!("Failed to connect to {}, reason: {}", address, e);
+                Log::writeln(MessageKind::Error, text.clone());
+                self.menu
+                    .set_network_status(&mut self.engine.user_interface, &text);
+            }
+        }
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        Log::writeln(
+            MessageKind::Information,
+            format!("Disconnected from server: {}", reason),
+        );
+        self.client = None;
+        self.destroy_level();
+        self.set_menu_visible(true);
+        self.menu.set_network_status(
+            &mut self.engine.user_interface,
+            &format!("Disconnected from server: {}", reason),
+        );
+    }
+
+    /// Opens a lobby of a listen server, the match is started by [`Message::StartHostedMatch`].
+    fn host_match(&mut self, options: MatchOptions, name: String) {
+        self.destroy_level();
+        self.playback = None;
+        self.client = None;
+
+        let port = self.host_port.unwrap_or(net::DEFAULT_PORT);
+        match Server::bind(
+            port,
+            self.network_conditions,
+            format!("{}'s match", name),
+            options,
+        ) {
+            Ok(mut server) => {
+                server.set_host(name);
+                self.server = Some(server);
+            }
+            Err(e) => {
+                let text = format!("Failed to start server, reason: {}", e);
+                Log::writeln(MessageKind::Error, text.clone());
+                self.menu
+                    .set_network_status(&mut self.engine.user_interface, &text);
+            }
+        }
+    }
+
+    fn start_hosted_match(&mut self) {
+        let options = match self.server.as_ref() {
+            Some(server) if server.is_in_lobby() => {
+                if !server.is_everyone_ready() {
+                    self.menu.set_network_status(
+                        &mut self.engine.user_interface,
+                        "Waiting for everyone to get ready",
+                    );
+                    return;
+                }
+                server.options().clone()
+            }
+            _ => return,
+        };
+
+        // Server outlives the lobby, its clients join the match when the level is loaded.
+        let server = self.server.take();
+        self.start_new_game(options);
+        self.server = server;
+    }
+
+    /// Leaves a joined lobby or closes the hosted one.
+    fn leave_lobby(&mut self) {
+        self.client = None;
+        if self.level.is_none() {
+            if let Some(mut server) = self.server.take() {
+                server.disconnect_all("Host has left");
+            }
+        }
+    }
+
+    /// Handles packets of the server and sends input of the player to it.
+    fn update_client(&mut self, time: GameTime) {
+        let packets = match self.client.as_mut() {
+            Some(client) if client.is_timed_out() => {
+                self.disconnect("connection timed out");
+                return;
+            }
+            Some(client) => client.receive(),
+            None => return,
+        };
+
+        for packet in packets {
+            match packet {
+                ServerPacket::Accepted { options, .. } => {
+                    if !self.map_rotation.contains(options.map()) {
+                        self.disconnect(&format!(
+                            "map {} is not installed",
+                            options.map().file_name()
+                        ));
+                        return;
+                    }
+                    // Client's level has no bots and its seed does not matter, the server
+                    // decides everything except movement of the player.
+                    self.start_level(options, 0, self.control_scheme.clone());
+                }
+                ServerPacket::Snapshot(snapshot) => {
+                    if let (Some(client), Some(level)) = (self.client.as_mut(), self.level.as_mut())
+                    {
+                        client.apply_snapshot(
+                            &snapshot,
+                            level,
+                            &mut self.engine.scenes[level.scene],
+                            &self.engine.resource_manager,
+                            &self.events_sender,
+                        );
+                    }
+                }
+                ServerPacket::Event(message) => self.dispatch_to_level(&message, time),
+                ServerPacket::Disconnect { reason } => {
+                    self.disconnect(&reason);
+                    return;
+                }
+                ServerPacket::Lobby { lobby, .. } => {
+                    self.set_menu_visible(true);
+                    self.menu
+                        .show_lobby(&mut self.engine.user_interface, &lobby, false);
+                }
+                // Only server browsers ask for descriptions of servers.
+                ServerPacket::Info { .. } => (),
+            }
+        }
+
+        if let (Some(client), Some(level)) = (self.client.as_mut(), self.level.as_ref()) {
+            if let Some(Actor::Player(player)) = level.actors().try_get(level.get_player()) {
+                client.send_input(&player.input());
+            }
+        }
+    }
+
+    fn start_level(
+        &mut self,
+        options: MatchOptions,
+        seed: u64,
+        control_scheme: Arc<RwLock<ControlScheme>>,
+    ) {
         self.destroy_level();
 
         let ctx = Arc::new(Mutex::new(LoadContext { level: None }));
@@ -425,9 +1003,10 @@ impl Game {
             ));
         self.menu
             .set_visible(&mut self.engine.user_interface, false);
+        self.hud.close_chat(&mut self.engine.user_interface);
+        self.hud.clear_chat(&mut self.engine.user_interface);
 
         let resource_manager = self.engine.resource_manager.clone();
-        let control_scheme = self.control_scheme.clone();
         let sender = self.events_sender.clone();
 
         std::thread::spawn(move || {
@@ -436,6 +1015,8 @@ impl Game {
                 control_scheme,
                 sender,
                 options,
+                true,
+                seed,
             ));
 
             ctx.lock().unwrap().level = Some(level);
@@ -446,6 +1027,9 @@ impl Game {
         let ui = &mut self.engine.user_interface;
         self.menu.set_visible(ui, visible);
         self.hud.set_visible(ui, !visible);
+        if visible {
+            self.hud.close_chat(ui);
+        }
     }
 
     pub fn is_menu_visible(&self) -> bool {
@@ -463,7 +1047,39 @@ impl Game {
 
         if let Some(ctx) = self.load_context.clone() {
             if let Ok(mut ctx) = ctx.try_lock() {
-                if let Some((mut level, scene)) = ctx.level.take() {
+                if let Some((mut level, mut scene)) = ctx.level.take() {
+                    if let Some(playback) = self.playback.as_mut() {
+                        playback.on_level_started(&mut scene);
+                    } else if self.client.is_none() {
+                        self.recorder = Some(Demo::new(
+                            level.options.clone(),
+                            level.seed(),
+                            &self.control_scheme.read().unwrap(),
+                        ));
+                        if let (None, Some(port)) = (self.server.as_ref(), self.host_port) {
+                            self.server = Server::bind(
+                                port,
+                                self.network_conditions,
+                                DEFAULT_SERVER_NAME.to_owned(),
+                                level.options.clone(),
+                            )
+                            .map_err(|e| {
+                                Log::writeln(
+                                    MessageKind::Error,
+                                    format!("Failed to start server, reason: {}", e),
+                                )
+                            })
+                            .ok();
+                        }
+                        if let Some(server) = self.server.as_mut() {
+                            server.start_match(&level, &self.events_sender);
+                        }
+                    }
+                    // Simulation of every level starts from zero, demos rely on this.
+                    self.time = GameTime::new(1.0 / FIXED_FPS);
+                    self.last_autosave_time = 0.0;
+                    self.last_update_time = Instant::now();
+                    self.lag = 0.0;
                     level.scene = self.engine.scenes.add(scene);
                     self.level = Some(level);
                     self.load_context = None;
@@ -490,8 +1106,59 @@ impl Game {
         let mut lag = 0f32;
         self.engine.update(time.delta, control_flow, &mut lag);
 
+        self.update_client(time);
+        self.menu.update(&mut self.engine.user_interface);
+
+        // Hosted lobby, server also waits there while the level is loading.
+        if let (None, Some(server)) = (self.level.as_ref(), self.server.as_mut()) {
+            server.receive(None, &self.events_sender);
+            server.update_lobby();
+            if server.is_in_lobby() {
+                self.menu
+                    .show_lobby(&mut self.engine.user_interface, &server.lobby(), true);
+            }
+        }
+
         if let Some(ref mut level) = self.level {
-            level.update(&mut self.engine, time);
+            if let Some(server) = self.server.as_mut() {
+                server.receive(Some(level), &self.events_sender);
+            }
+
+            let player = level.get_player();
+            let mut has_input = true;
+            if let Some(playback) = self.playback.as_mut() {
+                let input = playback.next_input();
+                has_input = input.is_some();
+                for message in playback.chat_messages() {
+                    self.events_sender.send(message).unwrap();
+                }
+                if let (Some(input), Some(Actor::Player(player))) =
+                    (input, level.actors_mut().try_get_mut(player))
+                {
+                    player.set_input(&input);
+                }
+            } else if let Some(recorder) = self.recorder.as_mut() {
+                // Input is recorded even if player is dead, demo must have input for every
+                // simulation step.
+                recorder.push(match level.actors().try_get(player) {
+                    Some(Actor::Player(player)) => player.input(),
+                    _ => Default::default(),
+                });
+            }
+
+            // Level is frozen at the end of a demo.
+            if has_input {
+                level.update(
+                    &mut LevelContext::from_engine(&mut self.engine, level.scene),
+                    time,
+                );
+            }
+            if let Some(server) = self.server.as_mut() {
+                server.send_snapshots(level, &self.engine.scenes[level.scene]);
+            }
+            if let Some(client) = self.client.as_mut() {
+                client.update(level, &mut self.engine.scenes[level.scene], time.delta);
+            }
             let ui = &mut self.engine.user_interface;
             self.hud.set_time(ui, level.time());
             let player = level.get_player();
@@ -501,10 +1168,22 @@ impl Game {
                 self.hud.set_health(ui, player.get_health());
                 self.hud.set_armor(ui, player.get_armor());
                 let current_weapon = player.current_weapon();
-                if current_weapon.is_some() {
-                    self.hud
-                        .set_ammo(ui, level.weapons()[current_weapon].ammo());
-                }
+                let current_kind = if current_weapon.is_some() {
+                    let current_weapon = &level.weapons()[current_weapon];
+                    self.hud.set_ammo(ui, current_weapon.ammo());
+                    Some(current_weapon.get_kind())
+                } else {
+                    None
+                };
+                let weapons = player
+                    .weapons()
+                    .iter()
+                    .map(|&weapon| {
+                        let weapon = &level.weapons()[weapon];
+                        (weapon.get_kind(), weapon.ammo())
+                    })
+                    .collect::<Vec<_>>();
+                self.hud.set_weapons(ui, &weapons, current_kind);
                 self.hud.set_is_died(ui, false);
             } else {
                 self.hud.set_is_died(ui, true);
@@ -512,68 +1191,165 @@ impl Game {
         }
 
         self.handle_messages(time);
+        self.autosave();
 
         self.hud.update(&mut self.engine.user_interface, &self.time);
     }
 
+    /// Saves single player match to [`AUTOSAVE_SLOT`] from time to time.
+    fn autosave(&mut self) {
+        let time = match self.level.as_ref() {
+            Some(level) => level.time(),
+            None => return,
+        };
+        if time - self.last_autosave_time < AUTOSAVE_INTERVAL
+            || self.client.is_some()
+            || self.server.is_some()
+            || self.playback.is_some()
+        {
+            return;
+        }
+
+        // Failed autosave is not repeated every step.
+        self.last_autosave_time = time;
+        match self.save_game(AUTOSAVE_SLOT) {
+            Ok(_) => self.hud.add_message("Game saved"),
+            Err(e) => Log::writeln(MessageKind::Error, format!("Autosave failed: {}", e)),
+        }
+    }
+
     fn handle_messages(&mut self, time: GameTime) {
         while let Ok(message) = self.events_receiver.try_recv() {
             match &message {
                 Message::StartNewGame { options } => {
-                    self.start_new_game(*options);
+                    self.start_new_game(options.clone());
+                }
+                Message::HostMatch { options, name } => {
+                    self.host_match(options.clone(), name.clone());
                 }
-                Message::SaveGame => match self.save_game() {
-                    Ok(_) => {
-                        Log::writeln(MessageKind::Information, "Successfully saved".to_owned())
+                Message::StartHostedMatch => self.start_hosted_match(),
+                Message::ConnectToServer { address, name } => {
+                    self.connect(address, name.clone());
+                }
+                Message::SetReady { team, ready } => {
+                    if let Some(client) = self.client.as_mut() {
+                        client.set_ready(*team, *ready);
                     }
-                    Err(e) => Log::writeln(
-                        MessageKind::Error,
-                        format!("Failed to make a save, reason: {}", e),
-                    ),
-                },
-                Message::LoadGame => {
-                    if let Err(e) = self.load_game() {
-                        Log::writeln(
-                            MessageKind::Error,
-                            format!("Failed to load saved game. Reason: {:?}", e),
-                        );
+                }
+                Message::LeaveLobby => self.leave_lobby(),
+                Message::SendChat { channel, text } => self.send_chat(*channel, text.clone()),
+                Message::Chat {
+                    sender,
+                    team,
+                    channel,
+                    text,
+                } => {
+                    if let Some(recorder) = self.recorder.as_mut() {
+                        recorder.push_chat(sender, *team, *channel, text);
+                    }
+                }
+                Message::SaveGame { slot } => {
+                    let status = match self.save_game(slot) {
+                        Ok(_) => format!("Game saved to \"{}\"", slot),
+                        Err(e) => {
+                            Log::writeln(MessageKind::Error, e.clone());
+                            e
+                        }
+                    };
+                    self.menu
+                        .set_save_status(&mut self.engine.user_interface, &status);
+                }
+                Message::LoadGame { slot } => {
+                    if let Err(e) = self.load_game(slot) {
+                        Log::writeln(MessageKind::Error, e.clone());
+                        self.menu
+                            .set_save_status(&mut self.engine.user_interface, &e);
                     }
                 }
                 Message::QuitGame => {
                     self.destroy_level();
                     self.running = false;
                 }
-                Message::EndMatch => {
-                    self.destroy_level();
-                    self.hud
-                        .leader_board()
-                        .set_visible(true, &mut self.engine.user_interface);
-                }
+                Message::EndMatch => self.end_match(),
+                Message::SaveConfig => self.save_config(),
                 Message::SetMusicVolume { volume } => {
                     self.engine.scenes[self.menu_scene].graph[self.music]
                         .as_sound_mut()
                         .set_gain(*volume);
                 }
+                Message::SetItemAnnouncements { enabled } => {
+                    self.hud.set_item_announcements(*enabled);
+                }
                 _ => (),
             }
 
-            if let Some(ref mut level) = self.level {
-                fyrox::core::futures::executor::block_on(level.handle_message(
-                    &mut self.engine,
-                    &message,
-                    time,
-                ));
+            if let Some(server) = self.server.as_mut() {
+                server.broadcast(&message);
+            }
 
-                self.hud.handle_message(
-                    &message,
-                    &mut self.engine.user_interface,
-                    &level.leader_board,
-                    &level.options,
-                );
+            if (self.client.is_none() || client::is_handled_locally(&message))
+                && self.is_visible_locally(&message)
+            {
+                self.dispatch_to_level(&message, time);
             }
         }
     }
 
+    /// Sends chat message of the local player, in multiplayer matches it goes through the
+    /// server.
+    fn send_chat(&mut self, channel: ChatChannel, text: String) {
+        if !self.chat_limiter.try_send(self.time.elapsed) {
+            self.hud.add_message("You are sending messages too fast");
+            return;
+        }
+
+        if let Some(client) = self.client.as_mut() {
+            client.send_chat(channel, text);
+        } else if let Some(level) = self.level.as_ref() {
+            let sender = self
+                .server
+                .as_ref()
+                .and_then(|server| server.host())
+                .unwrap_or("Player")
+                .to_owned();
+            self.events_sender
+                .send(Message::Chat {
+                    sender,
+                    team: level::player_team(&level.options),
+                    channel,
+                    text,
+                })
+                .unwrap();
+        }
+    }
+
+    /// Team chat of the other team is not shown to the local player of a listen server.
+    fn is_visible_locally(&self, message: &Message) -> bool {
+        match (message, self.level.as_ref()) {
+            (Message::Chat { team, channel, .. }, Some(level)) => {
+                chat::is_visible_to(*channel, *team, level::player_team(&level.options))
+            }
+            _ => true,
+        }
+    }
+
+    fn dispatch_to_level(&mut self, message: &Message, time: GameTime) {
+        if let Some(ref mut level) = self.level {
+            fyrox::core::futures::executor::block_on(level.handle_message(
+                &mut LevelContext::from_engine(&mut self.engine, level.scene),
+                message,
+                time,
+            ));
+
+            self.hud.handle_message(
+                message,
+                &mut self.engine.user_interface,
+                &level.leader_board,
+                &level.options,
+            );
+        }
+    }
+
     pub fn update_statistics(&mut self, elapsed: f64) {
         self.debug_string.clear();
         use std::fmt::Write;
@@ -597,6 +1373,10 @@ impl Game {
         )
         .unwrap();
 
+        if let Some(playback) = self.playback.as_ref() {
+            write!(self.debug_string, "\n{}", playback.status()).unwrap();
+        }
+
         self.engine.user_interface.send_message(TextMessage::text(
             self.debug_text,
             MessageDirection::ToWidget,
@@ -612,21 +1392,86 @@ impl Game {
         }
 
         if !self.is_menu_visible() {
-            if let Some(ref mut level) = self.level {
+            if let Some(playback) = self.playback.as_mut() {
+                // Player is controlled by the demo.
+                if playback.process_input_event(event) {
+                    self.restart_playback();
+                }
+            } else if let Some(ref mut level) = self.level {
                 level.process_input_event(event);
             }
         }
     }
 
+    /// Lets the player type a chat message, returns `true` if the event was used by chat.
+    fn process_chat_input(&mut self, event: &Event<()>) -> bool {
+        // Keyboard controls demo playback.
+        if self.level.is_none() || self.playback.is_some() || self.is_menu_visible() {
+            return false;
+        }
+
+        match self.hud.process_chat_input(
+            &mut self.engine.user_interface,
+            event,
+            &self.control_scheme.read().unwrap(),
+        ) {
+            ChatInputEvent::Ignored => return false,
+            ChatInputEvent::Consumed => (),
+            ChatInputEvent::Send { channel, text } => self
+                .events_sender
+                .send(Message::SendChat { channel, text })
+                .unwrap(),
+        }
+
+        // Keys that were held when chat was opened must not keep the player moving.
+        if let Some(level) = self.level.as_mut() {
+            let player = level.get_player();
+            if let Some(Actor::Player(player)) = level.actors_mut().try_get_mut(player) {
+                player.release_controls();
+            }
+        }
+        true
+    }
+
     pub fn process_input_event(&mut self, event: &Event<()>) {
+        if self.process_chat_input(event) {
+            return;
+        }
+
         self.process_dispatched_event(event);
 
         if let Event::WindowEvent { event, .. } = event {
             if let WindowEvent::KeyboardInput { input, .. } = event {
                 if let ElementState::Pressed = input.state {
                     if let Some(key) = input.virtual_keycode {
-                        if key == VirtualKeyCode::Escape {
-                            self.set_menu_visible(!self.is_menu_visible());
+                        match key {
+                            VirtualKeyCode::Escape => {
+                                self.set_menu_visible(!self.is_menu_visible());
+                            }
+                            // Debug keys: F3 toggles AI inspector and debug geometry, F4
+                            // toggles writing of per-tick AI trace.
+                            VirtualKeyCode::F3 => {
+                                let visible = !self.ai_inspector.is_visible();
+                                self.ai_inspector
+                                    .set_visible(visible, &mut self.engine.user_interface);
+                                if let Some(level) = self.level.as_ref() {
+                                    if !visible {
+                                        self.engine.scenes[level.scene]
+                                            .drawing_context
+                                            .clear_lines();
+                                    }
+                                }
+                            }
+                            VirtualKeyCode::C if self.playback.is_some() => {
+                                self.toggle_free_camera();
+                            }
+                            VirtualKeyCode::F4 => {
+                                if let Some(level) = self.level.as_mut() {
+                                    let enabled = !level.is_ai_trace_enabled();
+                                    level.set_ai_trace_enabled(enabled);
+                                }
+                            }
+                            _ => (),
                         }
                     }
                 }
@@ -639,5 +1484,73 @@ impl Game {
 }
 
 fn main() {
-    Game::run();
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let conditions = NetworkConditions::take_from_args(&mut args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    if args.iter().any(|arg| arg == "--server") {
+        match server::parse_args(&args) {
+            Ok((server_options, options)) => server::run(server_options, conditions, options),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.iter().any(|arg| arg == "--headless") {
+        match headless::HeadlessOptions::from_args(&args) {
+            Ok(options) => headless::run(options),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(i) = args.iter().position(|arg| arg == "--validate") {
+        let map = match args.get(i + 1) {
+            Some(map) => MapPath::resolve(map),
+            None => {
+                eprintln!("Missing value for --validate argument");
+                std::process::exit(1);
+            }
+        };
+        if !validator::run(&map) {
+            std::process::exit(1);
+        }
+    } else if let Some(i) = args.iter().position(|arg| arg == "--demo") {
+        let path = args.get(i + 1).map_or(DEMO_PATH, |path| path.as_str());
+        match block_on(Demo::load(Path::new(path))) {
+            Ok(demo) => Game::run(StartMode::Demo(demo)),
+            Err(e) => {
+                eprintln!("Failed to load demo {}: {:?}", path, e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(i) = args.iter().position(|arg| arg == "--connect") {
+        let address = match args.get(i + 1) {
+            Some(address) => address.clone(),
+            None => {
+                eprintln!("Missing value for --connect argument");
+                std::process::exit(1);
+            }
+        };
+        let name = args
+            .iter()
+            .position(|arg| arg == "--name")
+            .and_then(|i| args.get(i + 1))
+            .cloned()
+            .unwrap_or_else(|| "Player".to_owned());
+        Game::run(StartMode::Connect {
+            address,
+            name,
+            conditions,
+        });
+    } else if let Some(i) = args.iter().position(|arg| arg == "--host") {
+        let port = args
+            .get(i + 1)
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(net::DEFAULT_PORT);
+        Game::run(StartMode::Host { port, conditions });
+    } else {
+        Game::run(StartMode::Menu);
+    }
 }
@@ -0,0 +1,224 @@
+//! Maps of matches. Every map is a scene (`.rgs`) in [`MAPS_DIR`], a metadata file with the same
+//! name and `.ron` extension describes it:
+//!
+//! ```ron
+//! (
+//!     name: "DM6",
+//!     modes: [DeathMatch, TeamDeathMatch],
+//!     recommended_players: (2, 8),
+//!     preview: Some("dm6_preview.jpg"),
+//! )
+//! ```
+//!
+//! Path of the preview is relative to the map. Maps without metadata support every mode.
+//! When a match ends, the next match is played on the next map of [`MapRotation`].
+
+use crate::{save, GameMode};
+use fyrox::{
+    core::visitor::{Visit, VisitResult, Visitor},
+    utils::log::{Log, MessageKind},
+};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const MAPS_DIR: &str = "data/levels";
+
+/// The map of matches that do not choose a map.
+pub const DEFAULT_MAP: &str = "data/levels/dm6.rgs";
+
+/// Path of the map of a match. Saves made before map selection do not have it, they were made
+/// on [`DEFAULT_MAP`] and get it by migration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapPath(pub String);
+
+impl Default for MapPath {
+    fn default() -> Self {
+        Self(DEFAULT_MAP.to_owned())
+    }
+}
+
+impl Visit for MapPath {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        if !save::has_state_of_version(4) {
+            return Ok(());
+        }
+        self.0.visit(name, visitor)
+    }
+}
+
+impl MapPath {
+    /// Finds a map by its name (file name without extension) or its path.
+    pub fn resolve(name: &str) -> Self {
+        if name.ends_with(".rgs") {
+            Self(name.to_owned())
+        } else {
+            Self(format!("{}/{}.rgs", MAPS_DIR, name))
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        Path::new(&self.0)
+    }
+
+    /// Short name of the map, it is the name of its file without extension.
+    pub fn file_name(&self) -> String {
+        self.path()
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path().exists()
+    }
+
+    /// Returns `true` if the path names a map file right in [`MAPS_DIR`]. Paths that come from
+    /// the network must not point anywhere else.
+    pub fn is_in_maps_dir(&self) -> bool {
+        let path = self.path();
+        path.parent() == Some(Path::new(MAPS_DIR))
+            && path.extension().map_or(false, |ext| ext == "rgs")
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct MapMetadata {
+    name: Option<String>,
+    modes: Vec<GameMode>,
+    recommended_players: (u32, u32),
+    preview: Option<String>,
+}
+
+impl Default for MapMetadata {
+    fn default() -> Self {
+        Self {
+            name: None,
+            modes: GameMode::ALL.to_vec(),
+            recommended_players: (2, 8),
+            preview: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MapInfo {
+    pub path: MapPath,
+    pub name: String,
+    pub modes: Vec<GameMode>,
+    /// Minimum and maximum amount of players.
+    pub recommended_players: (u32, u32),
+    pub preview: Option<PathBuf>,
+}
+
+impl MapInfo {
+    /// Reads metadata of a map, broken metadata is reported to the log and ignored.
+    pub fn load(path: MapPath) -> Self {
+        let metadata_path = path.path().with_extension("ron");
+        let metadata = match fs::read_to_string(&metadata_path) {
+            Ok(text) => ron::from_str::<MapMetadata>(&text).unwrap_or_else(|e| {
+                Log::writeln(
+                    MessageKind::Warning,
+                    format!(
+                        "Invalid map metadata {}, reason: {}",
+                        metadata_path.display(),
+                        e
+                    ),
+                );
+                Default::default()
+            }),
+            Err(_) => Default::default(),
+        };
+
+        Self {
+            name: metadata.name.unwrap_or_else(|| path.file_name()),
+            modes: metadata.modes,
+            recommended_players: metadata.recommended_players,
+            preview: metadata.preview.map(|preview| {
+                path.path()
+                    .parent()
+                    .map_or_else(|| PathBuf::from(&preview), |dir| dir.join(&preview))
+            }),
+            path,
+        }
+    }
+
+    pub fn supports(&self, mode: GameMode) -> bool {
+        self.modes.contains(&mode)
+    }
+
+    pub fn description(&self) -> String {
+        format!(
+            "{}\n{} - {} players\n{}",
+            self.name,
+            self.recommended_players.0,
+            self.recommended_players.1,
+            self.modes
+                .iter()
+                .map(|mode| mode.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/// Finds every map of a directory, maps are sorted by their paths.
+pub fn discover(dir: &Path) -> Vec<MapInfo> {
+    let mut paths = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == "rgs"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            // Maps are referenced by relative paths, like other resources.
+            let path = dir.join(path.file_name().unwrap_or_default());
+            MapInfo::load(MapPath(path.to_string_lossy().replace('\\', "/")))
+        })
+        .collect()
+}
+
+/// Cycle of maps, every map is played in turn.
+#[derive(Default)]
+pub struct MapRotation {
+    maps: Vec<MapInfo>,
+}
+
+impl MapRotation {
+    pub fn new(maps: Vec<MapInfo>) -> Self {
+        Self { maps }
+    }
+
+    pub fn maps(&self) -> &[MapInfo] {
+        &self.maps
+    }
+
+    pub fn contains(&self, map: &MapPath) -> bool {
+        self.maps.iter().any(|info| info.path == *map)
+    }
+
+    /// Returns the map that follows `current` and supports `mode`. The current map is played
+    /// again if no other map supports the mode.
+    pub fn next(&self, current: &MapPath, mode: GameMode) -> MapPath {
+        let start = self
+            .maps
+            .iter()
+            .position(|map| map.path == *current)
+            .map_or(0, |index| index + 1);
+
+        (0..self.maps.len())
+            .map(|offset| &self.maps[(start + offset) % self.maps.len()])
+            .find(|map| map.supports(mode))
+            .map_or_else(|| current.clone(), |map| map.path.clone())
+    }
+}
@@ -1,45 +1,255 @@
 use crate::{
-    gui::{create_scroll_bar, ScrollBarData},
+    bot::{BotKind, BotSkill},
+    character::Team,
+    gui::{create_check_box, create_dropdown_items, create_scroll_bar, ScrollBarData},
+    map::{self, MapInfo, MapPath, MAPS_DIR},
     message::Message,
-    DeathMatch, MatchOptions,
+    roster::{BotConfig, BotRoster},
+    weapon::WeaponRules,
+    GameMode, MatchOptions,
 };
 use fyrox::{
     core::pool::Handle,
-    engine::Engine,
+    engine::{resource_manager::ResourceManager, Engine},
     gui::{
-        border::BorderBuilder,
         button::{ButtonBuilder, ButtonMessage},
-        decorator::DecoratorBuilder,
-        dropdown_list::DropdownListBuilder,
+        check_box::CheckBox,
+        draw,
+        dropdown_list::{DropdownList, DropdownListBuilder, DropdownListMessage},
         grid::{Column, GridBuilder, Row},
-        message::UiMessage,
+        image::{ImageBuilder, ImageMessage},
+        message::{MessageDirection, UiMessage},
         scroll_bar::ScrollBar,
-        text::TextBuilder,
-        text_box::TextBoxBuilder,
-        widget::WidgetBuilder,
-        window::{WindowBuilder, WindowTitle},
-        HorizontalAlignment, Orientation, Thickness, UiNode, UserInterface, VerticalAlignment,
+        scroll_viewer::ScrollViewerBuilder,
+        stack_panel::StackPanelBuilder,
+        text::{TextBuilder, TextMessage},
+        text_box::{TextBox, TextBoxBuilder},
+        widget::{WidgetBuilder, WidgetMessage},
+        window::{WindowBuilder, WindowMessage, WindowTitle},
+        BuildContext, Orientation, Thickness, UiNode, UserInterface, VerticalAlignment,
     },
+    utils,
 };
-use std::sync::mpsc::Sender;
+use std::{path::Path, sync::mpsc::Sender};
+
+pub const TEAMS: [Team; 3] = [Team::None, Team::Red, Team::Blue];
+
+/// Names of [`TEAMS`] in team choosers.
+pub const TEAM_NAMES: [&str; 3] = ["Auto", "Red", "Blue"];
+
+/// Widgets of a single bot in roster list.
+struct RosterEntry {
+    root: Handle<UiNode>,
+    dl_kind: Handle<UiNode>,
+    tb_name: Handle<UiNode>,
+    dl_skill: Handle<UiNode>,
+    dl_team: Handle<UiNode>,
+    remove_button: Handle<UiNode>,
+}
+
+impl RosterEntry {
+    fn new(ctx: &mut BuildContext, config: &BotConfig) -> Self {
+        let kinds = BotKind::ALL
+            .iter()
+            .map(|k| k.description())
+            .collect::<Vec<_>>();
+        let skills = BotSkill::ALL
+            .iter()
+            .map(|s| s.description())
+            .collect::<Vec<_>>();
+
+        let dl_kind;
+        let tb_name;
+        let dl_skill;
+        let dl_team;
+        let remove_button;
+        let root = GridBuilder::new(
+            WidgetBuilder::new()
+                .with_height(30.0)
+                .with_child({
+                    dl_kind = DropdownListBuilder::new(
+                        WidgetBuilder::new()
+                            .on_column(0)
+                            .with_margin(Thickness::uniform(1.0)),
+                    )
+                    .with_items(create_dropdown_items(ctx, &kinds))
+                    .with_selected(
+                        BotKind::ALL
+                            .iter()
+                            .position(|k| *k == config.kind)
+                            .unwrap_or_default(),
+                    )
+                    .build(ctx);
+                    dl_kind
+                })
+                .with_child({
+                    tb_name = TextBoxBuilder::new(
+                        WidgetBuilder::new()
+                            .on_column(1)
+                            .with_margin(Thickness::uniform(1.0)),
+                    )
+                    .with_text(config.name.clone())
+                    .with_vertical_text_alignment(VerticalAlignment::Center)
+                    .build(ctx);
+                    tb_name
+                })
+                .with_child({
+                    dl_skill = DropdownListBuilder::new(
+                        WidgetBuilder::new()
+                            .on_column(2)
+                            .with_margin(Thickness::uniform(1.0)),
+                    )
+                    .with_items(create_dropdown_items(ctx, &skills))
+                    .with_selected(
+                        BotSkill::ALL
+                            .iter()
+                            .position(|s| *s == config.skill)
+                            .unwrap_or_default(),
+                    )
+                    .build(ctx);
+                    dl_skill
+                })
+                .with_child({
+                    dl_team = DropdownListBuilder::new(
+                        WidgetBuilder::new()
+                            .on_column(3)
+                            .with_margin(Thickness::uniform(1.0)),
+                    )
+                    .with_items(create_dropdown_items(ctx, &TEAM_NAMES))
+                    .with_selected(
+                        TEAMS
+                            .iter()
+                            .position(|t| *t == config.team)
+                            .unwrap_or_default(),
+                    )
+                    .build(ctx);
+                    dl_team
+                })
+                .with_child({
+                    remove_button = ButtonBuilder::new(
+                        WidgetBuilder::new()
+                            .on_column(4)
+                            .with_margin(Thickness::uniform(1.0)),
+                    )
+                    .with_text("X")
+                    .build(ctx);
+                    remove_button
+                }),
+        )
+        .add_column(Column::strict(110.0))
+        .add_column(Column::stretch())
+        .add_column(Column::strict(90.0))
+        .add_column(Column::strict(70.0))
+        .add_column(Column::strict(30.0))
+        .add_row(Row::stretch())
+        .build(ctx);
+
+        Self {
+            root,
+            dl_kind,
+            tb_name,
+            dl_skill,
+            dl_team,
+            remove_button,
+        }
+    }
+
+    fn config(&self, ui: &UserInterface) -> BotConfig {
+        let selection = |handle: Handle<UiNode>| {
+            ui.node(handle)
+                .cast::<DropdownList>()
+                .and_then(|dropdown_list| dropdown_list.selection())
+                .unwrap_or_default()
+        };
+
+        BotConfig {
+            kind: BotKind::ALL[selection(self.dl_kind).min(BotKind::ALL.len() - 1)],
+            name: ui
+                .node(self.tb_name)
+                .cast::<TextBox>()
+                .map(|text_box| text_box.text().trim().to_owned())
+                .unwrap_or_default(),
+            skill: BotSkill::ALL[selection(self.dl_skill).min(BotSkill::ALL.len() - 1)],
+            team: TEAMS[selection(self.dl_team).min(TEAMS.len() - 1)],
+        }
+    }
+}
+
+/// Preview image and description of a map in the map picker.
+fn map_preview(
+    resource_manager: &ResourceManager,
+    map: Option<&MapInfo>,
+) -> (Option<draw::SharedTexture>, String) {
+    match map {
+        Some(map) => (
+            map.preview
+                .as_ref()
+                .map(|preview| utils::into_gui_texture(resource_manager.request_texture(preview))),
+            map.description(),
+        ),
+        None => (None, format!("No maps found in {}", MAPS_DIR)),
+    }
+}
 
 pub struct MatchMenu {
     sender: Sender<Message>,
+    resource_manager: ResourceManager,
     pub window: Handle<UiNode>,
+    dl_match_type: Handle<UiNode>,
+    maps: Vec<MapInfo>,
+    dl_map: Handle<UiNode>,
+    img_map_preview: Handle<UiNode>,
+    txt_map_info: Handle<UiNode>,
     sb_frag_limit: Handle<UiNode>,
+    cb_weapon_stay: Handle<UiNode>,
+    cb_drop_current_only: Handle<UiNode>,
     sb_time_limit: Handle<UiNode>,
+    sb_fill_to: Handle<UiNode>,
+    tb_player_name: Handle<UiNode>,
+    sp_roster: Handle<UiNode>,
+    add_bot_button: Handle<UiNode>,
+    roster: Vec<RosterEntry>,
     start_button: Handle<UiNode>,
+    host_button: Handle<UiNode>,
 }
 
 impl MatchMenu {
-    pub fn new(ui: &mut UserInterface, sender: Sender<Message>) -> Self {
+    pub fn new(
+        ui: &mut UserInterface,
+        resource_manager: ResourceManager,
+        sender: Sender<Message>,
+    ) -> Self {
         let common_row = Row::strict(36.0);
 
+        let maps = map::discover(Path::new(MAPS_DIR));
+        let map_names = maps.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+        let selected_map = maps
+            .iter()
+            .position(|m| m.path == MapPath::default())
+            .unwrap_or_default();
+        let (preview, description) = map_preview(&resource_manager, maps.get(selected_map));
+
         let ctx = &mut ui.build_ctx();
+        let dl_match_type;
+        let dl_map;
+        let img_map_preview;
+        let txt_map_info;
         let sb_frag_limit;
+        let cb_weapon_stay;
+        let cb_drop_current_only;
         let sb_time_limit;
+        let sb_fill_to;
+        let tb_player_name;
+        let sp_roster;
+        let add_bot_button;
         let start_button;
-        let window = WindowBuilder::new(WidgetBuilder::new().with_width(500.0))
+        let host_button;
+        let roster = BotRoster::default()
+            .bots
+            .iter()
+            .map(|config| RosterEntry::new(ctx, config))
+            .collect::<Vec<_>>();
+        let window = WindowBuilder::new(WidgetBuilder::new().with_width(700.0))
             .with_title(WindowTitle::text("Match Options"))
             .open(false)
             .with_content(
@@ -50,37 +260,67 @@ impl MatchMenu {
                                 .with_text("Match Type")
                                 .build(ctx),
                         )
+                        .with_child({
+                            dl_match_type = DropdownListBuilder::new(
+                                WidgetBuilder::new().on_column(1).on_row(0),
+                            )
+                            .with_items(create_dropdown_items(
+                                ctx,
+                                &["Deathmatch", "Team Deathmatch", "Capture The Flag"],
+                            ))
+                            .with_selected(0)
+                            .build(ctx);
+                            dl_match_type
+                        })
                         .with_child(
-                            DropdownListBuilder::new(WidgetBuilder::new().on_column(1).on_row(0))
-                                .with_items({
-                                    let mut items = Vec::new();
-                                    for mode in
-                                        ["Deathmatch", "Team Deathmatch", "Capture The Flag"].iter()
-                                    {
-                                        let item = DecoratorBuilder::new(BorderBuilder::new(
-                                            WidgetBuilder::new().with_height(30.0).with_child(
-                                                TextBuilder::new(
-                                                    WidgetBuilder::new()
-                                                        .with_horizontal_alignment(
-                                                            HorizontalAlignment::Center,
-                                                        )
-                                                        .with_vertical_alignment(
-                                                            VerticalAlignment::Center,
-                                                        ),
-                                                )
-                                                .with_text(mode)
-                                                .build(ctx),
-                                            ),
-                                        ))
-                                        .build(ctx);
-                                        items.push(item);
-                                    }
-                                    items
-                                })
+                            TextBuilder::new(WidgetBuilder::new().on_row(1).on_column(0))
+                                .with_text("Map")
                                 .build(ctx),
                         )
+                        .with_child({
+                            dl_map = DropdownListBuilder::new(
+                                WidgetBuilder::new().on_column(1).on_row(1),
+                            )
+                            .with_items(create_dropdown_items(ctx, &map_names))
+                            .with_selected(selected_map)
+                            .build(ctx);
+                            dl_map
+                        })
                         .with_child(
-                            TextBuilder::new(WidgetBuilder::new().on_row(1).on_column(0))
+                            GridBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(2)
+                                    .on_column(1)
+                                    .with_child({
+                                        img_map_preview = ImageBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(0)
+                                                .with_width(160.0)
+                                                .with_height(90.0)
+                                                .with_margin(Thickness::uniform(2.0)),
+                                        )
+                                        .with_opt_texture(preview)
+                                        .build(ctx);
+                                        img_map_preview
+                                    })
+                                    .with_child({
+                                        txt_map_info = TextBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(1)
+                                                .with_margin(Thickness::uniform(2.0)),
+                                        )
+                                        .with_text(description)
+                                        .build(ctx);
+                                        txt_map_info
+                                    }),
+                            )
+                            .add_column(Column::strict(164.0))
+                            .add_column(Column::stretch())
+                            .add_row(Row::auto())
+                            .build(ctx),
+                        )
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(3).on_column(0))
                                 .with_text("Time Limit (min)")
                                 .build(ctx),
                         )
@@ -92,7 +332,7 @@ impl MatchMenu {
                                     max: 60.0,
                                     value: 10.0,
                                     step: 1.0,
-                                    row: 1,
+                                    row: 3,
                                     column: 1,
                                     margin: Thickness::uniform(2.0),
                                     show_value: true,
@@ -102,7 +342,7 @@ impl MatchMenu {
                             sb_time_limit
                         })
                         .with_child(
-                            TextBuilder::new(WidgetBuilder::new().on_row(2).on_column(0))
+                            TextBuilder::new(WidgetBuilder::new().on_row(4).on_column(0))
                                 .with_text("Frag Limit")
                                 .build(ctx),
                         )
@@ -114,7 +354,7 @@ impl MatchMenu {
                                     max: 200.0,
                                     value: 30.0,
                                     step: 1.0,
-                                    row: 2,
+                                    row: 4,
                                     column: 1,
                                     margin: Thickness::uniform(2.0),
                                     show_value: true,
@@ -123,10 +363,28 @@ impl MatchMenu {
                             );
                             sb_frag_limit
                         })
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(5).on_column(0))
+                                .with_text("Weapon Stay")
+                                .build(ctx),
+                        )
+                        .with_child({
+                            cb_weapon_stay = create_check_box(ctx, 5, 1, false);
+                            cb_weapon_stay
+                        })
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(6).on_column(0))
+                                .with_text("Drop Only Current Weapon")
+                                .build(ctx),
+                        )
+                        .with_child({
+                            cb_drop_current_only = create_check_box(ctx, 6, 1, false);
+                            cb_drop_current_only
+                        })
                         .with_child(
                             TextBuilder::new(
                                 WidgetBuilder::new()
-                                    .on_row(3)
+                                    .on_row(7)
                                     .on_column(0)
                                     .with_margin(Thickness::uniform(2.0)),
                             )
@@ -134,28 +392,95 @@ impl MatchMenu {
                             .with_vertical_text_alignment(VerticalAlignment::Center)
                             .build(ctx),
                         )
-                        .with_child(
-                            TextBoxBuilder::new(
+                        .with_child({
+                            tb_player_name = TextBoxBuilder::new(
                                 WidgetBuilder::new()
-                                    .on_row(3)
+                                    .on_row(7)
                                     .on_column(1)
                                     .with_margin(Thickness::uniform(2.0)),
                             )
                             .with_text("Unnamed Player".to_owned())
+                            .build(ctx);
+                            tb_player_name
+                        })
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(8).on_column(0))
+                                .with_text("Bots")
+                                .build(ctx),
+                        )
+                        .with_child({
+                            add_bot_button =
+                                ButtonBuilder::new(WidgetBuilder::new().on_row(8).on_column(1))
+                                    .with_text("Add Bot")
+                                    .build(ctx);
+                            add_bot_button
+                        })
+                        .with_child(
+                            ScrollViewerBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(9)
+                                    .on_column(1)
+                                    .with_height(200.0),
+                            )
+                            .with_content({
+                                sp_roster = StackPanelBuilder::new(
+                                    WidgetBuilder::new()
+                                        .with_children(roster.iter().map(|e| e.root)),
+                                )
+                                .build(ctx);
+                                sp_roster
+                            })
                             .build(ctx),
                         )
+                        .with_child(
+                            TextBuilder::new(WidgetBuilder::new().on_row(10).on_column(0))
+                                .with_text("Fill To Players (0 - Off)")
+                                .build(ctx),
+                        )
+                        .with_child({
+                            sb_fill_to = create_scroll_bar(
+                                ctx,
+                                ScrollBarData {
+                                    min: 0.0,
+                                    max: 16.0,
+                                    value: 0.0,
+                                    step: 1.0,
+                                    row: 10,
+                                    column: 1,
+                                    margin: Thickness::uniform(2.0),
+                                    show_value: true,
+                                    orientation: Orientation::Horizontal,
+                                },
+                            );
+                            sb_fill_to
+                        })
                         .with_child({
                             start_button =
-                                ButtonBuilder::new(WidgetBuilder::new().on_row(4).on_column(1))
+                                ButtonBuilder::new(WidgetBuilder::new().on_row(11).on_column(1))
                                     .with_text("Start")
                                     .build(ctx);
                             start_button
+                        })
+                        .with_child({
+                            host_button =
+                                ButtonBuilder::new(WidgetBuilder::new().on_row(12).on_column(1))
+                                    .with_text("Host Multiplayer Match")
+                                    .build(ctx);
+                            host_button
                         }),
                 )
                 .add_column(Column::strict(200.0))
                 .add_column(Column::stretch())
                 .add_row(common_row)
                 .add_row(common_row)
+                .add_row(Row::auto())
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(Row::auto())
                 .add_row(common_row)
                 .add_row(common_row)
                 .add_row(common_row)
@@ -165,38 +490,147 @@ impl MatchMenu {
             .build(ctx);
         Self {
             sender,
+            resource_manager,
             window,
+            dl_match_type,
+            maps,
+            dl_map,
+            img_map_preview,
+            txt_map_info,
             sb_frag_limit,
+            cb_weapon_stay,
+            cb_drop_current_only,
             sb_time_limit,
+            sb_fill_to,
+            tb_player_name,
+            sp_roster,
+            add_bot_button,
+            roster,
             start_button,
+            host_button,
         }
     }
 
+    fn read_roster(&self, ui: &UserInterface) -> BotRoster {
+        BotRoster {
+            bots: self.roster.iter().map(|entry| entry.config(ui)).collect(),
+            fill_to: ui
+                .node(self.sb_fill_to)
+                .cast::<ScrollBar>()
+                .map_or(0, |scroll_bar| scroll_bar.value as u32),
+        }
+    }
+
+    fn read_options(&self, ui: &UserInterface) -> MatchOptions {
+        let time_limit_minutes =
+            if let Some(scroll_bar) = ui.node(self.sb_time_limit).cast::<ScrollBar>() {
+                scroll_bar.value
+            } else {
+                0.0
+            };
+
+        let frag_limit = if let Some(scroll_bar) = ui.node(self.sb_frag_limit).cast::<ScrollBar>() {
+            scroll_bar.value
+        } else {
+            0.0
+        };
+
+        let match_type =
+            if let Some(dropdown_list) = ui.node(self.dl_match_type).cast::<DropdownList>() {
+                dropdown_list.selection().unwrap_or(0)
+            } else {
+                0
+            };
+
+        let map = ui
+            .node(self.dl_map)
+            .cast::<DropdownList>()
+            .and_then(|dropdown_list| dropdown_list.selection())
+            .and_then(|index| self.maps.get(index))
+            .map(|map| map.path.clone())
+            .unwrap_or_default();
+
+        let is_checked = |check_box: Handle<UiNode>| {
+            ui.node(check_box)
+                .cast::<CheckBox>()
+                .and_then(|check_box| check_box.checked)
+                .unwrap_or(false)
+        };
+
+        let mut options = MatchOptions::new(
+            GameMode::ALL[match_type.min(GameMode::ALL.len() - 1)],
+            time_limit_minutes * 60.0,
+            frag_limit as u32,
+            self.read_roster(ui),
+            map,
+        );
+        options.set_weapon_rules(WeaponRules {
+            weapon_stay: is_checked(self.cb_weapon_stay),
+            drop_current_only: is_checked(self.cb_drop_current_only),
+        });
+        options
+    }
+
     pub fn handle_ui_event(&mut self, engine: &mut Engine, message: &UiMessage) {
         let ui = &mut engine.user_interface;
 
-        if let Some(ButtonMessage::Click) = message.data() {
+        if let Some(&DropdownListMessage::SelectionChanged(Some(index))) = message.data() {
+            if message.destination() == self.dl_map
+                && message.direction() == MessageDirection::FromWidget
+            {
+                let (preview, description) =
+                    map_preview(&self.resource_manager, self.maps.get(index));
+                ui.send_message(ImageMessage::texture(
+                    self.img_map_preview,
+                    MessageDirection::ToWidget,
+                    preview,
+                ));
+                ui.send_message(TextMessage::text(
+                    self.txt_map_info,
+                    MessageDirection::ToWidget,
+                    description,
+                ));
+            }
+        } else if let Some(ButtonMessage::Click) = message.data() {
             if message.destination() == self.start_button {
-                let time_limit_minutes =
-                    if let Some(scroll_bar) = ui.node(self.sb_time_limit).cast::<ScrollBar>() {
-                        scroll_bar.value
-                    } else {
-                        0.0
-                    };
-
-                let frag_limit =
-                    if let Some(scroll_bar) = ui.node(self.sb_frag_limit).cast::<ScrollBar>() {
-                        scroll_bar.value
-                    } else {
-                        0.0
-                    };
-
-                let options = MatchOptions::DeathMatch(DeathMatch {
-                    time_limit_secs: time_limit_minutes * 60.0,
-                    frag_limit: frag_limit as u32,
-                });
-
+                let options = self.read_options(ui);
                 self.sender.send(Message::StartNewGame { options }).unwrap();
+            } else if message.destination() == self.host_button {
+                let options = self.read_options(ui);
+                let name = ui
+                    .node(self.tb_player_name)
+                    .cast::<TextBox>()
+                    .map(|text_box| text_box.text().trim().to_owned())
+                    .unwrap_or_default();
+                self.sender
+                    .send(Message::HostMatch { options, name })
+                    .unwrap();
+                ui.send_message(WindowMessage::close(
+                    self.window,
+                    MessageDirection::ToWidget,
+                ));
+            } else if message.destination() == self.add_bot_button {
+                let config = BotConfig {
+                    name: format!("Bot {}", self.roster.len() + 1),
+                    ..Default::default()
+                };
+                let entry = RosterEntry::new(&mut ui.build_ctx(), &config);
+                ui.send_message(WidgetMessage::link(
+                    entry.root,
+                    MessageDirection::ToWidget,
+                    self.sp_roster,
+                ));
+                self.roster.push(entry);
+            } else if let Some(index) = self
+                .roster
+                .iter()
+                .position(|entry| entry.remove_button == message.destination())
+            {
+                let entry = self.roster.remove(index);
+                ui.send_message(WidgetMessage::remove(
+                    entry.root,
+                    MessageDirection::ToWidget,
+                ));
             }
         }
     }
@@ -1,6 +1,6 @@
 use crate::{
-    control_scheme::ControlScheme, match_menu::MatchMenu, message::Message,
-    options_menu::OptionsMenu,
+    control_scheme::ControlScheme, lobby_menu::LobbyMenu, match_menu::MatchMenu, message::Message,
+    net::Lobby, options_menu::OptionsMenu, save_menu::SaveMenu,
 };
 use fyrox::core::parking_lot::Mutex;
 use fyrox::{
@@ -26,12 +26,15 @@ pub struct Menu {
     sender: Sender<Message>,
     root: Handle<UiNode>,
     btn_new_game: Handle<UiNode>,
+    btn_multiplayer: Handle<UiNode>,
     btn_save_game: Handle<UiNode>,
     btn_settings: Handle<UiNode>,
     btn_load_game: Handle<UiNode>,
     btn_quit_game: Handle<UiNode>,
     options_menu: OptionsMenu,
     match_menu: MatchMenu,
+    lobby_menu: LobbyMenu,
+    save_menu: SaveMenu,
 }
 
 impl Menu {
@@ -39,6 +42,8 @@ impl Menu {
         engine: &mut Engine,
         control_scheme: Arc<RwLock<ControlScheme>>,
         sender: Sender<Message>,
+        music_volume: f32,
+        item_announcements: bool,
     ) -> Self {
         let frame_size = engine.renderer.get_frame_size();
 
@@ -53,6 +58,7 @@ impl Menu {
         let ctx = &mut engine.user_interface.build_ctx();
 
         let btn_new_game;
+        let btn_multiplayer;
         let btn_settings;
         let btn_save_game;
         let btn_load_game;
@@ -83,12 +89,23 @@ impl Menu {
                                         btn_new_game
                                     })
                                     .with_child({
-                                        btn_save_game = ButtonBuilder::new(
+                                        btn_multiplayer = ButtonBuilder::new(
                                             WidgetBuilder::new()
                                                 .on_column(0)
                                                 .on_row(1)
                                                 .with_margin(Thickness::uniform(4.0)),
                                         )
+                                        .with_text_and_font("Multiplayer", font.clone())
+                                        .build(ctx);
+                                        btn_multiplayer
+                                    })
+                                    .with_child({
+                                        btn_save_game = ButtonBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(0)
+                                                .on_row(2)
+                                                .with_margin(Thickness::uniform(4.0)),
+                                        )
                                         .with_text_and_font("Save Game", font.clone())
                                         .build(ctx);
                                         btn_save_game
@@ -97,7 +114,7 @@ impl Menu {
                                         btn_load_game = ButtonBuilder::new(
                                             WidgetBuilder::new()
                                                 .on_column(0)
-                                                .on_row(2)
+                                                .on_row(3)
                                                 .with_margin(Thickness::uniform(4.0)),
                                         )
                                         .with_text_and_font("Load Game", font.clone())
@@ -108,7 +125,7 @@ impl Menu {
                                         btn_settings = ButtonBuilder::new(
                                             WidgetBuilder::new()
                                                 .on_column(0)
-                                                .on_row(3)
+                                                .on_row(4)
                                                 .with_margin(Thickness::uniform(4.0)),
                                         )
                                         .with_text_and_font("Settings", font.clone())
@@ -119,7 +136,7 @@ impl Menu {
                                         btn_quit_game = ButtonBuilder::new(
                                             WidgetBuilder::new()
                                                 .on_column(0)
-                                                .on_row(4)
+                                                .on_row(5)
                                                 .with_margin(Thickness::uniform(4.0)),
                                         )
                                         .with_text_and_font("Quit", font.clone())
@@ -133,6 +150,7 @@ impl Menu {
                             .add_row(Row::strict(75.0))
                             .add_row(Row::strict(75.0))
                             .add_row(Row::strict(75.0))
+                            .add_row(Row::strict(75.0))
                             .build(ctx),
                         )
                         .build(ctx),
@@ -150,12 +168,25 @@ impl Menu {
             sender: sender.clone(),
             root,
             btn_new_game,
+            btn_multiplayer,
             btn_settings,
             btn_save_game,
             btn_load_game,
             btn_quit_game,
-            options_menu: OptionsMenu::new(engine, control_scheme, sender.clone()),
-            match_menu: MatchMenu::new(&mut engine.user_interface, sender),
+            options_menu: OptionsMenu::new(
+                engine,
+                control_scheme,
+                sender.clone(),
+                music_volume,
+                item_announcements,
+            ),
+            match_menu: MatchMenu::new(
+                &mut engine.user_interface,
+                engine.resource_manager.clone(),
+                sender.clone(),
+            ),
+            lobby_menu: LobbyMenu::new(&mut engine.user_interface, sender.clone()),
+            save_menu: SaveMenu::new(&mut engine.user_interface, sender),
         }
     }
 
@@ -166,17 +197,40 @@ impl Menu {
             visible,
         ));
         if !visible {
-            ui.send_message(WindowMessage::close(
-                self.options_menu.window,
-                MessageDirection::ToWidget,
-            ));
+            self.options_menu.close(ui);
             ui.send_message(WindowMessage::close(
                 self.match_menu.window,
                 MessageDirection::ToWidget,
             ));
+            self.lobby_menu.close(ui);
+            self.save_menu.close(ui);
         }
     }
 
+    pub fn update(&mut self, ui: &mut UserInterface) {
+        self.lobby_menu.update(ui);
+    }
+
+    pub fn show_lobby(&mut self, ui: &mut UserInterface, lobby: &Lobby, is_host: bool) {
+        self.lobby_menu.show_lobby(ui, lobby, is_host);
+    }
+
+    pub fn close_lobby(&mut self, ui: &mut UserInterface) {
+        self.lobby_menu.close_lobby(ui);
+    }
+
+    pub fn set_network_status(&mut self, ui: &mut UserInterface, text: &str) {
+        self.lobby_menu.set_status(ui, text);
+        self.lobby_menu.set_lobby_status(ui, text);
+    }
+
+    /// Shows outcome of saving or loading in the save browser and refreshes the list of
+    /// saves.
+    pub fn set_save_status(&mut self, ui: &mut UserInterface, text: &str) {
+        self.save_menu.set_status(ui, text);
+        self.save_menu.refresh(ui);
+    }
+
     pub fn is_visible(&self, ui: &UserInterface) -> bool {
         ui.node(self.root).visibility()
     }
@@ -208,10 +262,12 @@ impl Menu {
                     MessageDirection::ToWidget,
                     true,
                 ));
-            } else if message.destination() == self.btn_save_game {
-                self.sender.send(Message::SaveGame).unwrap();
-            } else if message.destination() == self.btn_load_game {
-                self.sender.send(Message::LoadGame).unwrap();
+            } else if message.destination() == self.btn_multiplayer {
+                self.lobby_menu.open_browser(&mut engine.user_interface);
+            } else if message.destination() == self.btn_save_game
+                || message.destination() == self.btn_load_game
+            {
+                self.save_menu.open(&mut engine.user_interface);
             } else if message.destination() == self.btn_quit_game {
                 self.sender.send(Message::QuitGame).unwrap();
             } else if message.destination() == self.btn_settings {
@@ -225,5 +281,9 @@ impl Menu {
 
         self.options_menu.handle_ui_event(engine, message);
         self.match_menu.handle_ui_event(engine, message);
+        self.lobby_menu
+            .handle_ui_event(&mut engine.user_interface, message);
+        self.save_menu
+            .handle_ui_event(&mut engine.user_interface, message);
     }
 }
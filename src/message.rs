@@ -11,11 +11,14 @@
 
 use crate::{
     actor::Actor,
-    bot::BotKind,
+    bot::{BotKind, BotSkill},
+    character::Team,
+    chat::ChatChannel,
     effects::EffectKind,
     item::{Item, ItemKind},
+    net::ClientId,
     projectile::ProjectileKind,
-    weapon::{Weapon, WeaponKind},
+    weapon::{FireMode, Weapon, WeaponKind},
     MatchOptions,
 };
 use fyrox::core::{
@@ -24,7 +27,7 @@ use fyrox::core::{
 };
 use std::path::PathBuf;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Message {
     GiveNewWeapon {
         actor: Handle<Actor>,
@@ -34,6 +37,8 @@ pub enum Message {
         kind: BotKind,
         position: Vector3<f32>,
         name: Option<String>,
+        team: Team,
+        skill: BotSkill,
     },
     RemoveActor {
         actor: Handle<Actor>,
@@ -44,6 +49,8 @@ pub enum Message {
     SpawnBot {
         kind: BotKind,
         name: String,
+        team: Team,
+        skill: BotSkill,
     },
     /// Gives item of specified kind to a given actor. Basically it means that actor will take
     /// item and consume it immediately (heal itself, add ammo, etc.)
@@ -75,6 +82,7 @@ pub enum Message {
         weapon: Handle<Weapon>,
         initial_velocity: Vector3<f32>,
         direction: Option<Vector3<f32>>,
+        mode: FireMode,
     },
     PlaySound {
         path: PathBuf,
@@ -99,24 +107,114 @@ pub enum Message {
         position: Vector3<f32>,
     },
     SpawnPlayer,
+    /// Spawns player of a network client at random spawn point, the player is controlled by
+    /// input that comes from the client. [`Team::None`] puts the player to the smallest team
+    /// in team matches.
+    SpawnRemotePlayer {
+        client: ClientId,
+        name: String,
+        team: Team,
+    },
+    /// Removes player of a disconnected network client.
+    RemoveRemotePlayer {
+        client: ClientId,
+    },
     /// HUD listens such events and puts them into queue.
     AddNotification {
         text: String,
     },
+    /// Major item will appear in `time` seconds, HUD shows it if item announcements are
+    /// enabled.
+    AnnounceItem {
+        kind: ItemKind,
+        time: f32,
+    },
+    /// Local player wants to say something. The message is checked against rate limit and
+    /// becomes [`Message::Chat`], in multiplayer matches this is done by the server.
+    SendChat {
+        channel: ChatChannel,
+        text: String,
+    },
+    /// Chat message that is put to chat log of HUD, unlike notifications it is shown only to
+    /// players who can see given channel.
+    Chat {
+        sender: String,
+        team: Team,
+        channel: ChatChannel,
+        text: String,
+    },
+    /// Bot tells its team that it sees an enemy. Team remembers enemy position for a while
+    /// so other bots of the team can hunt for it.
+    ReportEnemy {
+        reporter: Handle<Actor>,
+        enemy: Handle<Actor>,
+        position: Vector3<f32>,
+    },
+    /// Badly damaged bot asks its team for help.
+    CallForHelp {
+        actor: Handle<Actor>,
+        position: Vector3<f32>,
+    },
     /// Removes specified actor and creates new one at random spawn point.
     RespawnActor {
         actor: Handle<Actor>,
     },
-    /// Save game state to a file. TODO: Add filename field.
-    SaveGame,
-    /// Loads game state from a file. TODO: Add filename field.
-    LoadGame,
+    /// Saves game state to a save slot, see [`crate::save`].
+    SaveGame {
+        slot: String,
+    },
+    /// Loads game state from a save slot.
+    LoadGame {
+        slot: String,
+    },
     StartNewGame {
         options: MatchOptions,
     },
+    /// Opens a lobby of a listen server, other players can join it before the match starts.
+    HostMatch {
+        options: MatchOptions,
+        /// Name of the player who hosts the match.
+        name: String,
+    },
+    /// Starts the match of the hosted lobby when every player is ready.
+    StartHostedMatch,
+    ConnectToServer {
+        address: String,
+        name: String,
+    },
+    /// Tells the server of a joined lobby which team the player wants to play for and
+    /// whether the player is ready.
+    SetReady {
+        team: Team,
+        ready: bool,
+    },
+    /// Leaves the lobby, closes it if it is hosted.
+    LeaveLobby,
     QuitGame,
+    /// Saves settings of the user to the config file, see [`crate::config`].
+    SaveConfig,
     SetMusicVolume {
         volume: f32,
     },
+    SetItemAnnouncements {
+        enabled: bool,
+    },
     EndMatch,
 }
+
+impl Message {
+    /// Returns `true` if the message must be sent by a server to its clients. Only messages
+    /// with visible or audible outcome are replicated, state of the game is sent in snapshots.
+    pub fn is_replicated(&self) -> bool {
+        matches!(
+            self,
+            Message::PlaySound { .. }
+                | Message::CreateEffect { .. }
+                | Message::AddNotification { .. }
+                | Message::AnnounceItem { .. }
+                | Message::Chat { .. }
+                | Message::CreateProjectile { .. }
+                | Message::SpawnItem { .. }
+        )
+    }
+}
@@ -0,0 +1,939 @@
+//! Network protocol of multiplayer matches. Server is authoritative: it runs the level, clients
+//! send input of their players and receive snapshots of actors and items together with
+//! replicated messages (sounds, effects, projectiles, notifications and chat), see
+//! [`Message::is_replicated`].
+//!
+//! Packets are sent over UDP and none of them is reliable: snapshots are superseded by newer
+//! ones, connection request is repeated until server answers and loss of a cosmetic event is
+//! not a problem. Malformed packets and packets of other protocols are silently ignored, so are
+//! match options whose map is outside of [`crate::map::MAPS_DIR`] and sounds outside of
+//! [`SOUNDS_DIR`].
+//!
+//! Servers answer discovery requests that clients broadcast over local network, see
+//! [`crate::discovery`]. Before a match starts, players can gather in a lobby of a server and
+//! choose their teams.
+//!
+//! Bad networks can be simulated on localhost with `--net-latency <ms>` and `--net-loss
+//! <percent>` arguments of both client and server, see [`NetworkConditions`].
+
+use crate::{
+    actor::Actor,
+    bot::BotKind,
+    character::Team,
+    chat::ChatChannel,
+    effects::EffectKind,
+    item::{Item, ItemKind},
+    map::MapPath,
+    message::Message,
+    player::PlayerInput,
+    projectile::ProjectileKind,
+    roster::BotRoster,
+    weapon::{WeaponKind, WeaponRules},
+    GameMode, MatchOptions,
+};
+use fyrox::{
+    core::{
+        algebra::{Matrix3, Vector3},
+        pool::Handle,
+    },
+    utils::log::{Log, MessageKind},
+};
+use std::{
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// Connection is considered lost if nothing was received from other side for this time.
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of UDP datagram, every packet must fit into it.
+pub const MAX_PACKET_SIZE: usize = 65507;
+
+/// Every packet starts with protocol identifier, it is changed when format of packets changes.
+const PROTOCOL_ID: [u8; 4] = *b"RSN8";
+
+pub type ClientId = u32;
+
+/// Sounds that servers ask to play must be in this directory.
+const SOUNDS_DIR: &str = "data/sounds";
+
+/// Amount of inputs in every input packet, a lost packet does not lose input because next
+/// packets repeat it.
+pub const INPUT_REDUNDANCY: usize = 3;
+
+pub enum ClientPacket {
+    Connect {
+        name: String,
+    },
+    /// Input of client's player, it is sent every simulation step.
+    Input {
+        /// Sequence number of the last input in `inputs`, previous inputs have previous
+        /// numbers. Numbers start from one.
+        sequence: u32,
+        /// Time of the match that client shows, see [`crate::lag_compensation`].
+        view_time: f32,
+        inputs: Vec<PlayerInput>,
+    },
+    Disconnect,
+    /// Request for [`ServerPacket::Info`], it is broadcast by server browsers.
+    Discover {
+        /// Server repeats the token in its answer, so outdated answers can be ignored.
+        token: u32,
+    },
+    /// Choice of a player in a lobby, it is repeated while the player is in the lobby to
+    /// keep the connection alive.
+    LobbyChoice {
+        team: Team,
+        ready: bool,
+    },
+    /// Chat message of client's player, server turns it into [`Message::Chat`].
+    Chat {
+        channel: ChatChannel,
+        text: String,
+    },
+}
+
+pub enum ServerPacket {
+    Accepted {
+        client: ClientId,
+        options: MatchOptions,
+    },
+    Snapshot(Snapshot),
+    /// Replicated message, see [`Message::is_replicated`].
+    Event(Message),
+    Disconnect {
+        reason: String,
+    },
+    Info {
+        token: u32,
+        info: ServerInfo,
+    },
+    /// State of the lobby, it is sent to connected clients until the match starts. Then
+    /// clients get [`ServerPacket::Accepted`].
+    Lobby {
+        client: ClientId,
+        lobby: Lobby,
+    },
+}
+
+/// Description of a server in server browser.
+#[derive(Clone)]
+pub struct ServerInfo {
+    pub name: String,
+    pub map: String,
+    pub options: MatchOptions,
+    pub players: u32,
+    /// `true` if the match has not started yet.
+    pub in_lobby: bool,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct LobbyPlayer {
+    pub name: String,
+    /// Chosen team, [`Team::None`] means any team.
+    pub team: Team,
+    pub ready: bool,
+}
+
+#[derive(Clone)]
+pub struct Lobby {
+    pub server_name: String,
+    pub options: MatchOptions,
+    pub players: Vec<LobbyPlayer>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ReplicatedActorKind {
+    Player,
+    Bot(BotKind),
+}
+
+#[derive(Clone)]
+pub struct ActorState {
+    /// Handle of the actor on server, it is used to identify actors between snapshots.
+    pub actor: Handle<Actor>,
+    pub kind: ReplicatedActorKind,
+    pub name: String,
+    pub team: Team,
+    pub position: Vector3<f32>,
+    /// Rotation of actor's body around vertical axis in radians.
+    pub yaw: f32,
+    pub health: f32,
+    pub armor: f32,
+}
+
+#[derive(Clone)]
+pub struct ItemState {
+    pub item: Handle<Item>,
+    pub active: bool,
+}
+
+#[derive(Clone)]
+pub struct ScoreState {
+    pub name: String,
+    pub kills: u32,
+    pub deaths: u32,
+}
+
+#[derive(Default, Clone)]
+pub struct Snapshot {
+    /// Simulation step of the server, older snapshots are dropped by clients.
+    pub tick: u32,
+    /// Time of the match in seconds.
+    pub time: f32,
+    /// Actor that is controlled by the client that receives the snapshot, it is
+    /// [`Handle::NONE`] while the actor is dead.
+    pub own_actor: Handle<Actor>,
+    /// Velocity of client's actor.
+    pub own_velocity: Vector3<f32>,
+    /// Weapons of client's actor and their ammo.
+    pub own_weapons: Vec<(WeaponKind, u32)>,
+    /// Sequence number of the last input of the client that was applied before the
+    /// snapshot was made, zero means none.
+    pub ack_input: u32,
+    pub actors: Vec<ActorState>,
+    pub items: Vec<ItemState>,
+    pub scores: Vec<ScoreState>,
+    pub team_scores: Vec<(Team, u32)>,
+}
+
+const TEAMS: [Team; 3] = [Team::None, Team::Red, Team::Blue];
+
+const WEAPON_KINDS: [WeaponKind; 4] = [
+    WeaponKind::M4,
+    WeaponKind::Ak47,
+    WeaponKind::PlasmaRifle,
+    WeaponKind::RocketLauncher,
+];
+
+const ITEM_KINDS: [ItemKind; 9] = [
+    ItemKind::Medkit,
+    ItemKind::Plasma,
+    ItemKind::Ak47Ammo,
+    ItemKind::M4Ammo,
+    ItemKind::PlasmaGun,
+    ItemKind::Ak47,
+    ItemKind::M4,
+    ItemKind::RocketLauncher,
+    ItemKind::RocketAmmo,
+];
+
+const EFFECT_KINDS: [EffectKind; 4] = [
+    EffectKind::BulletImpact,
+    EffectKind::ItemAppear,
+    EffectKind::Smoke,
+    EffectKind::Steam,
+];
+
+const CHAT_CHANNELS: [ChatChannel; 2] = [ChatChannel::All, ChatChannel::Team];
+
+const PROJECTILE_KINDS: [ProjectileKind; 6] = [
+    ProjectileKind::Plasma,
+    ProjectileKind::Bullet,
+    ProjectileKind::Rocket,
+    ProjectileKind::Grenade,
+    ProjectileKind::ChargedPlasma,
+    ProjectileKind::GuidedRocket,
+];
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn new() -> Self {
+        Self(PROTOCOL_ID.to_vec())
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn vec3(&mut self, value: &Vector3<f32>) {
+        for &v in value.iter() {
+            self.f32(v);
+        }
+    }
+
+    fn handle<T>(&mut self, handle: Handle<T>) {
+        self.u32(handle.index());
+        self.u32(handle.generation());
+    }
+
+    fn enumeration<T: PartialEq>(&mut self, all: &[T], value: &T) {
+        self.u8(all.iter().position(|v| v == value).unwrap_or_default() as u8);
+    }
+
+    fn input(&mut self, input: &PlayerInput) {
+        self.u16(input.controls);
+        self.u8(input.current_weapon);
+        self.f32(input.yaw);
+        self.f32(input.pitch);
+    }
+
+    fn match_options(&mut self, options: &MatchOptions) {
+        let (time_limit, limit) = match options {
+            MatchOptions::DeathMatch(dm) => (dm.time_limit_secs, dm.frag_limit),
+            MatchOptions::TeamDeathMatch(tdm) => (tdm.time_limit_secs, tdm.team_frag_limit),
+            MatchOptions::CaptureTheFlag(ctf) => (ctf.time_limit_secs, ctf.flag_limit),
+        };
+        self.enumeration(&GameMode::ALL, &options.mode());
+        self.f32(time_limit);
+        self.u32(limit);
+        self.str(&options.map().0);
+        let weapon_rules = options.weapon_rules();
+        self.bool(weapon_rules.weapon_stay);
+        self.bool(weapon_rules.drop_current_only);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        data.strip_prefix(&PROTOCOL_ID[..]).map(Self)
+    }
+
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.0.len() < count {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        self.u8().map(|v| v != 0)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn vec3(&mut self) -> Option<Vector3<f32>> {
+        Some(Vector3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn handle<T>(&mut self) -> Option<Handle<T>> {
+        Some(Handle::new(self.u32()?, self.u32()?))
+    }
+
+    fn enumeration<T: Copy>(&mut self, all: &[T]) -> Option<T> {
+        all.get(self.u8()? as usize).copied()
+    }
+
+    fn input(&mut self) -> Option<PlayerInput> {
+        Some(PlayerInput {
+            controls: self.u16()?,
+            current_weapon: self.u8()?,
+            yaw: self.f32()?,
+            pitch: self.f32()?,
+        })
+    }
+
+    fn match_options(&mut self) -> Option<MatchOptions> {
+        let mode = self.enumeration(&GameMode::ALL)?;
+        let time_limit_secs = self.f32()?;
+        let limit = self.u32()?;
+        let map = MapPath(self.str()?);
+        if !map.is_in_maps_dir() {
+            return None;
+        }
+        let weapon_rules = WeaponRules {
+            weapon_stay: self.bool()?,
+            drop_current_only: self.bool()?,
+        };
+        // Bots exist only on the server, clients see them in snapshots.
+        let roster = BotRoster {
+            bots: Vec::new(),
+            fill_to: 0,
+        };
+        let mut options = MatchOptions::new(mode, time_limit_secs, limit, roster, map);
+        options.set_weapon_rules(weapon_rules);
+        Some(options)
+    }
+}
+
+impl ClientPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        match self {
+            ClientPacket::Connect { name } => {
+                w.u8(0);
+                w.str(name);
+            }
+            ClientPacket::Input {
+                sequence,
+                view_time,
+                inputs,
+            } => {
+                w.u8(1);
+                w.u32(*sequence);
+                w.f32(*view_time);
+                w.u8(inputs.len() as u8);
+                for input in inputs.iter() {
+                    w.input(input);
+                }
+            }
+            ClientPacket::Disconnect => w.u8(2),
+            ClientPacket::Discover { token } => {
+                w.u8(3);
+                w.u32(*token);
+            }
+            ClientPacket::LobbyChoice { team, ready } => {
+                w.u8(4);
+                w.enumeration(&TEAMS, team);
+                w.bool(*ready);
+            }
+            ClientPacket::Chat { channel, text } => {
+                w.u8(5);
+                w.enumeration(&CHAT_CHANNELS, channel);
+                w.str(text);
+            }
+        }
+        w.0
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data)?;
+        Some(match r.u8()? {
+            0 => ClientPacket::Connect { name: r.str()? },
+            1 => {
+                let sequence = r.u32()?;
+                let view_time = r.f32()?;
+                let mut inputs = Vec::new();
+                for _ in 0..r.u8()? {
+                    inputs.push(r.input()?);
+                }
+                ClientPacket::Input {
+                    sequence,
+                    view_time,
+                    inputs,
+                }
+            }
+            2 => ClientPacket::Disconnect,
+            3 => ClientPacket::Discover { token: r.u32()? },
+            4 => ClientPacket::LobbyChoice {
+                team: r.enumeration(&TEAMS)?,
+                ready: r.bool()?,
+            },
+            5 => ClientPacket::Chat {
+                channel: r.enumeration(&CHAT_CHANNELS)?,
+                text: r.str()?,
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl ServerPacket {
+    /// Returns `None` if the packet cannot be sent, this happens for messages that are not
+    /// replicated.
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let mut w = Writer::new();
+        match self {
+            ServerPacket::Accepted { client, options } => {
+                w.u8(0);
+                w.u32(*client);
+                w.match_options(options);
+            }
+            ServerPacket::Snapshot(snapshot) => {
+                w.u8(1);
+                encode_snapshot(&mut w, snapshot);
+            }
+            ServerPacket::Event(message) => {
+                w.u8(2);
+                encode_message(&mut w, message)?;
+            }
+            ServerPacket::Disconnect { reason } => {
+                w.u8(3);
+                w.str(reason);
+            }
+            ServerPacket::Info { token, info } => {
+                w.u8(4);
+                w.u32(*token);
+                w.str(&info.name);
+                w.str(&info.map);
+                w.match_options(&info.options);
+                w.u32(info.players);
+                w.bool(info.in_lobby);
+            }
+            ServerPacket::Lobby { client, lobby } => {
+                w.u8(5);
+                w.u32(*client);
+                w.str(&lobby.server_name);
+                w.match_options(&lobby.options);
+                w.u32(lobby.players.len() as u32);
+                for player in lobby.players.iter() {
+                    w.str(&player.name);
+                    w.enumeration(&TEAMS, &player.team);
+                    w.bool(player.ready);
+                }
+            }
+        }
+        Some(w.0)
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data)?;
+        Some(match r.u8()? {
+            0 => ServerPacket::Accepted {
+                client: r.u32()?,
+                options: r.match_options()?,
+            },
+            1 => ServerPacket::Snapshot(decode_snapshot(&mut r)?),
+            2 => ServerPacket::Event(decode_message(&mut r)?),
+            3 => ServerPacket::Disconnect { reason: r.str()? },
+            4 => ServerPacket::Info {
+                token: r.u32()?,
+                info: ServerInfo {
+                    name: r.str()?,
+                    map: r.str()?,
+                    options: r.match_options()?,
+                    players: r.u32()?,
+                    in_lobby: r.bool()?,
+                },
+            },
+            5 => {
+                let client = r.u32()?;
+                let server_name = r.str()?;
+                let options = r.match_options()?;
+                let mut players = Vec::new();
+                for _ in 0..r.u32()? {
+                    players.push(LobbyPlayer {
+                        name: r.str()?,
+                        team: r.enumeration(&TEAMS)?,
+                        ready: r.bool()?,
+                    });
+                }
+                ServerPacket::Lobby {
+                    client,
+                    lobby: Lobby {
+                        server_name,
+                        options,
+                        players,
+                    },
+                }
+            }
+            _ => return None,
+        })
+    }
+}
+
+fn encode_snapshot(w: &mut Writer, snapshot: &Snapshot) {
+    w.u32(snapshot.tick);
+    w.f32(snapshot.time);
+    w.handle(snapshot.own_actor);
+    w.vec3(&snapshot.own_velocity);
+    w.u32(snapshot.ack_input);
+
+    w.u32(snapshot.own_weapons.len() as u32);
+    for (kind, ammo) in snapshot.own_weapons.iter() {
+        w.enumeration(&WEAPON_KINDS, kind);
+        w.u32(*ammo);
+    }
+
+    w.u32(snapshot.actors.len() as u32);
+    for actor in snapshot.actors.iter() {
+        w.handle(actor.actor);
+        match actor.kind {
+            ReplicatedActorKind::Player => w.u8(0),
+            ReplicatedActorKind::Bot(kind) => {
+                w.u8(1);
+                w.enumeration(&BotKind::ALL, &kind);
+            }
+        }
+        w.str(&actor.name);
+        w.enumeration(&TEAMS, &actor.team);
+        w.vec3(&actor.position);
+        w.f32(actor.yaw);
+        w.f32(actor.health);
+        w.f32(actor.armor);
+    }
+
+    w.u32(snapshot.items.len() as u32);
+    for item in snapshot.items.iter() {
+        w.handle(item.item);
+        w.bool(item.active);
+    }
+
+    w.u32(snapshot.scores.len() as u32);
+    for score in snapshot.scores.iter() {
+        w.str(&score.name);
+        w.u32(score.kills);
+        w.u32(score.deaths);
+    }
+
+    w.u32(snapshot.team_scores.len() as u32);
+    for (team, score) in snapshot.team_scores.iter() {
+        w.enumeration(&TEAMS, team);
+        w.u32(*score);
+    }
+}
+
+fn decode_snapshot(r: &mut Reader) -> Option<Snapshot> {
+    let tick = r.u32()?;
+    let time = r.f32()?;
+    let own_actor = r.handle()?;
+    let own_velocity = r.vec3()?;
+    let ack_input = r.u32()?;
+
+    // Counts are not trusted, collections grow as elements are actually read.
+    let mut own_weapons = Vec::new();
+    for _ in 0..r.u32()? {
+        own_weapons.push((r.enumeration(&WEAPON_KINDS)?, r.u32()?));
+    }
+
+    let mut actors = Vec::new();
+    for _ in 0..r.u32()? {
+        actors.push(ActorState {
+            actor: r.handle()?,
+            kind: match r.u8()? {
+                0 => ReplicatedActorKind::Player,
+                1 => ReplicatedActorKind::Bot(r.enumeration(&BotKind::ALL)?),
+                _ => return None,
+            },
+            name: r.str()?,
+            team: r.enumeration(&TEAMS)?,
+            position: r.vec3()?,
+            yaw: r.f32()?,
+            health: r.f32()?,
+            armor: r.f32()?,
+        });
+    }
+
+    let mut items = Vec::new();
+    for _ in 0..r.u32()? {
+        items.push(ItemState {
+            item: r.handle()?,
+            active: r.bool()?,
+        });
+    }
+
+    let mut scores = Vec::new();
+    for _ in 0..r.u32()? {
+        scores.push(ScoreState {
+            name: r.str()?,
+            kills: r.u32()?,
+            deaths: r.u32()?,
+        });
+    }
+
+    let mut team_scores = Vec::new();
+    for _ in 0..r.u32()? {
+        team_scores.push((r.enumeration(&TEAMS)?, r.u32()?));
+    }
+
+    Some(Snapshot {
+        tick,
+        time,
+        own_actor,
+        own_velocity,
+        own_weapons,
+        ack_input,
+        actors,
+        items,
+        scores,
+        team_scores,
+    })
+}
+
+fn encode_message(w: &mut Writer, message: &Message) -> Option<()> {
+    match message {
+        Message::PlaySound {
+            path,
+            position,
+            gain,
+            rolloff_factor,
+            radius,
+        } => {
+            w.u8(0);
+            w.str(&path.to_string_lossy());
+            w.vec3(position);
+            w.f32(*gain);
+            w.f32(*rolloff_factor);
+            w.f32(*radius);
+        }
+        Message::CreateEffect { kind, position } => {
+            w.u8(1);
+            w.enumeration(&EFFECT_KINDS, kind);
+            w.vec3(position);
+        }
+        Message::AddNotification { text } => {
+            w.u8(2);
+            w.str(text);
+        }
+        Message::CreateProjectile {
+            kind,
+            position,
+            direction,
+            initial_velocity,
+            basis,
+            ..
+        } => {
+            // Owner is not replicated, weapons of the server do not exist on clients.
+            w.u8(3);
+            w.enumeration(&PROJECTILE_KINDS, kind);
+            w.vec3(position);
+            w.vec3(direction);
+            w.vec3(initial_velocity);
+            for &v in basis.iter() {
+                w.f32(v);
+            }
+        }
+        Message::SpawnItem {
+            kind,
+            position,
+            adjust_height,
+            lifetime,
+        } => {
+            w.u8(4);
+            w.enumeration(&ITEM_KINDS, kind);
+            w.vec3(position);
+            w.bool(*adjust_height);
+            w.bool(lifetime.is_some());
+            w.f32(lifetime.unwrap_or_default());
+        }
+        Message::Chat {
+            sender,
+            team,
+            channel,
+            text,
+        } => {
+            w.u8(5);
+            w.str(sender);
+            w.enumeration(&TEAMS, team);
+            w.enumeration(&CHAT_CHANNELS, channel);
+            w.str(text);
+        }
+        Message::AnnounceItem { kind, time } => {
+            w.u8(6);
+            w.enumeration(&ITEM_KINDS, kind);
+            w.f32(*time);
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Returns `true` if the path names a file in [`SOUNDS_DIR`] or its subdirectories. Paths that
+/// come from the network must not point anywhere else.
+fn is_in_sounds_dir(path: &Path) -> bool {
+    path.strip_prefix(SOUNDS_DIR).map_or(false, |relative| {
+        relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    })
+}
+
+fn decode_message(r: &mut Reader) -> Option<Message> {
+    Some(match r.u8()? {
+        0 => Message::PlaySound {
+            path: {
+                let path = PathBuf::from(r.str()?);
+                if !is_in_sounds_dir(&path) {
+                    return None;
+                }
+                path
+            },
+            position: r.vec3()?,
+            gain: r.f32()?,
+            rolloff_factor: r.f32()?,
+            radius: r.f32()?,
+        },
+        1 => Message::CreateEffect {
+            kind: r.enumeration(&EFFECT_KINDS)?,
+            position: r.vec3()?,
+        },
+        2 => Message::AddNotification { text: r.str()? },
+        3 => Message::CreateProjectile {
+            kind: r.enumeration(&PROJECTILE_KINDS)?,
+            position: r.vec3()?,
+            direction: r.vec3()?,
+            initial_velocity: r.vec3()?,
+            owner: Handle::NONE,
+            basis: {
+                let mut basis = [0.0; 9];
+                for v in basis.iter_mut() {
+                    *v = r.f32()?;
+                }
+                Matrix3::from_column_slice(&basis)
+            },
+        },
+        4 => Message::SpawnItem {
+            kind: r.enumeration(&ITEM_KINDS)?,
+            position: r.vec3()?,
+            adjust_height: r.bool()?,
+            lifetime: {
+                let has_lifetime = r.bool()?;
+                let lifetime = r.f32()?;
+                if has_lifetime {
+                    Some(lifetime)
+                } else {
+                    None
+                }
+            },
+        },
+        5 => Message::Chat {
+            sender: r.str()?,
+            team: r.enumeration(&TEAMS)?,
+            channel: r.enumeration(&CHAT_CHANNELS)?,
+            text: r.str()?,
+        },
+        6 => Message::AnnounceItem {
+            kind: r.enumeration(&ITEM_KINDS)?,
+            time: r.f32()?,
+        },
+        _ => return None,
+    })
+}
+
+/// Simulated network conditions, they are applied to outgoing packets. If both client and
+/// server simulate latency, round trip time grows by two latencies.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct NetworkConditions {
+    pub latency: Duration,
+    /// Probability of a packet to be lost, from zero to one.
+    pub loss: f32,
+}
+
+impl NetworkConditions {
+    /// Takes `--net-latency` and `--net-loss` arguments out of given arguments.
+    pub fn take_from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut conditions = Self::default();
+
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].clone();
+            if arg != "--net-latency" && arg != "--net-loss" {
+                i += 1;
+                continue;
+            }
+
+            let value = args
+                .get(i + 1)
+                .ok_or_else(|| format!("Missing value for {} argument", arg))?;
+            let number = value
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid value {} for {} argument", value, arg))?;
+            if arg == "--net-latency" {
+                conditions.latency = Duration::from_secs_f32(number.max(0.0) / 1000.0);
+            } else {
+                conditions.loss = (number / 100.0).max(0.0).min(1.0);
+            }
+            args.drain(i..i + 2);
+        }
+
+        Ok(conditions)
+    }
+}
+
+struct DelayedPacket {
+    send_time: Instant,
+    address: SocketAddr,
+    data: Vec<u8>,
+}
+
+/// Non-blocking UDP socket that simulates given network conditions.
+pub struct Socket {
+    socket: UdpSocket,
+    conditions: NetworkConditions,
+    delayed: VecDeque<DelayedPacket>,
+}
+
+impl Socket {
+    pub fn bind<A: ToSocketAddrs>(address: A, conditions: NetworkConditions) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            conditions,
+            delayed: Default::default(),
+        })
+    }
+
+    /// Lets the socket send packets to broadcast addresses.
+    pub fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        self.socket.set_broadcast(broadcast)
+    }
+
+    /// Sends a packet, network errors are only logged - lost packets are normal for UDP.
+    pub fn send(&mut self, address: SocketAddr, data: &[u8]) {
+        if self.conditions.loss > 0.0 && fyrox::rand::random::<f32>() < self.conditions.loss {
+            return;
+        }
+
+        self.delayed.push_back(DelayedPacket {
+            send_time: Instant::now() + self.conditions.latency,
+            address,
+            data: data.to_vec(),
+        });
+        self.flush();
+    }
+
+    /// Receives a packet, `WouldBlock` error means that there are no packets.
+    pub fn recv_from(&mut self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.flush();
+        self.socket.recv_from(buffer)
+    }
+
+    /// Sends delayed packets whose time has come.
+    fn flush(&mut self) {
+        let now = Instant::now();
+        while let Some(packet) = self.delayed.front() {
+            if packet.send_time > now {
+                break;
+            }
+            if let Err(e) = self.socket.send_to(&packet.data, packet.address) {
+                Log::writeln(
+                    MessageKind::Warning,
+                    format!("Failed to send packet to {}, reason: {}", packet.address, e),
+                );
+            }
+            self.delayed.pop_front();
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use crate::{
     control_scheme::{ControlButton, ControlScheme},
-    gui::{create_check_box, create_scroll_bar, create_scroll_viewer, ScrollBarData},
+    gui::{
+        create_check_box, create_dropdown_items, create_scroll_bar, create_scroll_viewer,
+        ScrollBarData,
+    },
     message::Message,
+    weapon::AutoSwitch,
 };
 use fyrox::{
     core::pool::Handle,
@@ -12,15 +16,17 @@ use fyrox::{
         button::{Button, ButtonBuilder, ButtonMessage},
         check_box::CheckBoxMessage,
         decorator::DecoratorBuilder,
+        dropdown_list::{DropdownListBuilder, DropdownListMessage},
         grid::{Column, GridBuilder, Row},
         list_view::{ListViewBuilder, ListViewMessage},
         message::{MessageDirection, UiMessage},
         scroll_bar::ScrollBarMessage,
+        scroll_viewer::ScrollViewerBuilder,
         tab_control::{TabControlBuilder, TabDefinition},
         text::{TextBuilder, TextMessage},
         widget::WidgetBuilder,
-        window::{WindowBuilder, WindowTitle},
-        HorizontalAlignment, Orientation, Thickness, UiNode, VerticalAlignment,
+        window::{WindowBuilder, WindowMessage, WindowTitle},
+        HorizontalAlignment, Orientation, Thickness, UiNode, UserInterface, VerticalAlignment,
     },
     monitor::VideoMode,
     utils::log::{Log, MessageKind},
@@ -50,8 +56,12 @@ pub struct OptionsMenu {
     cb_mouse_y_inverse: Handle<UiNode>,
     cb_smooth_mouse: Handle<UiNode>,
     cb_shake_camera: Handle<UiNode>,
+    dl_auto_switch: Handle<UiNode>,
     btn_reset_control_scheme: Handle<UiNode>,
     cb_use_hrtf: Handle<UiNode>,
+    cb_item_announcements: Handle<UiNode>,
+    /// Settings were changed since the config was saved last time.
+    changed: bool,
     btn_reset_audio_settings: Handle<UiNode>,
 }
 
@@ -60,6 +70,8 @@ impl OptionsMenu {
         engine: &mut Engine,
         control_scheme: Arc<RwLock<ControlScheme>>,
         sender: Sender<Message>,
+        music_volume: f32,
+        item_announcements: bool,
     ) -> Self {
         let video_modes: Vec<VideoMode> = engine
             .get_window()
@@ -69,6 +81,9 @@ impl OptionsMenu {
             .filter(|vm| vm.size().width > 800 && vm.size().height > 600 && vm.bit_depth() == 32)
             .collect();
 
+        let sound_volume = engine.sound_gain();
+        let is_fullscreen = engine.get_window().fullscreen().is_some();
+
         let ctx = &mut engine.user_interface.build_ctx();
 
         let common_row = Row::strict(36.0);
@@ -91,9 +106,11 @@ impl OptionsMenu {
         let cb_mouse_y_inverse;
         let cb_smooth_mouse;
         let cb_shake_camera;
+        let dl_auto_switch;
         let btn_reset_control_scheme;
         let mut control_scheme_buttons = Vec::new();
         let cb_use_hrtf;
+        let cb_item_announcements;
         let btn_reset_audio_settings;
         let cb_use_light_scatter;
         let tab_control = TabControlBuilder::new(WidgetBuilder::new())
@@ -183,7 +200,7 @@ impl OptionsMenu {
                                 .build(ctx),
                             )
                             .with_child({
-                                cb_fullscreen = create_check_box(ctx, 1, 1, false);
+                                cb_fullscreen = create_check_box(ctx, 1, 1, is_fullscreen);
                                 cb_fullscreen
                             })
                             // Spot Shadows Enabled
@@ -369,7 +386,7 @@ impl OptionsMenu {
                                     ScrollBarData {
                                         min: 0.0,
                                         max: 1.0,
-                                        value: 1.0,
+                                        value: sound_volume,
                                         step: 0.025,
                                         row: 0,
                                         column: 1,
@@ -397,7 +414,7 @@ impl OptionsMenu {
                                     ScrollBarData {
                                         min: 0.0,
                                         max: 1.0,
-                                        value: 0.0,
+                                        value: music_volume,
                                         step: 0.025,
                                         row: 1,
                                         column: 1,
@@ -423,9 +440,25 @@ impl OptionsMenu {
                                 cb_use_hrtf = create_check_box(ctx, 2, 1, true);
                                 cb_use_hrtf
                             })
+                            .with_child(
+                                TextBuilder::new(
+                                    WidgetBuilder::new()
+                                        .on_row(3)
+                                        .on_column(0)
+                                        .with_margin(margin),
+                                )
+                                .with_text("Item Announcements")
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx),
+                            )
+                            .with_child({
+                                cb_item_announcements =
+                                    create_check_box(ctx, 3, 1, item_announcements);
+                                cb_item_announcements
+                            })
                             .with_child({
                                 btn_reset_audio_settings = ButtonBuilder::new(
-                                    WidgetBuilder::new().on_row(3).with_margin(margin),
+                                    WidgetBuilder::new().on_row(4).with_margin(margin),
                                 )
                                 .with_text("Reset")
                                 .build(ctx);
@@ -436,6 +469,7 @@ impl OptionsMenu {
                     .add_row(common_row)
                     .add_row(common_row)
                     .add_row(common_row)
+                    .add_row(common_row)
                     .add_column(Column::strict(250.0))
                     .add_column(Column::stretch())
                     .build(ctx)
@@ -453,7 +487,7 @@ impl OptionsMenu {
                     for (row, button) in control_scheme.read().unwrap().buttons().iter().enumerate()
                     {
                         // Offset by total amount of rows that goes before
-                        let row = row + 4;
+                        let row = row + 5;
 
                         let text = TextBuilder::new(
                             WidgetBuilder::new()
@@ -478,7 +512,8 @@ impl OptionsMenu {
                         control_scheme_buttons.push(button);
                     }
 
-                    GridBuilder::new(
+                    // There are too many controls to fit the window.
+                    let grid = GridBuilder::new(
                         WidgetBuilder::new()
                             .with_child(
                                 TextBuilder::new(
@@ -568,10 +603,40 @@ impl OptionsMenu {
                                 );
                                 cb_shake_camera
                             })
+                            .with_child(
+                                TextBuilder::new(
+                                    WidgetBuilder::new()
+                                        .on_row(4)
+                                        .on_column(0)
+                                        .with_margin(margin),
+                                )
+                                .with_text("Switch To New Weapon")
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx),
+                            )
+                            .with_child({
+                                let names = AutoSwitch::ALL.map(|auto_switch| auto_switch.name());
+                                let auto_switch = control_scheme.read().unwrap().auto_switch;
+                                dl_auto_switch = DropdownListBuilder::new(
+                                    WidgetBuilder::new()
+                                        .on_row(4)
+                                        .on_column(1)
+                                        .with_margin(margin),
+                                )
+                                .with_items(create_dropdown_items(ctx, &names))
+                                .with_selected(
+                                    AutoSwitch::ALL
+                                        .iter()
+                                        .position(|&a| a == auto_switch)
+                                        .unwrap_or(0),
+                                )
+                                .build(ctx);
+                                dl_auto_switch
+                            })
                             .with_child({
                                 btn_reset_control_scheme = ButtonBuilder::new(
                                     WidgetBuilder::new()
-                                        .on_row(4 + control_scheme.read().unwrap().buttons().len())
+                                        .on_row(5 + control_scheme.read().unwrap().buttons().len())
                                         .with_margin(margin),
                                 )
                                 .with_text("Reset")
@@ -586,13 +651,18 @@ impl OptionsMenu {
                     .add_row(common_row)
                     .add_row(common_row)
                     .add_row(common_row)
+                    .add_row(common_row)
                     .add_rows(
                         (0..control_scheme.read().unwrap().buttons().len())
                             .map(|_| common_row)
                             .collect(),
                     )
                     .add_row(common_row)
-                    .build(ctx)
+                    .build(ctx);
+
+                    ScrollViewerBuilder::new(WidgetBuilder::new().with_height(500.0))
+                        .with_content(grid)
+                        .build(ctx)
                 },
             })
             .build(ctx);
@@ -625,10 +695,29 @@ impl OptionsMenu {
             cb_mouse_y_inverse,
             cb_smooth_mouse,
             cb_shake_camera,
+            dl_auto_switch,
             btn_reset_control_scheme,
             cb_use_hrtf,
+            cb_item_announcements,
             btn_reset_audio_settings,
             cb_use_light_scatter,
+            changed: false,
+        }
+    }
+
+    pub fn close(&mut self, ui: &mut UserInterface) {
+        ui.send_message(WindowMessage::close(
+            self.window,
+            MessageDirection::ToWidget,
+        ));
+        self.save_changes();
+    }
+
+    /// Asks the game to save the config if something was changed.
+    fn save_changes(&mut self) {
+        if self.changed {
+            self.changed = false;
+            self.sender.send(Message::SaveConfig).unwrap();
         }
     }
 
@@ -653,6 +742,13 @@ impl OptionsMenu {
         sync_check_box(self.cb_mouse_y_inverse, control_scheme.mouse_y_inverse);
         sync_check_box(self.cb_smooth_mouse, control_scheme.smooth_mouse);
         sync_check_box(self.cb_shake_camera, control_scheme.shake_camera);
+        ui.send_message(DropdownListMessage::selection(
+            self.dl_auto_switch,
+            MessageDirection::ToWidget,
+            AutoSwitch::ALL
+                .iter()
+                .position(|&auto_switch| auto_switch == control_scheme.auto_switch),
+        ));
         let is_hrtf = true; /*if let fyrox::sound::renderer::Renderer::HrtfRenderer(_) =
                                 engine.sound_context.lock().unwrap().renderer()
                             {
@@ -742,6 +838,7 @@ impl OptionsMenu {
 
                     self.control_scheme.write().unwrap().buttons_mut()[active_control_button]
                         .button = control_button;
+                    self.changed = true;
 
                     self.active_control_button = None;
                 }
@@ -756,6 +853,7 @@ impl OptionsMenu {
 
         if let Some(ScrollBarMessage::Value(new_value)) = message.data() {
             if message.direction() == MessageDirection::FromWidget {
+                self.changed = true;
                 if message.destination() == self.sb_sound_volume {
                     engine.set_sound_gain(*new_value)
                 } else if message.destination() == self.sb_point_shadow_distance {
@@ -770,19 +868,46 @@ impl OptionsMenu {
                         .unwrap();
                 }
             }
+        } else if let Some(&DropdownListMessage::SelectionChanged(Some(index))) = message.data() {
+            if message.destination() == self.dl_auto_switch
+                && message.direction() == MessageDirection::FromWidget
+            {
+                if let Some(&auto_switch) = AutoSwitch::ALL.get(index) {
+                    self.control_scheme.write().unwrap().auto_switch = auto_switch;
+                    self.changed = true;
+                }
+            }
         } else if let Some(ListViewMessage::SelectionChanged(new_value)) = message.data() {
             if message.destination() == self.lb_video_modes {
                 if let Some(index) = new_value {
                     let video_mode = self.video_modes[*index].clone();
                     engine
                         .get_window()
-                        .set_fullscreen(Some(Fullscreen::Exclusive(video_mode)))
+                        .set_fullscreen(Some(Fullscreen::Exclusive(video_mode)));
+                    engine.user_interface.send_message(CheckBoxMessage::checked(
+                        self.cb_fullscreen,
+                        MessageDirection::ToWidget,
+                        Some(true),
+                    ));
+                    self.changed = true;
                 }
             }
         } else if let Some(CheckBoxMessage::Check(value)) = message.data() {
             let value = value.unwrap_or(false);
             let mut control_scheme = self.control_scheme.write().unwrap();
-            if message.destination() == self.cb_point_shadows {
+            if message.direction() == MessageDirection::FromWidget {
+                self.changed = true;
+            }
+            if message.destination() == self.cb_fullscreen {
+                let window = engine.get_window();
+                if value != window.fullscreen().is_some() {
+                    window.set_fullscreen(if value {
+                        Some(Fullscreen::Borderless(None))
+                    } else {
+                        None
+                    });
+                }
+            } else if message.destination() == self.cb_point_shadows {
                 settings.point_shadows_enabled = value;
             } else if message.destination() == self.cb_spot_shadows {
                 settings.spot_shadows_enabled = value;
@@ -798,14 +923,22 @@ impl OptionsMenu {
                 control_scheme.shake_camera = value;
             } else if message.destination() == self.cb_use_light_scatter {
                 settings.light_scatter_enabled = value;
+            } else if message.destination() == self.cb_item_announcements
+                && message.direction() == MessageDirection::FromWidget
+            {
+                self.sender
+                    .send(Message::SetItemAnnouncements { enabled: value })
+                    .unwrap();
             }
         } else if let Some(ButtonMessage::Click) = message.data() {
             if message.destination() == self.btn_reset_control_scheme {
                 self.control_scheme.write().unwrap().reset();
                 self.sync_to_model(engine);
+                self.changed = true;
             } else if message.destination() == self.btn_reset_audio_settings {
                 engine.set_sound_gain(1.0);
                 self.sync_to_model(engine);
+                self.changed = true;
             }
 
             for (i, button) in self.control_scheme_buttons.iter().enumerate() {
@@ -821,6 +954,12 @@ impl OptionsMenu {
                     self.active_control_button = Some(i);
                 }
             }
+        } else if let Some(WindowMessage::Close) = message.data() {
+            if message.destination() == self.window
+                && message.direction() == MessageDirection::FromWidget
+            {
+                self.save_changes();
+            }
         }
 
         if settings != old_settings {
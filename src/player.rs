@@ -3,6 +3,7 @@ use crate::{
     control_scheme::{ControlButton, ControlScheme},
     level::UpdateContext,
     message::Message,
+    weapon::{FireMode, WeaponContainer},
 };
 use fyrox::{
     core::{
@@ -13,7 +14,6 @@ use fyrox::{
         visitor::{Visit, VisitResult, Visitor},
     },
     event::{DeviceEvent, ElementState, Event, MouseScrollDelta, WindowEvent},
-    rand,
     scene::{
         base::BaseBuilder,
         camera::CameraBuilder,
@@ -32,6 +32,7 @@ use std::{
     sync::{mpsc::Sender, Arc, RwLock},
 };
 
+#[derive(Copy, Clone)]
 pub struct Controller {
     move_forward: bool,
     move_backward: bool,
@@ -41,6 +42,7 @@ pub struct Controller {
     jump: bool,
     run: bool,
     shoot: bool,
+    alt_fire: bool,
 }
 
 impl Default for Controller {
@@ -54,10 +56,56 @@ impl Default for Controller {
             jump: false,
             run: false,
             shoot: false,
+            alt_fire: false,
         }
     }
 }
 
+impl Controller {
+    fn to_bits(self) -> u16 {
+        [
+            self.move_forward,
+            self.move_backward,
+            self.move_left,
+            self.move_right,
+            self.crouch,
+            self.jump,
+            self.run,
+            self.shoot,
+            self.alt_fire,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, &state)| bits | ((state as u16) << i))
+    }
+
+    fn from_bits(bits: u16) -> Self {
+        let state = |i: u16| bits & (1 << i) != 0;
+        Self {
+            move_forward: state(0),
+            move_backward: state(1),
+            move_left: state(2),
+            move_right: state(3),
+            crouch: state(4),
+            jump: state(5),
+            run: state(6),
+            shoot: state(7),
+            alt_fire: state(8),
+        }
+    }
+}
+
+/// State of player's controls at the beginning of a simulation step. Since simulation is
+/// deterministic, it is everything that is needed to repeat player's actions.
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct PlayerInput {
+    /// Packed state of movement and shooting controls.
+    pub controls: u16,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub current_weapon: u8,
+}
+
 #[derive(Visit)]
 pub struct Player {
     character: Character,
@@ -256,6 +304,12 @@ impl Player {
         self.control_scheme = Some(control_scheme);
     }
 
+    /// Sets yaw in radians immediately, without smoothing.
+    pub fn set_yaw(&mut self, yaw: f32) {
+        self.yaw = yaw.to_degrees();
+        self.dest_yaw = self.yaw;
+    }
+
     fn update_movement(&mut self, context: &mut UpdateContext) {
         let has_ground_contact = self.character.has_ground_contact(&context.scene.graph);
 
@@ -304,7 +358,8 @@ impl Player {
             self.weapon_dest_offset = Vector3::default();
         }
 
-        self.weapon_offset.follow(&self.weapon_dest_offset, 0.1);
+        self.weapon_offset
+            .follow(&self.weapon_dest_offset, context.time.smoothing(0.1));
 
         if self.controller.jump {
             if has_ground_contact {
@@ -318,8 +373,9 @@ impl Player {
         // Apply damping in XZ plane to prevent sliding.
         if has_ground_contact {
             let mut lin_vel = body.lin_vel();
-            lin_vel.x *= 0.9;
-            lin_vel.z *= 0.9;
+            let damping = 1.0 - context.time.smoothing(0.1);
+            lin_vel.x *= damping;
+            lin_vel.z *= damping;
             body.set_lin_vel(lin_vel);
         }
 
@@ -336,7 +392,8 @@ impl Player {
             .unwrap()
             .shake_camera
         {
-            self.camera_offset.follow(&self.camera_dest_offset, 0.1);
+            self.camera_offset
+                .follow(&self.camera_dest_offset, context.time.smoothing(0.1));
         } else {
             self.camera_offset = Vector3::default();
         }
@@ -369,9 +426,10 @@ impl Player {
                 self.pitch.to_radians(),
             ));
 
+        let weapon_switch_offset = self.character.weapon_switch_offset(context.weapons);
         context.scene.graph[self.character.weapon_pivot]
             .local_transform_mut()
-            .set_position(self.weapon_offset);
+            .set_position(self.weapon_offset + weapon_switch_offset);
 
         let camera_node = &mut context.scene.graph[self.camera];
         camera_node
@@ -387,8 +445,33 @@ impl Player {
         self.character.is_dead()
     }
 
+    pub fn input(&self) -> PlayerInput {
+        PlayerInput {
+            controls: self.controller.to_bits(),
+            yaw: self.dest_yaw,
+            pitch: self.dest_pitch,
+            current_weapon: self.character.current_weapon as u8,
+        }
+    }
+
+    pub fn set_input(&mut self, input: &PlayerInput) {
+        self.controller = Controller::from_bits(input.controls);
+        self.dest_yaw = input.yaw;
+        self.dest_pitch = input.pitch;
+        if input.current_weapon as u32 != self.character.current_weapon {
+            self.character
+                .set_current_weapon(input.current_weapon as usize);
+        }
+    }
+
+    /// Releases every pressed control, so the player stops when input goes elsewhere (for
+    /// example to chat).
+    pub fn release_controls(&mut self) {
+        self.controller = Default::default();
+    }
+
     #[allow(clippy::cognitive_complexity)]
-    pub fn process_input_event(&mut self, event: &Event<()>) -> bool {
+    pub fn process_input_event(&mut self, event: &Event<()>, weapons: &WeaponContainer) -> bool {
         let control_scheme = match self.control_scheme.clone() {
             Some(x) => x,
             None => return false,
@@ -430,9 +513,11 @@ impl Player {
                 DeviceEvent::MouseWheel { delta } => {
                     if let MouseScrollDelta::LineDelta(_, y) = delta {
                         if *y < 0.0 {
-                            self.prev_weapon();
+                            control_button = Some(ControlButton::WheelDown);
+                            control_button_state = ElementState::Pressed;
                         } else if *y > 0.0 {
-                            self.next_weapon();
+                            control_button = Some(ControlButton::WheelUp);
+                            control_button_state = ElementState::Pressed;
                         }
                     }
                 }
@@ -461,6 +546,8 @@ impl Player {
             ElementState::Pressed => {
                 if control_button == control_scheme.shoot.button {
                     self.controller.shoot = true;
+                } else if control_button == control_scheme.alt_fire.button {
+                    self.controller.alt_fire = true;
                 } else if control_button == control_scheme.move_forward.button {
                     self.controller.move_forward = true;
                 } else if control_button == control_scheme.move_backward.button {
@@ -475,11 +562,25 @@ impl Player {
                     self.controller.run = true;
                 } else if control_button == control_scheme.jump.button {
                     self.controller.jump = true;
+                } else if control_button == control_scheme.next_weapon.button {
+                    self.character.cycle_weapon(true, weapons);
+                } else if control_button == control_scheme.prev_weapon.button {
+                    self.character.cycle_weapon(false, weapons);
+                } else if control_button == control_scheme.last_weapon.button {
+                    self.character.select_last_weapon();
+                } else if let Some(slot) = control_scheme
+                    .weapon_slots
+                    .iter()
+                    .position(|definition| definition.button == control_button)
+                {
+                    self.character.select_slot(slot + 1, weapons);
                 }
             }
             ElementState::Released => {
                 if control_button == control_scheme.shoot.button {
                     self.controller.shoot = false;
+                } else if control_button == control_scheme.alt_fire.button {
+                    self.controller.alt_fire = false;
                 } else if control_button == control_scheme.move_forward.button {
                     self.controller.move_forward = false;
                 } else if control_button == control_scheme.move_backward.button {
@@ -511,7 +612,14 @@ impl Player {
                 .as_rigid_body()
                 .lin_vel();
 
-            if self.controller.shoot {
+            let mode = if self.controller.shoot {
+                Some(FireMode::Primary)
+            } else if self.controller.alt_fire {
+                Some(FireMode::Secondary)
+            } else {
+                None
+            };
+            if let Some(mode) = mode {
                 self.character
                     .sender
                     .as_ref()
@@ -520,6 +628,7 @@ impl Player {
                         weapon: *current_weapon_handle,
                         initial_velocity,
                         direction: None,
+                        mode,
                     })
                     .unwrap();
             }
@@ -537,7 +646,7 @@ impl Player {
                 .as_ref()
                 .unwrap()
                 .send(Message::PlaySound {
-                    path: footsteps[rand::thread_rng().gen_range(0..footsteps.len())].into(),
+                    path: footsteps[context.rng.gen_range(0..footsteps.len())].into(),
                     position: self.character.position(&context.scene.graph),
                     gain: 1.0,
                     rolloff_factor: 2.0,
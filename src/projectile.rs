@@ -35,6 +35,9 @@ pub enum ProjectileKind {
     Plasma,
     Bullet,
     Rocket,
+    Grenade,
+    ChargedPlasma,
+    GuidedRocket,
 }
 
 #[derive(Visit)]
@@ -59,6 +62,10 @@ pub struct Projectile {
     pub sender: Option<Sender<Message>>,
     #[visit(skip)]
     hits: HashSet<Hit>,
+    /// Velocity of projectiles affected by gravity, per update tick. It is not saved, it is
+    /// restored from direction and speed.
+    #[visit(skip)]
+    velocity: Vector3<f32>,
 }
 
 impl Default for Projectile {
@@ -75,20 +82,48 @@ impl Default for Projectile {
             last_position: Default::default(),
             sender: None,
             hits: Default::default(),
+            velocity: Default::default(),
         }
     }
 }
 
 pub struct ProjectileDefinition {
-    damage: f32,
-    speed: f32,
+    pub damage: f32,
+    /// Distance that projectile travels per one update tick.
+    pub speed: f32,
+    /// Radius of explosion, bots do not shoot if they would be inside of it. Zero means that
+    /// projectile does not explode.
+    pub splash_radius: f32,
     lifetime: f32,
     /// Means that movement of projectile controlled by code, not physics.
     /// However projectile still could have rigid body to detect collisions.
     is_kinematic: bool,
     impact_sound: &'static str,
+    /// Acceleration of falling in m/s², only kinematic projectiles fall.
+    pub gravity: f32,
+    /// Projectile bounces off walls instead of exploding.
+    bounces: bool,
+    /// How fast guided projectile turns to the aim point of its weapon, zero means that the
+    /// projectile is not guided.
+    turn_rate: f32,
 }
 
+/// Base of projectiles which fly straight.
+const STRAIGHT: ProjectileDefinition = ProjectileDefinition {
+    damage: 15.0,
+    speed: 0.75,
+    splash_radius: 0.0,
+    lifetime: 10.0,
+    is_kinematic: true,
+    impact_sound: "data/sounds/bullet_impact_concrete.ogg",
+    gravity: 0.0,
+    bounces: false,
+    turn_rate: 0.0,
+};
+
+/// Part of velocity that is left after bounce.
+const BOUNCE_DAMPING: f32 = 0.5;
+
 impl Projectile {
     pub fn get_definition(kind: ProjectileKind) -> &'static ProjectileDefinition {
         match kind {
@@ -96,29 +131,55 @@ impl Projectile {
                 static DEFINITION: ProjectileDefinition = ProjectileDefinition {
                     damage: 30.0,
                     speed: 0.15,
-                    lifetime: 10.0,
-                    is_kinematic: true,
-                    impact_sound: "data/sounds/bullet_impact_concrete.ogg",
+                    ..STRAIGHT
                 };
                 &DEFINITION
             }
             ProjectileKind::Bullet => {
-                static DEFINITION: ProjectileDefinition = ProjectileDefinition {
-                    damage: 15.0,
-                    speed: 0.75,
-                    lifetime: 10.0,
-                    is_kinematic: true,
-                    impact_sound: "data/sounds/bullet_impact_concrete.ogg",
-                };
+                static DEFINITION: ProjectileDefinition = STRAIGHT;
                 &DEFINITION
             }
             ProjectileKind::Rocket => {
                 static DEFINITION: ProjectileDefinition = ProjectileDefinition {
                     damage: 30.0,
                     speed: 0.5,
-                    lifetime: 10.0,
-                    is_kinematic: true,
+                    splash_radius: 2.5,
+                    impact_sound: "data/sounds/explosion.ogg",
+                    ..STRAIGHT
+                };
+                &DEFINITION
+            }
+            ProjectileKind::Grenade => {
+                static DEFINITION: ProjectileDefinition = ProjectileDefinition {
+                    damage: 40.0,
+                    speed: 0.3,
+                    splash_radius: 3.0,
+                    // Fuse time, grenade explodes when it hits an actor or when it runs out.
+                    lifetime: 2.0,
+                    impact_sound: "data/sounds/explosion.ogg",
+                    gravity: 9.81,
+                    bounces: true,
+                    ..STRAIGHT
+                };
+                &DEFINITION
+            }
+            ProjectileKind::ChargedPlasma => {
+                static DEFINITION: ProjectileDefinition = ProjectileDefinition {
+                    damage: 90.0,
+                    speed: 0.2,
+                    splash_radius: 1.5,
+                    ..STRAIGHT
+                };
+                &DEFINITION
+            }
+            ProjectileKind::GuidedRocket => {
+                static DEFINITION: ProjectileDefinition = ProjectileDefinition {
+                    damage: 30.0,
+                    speed: 0.3,
+                    splash_radius: 2.5,
                     impact_sound: "data/sounds/explosion.ogg",
+                    turn_rate: 3.0,
+                    ..STRAIGHT
                 };
                 &DEFINITION
             }
@@ -141,10 +202,13 @@ impl Projectile {
 
         let (model, body) = {
             match &kind {
-                ProjectileKind::Plasma => {
-                    let size = rand::thread_rng().gen_range(0.09..0.12);
-
-                    let color = Color::opaque(0, 162, 232);
+                ProjectileKind::Plasma | ProjectileKind::ChargedPlasma => {
+                    let mut size = rand::thread_rng().gen_range(0.09..0.12);
+                    let mut color = Color::opaque(0, 162, 232);
+                    if kind == ProjectileKind::ChargedPlasma {
+                        size *= 2.5;
+                        color = Color::opaque(120, 220, 255);
+                    }
 
                     let model;
                     let collider;
@@ -191,7 +255,29 @@ impl Projectile {
 
                     (model, None)
                 }
-                ProjectileKind::Rocket => {
+                ProjectileKind::Grenade => {
+                    let color = Color::opaque(255, 200, 0);
+                    let model = SpriteBuilder::new(
+                        BaseBuilder::new()
+                            .with_local_transform(
+                                TransformBuilder::new()
+                                    .with_local_position(position)
+                                    .build(),
+                            )
+                            .with_children(&[PointLightBuilder::new(
+                                BaseLightBuilder::new(BaseBuilder::new()).with_color(color),
+                            )
+                            .with_radius(1.0)
+                            .build(&mut scene.graph)]),
+                    )
+                    .with_size(0.08)
+                    .with_color(color)
+                    .with_texture(resource_manager.request_texture("data/particles/light_01.png"))
+                    .build(&mut scene.graph);
+
+                    (model, None)
+                }
+                ProjectileKind::Rocket | ProjectileKind::GuidedRocket => {
                     let resource = resource_manager
                         .request_model("data/models/rocket.FBX")
                         .await
@@ -247,7 +333,7 @@ impl Projectile {
         time: GameTime,
     ) {
         // Fetch current position of projectile.
-        let position = if let Some(body) = self.body.as_ref() {
+        let mut position = if let Some(body) = self.body.as_ref() {
             scene.graph[*body].global_position()
         } else {
             scene.graph[self.model].global_position()
@@ -262,7 +348,7 @@ impl Projectile {
         scene.graph.physics.cast_ray(
             RayCastOptions {
                 ray_origin: Point3::from(ray.origin),
-                ray_direction: ray.origin,
+                ray_direction: ray.dir,
                 max_len: ray.dir.norm(),
                 groups: InteractionGroups::default(),
                 sort_results: true,
@@ -276,8 +362,20 @@ impl Projectile {
             let body = collider.parent();
 
             if matches!(collider.shape(), ColliderShape::Trimesh(_)) {
-                self.kill();
-                effect_position = Some(hit.position.coords);
+                if self.definition().bounces {
+                    let normal = hit
+                        .normal
+                        .try_normalize(std::f32::EPSILON)
+                        .unwrap_or_else(Vector3::y);
+                    let velocity = self.current_velocity();
+                    self.velocity = (velocity - normal.scale(2.0 * velocity.dot(&normal)))
+                        .scale(BOUNCE_DAMPING);
+                    position = hit.position.coords + normal.scale(0.05);
+                    self.set_position(&mut scene.graph, position);
+                } else {
+                    self.kill();
+                    effect_position = Some(hit.position.coords);
+                }
                 break 'hit_loop;
             } else {
                 for (actor_handle, actor) in actors.pair_iter() {
@@ -299,9 +397,41 @@ impl Projectile {
             }
         }
 
+        let definition = self.definition();
+        if definition.turn_rate > 0.0 && weapons.contains(self.owner) {
+            let weapon = &weapons[self.owner];
+            // Guidance works only while the weapon is in hands.
+            if weapon.is_in_hands() {
+                let target = weapon
+                    .aim_point()
+                    .and_then(|point| (point - position).try_normalize(std::f32::EPSILON))
+                    .unwrap_or_else(|| weapon.get_shot_direction(&scene.graph));
+                self.dir = self
+                    .dir
+                    .lerp(&target, (definition.turn_rate * time.delta).min(1.0))
+                    .try_normalize(std::f32::EPSILON)
+                    .unwrap_or(self.dir);
+                if self.dir.y.abs() < 0.99 {
+                    scene.graph[self.model]
+                        .local_transform_mut()
+                        .set_rotation(UnitQuaternion::face_towards(&self.dir, &Vector3::y()));
+                }
+            }
+        }
+
         // Movement of kinematic projectiles are controlled explicitly.
-        if self.definition().is_kinematic {
-            let total_velocity = self.dir.scale(self.definition().speed);
+        if definition.is_kinematic {
+            let total_velocity = if definition.gravity > 0.0 {
+                self.velocity = self.current_velocity();
+                self.velocity.y -= definition.gravity * time.delta * time.delta;
+                self.dir = self
+                    .velocity
+                    .try_normalize(std::f32::EPSILON)
+                    .unwrap_or(self.dir);
+                self.velocity
+            } else {
+                self.dir.scale(definition.speed)
+            };
 
             // Special case for projectiles with rigid body.
             if let Some(body) = self.body.as_ref() {
@@ -324,7 +454,8 @@ impl Projectile {
 
         // Reduce initial velocity down to zero over time. This is needed because projectile
         // stabilizes its movement over time.
-        self.initial_velocity.follow(&Vector3::default(), 0.15);
+        self.initial_velocity
+            .follow(&Vector3::default(), time.smoothing(0.15));
 
         self.lifetime -= time.delta;
 
@@ -353,7 +484,6 @@ impl Projectile {
                 .unwrap();
         }
 
-        let definition = self.definition();
         for hit in self.hits.drain() {
             self.sender
                 .as_ref()
@@ -373,6 +503,20 @@ impl Projectile {
         graph[self.model].global_position()
     }
 
+    fn set_position(&self, graph: &mut Graph, position: Vector3<f32>) {
+        let node = self.body.unwrap_or(self.model);
+        graph[node].local_transform_mut().set_position(position);
+    }
+
+    /// Velocity per update tick, it is restored from direction and speed when it is not known.
+    fn current_velocity(&self) -> Vector3<f32> {
+        if self.velocity == Vector3::default() {
+            self.dir.scale(self.definition().speed)
+        } else {
+            self.velocity
+        }
+    }
+
     fn clean_up(&mut self, scene: &mut Scene) {
         if let Some(body) = self.body.as_ref() {
             scene.graph.remove_node(*body);
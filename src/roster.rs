@@ -0,0 +1,112 @@
+//! Bot roster defines which bots take part in a match. It is a part of match options, so it
+//! is configured in match menu and saved together with a level.
+
+use crate::{
+    bot::{BotKind, BotSkill},
+    character::Team,
+};
+use fyrox::core::visitor::{Visit, VisitResult, Visitor};
+
+#[derive(Clone, Debug, Visit)]
+pub struct BotConfig {
+    pub kind: BotKind,
+    pub name: String,
+    pub skill: BotSkill,
+    /// `Team::None` in team modes means that bot will be put in a team with less players.
+    pub team: Team,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            kind: BotKind::Mutant,
+            name: Default::default(),
+            skill: Default::default(),
+            team: Team::None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Visit)]
+pub struct BotRoster {
+    pub bots: Vec<BotConfig>,
+    /// Total amount of players (including player) in a match, missing players are added as
+    /// bots with average skill. Zero means that only configured bots will be spawned.
+    pub fill_to: u32,
+}
+
+impl Default for BotRoster {
+    fn default() -> Self {
+        Self {
+            bots: BotKind::ALL
+                .iter()
+                .map(|&kind| BotConfig {
+                    kind,
+                    name: kind.description().to_owned(),
+                    ..Default::default()
+                })
+                .collect(),
+            fill_to: 0,
+        }
+    }
+}
+
+impl BotRoster {
+    /// Returns final list of bots for a match: configured bots plus bots that fill the match
+    /// up to requested amount of players. Every bot gets unique name, in team modes bots
+    /// without a team are distributed so teams would be balanced, in other modes teams are
+    /// reset.
+    pub fn resolve(&self, team_match: bool, player_team: Team) -> Vec<BotConfig> {
+        let mut bots = self.bots.clone();
+
+        // Every kind of bots is used in turn to fill the match.
+        let mut i = 0;
+        while bots.len() + 1 < self.fill_to as usize {
+            let kind = BotKind::ALL[i % BotKind::ALL.len()];
+            bots.push(BotConfig {
+                kind,
+                name: format!("{} {}", kind.description(), i + 1),
+                ..Default::default()
+            });
+            i += 1;
+        }
+
+        let mut names = Vec::<String>::new();
+        for bot in bots.iter_mut() {
+            if bot.name.trim().is_empty() {
+                bot.name = bot.kind.description().to_owned();
+            }
+            // Leader board identifies actors by names, so they must be unique.
+            let base_name = bot.name.clone();
+            let mut n = 2;
+            while names.contains(&bot.name) {
+                bot.name = format!("{} ({})", base_name, n);
+                n += 1;
+            }
+            names.push(bot.name.clone());
+        }
+
+        if team_match {
+            let count = |team: Team| {
+                bots.iter().filter(|b| b.team == team).count() + (player_team == team) as usize
+            };
+            let mut red = count(Team::Red);
+            let mut blue = count(Team::Blue);
+            for bot in bots.iter_mut().filter(|b| b.team == Team::None) {
+                if blue <= red {
+                    bot.team = Team::Blue;
+                    blue += 1;
+                } else {
+                    bot.team = Team::Red;
+                    red += 1;
+                }
+            }
+        } else {
+            for bot in bots.iter_mut() {
+                bot.team = Team::None;
+            }
+        }
+
+        bots
+    }
+}
@@ -0,0 +1,572 @@
+//! Saved games. Every save slot is a pair of files in the saves directory of the user:
+//! `<slot>.bin` holds the scene and the level and `<slot>.meta` holds [`SaveMetadata`] with a
+//! thumbnail. Metadata is small, so the save browser reads it without loading whole saves.
+//!
+//! Single player matches are also saved to [`AUTOSAVE_SLOT`] every [`AUTOSAVE_INTERVAL`]
+//! seconds.
+//!
+//! Both files start with [`SaveHeader`]. Saves of older format versions are upgraded by
+//! [`MIGRATIONS`] after loading, saves of newer versions are rejected with a clear error.
+//! Saves of format versions are kept in the test corpus (`data/tests/saves`),
+//! `saves_of_corpus_keep_loading` test fails if a version has no save there or its save does
+//! not load. The corpus is empty yet, so the test is ignored until saves made by the game of
+//! every version are added. Saves of new versions are made by `write_save_to_corpus` test.
+//!
+//! `save.bin` that the game wrote to the working directory before save slots cannot be loaded:
+//! the level changed too much before saves got a format version.
+
+use crate::{
+    config::user_dir,
+    level::{self, Level},
+    map::MapPath,
+    weapon::WeaponRules,
+};
+use fyrox::{
+    core::{
+        futures::executor::block_on,
+        pool::Handle,
+        visitor::{PodVecView, Visit, VisitError, VisitResult, Visitor},
+    },
+    engine::{resource_manager::ResourceManager, SerializationContext},
+    renderer::{framework::gpu_texture::GpuTextureKind, Renderer},
+    resource::texture::{Texture, TextureKind, TexturePixelKind},
+    scene::{Scene, SceneLoader},
+};
+use std::{
+    cell::Cell,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the format of saves. It must be increased on every change of saved state, like a
+/// new field of [`Level`], together with a new migration in [`MIGRATIONS`].
+///
+/// History:
+/// - 0 - saves without a header, made by save slots before format versions.
+/// - 1 - saves start with [`SaveHeader`].
+/// - 2 - spawn points have a team, game modes and a direction.
+/// - 3 - items placed on a map have overrides of respawn time, initial delay and amount.
+/// - 4 - match options have a map and weapon rules.
+pub const SAVE_FORMAT_VERSION: u32 = 4;
+
+/// Upgrades the state loaded from a save of version `n` to version `n + 1`.
+type Migration = fn(&mut Level, &mut Scene) -> Result<(), String>;
+
+/// Migrations of saves, `MIGRATIONS[n]` upgrades a save of version `n`.
+const MIGRATIONS: [Migration; SAVE_FORMAT_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+];
+
+fn migrate_v0_to_v1(_level: &mut Level, _scene: &mut Scene) -> Result<(), String> {
+    // Only the header was added, the state is the same.
+    Ok(())
+}
+
+fn migrate_v1_to_v2(level: &mut Level, scene: &mut Scene) -> Result<(), String> {
+    // Spawn points are read again from markers of the map, they are still in the scene.
+    scene.graph.update_hierarchical_data();
+    level.set_spawn_points(level::find_spawn_points(&scene.graph));
+    Ok(())
+}
+
+fn migrate_v2_to_v3(_level: &mut Level, _scene: &mut Scene) -> Result<(), String> {
+    // Items of older saves have no overrides, they use definitions of their kinds.
+    Ok(())
+}
+
+fn migrate_v3_to_v4(level: &mut Level, _scene: &mut Scene) -> Result<(), String> {
+    // Matches were played only on the default map with default weapon rules before they could
+    // be chosen.
+    level.options.set_map(MapPath::default());
+    level.options.set_weapon_rules(WeaponRules::default());
+    Ok(())
+}
+
+thread_local! {
+    /// Format version of the save that is being read or written.
+    static FORMAT_VERSION: Cell<u32> = Cell::new(SAVE_FORMAT_VERSION);
+}
+
+/// Returns `true` if the save that is being read or written has state that was added in given
+/// format version. Visits skip such state in older saves and [`MIGRATIONS`] fill it instead,
+/// everything else is visited strictly. Files other than saves, like demos, always have it.
+pub fn has_state_of_version(version: u32) -> bool {
+    FORMAT_VERSION.with(|current| current.get() >= version)
+}
+
+/// Visits the level in the layout of given format version.
+fn visit_level(level: &mut Level, visitor: &mut Visitor, format_version: u32) -> VisitResult {
+    FORMAT_VERSION.with(|current| current.set(format_version));
+    let result = level.visit("Level", visitor);
+    FORMAT_VERSION.with(|current| current.set(SAVE_FORMAT_VERSION));
+    result
+}
+
+/// Saves of every format version, they are loaded by tests.
+pub const SAVE_CORPUS_DIR: &str = "data/tests/saves";
+
+pub const AUTOSAVE_SLOT: &str = "autosave";
+
+/// Time of a match in seconds between autosaves.
+pub const AUTOSAVE_INTERVAL: f32 = 120.0;
+
+const MAX_SLOT_NAME_LEN: usize = 32;
+
+const THUMBNAIL_WIDTH: u32 = 96;
+const THUMBNAIL_HEIGHT: u32 = 54;
+
+/// Directory of saves of current user, saves are put to the working directory if the home
+/// directory is unknown.
+pub fn saves_dir() -> PathBuf {
+    user_dir("XDG_DATA_HOME", ".local/share")
+        .map_or_else(|| PathBuf::from("saves"), |dir| dir.join("saves"))
+}
+
+/// Turns a name typed by the player into a name of a slot that can be used as a file name.
+/// Returns `None` if nothing is left.
+pub fn slot_name(name: &str) -> Option<String> {
+    let name = name
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .take(MAX_SLOT_NAME_LEN)
+        .collect::<String>();
+    let name = name.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_owned())
+    }
+}
+
+fn state_path(slot: &str) -> PathBuf {
+    saves_dir().join(format!("{}.bin", slot))
+}
+
+fn metadata_path(slot: &str) -> PathBuf {
+    saves_dir().join(format!("{}.meta", slot))
+}
+
+#[derive(Debug, Visit)]
+pub struct SaveHeader {
+    pub format_version: u32,
+    /// Version of the game that made the save, it is shown in errors.
+    pub game_version: String,
+}
+
+impl Default for SaveHeader {
+    fn default() -> Self {
+        Self {
+            format_version: SAVE_FORMAT_VERSION,
+            game_version: env!("CARGO_PKG_VERSION").to_owned(),
+        }
+    }
+}
+
+impl SaveHeader {
+    /// Reads the header of a save, saves without a header are of version 0.
+    pub fn read(visitor: &mut Visitor) -> Self {
+        let mut header = Self::default();
+        if header.visit("Header", visitor).is_err() {
+            header = Self {
+                format_version: 0,
+                game_version: "unknown".to_owned(),
+            };
+        }
+        header
+    }
+
+    fn check_supported(&self) -> Result<(), SaveError> {
+        if self.format_version > SAVE_FORMAT_VERSION {
+            Err(SaveError::TooNew {
+                game_version: self.game_version.clone(),
+                format_version: self.format_version,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn incompatible(&self, reason: impl Display) -> SaveError {
+        SaveError::Incompatible {
+            game_version: self.game_version.clone(),
+            format_version: self.format_version,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    NotFound,
+    /// Save cannot be read or written.
+    Io(String),
+    /// Save is not a valid save file.
+    Corrupt(String),
+    /// Save was made by a newer version of the game.
+    TooNew {
+        game_version: String,
+        format_version: u32,
+    },
+    /// Save cannot be loaded even after migration.
+    Incompatible {
+        game_version: String,
+        format_version: u32,
+        reason: String,
+    },
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::NotFound => write!(f, "Save does not exist"),
+            SaveError::Io(reason) => write!(f, "{}", reason),
+            SaveError::Corrupt(reason) => write!(f, "Save is corrupt, reason: {}", reason),
+            SaveError::TooNew {
+                game_version,
+                format_version,
+            } => write!(
+                f,
+                "Save was made by a newer version of the game ({}, save format {}), \
+                this version supports save formats up to {}",
+                game_version, format_version, SAVE_FORMAT_VERSION
+            ),
+            SaveError::Incompatible {
+                game_version,
+                format_version,
+                reason,
+            } => write!(
+                f,
+                "Save made by version {} (save format {}) is incompatible, reason: {}",
+                game_version, format_version, reason
+            ),
+        }
+    }
+}
+
+/// Pixels are stored as a single binary blob, like inputs of demos.
+#[derive(Default)]
+struct Pixels(Vec<u8>);
+
+impl Visit for Pixels {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        PodVecView::from_pod_vec(&mut self.0).visit(name, visitor)
+    }
+}
+
+/// Small RGB picture of what the player saw when the game was saved.
+#[derive(Default, Visit)]
+pub struct Thumbnail {
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+}
+
+impl Thumbnail {
+    /// Downscales the last frame that the renderer made of a scene. The frame has no user
+    /// interface on it, so menus do not get to thumbnails. The thumbnail is black if the scene
+    /// was not rendered yet.
+    pub fn capture(renderer: &mut Renderer, scene: Handle<Scene>) -> Self {
+        let texture = match renderer.scene_data_map.get(&scene) {
+            Some(data) => data.ldr_scene_frame_texture(),
+            None => return Self::from_frame(0, 0, &[]),
+        };
+        let mut texture = texture.borrow_mut();
+        let (width, height) = match texture.kind() {
+            GpuTextureKind::Rectangle { width, height } => (width as u32, height as u32),
+            _ => (0, 0),
+        };
+        let pixels = texture.bind_mut(renderer.pipeline_state(), 0).read_pixels();
+        Self::from_frame(width, height, &pixels)
+    }
+
+    /// Makes a thumbnail of an RGBA frame with rows from bottom to top, like OpenGL returns
+    /// them. Every pixel of the thumbnail is the average of the pixels of the frame it covers.
+    pub fn from_frame(width: u32, height: u32, rgba: &[u8]) -> Self {
+        let mut pixels = vec![0; (THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3) as usize];
+
+        if width > 0 && height > 0 && rgba.len() == (width * height * 4) as usize {
+            for y in 0..THUMBNAIL_HEIGHT {
+                // Frame rows go from bottom to top, thumbnail rows from top to bottom.
+                let frame_y0 = (THUMBNAIL_HEIGHT - 1 - y) * height / THUMBNAIL_HEIGHT;
+                let frame_y1 =
+                    ((THUMBNAIL_HEIGHT - y) * height / THUMBNAIL_HEIGHT).max(frame_y0 + 1);
+                for x in 0..THUMBNAIL_WIDTH {
+                    let frame_x0 = x * width / THUMBNAIL_WIDTH;
+                    let frame_x1 = ((x + 1) * width / THUMBNAIL_WIDTH).max(frame_x0 + 1);
+
+                    let mut sum = [0u32; 3];
+                    for frame_y in frame_y0..frame_y1 {
+                        for frame_x in frame_x0..frame_x1 {
+                            let i = ((frame_y * width + frame_x) * 4) as usize;
+                            for (c, sum) in sum.iter_mut().enumerate() {
+                                *sum += rgba[i + c] as u32;
+                            }
+                        }
+                    }
+
+                    let count = (frame_y1 - frame_y0) * (frame_x1 - frame_x0);
+                    let i = ((y * THUMBNAIL_WIDTH + x) * 3) as usize;
+                    for (c, sum) in sum.iter().enumerate() {
+                        pixels[i + c] = (sum / count) as u8;
+                    }
+                }
+            }
+        }
+
+        Self {
+            width: THUMBNAIL_WIDTH,
+            height: THUMBNAIL_HEIGHT,
+            pixels: Pixels(pixels),
+        }
+    }
+
+    pub fn to_texture(&self) -> Option<Texture> {
+        if self.pixels.0.len() != (self.width * self.height * 3) as usize {
+            return None;
+        }
+        Texture::from_bytes(
+            TextureKind::Rectangle {
+                width: self.width,
+                height: self.height,
+            },
+            TexturePixelKind::RGB8,
+            self.pixels.0.clone(),
+            false,
+        )
+    }
+}
+
+/// Description of a save that is shown in the save browser.
+#[derive(Default, Visit)]
+pub struct SaveMetadata {
+    pub map: String,
+    pub mode: String,
+    /// Time of the match in seconds.
+    pub time: f32,
+    pub kills: u32,
+    pub deaths: u32,
+    /// Seconds since Unix epoch.
+    pub timestamp: u64,
+    pub thumbnail: Thumbnail,
+}
+
+impl SaveMetadata {
+    pub fn new(level: &Level, thumbnail: Thumbnail) -> Self {
+        let player = level.actors().try_get(level.get_player());
+        let score = player.and_then(|player| level.leader_board.values().get(&player.name));
+
+        Self {
+            map: level.options.map().file_name(),
+            mode: level.options.name().to_owned(),
+            time: level.time(),
+            kills: score.map_or(0, |score| score.kills),
+            deaths: score.map_or(0, |score| score.deaths),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+            thumbnail,
+        }
+    }
+
+    pub fn description(&self) -> String {
+        let seconds = self.time as u32;
+        format!(
+            "{} on {}, {:02}:{:02}, {} frags, {} deaths\n{}",
+            self.mode,
+            self.map,
+            seconds / 60,
+            seconds % 60,
+            self.kills,
+            self.deaths,
+            format_timestamp(self.timestamp)
+        )
+    }
+}
+
+/// Formats seconds since Unix epoch as UTC date and time.
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Converts days since epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+/// Writes the state of the game with its header to a file.
+pub fn write_state(path: &Path, level: &mut Level, scene: &mut Scene) -> Result<(), SaveError> {
+    write_state_of_version(path, SAVE_FORMAT_VERSION, level, scene)
+}
+
+/// Writes the state of the game in the layout of given format version, saves of version 0 have
+/// no header. Saves of older versions are written only to test migrations.
+pub fn write_state_of_version(
+    path: &Path,
+    format_version: u32,
+    level: &mut Level,
+    scene: &mut Scene,
+) -> Result<(), SaveError> {
+    let mut visitor = Visitor::new();
+    let mut header = SaveHeader {
+        format_version,
+        ..Default::default()
+    };
+    let result = if format_version > 0 {
+        header.visit("Header", &mut visitor)
+    } else {
+        Ok(())
+    };
+    result
+        .and_then(|_| scene.save("Scene", &mut visitor))
+        .and_then(|_| visit_level(level, &mut visitor, format_version))
+        .and_then(|_| visitor.save_binary(path))
+        .map_err(|e| SaveError::Io(format!("Unable to write save, reason: {}", e)))
+}
+
+/// Reads the state of the game from a file and upgrades it to the current version. Nothing is
+/// returned if the save cannot be loaded completely.
+pub fn read_state(
+    path: &Path,
+    serialization_context: Arc<SerializationContext>,
+    resource_manager: ResourceManager,
+) -> Result<(Level, Scene), SaveError> {
+    if !path.exists() {
+        return Err(SaveError::NotFound);
+    }
+    let mut visitor =
+        block_on(Visitor::load_binary(path)).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+
+    let header = SaveHeader::read(&mut visitor);
+    header.check_supported()?;
+
+    let mut scene = block_on(
+        SceneLoader::load("Scene", serialization_context, &mut visitor)
+            .map_err(|e: VisitError| header.incompatible(e))?
+            .finish(resource_manager),
+    );
+
+    let mut level = Level::default();
+    visit_level(&mut level, &mut visitor, header.format_version)
+        .map_err(|e| header.incompatible(e))?;
+
+    for migration in &MIGRATIONS[header.format_version as usize..] {
+        migration(&mut level, &mut scene).map_err(|e| header.incompatible(e))?;
+    }
+    level.items_mut().resolve(&mut scene.graph);
+    level.resolve_weapons(&mut scene.graph);
+
+    Ok((level, scene))
+}
+
+/// Saves the game and its metadata to a slot.
+pub fn write(
+    slot: &str,
+    level: &mut Level,
+    scene: &mut Scene,
+    thumbnail: Thumbnail,
+) -> Result<(), SaveError> {
+    fs::create_dir_all(saves_dir())
+        .map_err(|e| SaveError::Io(format!("Unable to create saves directory, reason: {}", e)))?;
+
+    let mut metadata = SaveMetadata::new(level, thumbnail);
+    write_state(&state_path(slot), level, scene)?;
+
+    let mut visitor = Visitor::new();
+    SaveHeader::default()
+        .visit("Header", &mut visitor)
+        .and_then(|_| metadata.visit("Metadata", &mut visitor))
+        .and_then(|_| visitor.save_binary(&metadata_path(slot)))
+        .map_err(|e| SaveError::Io(format!("Unable to write save, reason: {}", e)))
+}
+
+/// Loads the game from a slot.
+pub fn read(
+    slot: &str,
+    serialization_context: Arc<SerializationContext>,
+    resource_manager: ResourceManager,
+) -> Result<(Level, Scene), SaveError> {
+    read_state(&state_path(slot), serialization_context, resource_manager)
+}
+
+pub fn read_metadata(slot: &str) -> Result<SaveMetadata, SaveError> {
+    let path = metadata_path(slot);
+    if !path.exists() {
+        return Err(SaveError::Corrupt("description is missing".to_owned()));
+    }
+    let mut visitor =
+        block_on(Visitor::load_binary(&path)).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    let header = SaveHeader::read(&mut visitor);
+    header.check_supported()?;
+
+    let mut metadata = SaveMetadata::default();
+    metadata
+        .visit("Metadata", &mut visitor)
+        .map_err(|e| header.incompatible(e))?;
+    Ok(metadata)
+}
+
+pub fn delete(slot: &str) -> Result<(), SaveError> {
+    // Metadata of a save can be missing, only the state matters.
+    let _ = fs::remove_file(metadata_path(slot));
+    fs::remove_file(state_path(slot))
+        .map_err(|e| SaveError::Io(format!("Unable to delete save, reason: {}", e)))
+}
+
+pub struct SaveSlot {
+    pub name: String,
+    /// Error describes why metadata of the save cannot be read.
+    pub metadata: Result<SaveMetadata, SaveError>,
+}
+
+/// Returns every save of the user, the most recent saves go first.
+pub fn list() -> Vec<SaveSlot> {
+    let mut slots = fs::read_dir(saves_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == "bin"))
+                .filter_map(|path| {
+                    path.file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                })
+                .map(|name| SaveSlot {
+                    metadata: read_metadata(&name),
+                    name,
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    slots.sort_by_key(|slot| {
+        std::cmp::Reverse(
+            slot.metadata
+                .as_ref()
+                .map_or(0, |metadata| metadata.timestamp),
+        )
+    });
+    slots
+}
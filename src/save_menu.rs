@@ -0,0 +1,293 @@
+use crate::{
+    message::Message,
+    save::{self, AUTOSAVE_SLOT},
+};
+use fyrox::{
+    core::pool::Handle,
+    gui::{
+        button::{ButtonBuilder, ButtonMessage},
+        grid::{Column, GridBuilder, Row},
+        image::ImageBuilder,
+        message::{MessageDirection, UiMessage},
+        scroll_viewer::ScrollViewerBuilder,
+        stack_panel::StackPanelBuilder,
+        text::{TextBuilder, TextMessage},
+        text_box::{TextBox, TextBoxBuilder, TextBoxMessage},
+        widget::{WidgetBuilder, WidgetMessage},
+        window::{WindowBuilder, WindowMessage, WindowTitle},
+        Thickness, UiNode, UserInterface, VerticalAlignment,
+    },
+    utils,
+};
+use std::sync::mpsc::Sender;
+
+/// Browser of saved games. Player picks a save from the list or types a name of a new one,
+/// then saves, loads or deletes it.
+pub struct SaveMenu {
+    sender: Sender<Message>,
+    pub window: Handle<UiNode>,
+    sp_slots: Handle<UiNode>,
+    /// Buttons of saves, a click puts name of the save to the name box.
+    slot_buttons: Vec<(Handle<UiNode>, String)>,
+    tb_name: Handle<UiNode>,
+    save_button: Handle<UiNode>,
+    load_button: Handle<UiNode>,
+    delete_button: Handle<UiNode>,
+    status: Handle<UiNode>,
+}
+
+impl SaveMenu {
+    pub fn new(ui: &mut UserInterface, sender: Sender<Message>) -> Self {
+        let common_row = Row::strict(36.0);
+
+        let ctx = &mut ui.build_ctx();
+        let sp_slots;
+        let tb_name;
+        let save_button;
+        let load_button;
+        let delete_button;
+        let status;
+        let window = WindowBuilder::new(WidgetBuilder::new().with_width(600.0))
+            .with_title(WindowTitle::text("Saved Games"))
+            .open(false)
+            .with_content(
+                GridBuilder::new(
+                    WidgetBuilder::new()
+                        .with_child(
+                            ScrollViewerBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(0)
+                                    .on_column(0)
+                                    .with_height(320.0),
+                            )
+                            .with_content({
+                                sp_slots = StackPanelBuilder::new(WidgetBuilder::new()).build(ctx);
+                                sp_slots
+                            })
+                            .build(ctx),
+                        )
+                        .with_child(
+                            GridBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(1)
+                                    .with_child(
+                                        TextBuilder::new(WidgetBuilder::new().on_column(0))
+                                            .with_text("Name")
+                                            .with_vertical_text_alignment(VerticalAlignment::Center)
+                                            .build(ctx),
+                                    )
+                                    .with_child({
+                                        tb_name = TextBoxBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(1)
+                                                .with_margin(Thickness::uniform(2.0)),
+                                        )
+                                        .with_text("Save 1".to_owned())
+                                        .with_vertical_text_alignment(VerticalAlignment::Center)
+                                        .build(ctx);
+                                        tb_name
+                                    }),
+                            )
+                            .add_column(Column::strict(80.0))
+                            .add_column(Column::stretch())
+                            .add_row(Row::stretch())
+                            .build(ctx),
+                        )
+                        .with_child(
+                            GridBuilder::new(
+                                WidgetBuilder::new()
+                                    .on_row(2)
+                                    .with_child({
+                                        save_button = ButtonBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(0)
+                                                .with_margin(Thickness::uniform(2.0)),
+                                        )
+                                        .with_text("Save")
+                                        .build(ctx);
+                                        save_button
+                                    })
+                                    .with_child({
+                                        load_button = ButtonBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(1)
+                                                .with_margin(Thickness::uniform(2.0)),
+                                        )
+                                        .with_text("Load")
+                                        .build(ctx);
+                                        load_button
+                                    })
+                                    .with_child({
+                                        delete_button = ButtonBuilder::new(
+                                            WidgetBuilder::new()
+                                                .on_column(2)
+                                                .with_margin(Thickness::uniform(2.0)),
+                                        )
+                                        .with_text("Delete")
+                                        .build(ctx);
+                                        delete_button
+                                    }),
+                            )
+                            .add_column(Column::stretch())
+                            .add_column(Column::stretch())
+                            .add_column(Column::stretch())
+                            .add_row(Row::stretch())
+                            .build(ctx),
+                        )
+                        .with_child({
+                            status = TextBuilder::new(WidgetBuilder::new().on_row(3))
+                                .with_vertical_text_alignment(VerticalAlignment::Center)
+                                .build(ctx);
+                            status
+                        }),
+                )
+                .add_column(Column::stretch())
+                .add_row(Row::auto())
+                .add_row(common_row)
+                .add_row(common_row)
+                .add_row(Row::strict(54.0))
+                .build(ctx),
+            )
+            .build(ctx);
+
+        Self {
+            sender,
+            window,
+            sp_slots,
+            slot_buttons: Default::default(),
+            tb_name,
+            save_button,
+            load_button,
+            delete_button,
+            status,
+        }
+    }
+
+    pub fn open(&mut self, ui: &mut UserInterface) {
+        ui.send_message(WindowMessage::open(
+            self.window,
+            MessageDirection::ToWidget,
+            true,
+        ));
+        self.set_status(ui, "");
+        self.refresh(ui);
+    }
+
+    pub fn close(&self, ui: &mut UserInterface) {
+        ui.send_message(WindowMessage::close(
+            self.window,
+            MessageDirection::ToWidget,
+        ));
+    }
+
+    /// Shows outcome of the last action, like errors of loading.
+    pub fn set_status(&self, ui: &mut UserInterface, text: &str) {
+        ui.send_message(TextMessage::text(
+            self.status,
+            MessageDirection::ToWidget,
+            text.to_owned(),
+        ));
+    }
+
+    /// Re-reads the list of saves.
+    pub fn refresh(&mut self, ui: &mut UserInterface) {
+        for (button, _) in self.slot_buttons.drain(..) {
+            ui.send_message(WidgetMessage::remove(button, MessageDirection::ToWidget));
+        }
+
+        for slot in save::list() {
+            let (thumbnail, description) = match &slot.metadata {
+                Ok(metadata) => (metadata.thumbnail.to_texture(), metadata.description()),
+                Err(e) => (None, e.to_string()),
+            };
+
+            let ctx = &mut ui.build_ctx();
+            let content = GridBuilder::new(
+                WidgetBuilder::new()
+                    .with_child(
+                        ImageBuilder::new(
+                            WidgetBuilder::new()
+                                .on_column(0)
+                                .with_margin(Thickness::uniform(2.0)),
+                        )
+                        .with_opt_texture(thumbnail.map(utils::into_gui_texture))
+                        .build(ctx),
+                    )
+                    .with_child(
+                        TextBuilder::new(
+                            WidgetBuilder::new()
+                                .on_column(1)
+                                .with_margin(Thickness::uniform(4.0)),
+                        )
+                        .with_text(format!("{}\n{}", slot.name, description))
+                        .with_vertical_text_alignment(VerticalAlignment::Center)
+                        .build(ctx),
+                    ),
+            )
+            .add_column(Column::strict(96.0))
+            .add_column(Column::stretch())
+            .add_row(Row::strict(58.0))
+            .build(ctx);
+            let button =
+                ButtonBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(1.0)))
+                    .with_content(content)
+                    .build(ctx);
+
+            ui.send_message(WidgetMessage::link(
+                button,
+                MessageDirection::ToWidget,
+                self.sp_slots,
+            ));
+            self.slot_buttons.push((button, slot.name));
+        }
+    }
+
+    fn slot(&self, ui: &mut UserInterface) -> Option<String> {
+        let name = ui
+            .node(self.tb_name)
+            .cast::<TextBox>()
+            .map(|text_box| text_box.text().trim().to_owned())
+            .unwrap_or_default();
+        let slot = save::slot_name(&name);
+        if slot.is_none() {
+            self.set_status(ui, "Enter a name of the save");
+        }
+        slot
+    }
+
+    pub fn handle_ui_event(&mut self, ui: &mut UserInterface, message: &UiMessage) {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.save_button {
+                if let Some(slot) = self.slot(ui) {
+                    if slot == AUTOSAVE_SLOT {
+                        self.set_status(ui, "This name is reserved for autosave");
+                    } else {
+                        self.sender.send(Message::SaveGame { slot }).unwrap();
+                    }
+                }
+            } else if message.destination() == self.load_button {
+                if let Some(slot) = self.slot(ui) {
+                    self.sender.send(Message::LoadGame { slot }).unwrap();
+                }
+            } else if message.destination() == self.delete_button {
+                if let Some(slot) = self.slot(ui) {
+                    match save::delete(&slot) {
+                        Ok(_) => self.set_status(ui, &format!("Save \"{}\" deleted", slot)),
+                        Err(e) => self.set_status(ui, &e.to_string()),
+                    }
+                    self.refresh(ui);
+                }
+            } else if let Some((_, slot)) = self
+                .slot_buttons
+                .iter()
+                .find(|(button, _)| *button == message.destination())
+            {
+                ui.send_message(TextBoxMessage::text(
+                    self.tb_name,
+                    MessageDirection::ToWidget,
+                    slot.clone(),
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,755 @@
+//! Server of multiplayer matches. It owns the authoritative level: clients only send input of
+//! their players and show what server tells them. Server can be dedicated - without a window,
+//! like headless mode - or a listen server that is hosted by the game itself:
+//!
+//! `rusty-shooter --server --port 7777 --mode tdm --fill-to 6`
+//! `rusty-shooter --host 7777`
+//!
+//! Clients can connect at any moment of a match, see [`crate::client`]. Server can also wait
+//! for players in a lobby: players choose their teams there and the match starts when
+//! everyone is ready. Dedicated server waits in a lobby if `--lobby` is passed, listen
+//! servers use lobby when a match is hosted from the menu.
+//!
+//! When a match ends, listen servers and dedicated servers with `--rotate` start the next map
+//! of [`crate::map::MapRotation`], connected clients load it.
+//!
+//! Chat of clients goes through the server, every chat message is written to the log of the
+//! server, see [`crate::chat`].
+
+use crate::{
+    actor::Actor,
+    character::Team,
+    chat::{self, ChatLimiter},
+    control_scheme::ControlScheme,
+    headless::HeadlessOptions,
+    level::{self, Level, LevelContext},
+    map::{self, MapRotation, MAPS_DIR},
+    message::Message,
+    net::{
+        ActorState, ClientId, ClientPacket, ItemState, Lobby, LobbyPlayer, NetworkConditions,
+        ReplicatedActorKind, ScoreState, ServerInfo, ServerPacket, Snapshot, Socket,
+        CONNECTION_TIMEOUT, DEFAULT_PORT, MAX_PACKET_SIZE,
+    },
+    player::PlayerInput,
+    GameTime, MatchOptions, FIXED_FPS,
+};
+use fyrox::{
+    core::{algebra::Vector2, futures::executor::block_on},
+    engine::{resource_manager::ResourceManager, SerializationContext},
+    scene::Scene,
+    utils::log::{Log, MessageKind},
+};
+use std::{
+    collections::VecDeque,
+    io,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Snapshots are sent every N-th simulation step to save bandwidth.
+const SNAPSHOT_INTERVAL: u32 = 2;
+
+/// Inputs of a client that runs ahead of the server are dropped, otherwise its latency would
+/// grow.
+const MAX_QUEUED_INPUTS: usize = 8;
+
+/// State of the lobby is sent to clients with this interval.
+const LOBBY_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+pub const DEFAULT_SERVER_NAME: &str = "Rusty Shooter Server";
+
+struct RemoteClient {
+    id: ClientId,
+    address: SocketAddr,
+    name: String,
+    last_packet_time: Instant,
+    /// Received inputs that are not applied yet, one input is applied per simulation step.
+    inputs: VecDeque<(u32, PlayerInput)>,
+    last_received_input: u32,
+    /// Sequence number of the last applied input, it is acknowledged in snapshots.
+    ack_input: u32,
+    /// The last applied input, it is repeated if client's input is late.
+    input: PlayerInput,
+    view_time: f32,
+    /// Team that the client has chosen in the lobby, during the match it is the team of
+    /// client's player.
+    team: Team,
+    ready: bool,
+    chat_limiter: ChatLimiter,
+}
+
+impl RemoteClient {
+    fn new(id: ClientId, address: SocketAddr, name: String) -> Self {
+        Self {
+            id,
+            address,
+            name,
+            last_packet_time: Instant::now(),
+            inputs: Default::default(),
+            last_received_input: 0,
+            ack_input: 0,
+            input: Default::default(),
+            view_time: 0.0,
+            team: Team::None,
+            ready: false,
+            chat_limiter: Default::default(),
+        }
+    }
+}
+
+pub struct Server {
+    socket: Socket,
+    clients: Vec<RemoteClient>,
+    next_client_id: ClientId,
+    tick: u32,
+    /// Name of the server in server browsers.
+    name: String,
+    options: MatchOptions,
+    /// Name of the player who hosts a listen server.
+    host: Option<String>,
+    /// Server waits in the lobby until the match is started.
+    in_lobby: bool,
+    last_lobby_update_time: Instant,
+    /// Rate of chat messages is limited in real time.
+    start_time: Instant,
+}
+
+impl Server {
+    /// Starts a server that waits in the lobby, see [`Self::start_match`].
+    pub fn bind(
+        port: u16,
+        conditions: NetworkConditions,
+        name: String,
+        options: MatchOptions,
+    ) -> io::Result<Self> {
+        let socket = Socket::bind(("0.0.0.0", port), conditions)?;
+
+        Log::writeln(
+            MessageKind::Information,
+            format!("Server is listening on port {}", port),
+        );
+
+        Ok(Self {
+            socket,
+            clients: Default::default(),
+            next_client_id: 0,
+            tick: 0,
+            name,
+            options,
+            host: None,
+            in_lobby: true,
+            last_lobby_update_time: Instant::now(),
+            start_time: Instant::now(),
+        })
+    }
+
+    pub fn set_host(&mut self, name: String) {
+        self.host = Some(name);
+    }
+
+    /// Name of the player who hosts a listen server.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    pub fn options(&self) -> &MatchOptions {
+        &self.options
+    }
+
+    pub fn is_in_lobby(&self) -> bool {
+        self.in_lobby
+    }
+
+    /// Returns `true` if every client in the lobby is ready to play.
+    pub fn is_everyone_ready(&self) -> bool {
+        self.clients.iter().all(|client| client.ready)
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn lobby(&self) -> Lobby {
+        let host = self.host.iter().map(|name| LobbyPlayer {
+            name: name.clone(),
+            team: level::player_team(&self.options),
+            ready: true,
+        });
+        Lobby {
+            server_name: self.name.clone(),
+            options: self.options.clone(),
+            players: host
+                .chain(self.clients.iter().map(|client| LobbyPlayer {
+                    name: client.name.clone(),
+                    team: client.team,
+                    ready: client.ready,
+                }))
+                .collect(),
+        }
+    }
+
+    /// Leaves the lobby: players of clients are spawned and clients start loading the level.
+    pub fn start_match(&mut self, level: &Level, sender: &Sender<Message>) {
+        self.in_lobby = false;
+        self.options = level.options.clone();
+
+        for index in 0..self.clients.len() {
+            let client = &self.clients[index];
+            sender
+                .send(Message::SpawnRemotePlayer {
+                    client: client.id,
+                    name: client.name.clone(),
+                    team: client.team,
+                })
+                .unwrap();
+            self.accept(index);
+        }
+    }
+
+    /// Sends state of the lobby to clients, must be called regularly while server is in the
+    /// lobby.
+    pub fn update_lobby(&mut self) {
+        if !self.in_lobby || self.last_lobby_update_time.elapsed() < LOBBY_UPDATE_INTERVAL {
+            return;
+        }
+        self.last_lobby_update_time = Instant::now();
+
+        for index in 0..self.clients.len() {
+            self.send_lobby(index);
+        }
+    }
+
+    fn send_lobby(&mut self, index: usize) {
+        let client = &self.clients[index];
+        let packet = ServerPacket::Lobby {
+            client: client.id,
+            lobby: self.lobby(),
+        };
+        if let Some(data) = packet.encode() {
+            self.socket.send(client.address, &data);
+        }
+    }
+
+    /// Handles packets of clients and applies their input to their players. Players of
+    /// connected and disconnected clients are spawned and removed using messages. Level does
+    /// not exist while server is in the lobby.
+    pub fn receive(&mut self, level: Option<&mut Level>, sender: &Sender<Message>) {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            let (size, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(result) => result,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    // Windows reports unreachable clients this way, it is not fatal.
+                    Log::writeln(
+                        MessageKind::Warning,
+                        format!("Failed to receive packet, reason: {}", e),
+                    );
+                    continue;
+                }
+            };
+
+            if let Some(packet) = ClientPacket::decode(&buffer[..size]) {
+                self.handle_packet(packet, address, level.as_deref(), sender);
+            }
+        }
+
+        let now = Instant::now();
+        for client in self.clients.iter() {
+            if now - client.last_packet_time > CONNECTION_TIMEOUT {
+                self.on_client_left(client, "timed out", sender);
+            }
+        }
+        self.clients
+            .retain(|client| now - client.last_packet_time <= CONNECTION_TIMEOUT);
+
+        let level = match level {
+            Some(level) => level,
+            None => return,
+        };
+        for client in self.clients.iter_mut() {
+            if let Some((sequence, input)) = client.inputs.pop_front() {
+                client.ack_input = sequence;
+                client.input = input;
+            }
+            level.set_view_time(client.id, client.view_time);
+            let player = level.remote_player(client.id);
+            if let Some(Actor::Player(player)) = level.actors_mut().try_get_mut(player) {
+                player.set_input(&client.input);
+                client.team = player.team();
+            }
+        }
+    }
+
+    fn handle_packet(
+        &mut self,
+        packet: ClientPacket,
+        address: SocketAddr,
+        level: Option<&Level>,
+        sender: &Sender<Message>,
+    ) {
+        let client = self
+            .clients
+            .iter()
+            .position(|client| client.address == address);
+
+        match (packet, client) {
+            (ClientPacket::Connect { name }, None) => {
+                let id = self.next_client_id;
+                self.next_client_id += 1;
+
+                let name = self.unique_name(level, name);
+                Log::writeln(
+                    MessageKind::Information,
+                    format!("{} connected from {}", name, address),
+                );
+
+                self.clients
+                    .push(RemoteClient::new(id, address, name.clone()));
+                if self.in_lobby {
+                    self.send_lobby(self.clients.len() - 1);
+                } else {
+                    sender
+                        .send(Message::SpawnRemotePlayer {
+                            client: id,
+                            name: name.clone(),
+                            team: Team::None,
+                        })
+                        .unwrap();
+                    sender
+                        .send(Message::AddNotification {
+                            text: format!("{} joined the match", name),
+                        })
+                        .unwrap();
+                    self.accept(self.clients.len() - 1);
+                }
+            }
+            (ClientPacket::Connect { .. }, Some(index)) => {
+                // Previous answer was lost, client repeats its request.
+                self.clients[index].last_packet_time = Instant::now();
+                if self.in_lobby {
+                    self.send_lobby(index);
+                } else {
+                    self.accept(index);
+                }
+            }
+            (ClientPacket::LobbyChoice { team, ready }, Some(index)) => {
+                let client = &mut self.clients[index];
+                client.last_packet_time = Instant::now();
+                if self.in_lobby {
+                    client.team = team;
+                    client.ready = ready;
+                }
+            }
+            (ClientPacket::Discover { token }, _) => {
+                let packet = ServerPacket::Info {
+                    token,
+                    info: self.info(),
+                };
+                if let Some(data) = packet.encode() {
+                    self.socket.send(address, &data);
+                }
+            }
+            (
+                ClientPacket::Input {
+                    sequence,
+                    view_time,
+                    inputs,
+                },
+                Some(index),
+            ) => {
+                let client = &mut self.clients[index];
+                client.last_packet_time = Instant::now();
+                client.view_time = view_time;
+
+                // Packets repeat previous inputs, only new ones are queued. The last input has
+                // the sequence number of the packet, packets that cannot have it are dropped.
+                let end = match sequence.checked_add(1) {
+                    Some(end) => end,
+                    None => return,
+                };
+                let count = (inputs.len() as u32).min(end);
+                let skipped = inputs.len() - count as usize;
+                let first_sequence = end - count;
+                for (input_sequence, input) in
+                    (first_sequence..=sequence).zip(inputs.into_iter().skip(skipped))
+                {
+                    if input_sequence > client.last_received_input {
+                        client.inputs.push_back((input_sequence, input));
+                        client.last_received_input = input_sequence;
+                    }
+                }
+                while client.inputs.len() > MAX_QUEUED_INPUTS {
+                    client.inputs.pop_front();
+                }
+            }
+            (ClientPacket::Chat { channel, text }, Some(index)) => {
+                let now = self.start_time.elapsed().as_secs_f64();
+                let client = &mut self.clients[index];
+                client.last_packet_time = Instant::now();
+                // Chat is available only during the match.
+                if self.in_lobby {
+                    return;
+                }
+                match chat::sanitize(&text) {
+                    Some(text) if client.chat_limiter.try_send(now) => sender
+                        .send(Message::Chat {
+                            sender: client.name.clone(),
+                            team: client.team,
+                            channel,
+                            text,
+                        })
+                        .unwrap(),
+                    Some(_) => Log::writeln(
+                        MessageKind::Warning,
+                        format!(
+                            "Chat message of {} is dropped, too many messages",
+                            client.name
+                        ),
+                    ),
+                    None => (),
+                }
+            }
+            (ClientPacket::Disconnect, Some(index)) => {
+                let client = self.clients.remove(index);
+                self.on_client_left(&client, "left", sender);
+            }
+            // Packets of unknown clients are ignored, they must connect first.
+            (_, None) => (),
+        }
+    }
+
+    fn accept(&mut self, index: usize) {
+        let client = &self.clients[index];
+        let packet = ServerPacket::Accepted {
+            client: client.id,
+            options: self.options.clone(),
+        };
+        if let Some(data) = packet.encode() {
+            self.socket.send(client.address, &data);
+        }
+    }
+
+    fn info(&self) -> ServerInfo {
+        ServerInfo {
+            name: self.name.clone(),
+            map: self.options.map().file_name(),
+            options: self.options.clone(),
+            players: (self.clients.len() + self.host.iter().count()) as u32,
+            in_lobby: self.in_lobby,
+        }
+    }
+
+    fn unique_name(&self, level: Option<&Level>, name: String) -> String {
+        let is_taken = |name: &str| {
+            level.map_or(false, |level| {
+                level.actors().iter().any(|actor| actor.name == name)
+            }) || self.host.as_deref() == Some(name)
+                || self.clients.iter().any(|client| client.name == name)
+        };
+        let mut unique_name = name.clone();
+        let mut i = 2;
+        while is_taken(&unique_name) {
+            unique_name = format!("{} ({})", name, i);
+            i += 1;
+        }
+        unique_name
+    }
+
+    fn on_client_left(&self, client: &RemoteClient, reason: &str, sender: &Sender<Message>) {
+        Log::writeln(
+            MessageKind::Information,
+            format!("{} {}", client.name, reason),
+        );
+        if self.in_lobby {
+            return;
+        }
+        sender
+            .send(Message::RemoveRemotePlayer { client: client.id })
+            .unwrap();
+        sender
+            .send(Message::AddNotification {
+                text: format!("{} left the match", client.name),
+            })
+            .unwrap();
+    }
+
+    /// Sends a message to every client if the message is replicated. Team chat is sent only
+    /// to teammates of the sender.
+    pub fn broadcast(&mut self, message: &Message) {
+        if !message.is_replicated() {
+            return;
+        }
+        if let Message::Chat {
+            sender,
+            channel,
+            text,
+            ..
+        } = message
+        {
+            Log::writeln(
+                MessageKind::Information,
+                format!("Chat: {}", chat::format_line(sender, *channel, text)),
+            );
+        }
+        if let Some(data) = ServerPacket::Event(message.clone()).encode() {
+            for client in self.clients.iter() {
+                if let Message::Chat { team, channel, .. } = message {
+                    if !chat::is_visible_to(*channel, *team, client.team) {
+                        continue;
+                    }
+                }
+                self.socket.send(client.address, &data);
+            }
+        }
+    }
+
+    /// Must be called once per simulation step after the level is updated.
+    pub fn send_snapshots(&mut self, level: &Level, scene: &Scene) {
+        self.tick += 1;
+        if self.tick % SNAPSHOT_INTERVAL != 0 || self.clients.is_empty() {
+            return;
+        }
+
+        let mut snapshot = Snapshot {
+            tick: self.tick,
+            time: level.time(),
+            actors: level
+                .actors()
+                .pair_iter()
+                .map(|(handle, actor)| {
+                    let look = scene.graph[actor.get_body()].look_vector();
+                    ActorState {
+                        actor: handle,
+                        kind: match actor {
+                            Actor::Bot(bot) => ReplicatedActorKind::Bot(bot.definition().kind),
+                            Actor::Player(_) => ReplicatedActorKind::Player,
+                        },
+                        name: actor.name.clone(),
+                        team: actor.team(),
+                        position: actor.position(&scene.graph),
+                        yaw: look.x.atan2(look.z),
+                        health: actor.get_health(),
+                        armor: actor.get_armor(),
+                    }
+                })
+                .collect(),
+            items: level
+                .items()
+                .pair_iter()
+                .map(|(handle, item)| ItemState {
+                    item: handle,
+                    active: !item.is_picked_up(),
+                })
+                .collect(),
+            scores: level
+                .leader_board
+                .values()
+                .iter()
+                .map(|(name, score)| ScoreState {
+                    name: name.clone(),
+                    kills: score.kills,
+                    deaths: score.deaths,
+                })
+                .collect(),
+            team_scores: [Team::Red, Team::Blue]
+                .iter()
+                .map(|&team| (team, level.leader_board.team_score(team)))
+                .collect(),
+            ..Default::default()
+        };
+
+        for client in self.clients.iter() {
+            snapshot.own_actor = level.remote_player(client.id);
+            snapshot.ack_input = client.ack_input;
+            snapshot.own_velocity = level
+                .actors()
+                .try_get(snapshot.own_actor)
+                .map(|actor| scene.graph[actor.get_body()].as_rigid_body().lin_vel())
+                .unwrap_or_default();
+            snapshot.own_weapons = level
+                .actors()
+                .try_get(snapshot.own_actor)
+                .map(|actor| {
+                    actor
+                        .weapons()
+                        .iter()
+                        .map(|&weapon| {
+                            let weapon = &level.weapons()[weapon];
+                            (weapon.get_kind(), weapon.ammo())
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            if let Some(data) = ServerPacket::Snapshot(snapshot.clone()).encode() {
+                self.socket.send(client.address, &data);
+            }
+        }
+    }
+
+    /// Tells every client that the server is gone, so they would not wait for a timeout.
+    pub fn disconnect_all(&mut self, reason: &str) {
+        let packet = ServerPacket::Disconnect {
+            reason: reason.to_owned(),
+        };
+        if let Some(data) = packet.encode() {
+            for client in self.clients.drain(..) {
+                self.socket.send(client.address, &data);
+            }
+        }
+    }
+}
+
+/// Options of a dedicated server that headless mode does not have.
+pub struct ServerOptions {
+    pub port: u16,
+    pub name: String,
+    /// Wait for players in the lobby before the match starts.
+    pub lobby: bool,
+    /// Play the next map of the rotation when a match ends, instead of stopping.
+    pub rotate: bool,
+}
+
+/// Parses arguments of a dedicated server: `--port`, `--server-name`, `--lobby`, `--rotate` and
+/// everything that headless mode accepts.
+pub fn parse_args(args: &[String]) -> Result<(ServerOptions, HeadlessOptions), String> {
+    let mut server_options = ServerOptions {
+        port: DEFAULT_PORT,
+        name: DEFAULT_SERVER_NAME.to_owned(),
+        lobby: false,
+        rotate: false,
+    };
+    let mut rest = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--server" => (),
+            "--lobby" => server_options.lobby = true,
+            "--rotate" => server_options.rotate = true,
+            "--port" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {} argument", arg))?;
+                server_options.port = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value {} for {} argument", value, arg))?;
+            }
+            "--server-name" => {
+                server_options.name = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {} argument", arg))?
+                    .clone();
+            }
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    Ok((server_options, HeadlessOptions::from_args(&rest)?))
+}
+
+/// Runs a dedicated server until the match ends, or forever if maps are rotated. Unlike headless
+/// mode simulation goes in real time, so players could play.
+pub fn run(server_options: ServerOptions, conditions: NetworkConditions, options: HeadlessOptions) {
+    let port = server_options.port;
+    let mut server = match Server::bind(
+        port,
+        conditions,
+        server_options.name,
+        options.match_options(),
+    ) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Failed to start server on port {}: {}", port, e);
+            std::process::exit(1);
+        }
+    };
+
+    let resource_manager = ResourceManager::new(Arc::new(SerializationContext::new()));
+    let (sender, receiver) = mpsc::channel();
+
+    if server_options.lobby {
+        println!("Waiting for players in the lobby...");
+        while server.client_count() == 0 || !server.is_everyone_ready() {
+            server.receive(None, &sender);
+            server.update_lobby();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    let rotation = MapRotation::new(map::discover(Path::new(MAPS_DIR)));
+    let mut match_options = options.match_options();
+    loop {
+        let (mut level, mut scene) = block_on(Level::new(
+            resource_manager.clone(),
+            Arc::new(RwLock::new(ControlScheme::default())),
+            sender.clone(),
+            match_options.clone(),
+            false,
+            options.seed,
+        ));
+        server.start_match(&level, &sender);
+
+        let fixed_timestep = 1.0 / FIXED_FPS;
+        let mut time = GameTime::new(fixed_timestep);
+        let mut last_update_time = Instant::now();
+        let mut lag = 0.0;
+
+        let mut match_over = false;
+        while !match_over && level.time() < options.time_limit_secs {
+            let now = Instant::now();
+            lag += (now - last_update_time).as_secs_f64();
+            last_update_time = now;
+
+            while lag >= fixed_timestep as f64 {
+                lag -= fixed_timestep as f64;
+
+                server.receive(Some(&mut level), &sender);
+
+                time.advance();
+                scene.update(Vector2::new(1.0, 1.0), time.delta);
+
+                let mut context = LevelContext {
+                    scene: &mut scene,
+                    resource_manager: resource_manager.clone(),
+                };
+
+                level.update(&mut context, time);
+
+                while let Ok(message) = receiver.try_recv() {
+                    if let Message::EndMatch = message {
+                        match_over = true;
+                    }
+                    server.broadcast(&message);
+                    block_on(level.handle_message(&mut context, &message, time));
+                }
+
+                server.send_snapshots(&level, &scene);
+            }
+
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        println!(
+            "Match on {} finished after {:.1} s",
+            match_options.map().file_name(),
+            level.time()
+        );
+        println!("{}", level.leader_board);
+
+        if !server_options.rotate {
+            break;
+        }
+        let next_map = rotation.next(match_options.map(), match_options.mode());
+        println!("Next map is {}", next_map.file_name());
+        match_options.set_map(next_map);
+    }
+
+    server.disconnect_all("Match is over");
+}
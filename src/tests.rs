@@ -0,0 +1,1814 @@
+//! Gameplay tests. Every test builds a small level (a floor and a few spawn points) without a
+//! window and a renderer, scripts it with messages the same way as game systems do and steps
+//! simulation with fixed time step.
+
+use crate::{
+    actor::Actor,
+    bot::{self, BotKind, BotSkill},
+    character::Team,
+    chat::{self, ChatChannel, ChatLimiter},
+    config::Config,
+    control_scheme::{ControlButton, ControlScheme, KEY_CODES},
+    demo::{Demo, DemoPlayback},
+    effects::EffectKind,
+    item::{Item, ItemKind, ItemOverrides, ANNOUNCEMENT_TIME},
+    lag_compensation,
+    level::{self, Level, LevelContext, Marker, SpawnPoint, RESPAWN_TIME, SPAWN_PROTECTION_TIME},
+    map::{self, MapInfo, MapPath, MapRotation, MAPS_DIR},
+    message::Message,
+    net::{
+        ActorState, ClientPacket, ItemState, Lobby, LobbyPlayer, ReplicatedActorKind, ScoreState,
+        ServerInfo, ServerPacket, Snapshot,
+    },
+    player::{Player, PlayerInput},
+    projectile::ProjectileKind,
+    roster::{BotConfig, BotRoster},
+    save::{
+        self, SaveError, SaveHeader, SaveMetadata, Thumbnail, SAVE_CORPUS_DIR, SAVE_FORMAT_VERSION,
+    },
+    validator::{self, Severity},
+    weapon::{AutoSwitch, FireMode, Weapon, WeaponKind, WeaponRules, WeaponState, PLAYER_LOADOUT},
+    GameMode, GameTime, MatchOptions, FIXED_FPS,
+};
+use fyrox::{
+    core::{
+        algebra::{Matrix3, Vector2, Vector3},
+        futures::executor::block_on,
+        pool::Handle,
+        visitor::{Visit, Visitor},
+    },
+    engine::{resource_manager::ResourceManager, SerializationContext},
+    event::VirtualKeyCode,
+    renderer::QualitySettings,
+    scene::{
+        base::BaseBuilder,
+        collider::{ColliderBuilder, ColliderShape},
+        pivot::PivotBuilder,
+        rigidbody::{RigidBodyBuilder, RigidBodyType},
+        transform::TransformBuilder,
+        Scene,
+    },
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, RwLock,
+    },
+};
+
+struct TestLevel {
+    level: Level,
+    scene: Scene,
+    resource_manager: ResourceManager,
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+    time: GameTime,
+    /// Every message handled by the level, the oldest first.
+    handled: Vec<Message>,
+}
+
+impl TestLevel {
+    /// Creates a level without bots and player: a flat floor with spawn points in its corners.
+    fn new() -> Self {
+        let resource_manager = ResourceManager::new(Arc::new(SerializationContext::new()));
+        let (sender, receiver) = mpsc::channel();
+
+        let mut scene = Scene::new();
+
+        let collider = ColliderBuilder::new(BaseBuilder::new())
+            .with_shape(ColliderShape::cuboid(50.0, 0.1, 50.0))
+            .build(&mut scene.graph);
+        let map_root = RigidBodyBuilder::new(BaseBuilder::new().with_children(&[collider]))
+            .with_body_type(RigidBodyType::Static)
+            .build(&mut scene.graph);
+
+        for (i, &(x, z)) in [(-40.0, -40.0), (40.0, -40.0), (-40.0, 40.0), (40.0, 40.0)]
+            .iter()
+            .enumerate()
+        {
+            let spawn_point = PivotBuilder::new(
+                BaseBuilder::new()
+                    .with_name(format!("SpawnPoint{}", i))
+                    .with_local_transform(
+                        TransformBuilder::new()
+                            .with_local_position(Vector3::new(x, 1.0, z))
+                            .build(),
+                    ),
+            )
+            .build(&mut scene.graph);
+            scene.graph.link_nodes(spawn_point, map_root);
+        }
+
+        let options = MatchOptions::new(
+            GameMode::DeathMatch,
+            600.0,
+            100,
+            BotRoster {
+                bots: Vec::new(),
+                fill_to: 0,
+            },
+            Default::default(),
+        );
+
+        let (level, scene) = block_on(Level::from_scene(
+            scene,
+            map_root,
+            resource_manager.clone(),
+            Arc::new(RwLock::new(ControlScheme::default())),
+            sender.clone(),
+            options,
+            false,
+            0,
+        ));
+
+        Self {
+            level,
+            scene,
+            resource_manager,
+            sender,
+            receiver,
+            time: GameTime::new(1.0 / FIXED_FPS),
+            handled: Vec::new(),
+        }
+    }
+
+    /// Loads a level from a save instead of building it.
+    fn load(path: &Path) -> Self {
+        let resource_manager = ResourceManager::new(Arc::new(SerializationContext::new()));
+        let (sender, receiver) = mpsc::channel();
+
+        let (mut level, scene) = save::read_state(
+            path,
+            Arc::new(SerializationContext::new()),
+            resource_manager.clone(),
+        )
+        .unwrap_or_else(|e| panic!("{} cannot be loaded: {}", path.display(), e));
+        level.set_message_sender(sender.clone());
+
+        Self {
+            level,
+            scene,
+            resource_manager,
+            sender,
+            receiver,
+            time: GameTime::new(1.0 / FIXED_FPS),
+            handled: Vec::new(),
+        }
+    }
+
+    fn send(&self, message: Message) {
+        self.sender.send(message).unwrap();
+    }
+
+    /// Updates the level given amount of times, messages are handled at the end of every tick.
+    fn step(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.time.advance();
+
+            self.scene.update(Vector2::new(1.0, 1.0), self.time.delta);
+
+            let mut context = LevelContext {
+                scene: &mut self.scene,
+                resource_manager: self.resource_manager.clone(),
+            };
+
+            self.level.update(&mut context, self.time);
+
+            while let Ok(message) = self.receiver.try_recv() {
+                block_on(self.level.handle_message(&mut context, &message, self.time));
+                self.handled.push(message);
+            }
+        }
+    }
+
+    fn step_secs(&mut self, secs: f32) {
+        self.step((secs * FIXED_FPS).ceil() as usize)
+    }
+
+    fn find_actor(&self, name: &str) -> Handle<Actor> {
+        self.level
+            .actors()
+            .pair_iter()
+            .find(|(_, actor)| actor.name == name)
+            .map_or(Handle::NONE, |(handle, _)| handle)
+    }
+
+    fn add_bot(&mut self, name: &str, position: Vector3<f32>) -> Handle<Actor> {
+        self.send(Message::AddBot {
+            kind: BotKind::Mutant,
+            position,
+            name: Some(name.to_owned()),
+            team: Team::None,
+            skill: BotSkill::Average,
+        });
+        self.step(1);
+        let bot = self.find_actor(name);
+        assert!(bot.is_some(), "bot {} was not added", name);
+        bot
+    }
+
+    /// Adds a bot and gives it every weapon of the player, so tests of weapons do not depend on
+    /// the loadout of bot kinds.
+    fn add_armed_bot(&mut self, name: &str, position: Vector3<f32>) -> Handle<Actor> {
+        let bot = self.add_bot(name, position);
+        for &kind in PLAYER_LOADOUT.iter() {
+            self.send(Message::GiveItem {
+                actor: bot,
+                kind: ItemKind::from_weapon(kind),
+            });
+        }
+        self.step(1);
+        for &kind in PLAYER_LOADOUT.iter() {
+            assert!(self.ammo(bot, kind).is_some(), "bot has no {:?}", kind);
+        }
+        bot
+    }
+
+    /// Makes weapon of given kind current weapon of an actor, the weapon is raised in next
+    /// ticks.
+    fn select_weapon(&mut self, actor: Handle<Actor>, kind: WeaponKind) -> Handle<Weapon> {
+        let character = self.level.actors().get(actor);
+        let weapon = character
+            .find_weapon(kind, self.level.weapons())
+            .unwrap_or_else(|| panic!("actor has no {:?}", kind));
+        let index = character
+            .weapons()
+            .iter()
+            .position(|&w| w == weapon)
+            .unwrap();
+        self.level
+            .actors_mut()
+            .get_mut(actor)
+            .set_current_weapon(index);
+        weapon
+    }
+
+    fn health(&self, actor: Handle<Actor>) -> f32 {
+        self.level.actors().get(actor).health
+    }
+
+    fn ammo(&self, actor: Handle<Actor>, kind: WeaponKind) -> Option<u32> {
+        self.level
+            .actors()
+            .get(actor)
+            .weapons()
+            .iter()
+            .map(|&weapon| &self.level.weapons()[weapon])
+            .find(|weapon| weapon.get_kind() == kind)
+            .map(|weapon| weapon.ammo())
+    }
+
+    /// Hash of everything that simulation decides: actors with their weapons, items, scores and
+    /// every handled message.
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let graph = &self.scene.graph;
+        let hash_vec = |v: Vector3<f32>, hasher: &mut DefaultHasher| {
+            for c in v.iter() {
+                c.to_bits().hash(hasher);
+            }
+        };
+
+        for (handle, actor) in self.level.actors().pair_iter() {
+            handle.index().hash(&mut hasher);
+            actor.name.hash(&mut hasher);
+            actor.health.to_bits().hash(&mut hasher);
+            actor.armor.to_bits().hash(&mut hasher);
+            hash_vec(actor.position(graph), &mut hasher);
+            for &weapon in actor.weapons() {
+                let weapon = &self.level.weapons()[weapon];
+                (weapon.get_kind() as u32).hash(&mut hasher);
+                weapon.ammo().hash(&mut hasher);
+            }
+        }
+
+        for item in self.level.items().iter() {
+            (item.get_kind() as u32).hash(&mut hasher);
+            item.is_picked_up().hash(&mut hasher);
+            hash_vec(item.position(graph), &mut hasher);
+        }
+
+        self.level.leader_board.to_string().hash(&mut hasher);
+        format!("{:?}", self.handled).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn kills(&self, name: &str) -> u32 {
+        self.level
+            .leader_board
+            .values()
+            .get(name)
+            .map_or(0, |score| score.kills)
+    }
+
+    fn deaths(&self, name: &str) -> u32 {
+        self.level
+            .leader_board
+            .values()
+            .get(name)
+            .map_or(0, |score| score.deaths)
+    }
+}
+
+#[test]
+fn damage_is_absorbed_by_armor_first() {
+    let mut level = TestLevel::new();
+    let bot = level.add_bot("Target", Vector3::new(-40.0, 1.0, -40.0));
+
+    level.send(Message::DamageActor {
+        actor: bot,
+        who: Handle::NONE,
+        amount: 150.0,
+    });
+    level.step(1);
+
+    assert_eq!(level.level.actors().get(bot).armor, -50.0);
+    assert_eq!(level.health(bot), 50.0);
+}
+
+#[test]
+fn killer_gets_a_frag() {
+    let mut level = TestLevel::new();
+    let killer = level.add_bot("Killer", Vector3::new(-40.0, 1.0, -40.0));
+    let victim = level.add_bot("Victim", Vector3::new(40.0, 1.0, 40.0));
+
+    level.send(Message::DamageActor {
+        actor: victim,
+        who: killer,
+        amount: 1000.0,
+    });
+    level.step(1);
+
+    assert!(level.level.actors().get(victim).is_dead());
+    assert_eq!(level.kills("Killer"), 1);
+    assert_eq!(level.kills("Victim"), 0);
+
+    // Damaging a dead actor must not give more frags.
+    level.send(Message::DamageActor {
+        actor: victim,
+        who: killer,
+        amount: 1000.0,
+    });
+    level.step(1);
+
+    assert_eq!(level.kills("Killer"), 1);
+}
+
+#[test]
+fn killing_a_teammate_is_not_a_frag() {
+    let mut level = TestLevel::new();
+    for &(name, team, x) in [("Red 1", Team::Red, -40.0), ("Red 2", Team::Red, 40.0)].iter() {
+        level.send(Message::AddBot {
+            kind: BotKind::Mutant,
+            position: Vector3::new(x, 1.0, -40.0),
+            name: Some(name.to_owned()),
+            team,
+            skill: BotSkill::Average,
+        });
+    }
+    level.step(1);
+    let killer = level.find_actor("Red 1");
+    let victim = level.find_actor("Red 2");
+
+    level.send(Message::DamageActor {
+        actor: victim,
+        who: killer,
+        amount: 1000.0,
+    });
+    level.step(1);
+
+    assert!(level.level.actors().get(victim).is_dead());
+    assert_eq!(level.kills("Red 1"), 0);
+    assert_eq!(level.level.leader_board.team_score(Team::Red), 0);
+}
+
+#[test]
+fn dead_bot_is_respawned() {
+    let mut level = TestLevel::new();
+    let bot = level.add_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+
+    level.send(Message::DamageActor {
+        actor: bot,
+        who: Handle::NONE,
+        amount: 1000.0,
+    });
+
+    // Wait until dying animation is finished and bot is removed from level.
+    let mut ticks = 0;
+    while level.level.actors().contains(bot) {
+        level.step(1);
+        ticks += 1;
+        assert!(ticks < 20 * FIXED_FPS as usize, "dead bot was not removed");
+    }
+
+    assert_eq!(level.deaths("Bot"), 1);
+    let time_left = level.level.respawn_time_left("Bot").unwrap();
+    assert!(time_left > 0.0 && time_left <= RESPAWN_TIME);
+
+    level.step_secs(RESPAWN_TIME + 0.1);
+
+    assert!(level.level.respawn_time_left("Bot").is_none());
+    let respawned = level.find_actor("Bot");
+    assert!(respawned.is_some());
+    assert_eq!(level.health(respawned), 100.0);
+}
+
+#[test]
+fn ammo_item_adds_ammo_to_weapon_of_its_kind() {
+    let mut level = TestLevel::new();
+    let bot = level.add_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+
+    let ammo = level.ammo(bot, WeaponKind::Ak47).unwrap();
+
+    level.send(Message::GiveItem {
+        actor: bot,
+        kind: ItemKind::Ak47Ammo,
+    });
+    level.step(1);
+
+    assert_eq!(level.ammo(bot, WeaponKind::Ak47), Some(ammo + 200));
+
+    // Ammo for a weapon that actor does not have is ignored.
+    level.send(Message::GiveItem {
+        actor: bot,
+        kind: ItemKind::M4Ammo,
+    });
+    level.step(1);
+
+    assert_eq!(level.ammo(bot, WeaponKind::M4), None);
+}
+
+#[test]
+fn weapon_item_gives_new_weapon() {
+    let mut level = TestLevel::new();
+    let bot = level.add_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+
+    level.send(Message::GiveItem {
+        actor: bot,
+        kind: ItemKind::M4,
+    });
+    level.step(1);
+
+    assert!(level.ammo(bot, WeaponKind::M4).is_some());
+}
+
+#[test]
+fn picked_up_medkit_heals_actor() {
+    let mut level = TestLevel::new();
+    let bot = level.add_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+
+    // Put medkit far away from the bot, so it won't be picked up automatically.
+    level.send(Message::SpawnItem {
+        kind: ItemKind::Medkit,
+        position: Vector3::new(40.0, 1.0, 40.0),
+        adjust_height: false,
+        lifetime: None,
+    });
+    level.send(Message::DamageActor {
+        actor: bot,
+        who: Handle::NONE,
+        amount: 150.0,
+    });
+    level.step(1);
+
+    let (medkit, _) = level
+        .level
+        .items()
+        .pair_iter()
+        .find(|(_, item)| item.get_kind() == ItemKind::Medkit)
+        .unwrap();
+
+    level.send(Message::PickUpItem {
+        actor: bot,
+        item: medkit,
+    });
+    level.step(1);
+
+    assert_eq!(level.health(bot), 70.0);
+    assert!(level
+        .level
+        .items()
+        .pair_iter()
+        .any(|(handle, item)| handle == medkit && item.is_picked_up()));
+}
+
+#[test]
+fn simulation_is_deterministic() {
+    let run = || {
+        let mut level = TestLevel::new();
+        level.add_armed_bot("A", Vector3::new(-15.0, 1.0, 0.0));
+        level.add_armed_bot("B", Vector3::new(15.0, 1.0, 0.0));
+        // Items under the bots are picked up right away, others are on their way.
+        for &(kind, x) in [
+            (ItemKind::RocketAmmo, -15.0),
+            (ItemKind::Plasma, 15.0),
+            (ItemKind::Medkit, -5.0),
+            (ItemKind::M4Ammo, 5.0),
+        ]
+        .iter()
+        {
+            level.send(Message::SpawnItem {
+                kind,
+                position: Vector3::new(x, 1.0, 0.0),
+                adjust_height: false,
+                lifetime: None,
+            });
+        }
+        level.step_secs(10.0);
+
+        // Otherwise the run would not test shooting and pickups.
+        assert!(level
+            .handled
+            .iter()
+            .any(|m| matches!(m, Message::CreateProjectile { .. })));
+        assert!(level
+            .handled
+            .iter()
+            .any(|m| matches!(m, Message::PickUpItem { .. })));
+
+        level.state_hash()
+    };
+
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn demo_survives_saving_and_loading() {
+    let level = TestLevel::new();
+    let mut demo = Demo::new(level.level.options.clone(), 42, &ControlScheme::default());
+    let inputs = (0..10u16)
+        .map(|i| PlayerInput {
+            controls: i * 37,
+            yaw: i as f32 * 0.5,
+            pitch: -(i as f32) * 0.1,
+            current_weapon: (i % 4) as u8,
+        })
+        .collect::<Vec<_>>();
+    for (i, &input) in inputs.iter().enumerate() {
+        demo.push(input);
+        if i == 3 {
+            demo.push_chat("Player", Team::None, ChatChannel::All, "gg");
+        }
+    }
+
+    let path = std::env::temp_dir().join("rusty-shooter-test-demo.bin");
+    demo.save(&path).unwrap();
+    let loaded = block_on(Demo::load(&path)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.seed(), 42);
+    assert_eq!(loaded.step_count(), inputs.len());
+    assert_eq!(loaded.options().mode(), GameMode::DeathMatch);
+    let mut playback = DemoPlayback::new(loaded);
+    for &input in inputs.iter() {
+        assert_eq!(playback.next_input(), Some(input));
+    }
+    assert_eq!(playback.next_input(), None);
+}
+
+/// Plays a match where the player is driven by `input`, which is called once per simulation
+/// step before the level is updated, and returns state of the level at the end.
+fn play_with_input(mut input: impl FnMut(usize, &mut Player)) -> u64 {
+    let mut level = TestLevel::new();
+    level.send(Message::SpawnPlayer);
+    level.step(1);
+    level.add_armed_bot("A", Vector3::new(-15.0, 1.0, 0.0));
+    level.add_armed_bot("B", Vector3::new(15.0, 1.0, 0.0));
+
+    let player = level.level.get_player();
+    for step in 0..(5.0 * FIXED_FPS) as usize {
+        if let Some(Actor::Player(player)) = level.level.actors_mut().try_get_mut(player) {
+            input(step, player);
+        }
+        level.step(1);
+    }
+
+    level.state_hash()
+}
+
+#[test]
+fn replayed_demo_reproduces_the_match() {
+    let mut demo = Demo::new(
+        TestLevel::new().level.options.clone(),
+        0,
+        &ControlScheme::default(),
+    );
+    let recorded = play_with_input(|step, player| {
+        // Walk forward and shoot in bursts while turning.
+        player.set_input(&PlayerInput {
+            controls: 1 | (((step / 20) % 2) as u16) << 7,
+            yaw: step as f32 * 0.01,
+            pitch: 0.0,
+            current_weapon: 0,
+        });
+        demo.push(player.input());
+    });
+
+    let mut playback = DemoPlayback::new(demo);
+    let replayed = play_with_input(|_, player| {
+        player.set_input(&playback.next_input().expect("demo is too short"));
+    });
+
+    assert_eq!(recorded, replayed);
+}
+
+#[test]
+fn remote_player_joins_and_leaves() {
+    let mut level = TestLevel::new();
+
+    level.send(Message::SpawnRemotePlayer {
+        client: 7,
+        name: "Remote".to_owned(),
+        team: Team::None,
+    });
+    level.step(1);
+
+    let player = level.level.remote_player(7);
+    assert!(player.is_some());
+    assert_eq!(level.find_actor("Remote"), player);
+
+    level.send(Message::RemoveRemotePlayer { client: 7 });
+    level.step(1);
+
+    assert!(level.level.remote_player(7).is_none());
+    assert!(!level.level.actors().contains(player));
+}
+
+#[test]
+fn shot_hits_capsule_of_actor() {
+    let origin = Vector3::new(0.0, 1.0, 0.0);
+    let direction = Vector3::z();
+
+    let hit =
+        lag_compensation::ray_hit_distance(origin, direction, 100.0, Vector3::new(0.1, 1.0, 10.0));
+    assert!(matches!(hit, Some(distance) if (distance - 10.0).abs() < 0.5));
+
+    // Actor has moved aside since the shooter saw it.
+    assert!(lag_compensation::ray_hit_distance(
+        origin,
+        direction,
+        100.0,
+        Vector3::new(2.0, 1.0, 10.0)
+    )
+    .is_none());
+    // Actor is behind a wall.
+    assert!(lag_compensation::ray_hit_distance(
+        origin,
+        direction,
+        5.0,
+        Vector3::new(0.0, 1.0, 10.0)
+    )
+    .is_none());
+}
+
+#[test]
+fn bots_lead_targets_that_move_with_constant_velocity() {
+    let shooter = Vector3::new(0.0, 1.0, 0.0);
+    let target = Vector3::new(10.0, 1.0, 0.0);
+    let velocity = Vector3::new(0.0, 0.0, 2.0);
+    let speed = 10.0;
+
+    let point = bot::predict_intercept_point(shooter, target, velocity, speed);
+    // Target and projectile reach the point at the same time.
+    let time = point.metric_distance(&target) / velocity.norm();
+    assert!(time > 0.0);
+    assert!((point.metric_distance(&shooter) - speed * time).abs() < 1.0e-3);
+    assert!((point - (target + velocity.scale(time))).norm() < 1.0e-3);
+
+    // Standing target is shot directly.
+    assert_eq!(
+        bot::predict_intercept_point(shooter, target, Vector3::default(), speed),
+        target
+    );
+    // Projectile can not catch up with a target that runs away faster.
+    assert_eq!(
+        bot::predict_intercept_point(shooter, target, Vector3::new(20.0, 0.0, 0.0), speed),
+        target
+    );
+}
+
+#[test]
+fn chat_is_rate_limited_and_team_chat_is_private() {
+    let mut limiter = ChatLimiter::default();
+    let sent = (0..10)
+        .filter(|&i| limiter.try_send(i as f64 * 0.1))
+        .count();
+    assert!(sent > 0 && sent < 10);
+    // Limit is lifted after a while.
+    assert!(limiter.try_send(60.0));
+
+    assert!(chat::is_visible_to(ChatChannel::Team, Team::Red, Team::Red));
+    assert!(!chat::is_visible_to(
+        ChatChannel::Team,
+        Team::Red,
+        Team::Blue
+    ));
+    assert!(chat::is_visible_to(ChatChannel::All, Team::Red, Team::Blue));
+
+    assert_eq!(chat::sanitize("  hi\u{7}  "), Some("hi".to_owned()));
+    assert_eq!(chat::sanitize(" \n "), None);
+}
+
+#[test]
+fn save_metadata_describes_the_match() {
+    let mut test = TestLevel::new();
+    test.step_secs(2.0);
+
+    // Frame of 192x108 pixels: the upper half is red, the lower half is blue. Rows of frames go
+    // from bottom to top.
+    let frame = (0..108)
+        .flat_map(|y| {
+            (0..192).map(move |_| {
+                if y < 54 {
+                    [0, 0, 255, 255]
+                } else {
+                    [255, 0, 0, 255]
+                }
+            })
+        })
+        .flatten()
+        .collect::<Vec<u8>>();
+    let metadata = SaveMetadata::new(&test.level, Thumbnail::from_frame(192, 108, &frame));
+    assert!(metadata.time >= 2.0);
+    assert!(metadata.description().starts_with("Deathmatch on"));
+    let texture = metadata.thumbnail.to_texture().unwrap();
+    let texture = texture.data_ref();
+    let pixels = texture.data();
+    assert_eq!(&pixels[..3], &[255, 0, 0]);
+    assert_eq!(&pixels[pixels.len() - 3..], &[0, 0, 255]);
+
+    // Nothing was rendered yet, the thumbnail is still made.
+    assert!(Thumbnail::from_frame(0, 0, &[]).to_texture().is_some());
+
+    assert_eq!(save::slot_name(" My save! "), Some("My save".to_owned()));
+    assert_eq!(save::slot_name("../.."), None);
+}
+
+fn has_bot(path: &Path, resource_manager: &ResourceManager, name: &str) -> bool {
+    let (level, _) = save::read_state(
+        path,
+        Arc::new(SerializationContext::new()),
+        resource_manager.clone(),
+    )
+    .unwrap_or_else(|e| panic!("{} cannot be loaded: {}", path.display(), e));
+    level
+        .actors()
+        .pair_iter()
+        .any(|(_, actor)| actor.name == name)
+}
+
+#[test]
+fn older_saves_are_migrated_and_newer_are_rejected() {
+    let mut test = TestLevel::new();
+    test.add_bot("Bot", Vector3::new(0.0, 1.0, 0.0));
+    test.step_secs(1.0);
+    let dir = std::env::temp_dir();
+
+    // Saves of version 0 had no header.
+    let unversioned = dir.join("rusty-shooter-test-v0.bin");
+    write_state_of_version(&unversioned, 0, &mut test);
+    assert!(has_bot(&unversioned, &test.resource_manager, "Bot"));
+
+    let current = dir.join("rusty-shooter-test-current.bin");
+    save::write_state(&current, &mut test.level, &mut test.scene).unwrap();
+    assert!(has_bot(&current, &test.resource_manager, "Bot"));
+
+    let newer = dir.join("rusty-shooter-test-newer.bin");
+    let mut visitor = Visitor::new();
+    SaveHeader {
+        format_version: SAVE_FORMAT_VERSION + 1,
+        game_version: "99.0.0".to_owned(),
+    }
+    .visit("Header", &mut visitor)
+    .unwrap();
+    visitor.save_binary(&newer).unwrap();
+    let result = save::read_state(
+        &newer,
+        Arc::new(SerializationContext::new()),
+        test.resource_manager.clone(),
+    );
+    assert!(matches!(result, Err(SaveError::TooNew { .. })));
+
+    for path in &[unversioned, current, newer] {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Writes the state in the layout of an older format version, so migrations from that version
+/// run when the save is loaded.
+fn write_state_of_version(path: &Path, format_version: u32, test: &mut TestLevel) {
+    save::write_state_of_version(path, format_version, &mut test.level, &mut test.scene).unwrap();
+}
+
+#[test]
+fn spawn_points_of_version_1_saves_are_read_from_markers() {
+    let mut test = TestLevel::new();
+    // Saves of version 1 have spawn points without teams, modes and directions. Migration
+    // replaces them with spawn points of markers, so any spawn points will do here.
+    test.level.set_spawn_points(Vec::new());
+    let path = std::env::temp_dir().join("rusty-shooter-test-v1.bin");
+    write_state_of_version(&path, 1, &mut test);
+
+    let mut loaded = TestLevel::load(&path);
+    loaded.send(Message::SpawnRemotePlayer {
+        client: 1,
+        name: "Remote".to_owned(),
+        team: Team::None,
+    });
+    loaded.step(1);
+    let player = loaded.level.remote_player(1);
+    let position = loaded
+        .level
+        .actors()
+        .get(player)
+        .position(&loaded.scene.graph);
+    // Player appears at one of spawn points in corners, not at the origin of the map.
+    assert!(position.x.abs() > 30.0 && position.z.abs() > 30.0);
+
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn truncated_spawn_points_of_current_saves_are_rejected() {
+    let path = std::env::temp_dir().join("rusty-shooter-test-spawn-point.bin");
+    let mut visitor = Visitor::new();
+    {
+        let mut region = visitor.enter_region("SpawnPoint").unwrap();
+        let mut position = Vector3::new(1.0, 2.0, 3.0);
+        position.visit("position", &mut region).unwrap();
+    }
+    visitor.save_binary(&path).unwrap();
+
+    let mut visitor = block_on(Visitor::load_binary(&path)).unwrap();
+    let mut spawn_point = SpawnPoint::default();
+    assert!(spawn_point.visit("SpawnPoint", &mut visitor).is_err());
+
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn items_of_version_2_saves_use_definitions_of_their_kinds() {
+    let mut test = TestLevel::new();
+    test.send(Message::SpawnItem {
+        kind: ItemKind::RocketAmmo,
+        position: Vector3::new(20.0, 1.0, 20.0),
+        adjust_height: false,
+        lifetime: None,
+    });
+    test.step(1);
+    let path = std::env::temp_dir().join("rusty-shooter-test-v2.bin");
+    write_state_of_version(&path, 2, &mut test);
+
+    let loaded = TestLevel::load(&path);
+    let item = loaded
+        .level
+        .items()
+        .iter()
+        .find(|item| item.get_kind() == ItemKind::RocketAmmo)
+        .expect("item must be loaded");
+    assert_eq!(item.amount(), Item::default_amount(ItemKind::RocketAmmo));
+
+    // Items of version 2 saves have no overrides at all.
+    let mut visitor = Visitor::new();
+    let mut kind = 0u32;
+    kind.visit("kind", &mut visitor).unwrap();
+    visitor.save_binary(&path).unwrap();
+    let mut visitor = block_on(Visitor::load_binary(&path)).unwrap();
+    let mut overrides = ItemOverrides {
+        amount: Some(5),
+        ..Default::default()
+    };
+    overrides.visit("overrides", &mut visitor).unwrap();
+    assert_eq!(overrides, ItemOverrides::default());
+
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn matches_of_version_3_saves_get_default_map_and_weapon_rules() {
+    let mut test = TestLevel::new();
+    test.level.options.set_map(MapPath::resolve("other"));
+    test.level.options.set_weapon_rules(WeaponRules {
+        weapon_stay: true,
+        drop_current_only: true,
+    });
+    let path = std::env::temp_dir().join("rusty-shooter-test-v3.bin");
+    write_state_of_version(&path, 3, &mut test);
+
+    let loaded = TestLevel::load(&path);
+    assert_eq!(loaded.level.options.map(), &MapPath::default());
+    assert_eq!(loaded.level.options.weapon_rules(), WeaponRules::default());
+
+    let _ = fs::remove_file(path);
+}
+
+/// Adds a save of current format version to the corpus, run it with `--ignored` after
+/// every increase of [`SAVE_FORMAT_VERSION`] and commit the file.
+#[test]
+#[ignore]
+fn write_save_to_corpus() {
+    let mut test = TestLevel::new();
+    test.add_bot("Bot", Vector3::new(0.0, 1.0, 0.0));
+    test.step_secs(1.0);
+
+    fs::create_dir_all(SAVE_CORPUS_DIR).unwrap();
+    let path = Path::new(SAVE_CORPUS_DIR).join(format!("v{}.bin", SAVE_FORMAT_VERSION));
+    save::write_state(&path, &mut test.level, &mut test.scene).unwrap();
+}
+
+#[test]
+#[ignore = "the corpus has no saves yet, see data/tests/saves/README.md"]
+fn saves_of_corpus_keep_loading() {
+    let resource_manager = ResourceManager::new(Arc::new(SerializationContext::new()));
+    // An empty corpus would test nothing, so every version must have a save.
+    for version in 0..=SAVE_FORMAT_VERSION {
+        let path = Path::new(SAVE_CORPUS_DIR).join(format!("v{}.bin", version));
+        assert!(
+            path.exists(),
+            "no save of format version {} in the corpus, see {}/README.md",
+            version,
+            SAVE_CORPUS_DIR
+        );
+        assert!(has_bot(&path, &resource_manager, "Bot"));
+    }
+}
+
+#[test]
+fn config_tolerates_unknown_and_missing_fields() {
+    let config = Config::parse(
+        r#"(
+            controls: (
+                buttons: {"Jump": Key("J"), "Shoot": Key("NoSuchKey"), "Dance": Mouse(4)},
+                mouse_sens: 0.5,
+            ),
+            graphics: (
+                use_ssao: false,
+                csm_settings: (size: 512),
+            ),
+            sound_volume: 0.5,
+            brightness: 2.0,
+        )"#,
+    )
+    .unwrap();
+    let default = ControlScheme::default();
+    assert!(config.controls.jump.button == ControlButton::Key(VirtualKeyCode::J));
+    assert!(config.controls.shoot.button == default.shoot.button);
+    assert!(config.controls.run.button == default.run.button);
+    assert_eq!(config.controls.mouse_sens, 0.5);
+    assert_eq!(config.sound_volume, 0.5);
+    assert_eq!(config.music_volume, Config::default().music_volume);
+    let graphics = QualitySettings::default();
+    assert!(!config.graphics.use_ssao);
+    assert_eq!(config.graphics.csm_settings.size, 512);
+    assert_eq!(config.graphics.csm_settings.pcf, graphics.csm_settings.pcf);
+    assert_eq!(config.graphics.fxaa, graphics.fxaa);
+
+    // Saved config is loaded back as it was.
+    let text = ron::to_string(&config).unwrap();
+    let loaded = Config::parse(&text).unwrap();
+    assert!(loaded.controls.jump.button == ControlButton::Key(VirtualKeyCode::J));
+    assert_eq!(loaded.sound_volume, 0.5);
+    assert_eq!(loaded.graphics.csm_settings.size, 512);
+}
+
+#[test]
+fn every_key_can_be_bound() {
+    // Keys are listed in order of declaration, so a new key of `VirtualKeyCode` is noticed.
+    assert_eq!(KEY_CODES.len(), VirtualKeyCode::Cut as usize + 1);
+    for (i, &code) in KEY_CODES.iter().enumerate() {
+        assert_eq!(code as usize, i, "{:?} is out of order", code);
+    }
+}
+
+#[test]
+fn roster_fills_match_and_balances_teams() {
+    let roster = BotRoster {
+        bots: vec![
+            BotConfig {
+                name: "Red".to_owned(),
+                team: Team::Red,
+                ..Default::default()
+            },
+            BotConfig {
+                name: "Any".to_owned(),
+                ..Default::default()
+            },
+            BotConfig {
+                name: "Any".to_owned(),
+                ..Default::default()
+            },
+        ],
+        fill_to: 6,
+    };
+
+    // Five bots and the player, kinds of bots are used in turn to fill the match.
+    let bots = roster.resolve(true, Team::Blue);
+    assert_eq!(bots.len(), 5);
+    assert_eq!(bots[3].kind, BotKind::ALL[0]);
+    assert_eq!(bots[4].kind, BotKind::ALL[1]);
+    assert_eq!(bots[0].team, Team::Red);
+    // Names are unique even if they are configured the same.
+    assert_eq!(bots[1].name, "Any");
+    assert_eq!(bots[2].name, "Any (2)");
+    // With the player there are three players in each team.
+    let count = |team| bots.iter().filter(|bot| bot.team == team).count();
+    assert_eq!(count(Team::Red), 3);
+    assert_eq!(count(Team::Blue), 2);
+
+    // Teams are reset out of team modes and fill is not needed if there are enough bots.
+    let roster = BotRoster {
+        fill_to: 2,
+        ..roster
+    };
+    let bots = roster.resolve(false, Team::None);
+    assert_eq!(bots.len(), 3);
+    assert!(bots.iter().all(|bot| bot.team == Team::None));
+}
+
+#[test]
+fn maps_are_discovered_and_rotated() {
+    let maps = map::discover(Path::new(MAPS_DIR));
+    let dm6 = maps
+        .iter()
+        .find(|map| map.path == MapPath::default())
+        .expect("default map must be discovered");
+    assert_eq!(dm6.name, "DM6");
+    assert!(dm6.supports(GameMode::CaptureTheFlag));
+    assert!(dm6
+        .preview
+        .as_ref()
+        .map_or(false, |preview| preview.exists()));
+
+    let info = |name: &str, modes: &[GameMode]| MapInfo {
+        path: MapPath::resolve(name),
+        name: name.to_owned(),
+        modes: modes.to_vec(),
+        recommended_players: (2, 8),
+        preview: None,
+    };
+    let rotation = MapRotation::new(vec![
+        info("a", &GameMode::ALL),
+        info("b", &[GameMode::DeathMatch]),
+        info("c", &GameMode::ALL),
+    ]);
+    let next = |current: &str, mode| rotation.next(&MapPath::resolve(current), mode);
+    assert_eq!(next("a", GameMode::DeathMatch), MapPath::resolve("b"));
+    assert_eq!(next("c", GameMode::DeathMatch), MapPath::resolve("a"));
+    // Maps that do not support the mode are skipped.
+    assert_eq!(next("a", GameMode::CaptureTheFlag), MapPath::resolve("c"));
+    assert!(rotation.contains(&MapPath::resolve("b")));
+    assert!(!rotation.contains(&MapPath::resolve("z")));
+
+    let single = MapRotation::new(vec![info("a", &[GameMode::DeathMatch])]);
+    assert_eq!(
+        single.next(&MapPath::resolve("z"), GameMode::TeamDeathMatch),
+        MapPath::resolve("z")
+    );
+}
+
+/// Encodes a packet, decodes it back and checks that nothing was lost: decoded packet encodes
+/// to the same bytes and every truncated copy of the packet is rejected.
+fn client_packet_round_trip(packet: &ClientPacket) -> ClientPacket {
+    let data = packet.encode();
+    for len in 0..data.len() {
+        assert!(ClientPacket::decode(&data[..len]).is_none());
+    }
+    let decoded = ClientPacket::decode(&data).expect("packet must be decoded");
+    assert_eq!(decoded.encode(), data);
+    decoded
+}
+
+fn server_packet_round_trip(packet: &ServerPacket) -> ServerPacket {
+    let data = packet.encode().expect("packet must be encoded");
+    for len in 0..data.len() {
+        assert!(ServerPacket::decode(&data[..len]).is_none());
+    }
+    let decoded = ServerPacket::decode(&data).expect("packet must be decoded");
+    assert_eq!(decoded.encode(), Some(data));
+    decoded
+}
+
+fn test_match_options() -> MatchOptions {
+    let mut options = MatchOptions::new(
+        GameMode::TeamDeathMatch,
+        300.0,
+        25,
+        BotRoster {
+            bots: Vec::new(),
+            fill_to: 0,
+        },
+        MapPath::resolve("dm6"),
+    );
+    options.set_weapon_rules(WeaponRules {
+        weapon_stay: true,
+        drop_current_only: false,
+    });
+    options
+}
+
+#[test]
+fn client_packets_survive_encoding() {
+    let input = PlayerInput {
+        controls: 0x1ff,
+        yaw: 1.5,
+        pitch: -0.25,
+        current_weapon: 3,
+    };
+    let packets = vec![
+        ClientPacket::Connect {
+            name: "Player".to_owned(),
+        },
+        ClientPacket::Input {
+            sequence: 42,
+            view_time: 12.5,
+            inputs: vec![input, input],
+        },
+        ClientPacket::Disconnect,
+        ClientPacket::Discover { token: 7 },
+        ClientPacket::LobbyChoice {
+            team: Team::Blue,
+            ready: true,
+        },
+        ClientPacket::Chat {
+            channel: ChatChannel::Team,
+            text: "gg".to_owned(),
+        },
+    ];
+
+    for packet in packets {
+        match (&packet, client_packet_round_trip(&packet)) {
+            (
+                ClientPacket::Input { inputs, .. },
+                ClientPacket::Input {
+                    inputs: decoded, ..
+                },
+            ) => {
+                assert_eq!(decoded.len(), inputs.len());
+                assert_eq!(decoded[0].controls, 0x1ff);
+                assert_eq!(decoded[0].current_weapon, 3);
+            }
+            (ClientPacket::LobbyChoice { .. }, ClientPacket::LobbyChoice { team, ready }) => {
+                assert_eq!(team, Team::Blue);
+                assert!(ready);
+            }
+            (original, decoded) => assert_eq!(
+                std::mem::discriminant(original),
+                std::mem::discriminant(&decoded)
+            ),
+        }
+    }
+}
+
+#[test]
+fn server_packets_survive_encoding() {
+    let snapshot = Snapshot {
+        tick: 100,
+        time: 10.0,
+        own_actor: Handle::new(1, 2),
+        own_velocity: Vector3::new(1.0, 0.0, -1.0),
+        own_weapons: vec![(WeaponKind::M4, 30), (WeaponKind::RocketLauncher, 4)],
+        ack_input: 99,
+        actors: vec![
+            ActorState {
+                actor: Handle::new(1, 2),
+                kind: ReplicatedActorKind::Player,
+                name: "Player".to_owned(),
+                team: Team::Red,
+                position: Vector3::new(1.0, 2.0, 3.0),
+                yaw: 0.5,
+                health: 75.0,
+                armor: 10.0,
+            },
+            ActorState {
+                actor: Handle::new(3, 1),
+                kind: ReplicatedActorKind::Bot(BotKind::Maw),
+                name: "Bot".to_owned(),
+                team: Team::Blue,
+                position: Vector3::new(-1.0, 0.0, 5.0),
+                yaw: -0.5,
+                health: 100.0,
+                armor: 0.0,
+            },
+        ],
+        items: vec![ItemState {
+            item: Handle::new(4, 1),
+            active: false,
+        }],
+        scores: vec![ScoreState {
+            name: "Player".to_owned(),
+            kills: 3,
+            deaths: 1,
+        }],
+        team_scores: vec![(Team::Red, 3), (Team::Blue, 0)],
+    };
+    match server_packet_round_trip(&ServerPacket::Snapshot(snapshot)) {
+        ServerPacket::Snapshot(decoded) => {
+            assert_eq!(decoded.tick, 100);
+            assert_eq!(decoded.own_weapons.len(), 2);
+            assert_eq!(
+                decoded.actors[1].kind,
+                ReplicatedActorKind::Bot(BotKind::Maw)
+            );
+            assert_eq!(decoded.team_scores, vec![(Team::Red, 3), (Team::Blue, 0)]);
+        }
+        _ => panic!("snapshot must stay a snapshot"),
+    }
+
+    match server_packet_round_trip(&ServerPacket::Accepted {
+        client: 5,
+        options: test_match_options(),
+    }) {
+        ServerPacket::Accepted { client, options } => {
+            assert_eq!(client, 5);
+            assert_eq!(options.mode(), GameMode::TeamDeathMatch);
+            assert_eq!(*options.map(), MapPath::resolve("dm6"));
+            assert!(options.weapon_rules().weapon_stay);
+        }
+        _ => panic!("accepted packet must stay accepted"),
+    }
+
+    assert!(matches!(
+        server_packet_round_trip(&ServerPacket::Disconnect {
+            reason: "Server is full".to_owned(),
+        }),
+        ServerPacket::Disconnect { reason } if reason == "Server is full"
+    ));
+
+    assert!(matches!(
+        server_packet_round_trip(&ServerPacket::Info {
+            token: 9,
+            info: ServerInfo {
+                name: "Server".to_owned(),
+                map: "DM6".to_owned(),
+                options: test_match_options(),
+                players: 2,
+                in_lobby: true,
+            },
+        }),
+        ServerPacket::Info { token: 9, info } if info.players == 2 && info.in_lobby
+    ));
+
+    assert!(matches!(
+        server_packet_round_trip(&ServerPacket::Lobby {
+            client: 1,
+            lobby: Lobby {
+                server_name: "Server".to_owned(),
+                options: test_match_options(),
+                players: vec![LobbyPlayer {
+                    name: "Player".to_owned(),
+                    team: Team::Red,
+                    ready: false,
+                }],
+            },
+        }),
+        ServerPacket::Lobby { client: 1, lobby } if lobby.players[0].team == Team::Red
+    ));
+}
+
+#[test]
+fn replicated_messages_survive_encoding() {
+    let messages = vec![
+        Message::PlaySound {
+            path: "data/sounds/shot.ogg".into(),
+            position: Vector3::new(1.0, 2.0, 3.0),
+            gain: 0.5,
+            rolloff_factor: 2.0,
+            radius: 3.0,
+        },
+        Message::CreateEffect {
+            kind: EffectKind::Smoke,
+            position: Vector3::new(0.0, 1.0, 0.0),
+        },
+        Message::AddNotification {
+            text: "Bot was killed".to_owned(),
+        },
+        Message::AnnounceItem {
+            kind: ItemKind::RocketLauncher,
+            time: 10.0,
+        },
+        Message::Chat {
+            sender: "Player".to_owned(),
+            team: Team::Blue,
+            channel: ChatChannel::All,
+            text: "hello".to_owned(),
+        },
+        Message::CreateProjectile {
+            kind: ProjectileKind::GuidedRocket,
+            position: Vector3::new(1.0, 1.0, 1.0),
+            direction: Vector3::z(),
+            initial_velocity: Vector3::new(0.0, 0.0, 2.0),
+            owner: Handle::NONE,
+            basis: Matrix3::identity(),
+        },
+        Message::SpawnItem {
+            kind: ItemKind::Medkit,
+            position: Vector3::new(2.0, 0.0, 2.0),
+            adjust_height: true,
+            lifetime: Some(30.0),
+        },
+        Message::SpawnItem {
+            kind: ItemKind::M4Ammo,
+            position: Vector3::new(2.0, 0.0, 2.0),
+            adjust_height: false,
+            lifetime: None,
+        },
+    ];
+
+    for message in messages {
+        assert!(message.is_replicated());
+        match server_packet_round_trip(&ServerPacket::Event(message.clone())) {
+            ServerPacket::Event(decoded) => {
+                assert_eq!(format!("{:?}", decoded), format!("{:?}", message))
+            }
+            _ => panic!("event must stay an event"),
+        }
+    }
+
+    // Messages that are not replicated can not be sent at all.
+    assert!(ServerPacket::Event(Message::SpawnPlayer).encode().is_none());
+}
+
+#[test]
+fn match_options_with_map_outside_of_maps_dir_are_rejected() {
+    for map in &[
+        "data/levels/../../secret.rgs",
+        "/tmp/map.rgs",
+        "data/levels/dm6.ron",
+    ] {
+        let mut options = test_match_options();
+        options.set_map(MapPath((*map).to_owned()));
+        let data = ServerPacket::Accepted { client: 1, options }
+            .encode()
+            .unwrap();
+        assert!(ServerPacket::decode(&data).is_none(), "{} is accepted", map);
+    }
+}
+
+#[test]
+fn sounds_outside_of_sounds_dir_are_rejected() {
+    for path in &[
+        "data/sounds/../../secret.ogg",
+        "/tmp/sound.ogg",
+        "data/music/track.ogg",
+    ] {
+        let data = ServerPacket::Event(Message::PlaySound {
+            path: (*path).into(),
+            position: Vector3::default(),
+            gain: 1.0,
+            rolloff_factor: 1.0,
+            radius: 1.0,
+        })
+        .encode()
+        .unwrap();
+        assert!(
+            ServerPacket::decode(&data).is_none(),
+            "{} is accepted",
+            path
+        );
+    }
+}
+
+#[test]
+fn validator_reports_broken_markers() {
+    let mut scene = Scene::new();
+    let collider = ColliderBuilder::new(BaseBuilder::new())
+        .with_shape(ColliderShape::cuboid(50.0, 0.1, 50.0))
+        .build(&mut scene.graph);
+    RigidBodyBuilder::new(BaseBuilder::new().with_children(&[collider]))
+        .with_body_type(RigidBodyType::Static)
+        .build(&mut scene.graph);
+    for (name, y) in [
+        ("JumpPad1", 1.0),
+        ("Ammo_Ak74", 0.5),
+        ("Medkit1", 5.0),
+        ("SpawnPoint1", -0.45),
+    ] {
+        PivotBuilder::new(
+            BaseBuilder::new().with_name(name).with_local_transform(
+                TransformBuilder::new()
+                    .with_local_position(Vector3::new(0.0, y, 0.0))
+                    .build(),
+            ),
+        )
+        .build(&mut scene.graph);
+    }
+    scene.update(Vector2::new(1.0, 1.0), 0.0);
+
+    let report = validator::validate(&scene);
+    let has = |severity, node: &str, text: &str| {
+        report
+            .issues
+            .iter()
+            .any(|i| i.severity == severity && i.node == node && i.text.contains(text))
+    };
+    assert_eq!(report.entities.len(), 3);
+    assert!(has(Severity::Error, "JumpPad1", "no Begin"));
+    assert!(has(Severity::Error, "JumpPad1", "no End"));
+    assert!(has(Severity::Error, "JumpPad1", "no collider"));
+    assert!(has(Severity::Warning, "Ammo_Ak74", "Ammo_Ak47"));
+    assert!(has(Severity::Warning, "Medkit1", "floats"));
+    assert!(has(Severity::Error, "SpawnPoint1", "inside of geometry"));
+    assert!(has(Severity::Warning, "", "navmesh"));
+    assert!(!has(Severity::Error, "", "spawn points"));
+
+    let report = validator::validate(&Scene::new());
+    assert!(report.entities.is_empty());
+    assert_eq!(report.errors().count(), 2);
+}
+
+#[test]
+fn spawned_actor_is_protected_until_it_shoots() {
+    let tagged = SpawnPoint::new("SpawnPoint_Red_CTF_2", Vector3::default(), 0.0);
+    assert!(tagged.supports(GameMode::CaptureTheFlag, Team::Red));
+    assert!(!tagged.supports(GameMode::CaptureTheFlag, Team::Blue));
+    assert!(!tagged.supports(GameMode::DeathMatch, Team::Red));
+    let plain = SpawnPoint::new("SpawnPoint3", Vector3::default(), 0.0);
+    assert!(plain.supports(GameMode::TeamDeathMatch, Team::Blue));
+    assert_eq!(SpawnPoint::unknown_tags("SpawnPoint_Rde_DM1"), vec!["Rde"]);
+
+    let mut level = TestLevel::new();
+    let enemy = level.add_bot("Enemy", Vector3::new(-40.0, 1.0, -40.0));
+    level.send(Message::SpawnBot {
+        kind: BotKind::Mutant,
+        name: "Spawned".to_owned(),
+        team: Team::None,
+        skill: BotSkill::Average,
+    });
+    level.step(1);
+    let spawned = level.find_actor("Spawned");
+
+    // The farthest spawn point from the enemy is chosen.
+    let position = level
+        .level
+        .actors()
+        .get(spawned)
+        .position(&level.scene.graph);
+    assert!(position.x > 0.0 && position.z > 0.0);
+
+    level.send(Message::DamageActor {
+        actor: spawned,
+        who: enemy,
+        amount: 10.0,
+    });
+    level.step(1);
+    assert_eq!(level.level.actors().get(spawned).armor, 100.0);
+
+    // Shooting ends protection, the weapon must be raised before it can shoot.
+    level.step_secs(1.0);
+    let actor = level.level.actors().get(spawned);
+    let weapon = actor.weapons()[actor.current_weapon as usize];
+    level.send(Message::ShootWeapon {
+        weapon,
+        initial_velocity: Vector3::default(),
+        direction: None,
+        mode: FireMode::Primary,
+    });
+    level.step(1);
+    assert!(!level.level.actors().get(spawned).is_spawn_protected());
+
+    // Protection also ends after a while.
+    level.send(Message::SpawnBot {
+        kind: BotKind::Mutant,
+        name: "Late".to_owned(),
+        team: Team::None,
+        skill: BotSkill::Average,
+    });
+    level.step(1);
+    let late = level.find_actor("Late");
+    assert!(level.level.actors().get(late).is_spawn_protected());
+    level.step_secs(SPAWN_PROTECTION_TIME);
+    assert!(!level.level.actors().get(late).is_spawn_protected());
+}
+
+#[test]
+fn every_item_kind_has_a_marker_with_overrides() {
+    for kind in [
+        ItemKind::Medkit,
+        ItemKind::Plasma,
+        ItemKind::Ak47Ammo,
+        ItemKind::M4Ammo,
+        ItemKind::RocketAmmo,
+        ItemKind::PlasmaGun,
+        ItemKind::Ak47,
+        ItemKind::M4,
+        ItemKind::RocketLauncher,
+    ] {
+        assert!(
+            level::MARKERS.iter().any(|&(_, m)| m == Marker::Item(kind)),
+            "{:?} has no marker",
+            kind
+        );
+    }
+
+    let name = "Ammo_Rocket_respawn=45_delay=10.5_amount=5";
+    assert_eq!(
+        Marker::from_name(name),
+        Some(Marker::Item(ItemKind::RocketAmmo))
+    );
+    assert_eq!(
+        ItemOverrides::parse(name),
+        Ok(ItemOverrides {
+            respawn_time: Some(45.0),
+            initial_delay: Some(10.5),
+            amount: Some(5),
+        })
+    );
+    assert_eq!(
+        ItemOverrides::parse("Medkit_2"),
+        Ok(ItemOverrides::default())
+    );
+    assert!(ItemOverrides::parse("Medkit_amount=lots").is_err());
+    assert!(ItemOverrides::parse("Medkit_size=2").is_err());
+
+    let mut level = TestLevel::new();
+    let bot = level.add_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+    level.send(Message::GiveItem {
+        actor: bot,
+        kind: ItemKind::RocketLauncher,
+    });
+    level.step(1);
+    let rockets = level.ammo(bot, WeaponKind::RocketLauncher).unwrap();
+    level.send(Message::GiveItem {
+        actor: bot,
+        kind: ItemKind::RocketAmmo,
+    });
+    level.step(1);
+    assert_eq!(
+        level.ammo(bot, WeaponKind::RocketLauncher),
+        Some(rockets + 10)
+    );
+}
+
+#[test]
+fn picked_up_item_is_announced_and_appears_again() {
+    let mut level = TestLevel::new();
+    let bot = level.add_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+    level.send(Message::SpawnItem {
+        kind: ItemKind::RocketLauncher,
+        position: Vector3::new(40.0, 1.0, 40.0),
+        adjust_height: false,
+        lifetime: None,
+    });
+    level.step(1);
+
+    let (launcher, _) = level
+        .level
+        .items()
+        .pair_iter()
+        .find(|(_, item)| item.get_kind() == ItemKind::RocketLauncher)
+        .unwrap();
+    level.send(Message::PickUpItem {
+        actor: bot,
+        item: launcher,
+    });
+    level.step(1);
+    // Bot would pick up the launcher again when it appears.
+    level.send(Message::RemoveActor { actor: bot });
+    level.step(1);
+
+    let respawn_time = Item::get_definition(ItemKind::RocketLauncher).reactivation_interval();
+    let announced = |level: &TestLevel| {
+        level.handled.iter().any(|message| {
+            matches!(
+                message,
+                Message::AnnounceItem {
+                    kind: ItemKind::RocketLauncher,
+                    ..
+                }
+            )
+        })
+    };
+
+    level.step_secs(respawn_time - ANNOUNCEMENT_TIME - 1.0);
+    assert!(!announced(&level));
+    assert!(level
+        .level
+        .items()
+        .respawn_report(&level.scene.graph)
+        .starts_with("Rocket Launcher"));
+
+    level.step_secs(2.0);
+    assert!(announced(&level));
+
+    level.step_secs(ANNOUNCEMENT_TIME);
+    assert!(!level.level.items().iter().any(|item| item.is_picked_up()));
+    assert!(level
+        .level
+        .items()
+        .respawn_report(&level.scene.graph)
+        .is_empty());
+}
+
+#[test]
+fn staying_weapons_are_taken_once_and_only_current_weapon_is_dropped() {
+    let mut level = TestLevel::new();
+    level.level.options.set_weapon_rules(WeaponRules {
+        weapon_stay: true,
+        drop_current_only: true,
+    });
+    let bot = level.add_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+    level.send(Message::SpawnItem {
+        kind: ItemKind::M4,
+        position: Vector3::new(40.0, 1.0, 40.0),
+        adjust_height: false,
+        lifetime: None,
+    });
+    level.step(1);
+
+    let (m4, _) = level
+        .level
+        .items()
+        .pair_iter()
+        .find(|(_, item)| item.get_kind() == ItemKind::M4)
+        .unwrap();
+    for _ in 0..2 {
+        level.send(Message::PickUpItem {
+            actor: bot,
+            item: m4,
+        });
+        level.step(1);
+    }
+
+    // Ammo comes from the weapon definition and the second pickup is ignored.
+    assert_eq!(
+        level.ammo(bot, WeaponKind::M4),
+        Some(Weapon::get_definition(WeaponKind::M4).ammo)
+    );
+    assert!(!level.level.items().get(m4).is_picked_up());
+
+    level.send(Message::RemoveActor { actor: bot });
+    level.step(1);
+    assert_eq!(
+        level
+            .level
+            .items()
+            .iter()
+            .filter(|item| item.is_temporary())
+            .count(),
+        1
+    );
+
+    assert!(AutoSwitch::IfBetter.should_switch(Some(WeaponKind::M4), WeaponKind::RocketLauncher));
+    assert!(!AutoSwitch::IfBetter.should_switch(Some(WeaponKind::M4), WeaponKind::Ak47));
+    assert!(!AutoSwitch::Never.should_switch(Some(WeaponKind::M4), WeaponKind::RocketLauncher));
+    assert!(AutoSwitch::Never.should_switch(None, WeaponKind::Ak47));
+}
+
+#[test]
+fn weapon_slots_skip_empty_weapons_and_last_weapon_toggles() {
+    let mut level = TestLevel::new();
+    let bot = level.add_armed_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+    let weapon = |level: &TestLevel, kind| {
+        level
+            .level
+            .actors()
+            .get(bot)
+            .find_weapon(kind, level.level.weapons())
+            .unwrap()
+    };
+    let ak47 = weapon(&level, WeaponKind::Ak47);
+    level.level.weapons_mut()[ak47].set_ammo(0);
+
+    // Character is taken out of the actor to borrow weapons of the level at the same time.
+    let mut character = std::mem::take(&mut **level.level.actors_mut().get_mut(bot));
+    let weapons = level.level.weapons();
+
+    character.select_slot(WeaponKind::PlasmaRifle.slot(), weapons);
+    let plasma_rifle = character.current_weapon();
+    assert_eq!(weapons[plasma_rifle].get_kind(), WeaponKind::PlasmaRifle);
+
+    // Empty weapon can not be selected and is skipped when cycling.
+    character.select_slot(WeaponKind::Ak47.slot(), weapons);
+    assert_eq!(character.current_weapon(), plasma_rifle);
+    character.cycle_weapon(false, weapons);
+    assert_eq!(
+        weapons[character.current_weapon()].get_kind(),
+        WeaponKind::M4
+    );
+
+    character.select_last_weapon();
+    assert_eq!(character.current_weapon(), plasma_rifle);
+    character.select_last_weapon();
+    assert_eq!(
+        weapons[character.current_weapon()].get_kind(),
+        WeaponKind::M4
+    );
+
+    **level.level.actors_mut().get_mut(bot) = character;
+}
+
+#[test]
+fn switched_weapon_can_not_shoot_until_raised() {
+    let mut level = TestLevel::new();
+    let bot = level.add_armed_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+    let rocket_launcher = level.select_weapon(bot, WeaponKind::RocketLauncher);
+    level.step_secs(1.5);
+    assert!(level.level.weapons()[rocket_launcher].is_ready());
+    let m4 = level
+        .level
+        .actors()
+        .get(bot)
+        .find_weapon(WeaponKind::M4, level.level.weapons())
+        .unwrap();
+    assert_eq!(level.level.weapons()[m4].state(), WeaponState::Holstered);
+
+    assert_eq!(level.select_weapon(bot, WeaponKind::M4), m4);
+    level.step(1);
+    assert_eq!(
+        level.level.weapons()[rocket_launcher].state(),
+        WeaponState::Lowering
+    );
+    assert_eq!(level.level.weapons()[m4].state(), WeaponState::Raising);
+
+    let ammo = level.ammo(bot, WeaponKind::M4).unwrap();
+    let shoot = |level: &mut TestLevel| {
+        level.send(Message::ShootWeapon {
+            weapon: m4,
+            initial_velocity: Vector3::default(),
+            direction: None,
+            mode: FireMode::Primary,
+        });
+        level.step(1);
+    };
+    shoot(&mut level);
+    assert_eq!(level.ammo(bot, WeaponKind::M4), Some(ammo));
+
+    let definition = Weapon::get_definition(WeaponKind::M4);
+    level.step_secs(
+        Weapon::get_definition(WeaponKind::RocketLauncher).lower_time + definition.raise_time,
+    );
+    assert_eq!(
+        level.level.weapons()[rocket_launcher].state(),
+        WeaponState::Holstered
+    );
+    assert!(level.level.weapons()[m4].is_ready());
+    shoot(&mut level);
+    assert_eq!(level.ammo(bot, WeaponKind::M4), Some(ammo - 1));
+    assert_eq!(level.level.weapons()[m4].state(), WeaponState::Firing);
+}
+
+#[test]
+fn secondary_fire_bursts_and_charges() {
+    let mut level = TestLevel::new();
+    let bot = level.add_armed_bot("Bot", Vector3::new(-40.0, 1.0, -40.0));
+    let select = |level: &mut TestLevel, kind| {
+        let weapon = level.select_weapon(bot, kind);
+        level.step_secs(1.5);
+        weapon
+    };
+    let shoot = |level: &mut TestLevel, weapon, ticks| {
+        for _ in 0..ticks {
+            level.send(Message::ShootWeapon {
+                weapon,
+                initial_velocity: Vector3::default(),
+                direction: None,
+                mode: FireMode::Secondary,
+            });
+            level.step(1);
+        }
+    };
+    let projectiles = |level: &TestLevel, kind| {
+        level
+            .handled
+            .iter()
+            .filter(|m| matches!(m, Message::CreateProjectile { kind: k, .. } if *k == kind))
+            .count()
+    };
+
+    // One pull of the trigger fires the whole burst.
+    let m4 = select(&mut level, WeaponKind::M4);
+    let ammo = level.ammo(bot, WeaponKind::M4).unwrap();
+    level.handled.clear();
+    shoot(&mut level, m4, 1);
+    level.step_secs(0.5);
+    let burst = &Weapon::get_definition(WeaponKind::M4).secondary;
+    assert_eq!(
+        projectiles(&level, ProjectileKind::Bullet),
+        burst.burst as usize
+    );
+    assert_eq!(
+        level.ammo(bot, WeaponKind::M4),
+        Some(ammo - burst.ammo_cost)
+    );
+
+    // Charged shot is lost if the trigger is released too early.
+    let plasma_rifle = select(&mut level, WeaponKind::PlasmaRifle);
+    let ammo = level.ammo(bot, WeaponKind::PlasmaRifle).unwrap();
+    let charge = &Weapon::get_definition(WeaponKind::PlasmaRifle).secondary;
+    level.handled.clear();
+    shoot(&mut level, plasma_rifle, 5);
+    level.step(2);
+    assert_eq!(projectiles(&level, ProjectileKind::ChargedPlasma), 0);
+
+    let ticks = (charge.charge_time * FIXED_FPS).ceil() as usize + 2;
+    shoot(&mut level, plasma_rifle, ticks);
+    assert_eq!(projectiles(&level, ProjectileKind::ChargedPlasma), 1);
+    assert_eq!(
+        level.ammo(bot, WeaponKind::PlasmaRifle),
+        Some(ammo - charge.ammo_cost)
+    );
+}
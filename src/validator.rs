@@ -0,0 +1,340 @@
+//! Validation of maps. Markers of a map (spawn points, items, jump pads and death zones) are
+//! found by names of scene nodes, mistakes in them are easy to make and hard to notice, so every
+//! map is validated when a level is loaded and the report is written to the log. Map makers can
+//! also validate a map without starting the game:
+//!
+//! `rusty-shooter --validate dm6`
+//!
+//! Errors make the map unplayable, warnings point to things that are most likely mistakes.
+
+use crate::{
+    item::ItemOverrides,
+    level::{Marker, SpawnPoint, MARKERS},
+    map::MapPath,
+};
+use fyrox::{
+    core::{
+        algebra::{Point3, Vector2, Vector3},
+        futures::executor::block_on,
+    },
+    engine::{resource_manager::ResourceManager, SerializationContext},
+    scene::{
+        collider::InteractionGroups,
+        graph::{physics::RayCastOptions, Graph},
+        Scene,
+    },
+    utils::log::{Log, MessageKind},
+};
+use std::{fmt, sync::Arc};
+
+/// Items that hang higher above the ground can not be picked up.
+const MAX_ITEM_HEIGHT: f32 = 2.0;
+
+/// Spawn points are checked at the height of the body of an actor.
+const SPAWN_POINT_CLEARANCE: f32 = 0.5;
+
+/// Ground is searched within this distance below markers.
+const MAX_GROUND_DISTANCE: f32 = 100.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Name of the scene node, empty for issues of the whole map.
+    pub node: String,
+    pub text: String,
+}
+
+/// Recognized marker of a map.
+#[derive(Clone, Debug)]
+pub struct Entity {
+    pub marker: Marker,
+    pub node: String,
+    pub position: Vector3<f32>,
+}
+
+#[derive(Default, Debug)]
+pub struct ValidationReport {
+    pub entities: Vec<Entity>,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    fn add(&mut self, severity: Severity, node: &str, text: String) {
+        self.issues.push(Issue {
+            severity,
+            node: node.to_owned(),
+            text,
+        });
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Writes issues of a map to the log, a valid map leaves no trace.
+    pub fn log(&self, map: &str) {
+        for issue in self.issues.iter() {
+            let kind = match issue.severity {
+                Severity::Error => MessageKind::Error,
+                Severity::Warning => MessageKind::Warning,
+            };
+            Log::writeln(kind, format!("Map {}: {}", map, issue));
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.node.is_empty() {
+            write!(f, "{}: {}", severity, self.text)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.node, self.text)
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Entities:")?;
+        for entity in self.entities.iter() {
+            writeln!(
+                f,
+                "  {:<16} {:<24} ({:.2}, {:.2}, {:.2})",
+                entity.marker.description(),
+                entity.node,
+                entity.position.x,
+                entity.position.y,
+                entity.position.z
+            )?;
+        }
+        for issue in self.issues.iter() {
+            writeln!(f, "{}", issue)?;
+        }
+        write!(
+            f,
+            "{} error(s), {} warning(s)",
+            self.errors().count(),
+            self.warnings().count()
+        )
+    }
+}
+
+/// Returns distance from a point to the closest geometry below it. Zero distance means that the
+/// point is inside of geometry.
+fn ground_distance(graph: &Graph, position: Vector3<f32>) -> Option<f32> {
+    let mut query_buffer = Vec::default();
+    graph.physics.cast_ray(
+        RayCastOptions {
+            ray_origin: Point3::from(position),
+            ray_direction: -Vector3::y(),
+            max_len: MAX_GROUND_DISTANCE,
+            groups: InteractionGroups::default(),
+            sort_results: true,
+        },
+        &mut query_buffer,
+    );
+    query_buffer.first().map(|hit| hit.toi)
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and transpositions
+/// of adjacent characters.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Returns prefix of a marker that an unrecognized name most likely misspells, like
+/// `Ammo_Ak74` or `spawnpoint1`.
+fn misspelled_marker(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase().chars().collect::<Vec<_>>();
+    MARKERS.iter().map(|&(prefix, _)| prefix).find(|prefix| {
+        let prefix = prefix.to_lowercase().chars().collect::<Vec<_>>();
+        let head = &name[..name.len().min(prefix.len())];
+        edit_distance(head, &prefix) <= 1
+    })
+}
+
+/// Lists markers of a map and checks them. Global transforms and physics of the scene must be
+/// up to date.
+pub fn validate(scene: &Scene) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let graph = &scene.graph;
+
+    let mut spawn_point_count = 0;
+    for (handle, node) in graph.pair_iter() {
+        let name = node.name();
+        let position = node.global_position();
+        let marker = match Marker::from_name(name) {
+            Some(marker) => marker,
+            None => {
+                if let Some(prefix) = misspelled_marker(name) {
+                    report.add(
+                        Severity::Warning,
+                        name,
+                        format!("unknown marker, did you mean {}?", prefix),
+                    );
+                }
+                continue;
+            }
+        };
+
+        match marker {
+            Marker::JumpPad => {
+                for child in ["Begin", "End"] {
+                    if graph.find_by_name(handle, child).is_none() {
+                        report.add(
+                            Severity::Error,
+                            name,
+                            format!("jump pad has no {} child", child),
+                        );
+                    }
+                }
+                if graph.find(handle, &mut |n| n.is_collider()).is_none() {
+                    report.add(
+                        Severity::Error,
+                        name,
+                        "jump pad has no collider, it can not be touched".to_owned(),
+                    );
+                }
+            }
+            Marker::Item(_) => {
+                if let Err(e) = ItemOverrides::parse(name) {
+                    report.add(
+                        Severity::Warning,
+                        name,
+                        format!("{}, item settings are ignored", e),
+                    );
+                }
+                match ground_distance(graph, position) {
+                    Some(distance) if distance > MAX_ITEM_HEIGHT => report.add(
+                        Severity::Warning,
+                        name,
+                        format!("item floats {:.2} m above the ground", distance),
+                    ),
+                    Some(_) => (),
+                    None => report.add(
+                        Severity::Warning,
+                        name,
+                        "there is no ground below the item".to_owned(),
+                    ),
+                }
+            }
+            Marker::SpawnPoint => {
+                spawn_point_count += 1;
+                for tag in SpawnPoint::unknown_tags(name) {
+                    report.add(
+                        Severity::Warning,
+                        name,
+                        format!("unknown tag {}, expected Red, Blue, DM, TDM or CTF", tag),
+                    );
+                }
+                let body = position + Vector3::new(0.0, SPAWN_POINT_CLEARANCE, 0.0);
+                match ground_distance(graph, body) {
+                    Some(distance) if distance <= std::f32::EPSILON => report.add(
+                        Severity::Error,
+                        name,
+                        "spawn point is inside of geometry".to_owned(),
+                    ),
+                    Some(_) => (),
+                    None => report.add(
+                        Severity::Warning,
+                        name,
+                        "there is no ground below the spawn point".to_owned(),
+                    ),
+                }
+            }
+            Marker::DeathZone => (),
+        }
+
+        report.entities.push(Entity {
+            marker,
+            node: name.to_owned(),
+            position,
+        });
+    }
+
+    if spawn_point_count == 0 {
+        report.add(
+            Severity::Error,
+            "",
+            "map has no spawn points, add nodes named SpawnPoint".to_owned(),
+        );
+    }
+    if !graph.linear_iter().any(|node| node.is_collider()) {
+        report.add(
+            Severity::Error,
+            "",
+            "map has no colliders, actors will fall through it".to_owned(),
+        );
+    }
+    if scene.navmeshes.iter().next().is_none() {
+        report.add(
+            Severity::Warning,
+            "",
+            "map has no navmesh, bots will not be able to move".to_owned(),
+        );
+    }
+
+    report
+}
+
+/// Loads a map, prints its report and returns `false` if the map has errors.
+pub fn run(map: &MapPath) -> bool {
+    let resource_manager = ResourceManager::new(Arc::new(SerializationContext::new()));
+    let mut scene = Scene::new();
+    match block_on(resource_manager.request_model(map.path())) {
+        Ok(model) => {
+            model.instantiate_geometry(&mut scene);
+        }
+        Err(e) => {
+            eprintln!("Unable to load map {}: {:?}", map.0, e);
+            return false;
+        }
+    }
+    // Global transforms and physics are updated by the first update of the scene.
+    scene.update(Vector2::new(1.0, 1.0), 0.0);
+
+    let report = validate(&scene);
+    println!("Map {}", map.0);
+    println!("{}", report);
+    !report.has_errors()
+}
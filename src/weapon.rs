@@ -1,5 +1,6 @@
 use crate::{
-    actor::Actor, actor::ActorContainer, message::Message, projectile::ProjectileKind, GameTime,
+    actor::Actor, actor::ActorContainer, character::Character, item::Item, message::Message,
+    projectile::ProjectileKind, save, GameTime,
 };
 use fyrox::{
     core::{
@@ -20,6 +21,7 @@ use fyrox::{
     },
     utils::log::{Log, MessageKind},
 };
+use serde::{Deserialize, Serialize};
 use std::{
     ops::{Index, IndexMut},
     path::{Path, PathBuf},
@@ -34,6 +36,140 @@ pub enum WeaponKind {
     RocketLauncher,
 }
 
+/// Weapons the player spawns with. Bots get the same set to play by the same rules.
+pub const PLAYER_LOADOUT: [WeaponKind; 4] = [
+    WeaponKind::M4,
+    WeaponKind::Ak47,
+    WeaponKind::PlasmaRifle,
+    WeaponKind::RocketLauncher,
+];
+
+/// Weapons in order of their slots, the first weapon is in slot 1. The player spawns with every
+/// weapon, so slots follow the loadout.
+pub const WEAPON_SLOTS: [WeaponKind; 4] = PLAYER_LOADOUT;
+
+impl WeaponKind {
+    /// Number of the slot of the weapon, slots are numbered from 1 like the keys selecting them.
+    pub fn slot(self) -> usize {
+        WEAPON_SLOTS.iter().position(|&kind| kind == self).unwrap() + 1
+    }
+
+    /// Rank of the weapon for [`AutoSwitch::IfBetter`], better weapons have higher rank.
+    pub fn rank(self) -> u32 {
+        match self {
+            WeaponKind::Ak47 => 0,
+            WeaponKind::M4 => 1,
+            WeaponKind::PlasmaRifle => 2,
+            WeaponKind::RocketLauncher => 3,
+        }
+    }
+}
+
+/// Preference of a player: whether to switch to a weapon that was picked up for the first
+/// time.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Visit, Serialize, Deserialize)]
+pub enum AutoSwitch {
+    Always,
+    Never,
+    /// Switch only if the new weapon has higher [rank](WeaponKind::rank) than the current one.
+    IfBetter,
+}
+
+impl Default for AutoSwitch {
+    fn default() -> Self {
+        AutoSwitch::Always
+    }
+}
+
+impl AutoSwitch {
+    pub const ALL: [AutoSwitch; 3] = [AutoSwitch::Always, AutoSwitch::Never, AutoSwitch::IfBetter];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AutoSwitch::Always => "Always",
+            AutoSwitch::Never => "Never",
+            AutoSwitch::IfBetter => "If Better",
+        }
+    }
+
+    /// `current` is the kind of the current weapon, `None` if there is no weapon.
+    pub fn should_switch(self, current: Option<WeaponKind>, new: WeaponKind) -> bool {
+        match (self, current) {
+            (_, None) | (AutoSwitch::Always, _) => true,
+            (AutoSwitch::Never, _) => false,
+            (AutoSwitch::IfBetter, Some(current)) => new.rank() > current.rank(),
+        }
+    }
+}
+
+/// Rules of weapon pickups and drops, they are a part of options of a match.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct WeaponRules {
+    /// Weapons placed on a map are not consumed when picked up, every actor can take each
+    /// kind of weapon once per life. Dropped weapons are always consumed.
+    pub weapon_stay: bool,
+    /// Dead actors drop only their current weapon instead of every weapon.
+    pub drop_current_only: bool,
+}
+
+impl Visit for WeaponRules {
+    fn visit(&mut self, name: &str, visitor: &mut Visitor) -> VisitResult {
+        // Saves made before weapon rules get default rules by migration.
+        if !save::has_state_of_version(4) {
+            return Ok(());
+        }
+        let mut region = visitor.enter_region(name)?;
+
+        self.weapon_stay.visit("weapon_stay", &mut region)?;
+        self.drop_current_only
+            .visit("drop_current_only", &mut region)?;
+
+        Ok(())
+    }
+}
+
+impl WeaponRules {
+    /// Returns `true` if the item stays active when it is picked up.
+    pub fn item_stays(&self, item: &Item) -> bool {
+        self.weapon_stay && item.get_kind().weapon().is_some() && !item.is_temporary()
+    }
+
+    /// Returns `false` if the item is inactive or it is a staying weapon that the character
+    /// already has.
+    pub fn can_pick_up(
+        &self,
+        item: &Item,
+        character: &Character,
+        weapons: &WeaponContainer,
+    ) -> bool {
+        if item.is_picked_up() {
+            return false;
+        }
+        match item.get_kind().weapon() {
+            Some(kind) if self.item_stays(item) => character.find_weapon(kind, weapons).is_none(),
+            _ => true,
+        }
+    }
+}
+
+/// State of a weapon in hands of its owner. Weapon goes through lowering when owner switches
+/// to another weapon and then the other weapon is raised, only ready weapon can shoot.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WeaponState {
+    /// Weapon is not in hands, it is hidden.
+    Holstered,
+    Lowering,
+    Raising,
+    Ready,
+    /// Short moment right after a shot.
+    Firing,
+    /// Weapon is waiting for the end of its shoot interval.
+    Reloading,
+}
+
+/// How far a lowered weapon moves down from its place in hands.
+const LOWERED_OFFSET: f32 = 0.3;
+
 #[derive(Visit)]
 pub struct Weapon {
     kind: WeaponKind,
@@ -48,16 +184,81 @@ pub struct Weapon {
     ammo: u32,
     #[visit(skip)]
     pub sender: Option<Sender<Message>>,
+    /// State is not saved, it is restored by [`WeaponContainer::resolve`].
+    #[visit(skip)]
+    state: WeaponState,
+    /// Time left in current state, only lowering and raising have limited time.
+    #[visit(skip)]
+    state_time: f32,
+    /// Mode of the last shot, its shoot interval is used after the shot.
+    #[visit(skip)]
+    fire_mode: FireMode,
+    /// Shots left in current burst, they are fired one after another.
+    #[visit(skip)]
+    burst_left: u32,
+    #[visit(skip)]
+    trigger_direction: Option<Vector3<f32>>,
+    /// Time of charging of a charged shot, charging is lost if it is not continued.
+    #[visit(skip)]
+    charge: f32,
+    #[visit(skip)]
+    charge_held: bool,
+    /// Point where the laser sight hits something, guided projectiles fly to it.
+    #[visit(skip)]
+    aim_point: Option<Vector3<f32>>,
 }
 
 pub struct WeaponDefinition {
     pub model: &'static str,
     pub shot_sound: &'static str,
     pub ammo: u32,
+    pub primary: FireModeDefinition,
+    pub secondary: FireModeDefinition,
+    pub lower_time: f32,
+    pub raise_time: f32,
+}
+
+impl WeaponDefinition {
+    pub fn fire_mode(&self, mode: FireMode) -> &FireModeDefinition {
+        match mode {
+            FireMode::Primary => &self.primary,
+            FireMode::Secondary => &self.secondary,
+        }
+    }
+}
+
+/// Every weapon can shoot in two ways, each with its own projectile, ammo cost and cooldown.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FireMode {
+    Primary,
+    Secondary,
+}
+
+pub struct FireModeDefinition {
     pub projectile: ProjectileKind,
+    /// Ammo spent by one shot.
+    pub ammo_cost: u32,
     pub shoot_interval: f64,
+    /// Duration of the firing state, the rest of the shoot interval is reloading.
+    pub fire_time: f64,
+    /// Amount of projectiles fired one after another by one shot.
+    pub burst: u32,
+    pub burst_interval: f64,
+    /// Time the trigger must be held before the shot, zero for instant shots.
+    pub charge_time: f32,
 }
 
+/// Base of fire modes which fire one projectile as soon as the trigger is pulled.
+const SINGLE_SHOT: FireModeDefinition = FireModeDefinition {
+    projectile: ProjectileKind::Bullet,
+    ammo_cost: 1,
+    shoot_interval: 0.15,
+    fire_time: 0.05,
+    burst: 1,
+    burst_interval: 0.0,
+    charge_time: 0.0,
+};
+
 impl Default for Weapon {
     fn default() -> Self {
         Self {
@@ -72,6 +273,14 @@ impl Default for Weapon {
             owner: Handle::NONE,
             ammo: 250,
             sender: None,
+            state: WeaponState::Holstered,
+            state_time: 0.0,
+            fire_mode: FireMode::Primary,
+            burst_left: 0,
+            trigger_direction: None,
+            charge: 0.0,
+            charge_held: false,
+            aim_point: None,
         }
     }
 }
@@ -84,8 +293,18 @@ impl Weapon {
                     model: "data/models/m4.FBX",
                     shot_sound: "data/sounds/m4_shot.ogg",
                     ammo: 200,
-                    projectile: ProjectileKind::Bullet,
-                    shoot_interval: 0.15,
+                    primary: SINGLE_SHOT,
+                    // Burst fire.
+                    secondary: FireModeDefinition {
+                        ammo_cost: 3,
+                        shoot_interval: 0.6,
+                        fire_time: 0.2,
+                        burst: 3,
+                        burst_interval: 0.07,
+                        ..SINGLE_SHOT
+                    },
+                    lower_time: 0.3,
+                    raise_time: 0.4,
                 };
                 &DEFINITION
             }
@@ -94,8 +313,17 @@ impl Weapon {
                     model: "data/models/ak47.FBX",
                     shot_sound: "data/sounds/ak47.ogg",
                     ammo: 200,
-                    projectile: ProjectileKind::Bullet,
-                    shoot_interval: 0.15,
+                    primary: SINGLE_SHOT,
+                    // Grenade launcher.
+                    secondary: FireModeDefinition {
+                        projectile: ProjectileKind::Grenade,
+                        ammo_cost: 10,
+                        shoot_interval: 1.0,
+                        fire_time: 0.2,
+                        ..SINGLE_SHOT
+                    },
+                    lower_time: 0.3,
+                    raise_time: 0.4,
                 };
                 &DEFINITION
             }
@@ -104,8 +332,23 @@ impl Weapon {
                     model: "data/models/plasma_rifle.FBX",
                     shot_sound: "data/sounds/plasma_shot.ogg",
                     ammo: 100,
-                    projectile: ProjectileKind::Plasma,
-                    shoot_interval: 0.25,
+                    primary: FireModeDefinition {
+                        projectile: ProjectileKind::Plasma,
+                        shoot_interval: 0.25,
+                        fire_time: 0.1,
+                        ..SINGLE_SHOT
+                    },
+                    // Charged shot.
+                    secondary: FireModeDefinition {
+                        projectile: ProjectileKind::ChargedPlasma,
+                        ammo_cost: 5,
+                        shoot_interval: 1.0,
+                        fire_time: 0.2,
+                        charge_time: 0.8,
+                        ..SINGLE_SHOT
+                    },
+                    lower_time: 0.35,
+                    raise_time: 0.5,
                 };
                 &DEFINITION
             }
@@ -114,8 +357,22 @@ impl Weapon {
                     model: "data/models/Rpg7.FBX",
                     shot_sound: "data/sounds/grenade_launcher_fire.ogg",
                     ammo: 100,
-                    projectile: ProjectileKind::Rocket,
-                    shoot_interval: 1.5,
+                    primary: FireModeDefinition {
+                        projectile: ProjectileKind::Rocket,
+                        shoot_interval: 1.5,
+                        fire_time: 0.3,
+                        ..SINGLE_SHOT
+                    },
+                    // Guided rocket, it flies to the point of the laser sight.
+                    secondary: FireModeDefinition {
+                        projectile: ProjectileKind::GuidedRocket,
+                        ammo_cost: 2,
+                        shoot_interval: 2.0,
+                        fire_time: 0.3,
+                        ..SINGLE_SHOT
+                    },
+                    lower_time: 0.5,
+                    raise_time: 0.7,
                 };
                 &DEFINITION
             }
@@ -171,14 +428,144 @@ impl Weapon {
         self.model
     }
 
-    pub fn update(&mut self, scene: &mut Scene, actors: &ActorContainer) {
-        self.offset.follow(&self.dest_offset, 0.2);
+    /// Returns kind of projectile if the weapon fires by itself, this happens during a burst
+    /// or when a charged shot is released.
+    pub fn update(
+        &mut self,
+        scene: &mut Scene,
+        actors: &ActorContainer,
+        time: GameTime,
+    ) -> Option<ProjectileKind> {
+        self.offset.follow(&self.dest_offset, time.smoothing(0.2));
+
+        self.update_state(&mut scene.graph, time);
+        let shot = self.update_pending_shot(scene, time);
 
         self.update_laser_sight(&mut scene.graph, actors);
 
         let node = &mut scene.graph[self.model];
         node.local_transform_mut().set_position(self.offset);
         self.shot_position = node.global_position();
+
+        shot
+    }
+
+    fn update_pending_shot(&mut self, scene: &mut Scene, time: GameTime) -> Option<ProjectileKind> {
+        let definition = self.definition();
+        if self.burst_left > 0 {
+            let fire_mode = definition.fire_mode(self.fire_mode);
+            if time.elapsed - self.last_shot_time >= fire_mode.burst_interval {
+                self.burst_left -= 1;
+                self.fire(scene, time, self.fire_mode);
+                return Some(fire_mode.projectile);
+            }
+        } else if self.charge > 0.0 {
+            let fire_mode = &definition.secondary;
+            if !self.charge_held || self.ammo < fire_mode.ammo_cost {
+                // Trigger was released too early or the ammo is gone.
+                self.charge = 0.0;
+            } else if self.charge >= fire_mode.charge_time {
+                self.charge = 0.0;
+                self.ammo -= fire_mode.ammo_cost;
+                self.fire(scene, time, FireMode::Secondary);
+                return Some(fire_mode.projectile);
+            }
+            self.charge_held = false;
+        }
+        None
+    }
+
+    fn update_state(&mut self, graph: &mut Graph, time: GameTime) {
+        let definition = self.definition();
+        let fire_mode = definition.fire_mode(self.fire_mode);
+        let since_shot = time.elapsed - self.last_shot_time;
+        self.state_time = (self.state_time - time.delta).max(0.0);
+        match self.state {
+            WeaponState::Lowering if self.state_time <= 0.0 => {
+                self.state = WeaponState::Holstered;
+                self.set_visibility(false, graph);
+            }
+            WeaponState::Raising => {
+                // Weapon appears only when the previous one is lowered.
+                self.set_visibility(self.state_time <= definition.raise_time, graph);
+                if self.state_time <= 0.0 {
+                    self.state = WeaponState::Ready;
+                }
+            }
+            WeaponState::Firing if self.burst_left == 0 && since_shot >= fire_mode.fire_time => {
+                self.state = WeaponState::Reloading;
+            }
+            WeaponState::Reloading if since_shot >= fire_mode.shoot_interval => {
+                self.state = WeaponState::Ready;
+            }
+            _ => (),
+        }
+    }
+
+    pub fn state(&self) -> WeaponState {
+        self.state
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state == WeaponState::Ready
+    }
+
+    /// Starts lowering of the weapon, it is hidden when lowered.
+    pub fn lower(&mut self) {
+        if self.state != WeaponState::Holstered && self.state != WeaponState::Lowering {
+            let lower_time = self.definition().lower_time;
+            self.state_time = lower_time * (1.0 - self.lowered_fraction());
+            self.state = WeaponState::Lowering;
+            self.burst_left = 0;
+            self.charge = 0.0;
+        }
+    }
+
+    /// Starts raising of the weapon after `delay`, which is time left to lower the previous
+    /// weapon.
+    pub fn raise(&mut self, delay: f32) {
+        let raise_time = self.definition().raise_time;
+        self.state_time = raise_time * (1.0 - self.lowered_fraction()) + delay;
+        self.state = WeaponState::Raising;
+    }
+
+    /// Time left until lowering is finished.
+    pub fn time_to_lower(&self) -> f32 {
+        if self.state == WeaponState::Lowering {
+            self.state_time
+        } else {
+            0.0
+        }
+    }
+
+    /// How much the weapon is lowered, `0.0` when it is in hands and `1.0` when it is holstered.
+    pub fn lowered_fraction(&self) -> f32 {
+        let definition = self.definition();
+        match self.state {
+            WeaponState::Holstered => 1.0,
+            WeaponState::Lowering => 1.0 - self.state_time / definition.lower_time,
+            WeaponState::Raising => (self.state_time / definition.raise_time).min(1.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Procedural motion of switching, it is applied to the weapon pivot of the owner.
+    pub fn switch_offset(&self) -> Vector3<f32> {
+        // Smooth step, so the weapon accelerates and slows down near the ends.
+        let t = self.lowered_fraction();
+        Vector3::new(0.0, -LOWERED_OFFSET * t * t * (3.0 - 2.0 * t), 0.0)
+    }
+
+    /// Puts the weapon into its final state without animation, the weapon is ready if it is in
+    /// hands.
+    pub fn set_in_hands(&mut self, in_hands: bool, graph: &mut Graph) {
+        self.state = if in_hands {
+            WeaponState::Ready
+        } else {
+            WeaponState::Holstered
+        };
+        self.state_time = 0.0;
+        self.set_visibility(in_hands, graph);
     }
 
     pub fn get_shot_position(&self, graph: &Graph) -> Vector3<f32> {
@@ -206,8 +593,13 @@ impl Weapon {
         self.ammo += amount;
     }
 
-    fn update_laser_sight(&self, graph: &mut Graph, actors: &ActorContainer) {
+    pub fn set_ammo(&mut self, ammo: u32) {
+        self.ammo = ammo;
+    }
+
+    fn update_laser_sight(&mut self, graph: &mut Graph, actors: &ActorContainer) {
         let mut laser_dot_position = Vector3::default();
+        let mut aim_point = None;
         let model = &graph[self.model];
         let begin = model.global_position();
         let end = begin + model.look_vector().scale(100.0);
@@ -237,12 +629,24 @@ impl Weapon {
                 .unwrap_or_default()
                 .scale(0.2);
             laser_dot_position = hit.position.coords + offset;
+            aim_point = Some(hit.position.coords);
             break 'hit_loop;
         }
 
         graph[self.laser_dot]
             .local_transform_mut()
             .set_position(laser_dot_position);
+        self.aim_point = aim_point;
+    }
+
+    /// Point where the laser sight hits something, `None` if it hits nothing.
+    pub fn aim_point(&self) -> Option<Vector3<f32>> {
+        self.aim_point
+    }
+
+    /// Returns `true` if the weapon is raised or being raised.
+    pub fn is_in_hands(&self) -> bool {
+        self.state != WeaponState::Holstered && self.state != WeaponState::Lowering
     }
 
     pub fn ammo(&self) -> u32 {
@@ -261,31 +665,64 @@ impl Weapon {
         Self::get_definition(self.kind)
     }
 
-    pub fn try_shoot(&mut self, scene: &mut Scene, time: GameTime) -> bool {
-        if self.ammo != 0 && time.elapsed - self.last_shot_time >= self.definition().shoot_interval
+    /// Pulls the trigger, returns kind of projectile to create if the weapon fired. Bursts
+    /// are continued and charged shots are released by [`Weapon::update`].
+    pub fn try_shoot(
+        &mut self,
+        scene: &mut Scene,
+        time: GameTime,
+        mode: FireMode,
+        direction: Option<Vector3<f32>>,
+    ) -> Option<ProjectileKind> {
+        let definition = self.definition();
+        let fire_mode = definition.fire_mode(mode);
+        if self.ammo < fire_mode.ammo_cost
+            || !self.is_ready()
+            || time.elapsed - self.last_shot_time
+                < definition.fire_mode(self.fire_mode).shoot_interval
         {
-            self.ammo -= 1;
-
-            self.offset = Vector3::new(0.0, 0.0, -0.05);
-            self.last_shot_time = time.elapsed;
-
-            let position = self.get_shot_position(&scene.graph);
-
-            if let Some(sender) = self.sender.as_ref() {
-                sender
-                    .send(Message::PlaySound {
-                        path: PathBuf::from(self.definition().shot_sound),
-                        position,
-                        gain: 1.0,
-                        rolloff_factor: 5.0,
-                        radius: 3.0,
-                    })
-                    .unwrap();
-            }
+            return None;
+        }
 
-            true
-        } else {
-            false
+        self.trigger_direction = direction;
+        if fire_mode.charge_time > 0.0 {
+            self.charge += time.delta;
+            self.charge_held = true;
+            return None;
+        }
+
+        self.ammo -= fire_mode.ammo_cost;
+        self.burst_left = fire_mode.burst - 1;
+        self.fire(scene, time, mode);
+
+        Some(fire_mode.projectile)
+    }
+
+    /// Direction of the last pull of the trigger, it is used for shots that the weapon fires by
+    /// itself.
+    pub fn trigger_direction(&self) -> Option<Vector3<f32>> {
+        self.trigger_direction
+    }
+
+    /// Plays the shot, the projectile is created by the level.
+    fn fire(&mut self, scene: &mut Scene, time: GameTime, mode: FireMode) {
+        self.state = WeaponState::Firing;
+        self.fire_mode = mode;
+        self.offset = Vector3::new(0.0, 0.0, -0.05);
+        self.last_shot_time = time.elapsed;
+
+        let position = self.get_shot_position(&scene.graph);
+
+        if let Some(sender) = self.sender.as_ref() {
+            sender
+                .send(Message::PlaySound {
+                    path: PathBuf::from(self.definition().shot_sound),
+                    position,
+                    gain: 1.0,
+                    rolloff_factor: 5.0,
+                    radius: 3.0,
+                })
+                .unwrap();
         }
     }
 
@@ -321,11 +758,43 @@ impl WeaponContainer {
         self.pool.iter_mut()
     }
 
-    pub fn update(&mut self, scene: &mut Scene, actors: &ActorContainer) {
-        for weapon in self.pool.iter_mut() {
-            weapon.update(scene, actors)
+    /// Returns shots that weapons fired by themselves, see [`Weapon::update`].
+    pub fn update(
+        &mut self,
+        scene: &mut Scene,
+        actors: &ActorContainer,
+        time: GameTime,
+    ) -> Vec<(Handle<Weapon>, ProjectileKind)> {
+        let mut shots = Vec::new();
+        for (handle, weapon) in self.pool.pair_iter_mut() {
+            if let Some(projectile) = weapon.update(scene, actors, time) {
+                shots.push((handle, projectile));
+            }
+        }
+        shots
+    }
+
+    /// Restores states of weapons after loading, current weapons of actors are ready and others
+    /// are holstered.
+    pub fn resolve(&mut self, actors: &ActorContainer, graph: &mut Graph) {
+        for actor in actors.iter() {
+            for &weapon in actor.weapons() {
+                self.pool[weapon].set_in_hands(weapon == actor.current_weapon(), graph);
+            }
         }
     }
+
+    /// Starts raising of a weapon once other weapons of its owner are lowered.
+    pub fn raise(&mut self, weapon: Handle<Weapon>) {
+        let owner = self.pool[weapon].owner;
+        let delay = self
+            .pool
+            .pair_iter()
+            .filter(|(handle, other)| *handle != weapon && other.owner == owner)
+            .map(|(_, other)| other.time_to_lower())
+            .fold(0.0, f32::max);
+        self.pool[weapon].raise(delay);
+    }
 }
 
 impl Index<Handle<Weapon>> for WeaponContainer {